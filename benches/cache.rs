@@ -0,0 +1,55 @@
+//! Cache operations: key building and reads and writes of results and page bodies.
+//! Run with `cargo bench --bench cache`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use rust_scrapper::{CacheBackend, MemoryCache, ScrapeCache};
+
+fn keys(c: &mut Criterion) {
+    let cache = ScrapeCache::new();
+    let mut headers = HeaderMap::new();
+    headers.insert(USER_AGENT, HeaderValue::from_static("rust-scrapper-bench/1.0"));
+    c.bench_function("cache/key_for", |b| {
+        b.iter(|| cache.key_for(black_box("https://example.com/products?page=3"), black_box(".product h2"), &headers))
+    });
+}
+
+fn results(c: &mut Criterion) {
+    let cache = ScrapeCache::new();
+    let items: Vec<String> = (0..100).map(|i| format!("Product {}", i)).collect();
+    for i in 0..1_000 {
+        cache.set(&format!("key-{}", i), items.clone());
+    }
+    c.bench_function("cache/set_results", |b| b.iter(|| cache.set(black_box("key-500"), items.clone())));
+    c.bench_function("cache/get_results_hit", |b| b.iter(|| cache.get(black_box("key-500"))));
+    c.bench_function("cache/get_results_miss", |b| b.iter(|| cache.get(black_box("missing"))));
+}
+
+fn bodies(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cache/body");
+    for &size in &[1_024usize, 64 * 1_024, 1_024 * 1_024] {
+        let body = "x".repeat(size);
+        let cache = ScrapeCache::new();
+        group.bench_with_input(BenchmarkId::new("set", size), &body, |b, body| {
+            b.iter(|| cache.set_body("body", body.clone()))
+        });
+        cache.set_body("body", body.clone());
+        group.bench_with_input(BenchmarkId::new("get", size), &size, |b, _| b.iter(|| cache.get_body(black_box("body"))));
+    }
+    group.finish();
+}
+
+fn bounded_memory(c: &mut Criterion) {
+    let backend = MemoryCache::new().with_max_bytes(256 * 1_024);
+    let value = "x".repeat(1_024);
+    let mut i = 0u64;
+    c.bench_function("cache/bounded_set_with_eviction", |b| {
+        b.iter(|| {
+            i += 1;
+            backend.set(&format!("key-{}", i), value.clone(), None)
+        })
+    });
+}
+
+criterion_group!(benches, keys, results, bodies, bounded_memory);
+criterion_main!(benches);
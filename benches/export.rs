@@ -0,0 +1,46 @@
+//! Export serialization of plain results and structured records.
+//! Run with `cargo bench --bench export`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rust_scrapper::Exporter;
+use serde_json::{json, Value};
+
+const SIZES: &[usize] = &[100, 10_000];
+
+fn records(count: usize) -> Vec<Value> {
+    (0..count)
+        .map(|i| {
+            json!({
+                "title": format!("Product {}", i),
+                "price": (i % 500) as f64 + 0.99,
+                "url": format!("https://example.com/p/{}", i),
+                "tags": ["new", "sale"],
+            })
+        })
+        .collect()
+}
+
+fn export(c: &mut Criterion) {
+    let mut group = c.benchmark_group("export");
+    let csv_path = std::env::temp_dir().join("rust-scrapper-bench.csv");
+    let csv_path = csv_path.to_string_lossy().to_string();
+    for &size in SIZES {
+        let items: Vec<String> = (0..size).map(|i| format!("<h2>Product {}</h2>", i)).collect();
+        let records = records(size);
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::new("to_json", size), &items, |b, items| {
+            b.iter(|| Exporter::to_json(black_box(items.clone())))
+        });
+        group.bench_with_input(BenchmarkId::new("records_to_json", size), &records, |b, records| {
+            b.iter(|| Exporter::records_to_json(black_box(records)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("records_to_csv", size), &records, |b, records| {
+            b.iter(|| Exporter::records_to_csv(black_box(records), &csv_path).unwrap())
+        });
+    }
+    group.finish();
+    let _ = std::fs::remove_file(csv_path);
+}
+
+criterion_group!(benches, export);
+criterion_main!(benches);
@@ -0,0 +1,79 @@
+//! Fetch-less extraction: parsing plus selecting on generated pages of growing size.
+//!
+//! Compares the extraction engines: `extract` (parse and inner HTML per call), a reused
+//! `ParsedPage` with owned and borrowed text, and a `Schema`. Run with
+//! `cargo bench --bench extraction`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rust_scrapper::{extract, Field, ParsedPage, Schema};
+
+const SIZES: &[usize] = &[10, 100, 1_000, 10_000];
+
+/// A product listing with `products` items, similar to a typical shop category page.
+fn fixture(products: usize) -> String {
+    let mut html = String::from("<html><head><title>Products</title></head><body><ul class=\"products\">");
+    for i in 0..products {
+        html.push_str(&format!(
+            r#"<li class="product" data-sku="SKU-{i:05}"><h2>Product {i}</h2><span class="price">{}.99</span><a href="/p/{i}">details</a><p class="description">Lorem ipsum <b>dolor</b> sit amet.</p></li>"#,
+            i % 500
+        ));
+    }
+    html.push_str("</ul></body></html>");
+    html
+}
+
+fn schema() -> Schema {
+    Schema::new()
+        .root(".product")
+        .field(Field::new("title", "h2"))
+        .field(Field::new("price", ".price"))
+        .field(Field::new("link", "a").attr("href"))
+        .field(Field::new("sku", "li").attr("data-sku"))
+}
+
+fn parse_and_select(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_and_select");
+    for &size in SIZES {
+        let html = fixture(size);
+        group.throughput(Throughput::Bytes(html.len() as u64));
+        group.bench_with_input(BenchmarkId::new("extract", size), &html, |b, html| {
+            b.iter(|| extract(black_box(html), ".product h2").unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("parsed_page", size), &html, |b, html| {
+            b.iter(|| ParsedPage::parse(black_box(html)).select_text(".product h2").unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("schema", size), &html, |b, html| {
+            let schema = schema();
+            b.iter(|| schema.extract(black_box(html)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn select_on_parsed(c: &mut Criterion) {
+    let mut group = c.benchmark_group("select_on_parsed_page");
+    for &size in SIZES {
+        let page = ParsedPage::parse(&fixture(size));
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::new("owned_text", size), &page, |b, page| {
+            b.iter(|| page.select_text(black_box(".product .description")).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("borrowed_text", size), &page, |b, page| {
+            b.iter(|| page.select_text_borrowed(black_box(".product .description")).unwrap().len())
+        });
+        group.bench_with_input(BenchmarkId::new("owned_attr", size), &page, |b, page| {
+            b.iter(|| page.select_attr(black_box(".product"), "data-sku").unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("borrowed_attr", size), &page, |b, page| {
+            b.iter(|| page.select_attr_borrowed(black_box(".product"), "data-sku").unwrap().len())
+        });
+        group.bench_with_input(BenchmarkId::new("schema", size), &page, |b, page| {
+            let schema = schema();
+            b.iter(|| page.extract(black_box(&schema)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse_and_select, select_on_parsed);
+criterion_main!(benches);
@@ -0,0 +1,93 @@
+//! Compares parsing pages inline on the async runtime with `parse_on_blocking_pool`.
+//!
+//! Many large pages are scraped concurrently on a single-threaded runtime while a timer
+//! task measures how late its 1 ms ticks fire. Inline parsing occupies the runtime thread
+//! and delays the timer; offloading keeps it responsive and parses pages in parallel.
+//! The difference needs spare CPU cores: on a single core, the blocking threads compete
+//! with the runtime thread. Run with `cargo bench --bench parse_offload`.
+
+use rust_scrapper::{Field, RustScrapper, Schema};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const PAGES: usize = 32;
+const PRODUCTS_PER_PAGE: usize = 5_000;
+
+fn fixture(products: usize) -> String {
+    let mut html = String::from("<html><body><ul>");
+    for i in 0..products {
+        html.push_str(&format!(
+            r#"<li class="product" data-id="{i}"><h2>Product {i}</h2><span class="price">{}.99</span><a href="/p/{i}">details</a></li>"#,
+            i % 500
+        ));
+    }
+    html.push_str("</ul></body></html>");
+    html
+}
+
+async fn run(url: &str, schema: &Schema, offload: bool) -> (Duration, Duration) {
+    // Clients are built up front, as building one blocks for a while too.
+    let scrappers: Vec<_> = (0..PAGES)
+        .map(|_| {
+            let scrapper = RustScrapper::new();
+            if offload {
+                scrapper.parse_on_blocking_pool()
+            } else {
+                scrapper
+            }
+        })
+        .collect();
+    let stop = Arc::new(AtomicBool::new(false));
+    let ticker = {
+        let stop = stop.clone();
+        tokio::task::spawn_local(async move {
+            let tick = Duration::from_millis(1);
+            let mut worst = Duration::ZERO;
+            while !stop.load(Ordering::Relaxed) {
+                let started = Instant::now();
+                tokio::time::sleep(tick).await;
+                worst = worst.max(started.elapsed().saturating_sub(tick));
+            }
+            worst
+        })
+    };
+
+    let started = Instant::now();
+    let tasks: Vec<_> = scrappers
+        .into_iter()
+        .map(|mut scrapper| {
+            let (url, schema) = (url.to_string(), schema.clone());
+            tokio::task::spawn_local(async move {
+                let records = scrapper.scrape_structured_async(&url, &schema).await.map_err(|e| e.to_string());
+                records.map(|records| records.len())
+            })
+        })
+        .collect();
+    for task in tasks {
+        assert_eq!(task.await.unwrap().unwrap(), PRODUCTS_PER_PAGE);
+    }
+    let elapsed = started.elapsed();
+    stop.store(true, Ordering::Relaxed);
+    (elapsed, ticker.await.unwrap())
+}
+
+fn main() {
+    let path = std::env::temp_dir().join("rust-scrapper-parse-offload.html");
+    std::fs::write(&path, fixture(PRODUCTS_PER_PAGE)).unwrap();
+    let url = format!("file://{}", path.display());
+    let schema = Schema::new()
+        .root(".product")
+        .field(Field::new("title", "h2"))
+        .field(Field::new("price", ".price"))
+        .field(Field::new("link", "a").attr("href"));
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    println!("{} pages x {} products", PAGES, PRODUCTS_PER_PAGE);
+    for (label, offload) in [("inline", false), ("blocking pool", true)] {
+        let local = tokio::task::LocalSet::new();
+        let (elapsed, worst) = local.block_on(&runtime, run(&url, &schema, offload));
+        println!("{:<14} total {:>10.1?}   worst timer delay {:>10.1?}", label, elapsed, worst);
+    }
+    let _ = std::fs::remove_file(path);
+}
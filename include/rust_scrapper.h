@@ -0,0 +1,33 @@
+/* C API of rust-scrapper, available when built with the `ffi` feature. */
+#ifndef RUST_SCRAPPER_H
+#define RUST_SCRAPPER_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct RustScrapper RustScrapper;
+
+/* Creates a scraper. Release it with rust_scrapper_free. */
+RustScrapper *rust_scrapper_new(void);
+
+/* Releases a scraper. Accepts NULL. */
+void rust_scrapper_free(RustScrapper *scrapper);
+
+/* Returns the inner HTML of every match as a JSON array of strings, or NULL on error. */
+char *rust_scrapper_scrape(RustScrapper *scrapper, const char *url, const char *selector);
+
+/* Returns the records described by a JSON schema as a JSON array, or NULL on error. */
+char *rust_scrapper_scrape_structured(RustScrapper *scrapper, const char *url, const char *schema_json);
+
+/* Returns the last error message on this thread, or NULL. */
+char *rust_scrapper_last_error(void);
+
+/* Releases a string returned by this library. Accepts NULL. */
+void rust_scrapper_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RUST_SCRAPPER_H */
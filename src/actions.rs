@@ -0,0 +1,159 @@
+//! Multi-step browser workflows.
+//!
+//! Some data is only reachable after interacting with a page: searching, filtering, opening
+//! a result. An `Action` list describes such a flow, e.g. goto → type → press Enter → wait →
+//! click → extract, and `JsScraper::run_actions` executes it in one browser session,
+//! collecting what the `Extract` steps matched.
+
+use crate::js::{self, BrowserBackend, BrowserSession, JsScrapeOptions, WaitFor};
+use crate::JsScraper;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// A step of a browser workflow.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Loads a URL and awaits the options' wait conditions.
+    Goto(String),
+    /// Clicks the first element matching the selector, waiting for it to appear.
+    Click(String),
+    /// Clicks the element matching the selector and types the text into it.
+    Type(String, String),
+    /// Presses a key in the focused element, e.g. `Enter` to submit a search.
+    PressKey(String),
+    /// Selects the option with this value in the `<select>` matching the selector.
+    Select(String, String),
+    /// Waits for a condition, e.g. the results of a search.
+    Wait(WaitFor),
+    /// Stores the inner HTML of the elements matching the selector under a name.
+    Extract(String, String),
+}
+
+impl Action {
+    pub fn goto(url: &str) -> Self {
+        Action::Goto(url.to_string())
+    }
+
+    pub fn click(selector: &str) -> Self {
+        Action::Click(selector.to_string())
+    }
+
+    pub fn type_text(selector: &str, text: &str) -> Self {
+        Action::Type(selector.to_string(), text.to_string())
+    }
+
+    pub fn press_key(key: &str) -> Self {
+        Action::PressKey(key.to_string())
+    }
+
+    pub fn select(selector: &str, value: &str) -> Self {
+        Action::Select(selector.to_string(), value.to_string())
+    }
+
+    pub fn wait(wait: WaitFor) -> Self {
+        Action::Wait(wait)
+    }
+
+    /// Extracts the elements matching `selector` as `name`. Extracting a name again appends
+    /// to its results, e.g. once per page of a paginated flow.
+    pub fn extract(name: &str, selector: &str) -> Self {
+        Action::Extract(name.to_string(), selector.to_string())
+    }
+}
+
+/// What a workflow extracted, with the page it ended on.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ActionsResult {
+    /// Results of the `Extract` steps by name.
+    pub extracted: BTreeMap<String, Vec<String>>,
+    /// URL of the page after the last step.
+    pub url: String,
+    /// HTML of the page after the last step.
+    pub html: String,
+}
+
+impl JsScraper {
+    /// Runs the steps one after another in one browser tab. Wait conditions of the options
+    /// apply after every `Goto`; steps that trigger a navigation, such as a click on a link,
+    /// should be followed by a `Wait` step. Fails with the first failed step. Chrome only.
+    pub fn run_actions(actions: &[Action], options: &JsScrapeOptions) -> Result<ActionsResult, Box<dyn std::error::Error>> {
+        if options.backend != BrowserBackend::Chrome {
+            return Err("Browser workflows are only supported by the Chrome backend".into());
+        }
+        let session = BrowserSession::launch(options)?;
+        let tab = session.open_tab(options)?;
+        js::intercept_network(&tab, options)?;
+        let mut result = ActionsResult::default();
+        for (step, action) in actions.iter().enumerate() {
+            run_action(&tab, action, options, &mut result.extracted)
+                .map_err(|e| format!("Step {} ({:?}) failed: {}", step + 1, action, e))?;
+        }
+        result.url = tab.get_url();
+        result.html = js::page_html(&tab, options.pierce_shadow_roots)?;
+        Ok(result)
+    }
+
+    /// Async variant of `run_actions`.
+    pub async fn run_actions_async(actions: &[Action], options: &JsScrapeOptions) -> Result<ActionsResult, Box<dyn std::error::Error>> {
+        let (actions, options) = (actions.to_vec(), options.clone());
+        js::run_blocking(move || JsScraper::run_actions(&actions, &options)).await
+    }
+}
+
+fn run_action(
+    tab: &headless_chrome::Tab,
+    action: &Action,
+    options: &JsScrapeOptions,
+    extracted: &mut BTreeMap<String, Vec<String>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        Action::Goto(url) => js::navigate(tab, url, options)?,
+        Action::Click(selector) => {
+            tab.wait_for_element(selector)?.click()?;
+        }
+        Action::Type(selector, text) => {
+            tab.wait_for_element(selector)?.type_into(text)?;
+        }
+        Action::PressKey(key) => {
+            tab.press_key(key)?;
+        }
+        Action::Select(selector, value) => {
+            // Setting the value programmatically fires no events, so the page's listeners are
+            // notified like after a user's choice.
+            let function = format!(
+                "function() {{ this.value = {}; \
+                 this.dispatchEvent(new Event('input', {{ bubbles: true }})); \
+                 this.dispatchEvent(new Event('change', {{ bubbles: true }})); }}",
+                serde_json::to_string(value)?
+            );
+            tab.wait_for_element(selector)?.call_js_fn(&function, false)?;
+        }
+        Action::Wait(wait) => js::run_waits(std::slice::from_ref(wait), options.wait_timeout, options.pierce_shadow_roots, |script| {
+            Ok(tab.evaluate(script, false)?.value.unwrap_or_default())
+        })?,
+        Action::Extract(name, selector) => {
+            let html = js::page_html(tab, options.pierce_shadow_roots)?;
+            extracted.entry(name.clone()).or_default().extend(crate::extract(&html, selector)?);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_steps_with_their_arguments() {
+        assert_eq!(Action::type_text("#q", "lamp"), Action::Type("#q".to_string(), "lamp".to_string()));
+        assert_eq!(Action::select("select.sort", "price"), Action::Select("select.sort".to_string(), "price".to_string()));
+        assert_eq!(Action::extract("titles", "h2"), Action::Extract("titles".to_string(), "h2".to_string()));
+    }
+
+    #[test]
+    fn runs_workflows_in_chrome_only() {
+        let options = JsScrapeOptions::new().backend(BrowserBackend::WebDriver("http://localhost:4444".to_string()));
+        let error = JsScraper::run_actions(&[Action::goto("https://example.com")], &options).unwrap_err();
+        assert!(error.to_string().contains("Chrome backend"));
+    }
+}
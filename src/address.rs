@@ -0,0 +1,279 @@
+//! Postal address extraction.
+//!
+//! Addresses are read from schema.org `PostalAddress` markup, as microdata or JSON-LD.
+//! Pages without such markup fall back to heuristics on `<address>` elements, which
+//! recognize postal codes, street names and country names in free-form text.
+
+use crate::schema::parse_selector;
+use regex::Regex;
+use scraper::{ElementRef, Html};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::OnceLock;
+
+/// Components of a postal address. Missing components are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Address {
+    pub street: Option<String>,
+    pub city: Option<String>,
+    /// State, province or county.
+    pub region: Option<String>,
+    pub postal_code: Option<String>,
+    pub country: Option<String>,
+}
+
+impl Address {
+    /// Whether no component is set.
+    pub fn is_empty(&self) -> bool {
+        self.components().next().is_none()
+    }
+
+    /// The address on one line, e.g. for a geocoding query.
+    pub fn formatted(&self) -> String {
+        self.components().collect::<Vec<_>>().join(", ")
+    }
+
+    fn components(&self) -> impl Iterator<Item = &str> {
+        [&self.street, &self.city, &self.region, &self.postal_code, &self.country]
+            .into_iter()
+            .filter_map(|component| component.as_deref())
+    }
+}
+
+const STREET_WORDS: &[&str] = &[
+    "street", "st.", "avenue", "ave", "road", "rd.", "boulevard", "blvd", "lane", "drive", "parkway", "pkwy",
+    "way", "square", "cadde", "cad.", "sokak", "sk.", "mahalle", "mah.", "bulvar", "str.", "rue", "avenida",
+    "calle", "via", "piazza", "no:",
+];
+
+/// Endings of compound street names, e.g. "Hauptstraße".
+const STREET_SUFFIXES: &[&str] = &["straße", "strasse", "weg", "platz", "gasse"];
+
+const COUNTRIES: &[&str] = &[
+    "usa", "us", "united states", "united states of america", "uk", "united kingdom", "great britain", "england",
+    "germany", "deutschland", "france", "turkey", "türkiye", "turkiye", "spain", "españa", "italy", "italia",
+    "netherlands", "nederland", "belgium", "canada", "australia", "new zealand", "japan", "india", "brazil",
+    "brasil", "mexico", "méxico", "switzerland", "schweiz", "suisse", "austria", "österreich", "poland", "polska",
+    "sweden", "norway", "denmark", "finland", "ireland", "portugal", "greece", "russia",
+];
+
+fn postal_code_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"\b(?:[A-Z]{1,2}\d[A-Z\d]? ?\d[A-Z]{2}|[A-Z]\d[A-Z] ?\d[A-Z]\d|\d{5}(?:-\d{4})?|\d{4}(?: ?[A-Z]{2})?|\d{2}-\d{3})\b").unwrap()
+    })
+}
+
+/// Extracts the addresses on a page, from schema.org markup or, without it, `<address>` elements.
+pub fn extract_addresses(html: &str) -> Vec<Address> {
+    addresses_in(&Html::parse_document(html))
+}
+
+/// Extracts the addresses in a parsed document.
+pub(crate) fn addresses_in(document: &Html) -> Vec<Address> {
+    let mut addresses = microdata_addresses(document);
+    addresses.extend(json_ld_addresses(document));
+    if addresses.is_empty() {
+        if let Ok(selector) = parse_selector("address") {
+            addresses.extend(document.select(&selector).filter_map(|elem| parse_address(&element_lines(elem))));
+        }
+    }
+    let mut unique = Vec::new();
+    for address in addresses {
+        if !address.is_empty() && !unique.contains(&address) {
+            unique.push(address);
+        }
+    }
+    unique
+}
+
+/// Parses a free-form address with components separated by commas or line breaks,
+/// e.g. "1600 Amphitheatre Parkway, Mountain View, CA 94043, USA". Returns `None` if
+/// neither a postal code nor both a street and a city are recognized.
+pub fn parse_address(text: &str) -> Option<Address> {
+    let mut parts: Vec<String> = text
+        .split(['\n', ','])
+        .map(|part| part.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|part| !part.is_empty())
+        .collect();
+    let mut address = Address::default();
+    if parts.last().is_some_and(|last| COUNTRIES.contains(&last.to_lowercase().as_str())) {
+        address.country = parts.pop();
+    }
+
+    let postal = parts
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, part)| !is_street(part))
+        .find_map(|(i, part)| postal_code_regex().find(part).map(|m| (i, m.range())));
+    if let Some((i, range)) = postal {
+        let part = parts.remove(i);
+        address.postal_code = Some(part[range.clone()].to_string());
+        let rest = format!("{} {}", &part[..range.start], &part[range.end..]).trim().to_string();
+        let region_only = !rest.is_empty() && rest.len() <= 3 && rest.chars().all(|c| c.is_ascii_uppercase());
+        if region_only {
+            address.region = Some(rest);
+            if i > 0 && !is_street(&parts[i - 1]) {
+                address.city = Some(parts.remove(i - 1));
+            }
+        } else if let Some((city, region)) = rest.split_once('/') {
+            address.city = Some(city.trim().to_string());
+            address.region = Some(region.trim().to_string());
+        } else if !rest.is_empty() {
+            address.city = Some(rest);
+        }
+    }
+
+    let street = parts
+        .iter()
+        .position(|part| is_street(part) || part.chars().any(|c| c.is_ascii_digit()))
+        .or_else(|| (!parts.is_empty() && (parts.len() > 1 || address.city.is_some())).then_some(0));
+    if let Some(i) = street {
+        address.street = Some(parts.remove(i));
+    }
+    if address.city.is_none() {
+        address.city = parts.pop();
+    }
+    let recognized = address.postal_code.is_some() || (address.street.is_some() && address.city.is_some());
+    recognized.then_some(address)
+}
+
+fn is_street(part: &str) -> bool {
+    part.to_lowercase().split_whitespace().any(|word| {
+        STREET_WORDS.iter().any(|street| word == *street || (street.ends_with(['.', ':']) && word.starts_with(street)))
+            || STREET_SUFFIXES.iter().any(|suffix| word.ends_with(suffix))
+    })
+}
+
+/// The text of an element, one line per text node, so `<br>`-separated lines stay apart.
+fn element_lines(elem: ElementRef) -> String {
+    elem.text().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().join("\n")
+}
+
+fn microdata_addresses(document: &Html) -> Vec<Address> {
+    let Ok(selector) = parse_selector(r#"[itemtype$="PostalAddress"]"#) else {
+        return Vec::new();
+    };
+    let prop = |elem: ElementRef, name: &str| {
+        let selector = parse_selector(&format!(r#"[itemprop="{}"]"#, name)).ok()?;
+        let found = elem.select(&selector).next()?;
+        let text = found.value().attr("content").map(str::to_string).unwrap_or_else(|| element_lines(found).replace('\n', " "));
+        Some(text).filter(|text| !text.is_empty())
+    };
+    document
+        .select(&selector)
+        .map(|elem| Address {
+            street: prop(elem, "streetAddress"),
+            city: prop(elem, "addressLocality"),
+            region: prop(elem, "addressRegion"),
+            postal_code: prop(elem, "postalCode"),
+            country: prop(elem, "addressCountry"),
+        })
+        .collect()
+}
+
+fn json_ld_addresses(document: &Html) -> Vec<Address> {
+    let mut addresses = Vec::new();
+    let Ok(selector) = parse_selector(r#"script[type="application/ld+json"]"#) else {
+        return addresses;
+    };
+    for script in document.select(&selector) {
+        if let Ok(value) = serde_json::from_str::<Value>(&script.text().collect::<String>()) {
+            collect_json_ld(&value, &mut addresses);
+        }
+    }
+    addresses
+}
+
+fn collect_json_ld(value: &Value, addresses: &mut Vec<Address>) {
+    match value {
+        Value::Array(values) => values.iter().for_each(|value| collect_json_ld(value, addresses)),
+        Value::Object(object) => {
+            let is_address = ["streetAddress", "addressLocality", "postalCode"].iter().any(|key| object.contains_key(*key));
+            if is_address {
+                let text = |key: &str| match object.get(key)? {
+                    Value::String(text) => Some(text.trim().to_string()),
+                    Value::Object(named) => named.get("name")?.as_str().map(str::to_string),
+                    _ => None,
+                };
+                addresses.push(Address {
+                    street: text("streetAddress"),
+                    city: text("addressLocality"),
+                    region: text("addressRegion"),
+                    postal_code: text("postalCode"),
+                    country: text("addressCountry"),
+                });
+                return;
+            }
+            for (key, value) in object {
+                match value {
+                    Value::String(text) if key == "address" => addresses.extend(parse_address(text)),
+                    value => collect_json_ld(value, addresses),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(street: &str, city: &str, region: &str, postal_code: &str, country: &str) -> Address {
+        let part = |text: &str| (!text.is_empty()).then(|| text.to_string());
+        Address {
+            street: part(street),
+            city: part(city),
+            region: part(region),
+            postal_code: part(postal_code),
+            country: part(country),
+        }
+    }
+
+    #[test]
+    fn parses_free_form_addresses() {
+        assert_eq!(
+            parse_address("1600 Amphitheatre Parkway, Mountain View, CA 94043, USA"),
+            Some(address("1600 Amphitheatre Parkway", "Mountain View", "CA", "94043", "USA"))
+        );
+        assert_eq!(
+            parse_address("Hauptstraße 5\n10115 Berlin\nGermany"),
+            Some(address("Hauptstraße 5", "Berlin", "", "10115", "Germany"))
+        );
+        assert_eq!(
+            parse_address("Atatürk Cad. No:12, 34000 Kadıköy/İstanbul"),
+            Some(address("Atatürk Cad. No:12", "Kadıköy", "İstanbul", "34000", ""))
+        );
+        assert_eq!(parse_address("Call us any time"), None);
+    }
+
+    #[test]
+    fn extracts_addresses_from_markup() {
+        let html = r#"
+            <div itemscope itemtype="https://schema.org/PostalAddress">
+              <span itemprop="streetAddress">1 Main St.</span>
+              <span itemprop="addressLocality">Springfield</span>
+              <meta itemprop="addressCountry" content="US">
+            </div>
+            <script type="application/ld+json">
+              {"@type": "Organization", "address": {"@type": "PostalAddress", "postalCode": "SW1A 1AA",
+               "addressLocality": "London", "addressCountry": {"name": "UK"}}}
+            </script>"#;
+        assert_eq!(
+            extract_addresses(html),
+            vec![
+                address("1 Main St.", "Springfield", "", "", "US"),
+                address("", "London", "", "SW1A 1AA", "UK"),
+            ]
+        );
+        assert_eq!(extract_addresses(html)[1].formatted(), "London, SW1A 1AA, UK");
+    }
+
+    #[test]
+    fn falls_back_to_address_elements() {
+        let html = "<address>221B Baker Street<br>London NW1 6XE<br>UK</address>";
+        assert_eq!(extract_addresses(html), vec![address("221B Baker Street", "London", "", "NW1 6XE", "UK")]);
+    }
+}
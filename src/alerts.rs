@@ -0,0 +1,281 @@
+//! Alerts on changes in watched pages.
+//!
+//! `Alerts` compare each new snapshot recorded in a `SnapshotStore` with the previous one and
+//! notify hooks when a field changes or crosses a threshold, e.g. a price drop. Hooks are
+//! closures, webhooks receiving the alert as JSON, or channels.
+
+use crate::coerce::parse_number;
+use crate::context::RequestContext;
+use crate::diff::key_text;
+use crate::snapshot::Snapshot;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+/// What triggered an alert.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum AlertKind {
+    /// The field has a different value than in the previous snapshot.
+    Changed,
+    /// The numeric value rose above the threshold.
+    Above { threshold: f64 },
+    /// The numeric value fell below the threshold.
+    Below { threshold: f64 },
+}
+
+/// A triggered alert.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alert {
+    pub url: String,
+    pub field: String,
+    /// Value of the key field of the record, with `Alerts::keyed_by`.
+    pub key: Option<String>,
+    pub kind: AlertKind,
+    /// The value in the previous snapshot, if there was one.
+    pub before: Option<Value>,
+    pub after: Value,
+    /// Timestamp of the snapshot that triggered the alert, in Unix milliseconds.
+    pub taken_at: i64,
+    /// Context of the scrape that recorded the snapshot.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<RequestContext>,
+}
+
+#[derive(Clone)]
+struct AlertRule {
+    field: String,
+    kind: AlertKind,
+}
+
+#[derive(Clone)]
+enum Hook {
+    Callback(Arc<dyn Fn(&Alert) + Send + Sync>),
+    #[cfg(feature = "native")]
+    Webhook(String),
+    Channel(Arc<Mutex<Sender<Alert>>>),
+}
+
+/// Alert rules and the hooks notified when one triggers. Attach them to a store with
+/// `SnapshotStore::with_alerts`.
+#[derive(Clone, Default)]
+pub struct Alerts {
+    rules: Vec<AlertRule>,
+    key: Option<String>,
+    hooks: Vec<Hook>,
+}
+
+impl Alerts {
+    /// Creates alerts without rules or hooks.
+    pub fn new() -> Self {
+        Alerts::default()
+    }
+
+    /// Alerts when the field's value differs from the previous snapshot.
+    pub fn on_change(mut self, field: &str) -> Self {
+        self.rules.push(AlertRule {
+            field: field.to_string(),
+            kind: AlertKind::Changed,
+        });
+        self
+    }
+
+    /// Alerts when the field's numeric value rises above `threshold` after being at or below it.
+    /// Prices such as "$1,299.99" are parsed as numbers.
+    pub fn when_above(mut self, field: &str, threshold: f64) -> Self {
+        self.rules.push(AlertRule {
+            field: field.to_string(),
+            kind: AlertKind::Above { threshold },
+        });
+        self
+    }
+
+    /// Alerts when the field's numeric value falls below `threshold` after being at or above it.
+    pub fn when_below(mut self, field: &str, threshold: f64) -> Self {
+        self.rules.push(AlertRule {
+            field: field.to_string(),
+            kind: AlertKind::Below { threshold },
+        });
+        self
+    }
+
+    /// Matches records between snapshots by the value of this field instead of by position,
+    /// for pages listing several records.
+    pub fn keyed_by(mut self, key: &str) -> Self {
+        self.key = Some(key.to_string());
+        self
+    }
+
+    /// Calls a closure for every alert.
+    pub fn callback<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Alert) + Send + Sync + 'static,
+    {
+        self.hooks.push(Hook::Callback(Arc::new(callback)));
+        self
+    }
+
+    /// POSTs every alert as JSON to the URL, from a background thread. Failures are logged.
+    #[cfg(feature = "native")]
+    pub fn webhook(mut self, url: &str) -> Self {
+        self.hooks.push(Hook::Webhook(url.to_string()));
+        self
+    }
+
+    /// Sends every alert into the channel. Alerts are dropped once the receiver is gone.
+    pub fn channel(mut self, sender: Sender<Alert>) -> Self {
+        self.hooks.push(Hook::Channel(Arc::new(Mutex::new(sender))));
+        self
+    }
+
+    /// Evaluates the rules on a new snapshot against the previous one, notifies the hooks
+    /// and returns the triggered alerts.
+    pub fn check(&self, previous: Option<&Snapshot>, current: &Snapshot) -> Vec<Alert> {
+        let alerts = self.evaluate(previous, current);
+        for alert in &alerts {
+            log::info!("Alert for {} ({}): {:?}", alert.url, alert.field, alert.kind);
+            self.notify(alert);
+        }
+        alerts
+    }
+
+    pub(crate) fn evaluate(&self, previous: Option<&Snapshot>, current: &Snapshot) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        for (index, record) in current.records.iter().enumerate() {
+            let key = self.key.as_ref().and_then(|key| key_text(&record[key.as_str()]));
+            let before_record = previous.and_then(|previous| match (&self.key, &key) {
+                (Some(field), Some(key)) => previous
+                    .records
+                    .iter()
+                    .find(|other| key_text(&other[field.as_str()]).as_ref() == Some(key)),
+                (Some(_), None) => None,
+                (None, _) => previous.records.get(index),
+            });
+            for rule in &self.rules {
+                let Some(after) = record.get(&rule.field).filter(|value| !value.is_null()) else {
+                    continue;
+                };
+                let before = before_record.and_then(|other| other.get(&rule.field)).filter(|value| !value.is_null());
+                let triggered = match rule.kind {
+                    AlertKind::Changed => before.is_some_and(|before| before != after),
+                    AlertKind::Above { threshold } => {
+                        number(after).is_some_and(|now| now > threshold) && !before.and_then(number).is_some_and(|then| then > threshold)
+                    }
+                    AlertKind::Below { threshold } => {
+                        number(after).is_some_and(|now| now < threshold) && !before.and_then(number).is_some_and(|then| then < threshold)
+                    }
+                };
+                if triggered {
+                    alerts.push(Alert {
+                        url: current.url.clone(),
+                        field: rule.field.clone(),
+                        key: key.clone(),
+                        kind: rule.kind.clone(),
+                        before: before.cloned(),
+                        after: after.clone(),
+                        taken_at: current.taken_at,
+                        context: current.context.clone(),
+                    });
+                }
+            }
+        }
+        alerts
+    }
+
+    fn notify(&self, alert: &Alert) {
+        for hook in &self.hooks {
+            match hook {
+                Hook::Callback(callback) => callback(alert),
+                #[cfg(feature = "native")]
+                Hook::Webhook(url) => {
+                    let (url, alert) = (url.clone(), alert.clone());
+                    // A thread of its own, as the blocking client must not run on an async runtime.
+                    std::thread::spawn(move || {
+                        let sent = reqwest::blocking::Client::new().post(&url).json(&alert).send().and_then(|r| r.error_for_status());
+                        if let Err(e) = sent {
+                            log::error!("Failed to send alert to {}: {}", url, e);
+                        }
+                    });
+                }
+                Hook::Channel(sender) => {
+                    let _ = sender.lock().unwrap().send(alert.clone());
+                }
+            }
+        }
+    }
+}
+
+/// A field value as a number, parsing text such as "1.299,99 TL".
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => parse_number(text, None),
+        Value::Object(object) => object.get("amount").and_then(Value::as_f64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    fn snapshot(records: Vec<Value>) -> Snapshot {
+        Snapshot {
+            url: "https://shop.com/lamp".to_string(),
+            taken_at: 1,
+            records,
+            context: None,
+        }
+    }
+
+    #[test]
+    fn alerts_when_prices_cross_thresholds() {
+        let alerts = Alerts::new().when_below("price", 10.0).when_above("price", 100.0);
+        let before = snapshot(vec![json!({"price": "$12.00"})]);
+        let after = snapshot(vec![json!({"price": "$9.50"})]);
+
+        let triggered = alerts.evaluate(Some(&before), &after);
+        assert_eq!(triggered.len(), 1);
+        assert_eq!(triggered[0].kind, AlertKind::Below { threshold: 10.0 });
+        assert_eq!(triggered[0].before, Some(json!("$12.00")));
+        // Already below the threshold, so no new alert.
+        assert!(alerts.evaluate(Some(&after), &after).is_empty());
+        assert_eq!(alerts.evaluate(None, &snapshot(vec![json!({"price": 150})])).len(), 1);
+    }
+
+    #[test]
+    fn matches_keyed_records_for_changes() {
+        let alerts = Alerts::new().on_change("price").keyed_by("name");
+        let before = snapshot(vec![json!({"name": "Desk", "price": 50}), json!({"name": "Lamp", "price": 12})]);
+        let after = snapshot(vec![json!({"name": "Lamp", "price": 12}), json!({"name": "Desk", "price": 45})]);
+
+        let triggered = alerts.evaluate(Some(&before), &after);
+        assert_eq!(triggered.len(), 1);
+        assert_eq!(triggered[0].key.as_deref(), Some("Desk"));
+        assert_eq!(triggered[0].after, json!(45));
+        assert!(alerts.evaluate(None, &after).is_empty());
+    }
+
+    #[test]
+    fn notifies_hooks_when_snapshots_are_recorded() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let calls = Arc::new(Mutex::new(0));
+        let counted = calls.clone();
+        let alerts = Alerts::new()
+            .on_change("price")
+            .channel(sender)
+            .callback(move |_| *counted.lock().unwrap() += 1);
+        let store = crate::snapshot::SnapshotStore::in_memory().with_alerts(alerts.clone());
+        let url = "https://shop.com/lamp";
+        store.record_at(url, vec![json!({"price": 12})], 1).unwrap();
+        store.record_at(url, vec![json!({"price": 9})], 2).unwrap();
+
+        let alert = receiver.try_recv().unwrap();
+        assert_eq!((alert.before, alert.after, alert.taken_at), (Some(json!(12)), json!(9), 2));
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(*calls.lock().unwrap(), 1);
+        assert_eq!(store.alerts_for_latest(url, &alerts).len(), 1);
+    }
+}
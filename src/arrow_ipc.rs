@@ -0,0 +1,125 @@
+//! Arrow IPC (Feather) exports of structured records.
+//!
+//! Arrow IPC files are columnar and typed, so Python (pyarrow, pandas, polars), R and DuckDB
+//! map them into memory instead of parsing text, which matters for multi-million-row scrapes.
+//! Enabled by the `arrow` feature.
+
+use crate::{cell_text, column_type, column_values, record_columns, ColumnType, Exporter};
+use arrow::array::{ArrayRef, BooleanArray, Float64Array, Int64Array, StringArray};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::RecordBatch;
+use serde_json::Value;
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::Arc;
+
+/// Records per record batch of an Arrow file, so readers can process files batch by batch.
+const BATCH_ROWS: usize = 64 * 1024;
+
+impl Exporter {
+    /// Builds an Arrow record batch with the columns of `records_to_csv`, typed like
+    /// `to_dataframe`: booleans, 64-bit integers, doubles or UTF-8 strings, all nullable.
+    pub fn to_record_batch(records: &[Value]) -> Result<RecordBatch, Box<dyn std::error::Error>> {
+        let schema = arrow_schema(records);
+        Ok(record_batch(&schema, records)?)
+    }
+
+    /// Writes the records to an Arrow IPC file (Feather v2), e.g. `products.arrow`, in
+    /// batches of 65,536 records. The schema is inferred from all records first.
+    pub fn records_to_arrow(records: &[Value], file_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let schema = arrow_schema(records);
+        let mut writer = FileWriter::try_new(BufWriter::new(File::create(file_name)?), &schema)?;
+        for batch in records.chunks(BATCH_ROWS) {
+            writer.write(&record_batch(&schema, batch)?)?;
+        }
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+fn arrow_schema(records: &[Value]) -> SchemaRef {
+    let fields: Vec<Field> = record_columns(records)
+        .into_iter()
+        .map(|column| {
+            let data_type = match column_type(&column_values(records, &column)) {
+                ColumnType::Boolean => DataType::Boolean,
+                ColumnType::Integer => DataType::Int64,
+                ColumnType::Float => DataType::Float64,
+                ColumnType::Text => DataType::Utf8,
+            };
+            Field::new(column, data_type, true)
+        })
+        .collect();
+    Arc::new(Schema::new(fields))
+}
+
+fn record_batch(schema: &SchemaRef, records: &[Value]) -> Result<RecordBatch, arrow::error::ArrowError> {
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let values = column_values(records, field.name());
+            let array: ArrayRef = match field.data_type() {
+                DataType::Boolean => Arc::new(BooleanArray::from(values.iter().map(|value| value.as_bool()).collect::<Vec<_>>())),
+                DataType::Int64 => Arc::new(Int64Array::from(values.iter().map(|value| value.as_i64()).collect::<Vec<_>>())),
+                DataType::Float64 => Arc::new(Float64Array::from(values.iter().map(|value| value.as_f64()).collect::<Vec<_>>())),
+                _ => Arc::new(StringArray::from(
+                    values
+                        .iter()
+                        .map(|value| (!value.is_null()).then(|| cell_text(value)))
+                        .collect::<Vec<_>>(),
+                )),
+            };
+            array
+        })
+        .collect();
+    RecordBatch::try_new(schema.clone(), columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Array;
+    use arrow::ipc::reader::FileReader;
+    use serde_json::json;
+
+    #[test]
+    fn types_columns_by_their_values() {
+        let records = [
+            json!({"name": "Lamp", "price": 20, "rating": 4.5, "stock": true}),
+            json!({"name": "Desk", "price": 150, "rating": 4, "tags": ["a", "b"]}),
+        ];
+        let batch = Exporter::to_record_batch(&records).unwrap();
+        let schema = batch.schema();
+        let types: Vec<(&str, &DataType)> = schema.fields().iter().map(|field| (field.name().as_str(), field.data_type())).collect();
+        assert_eq!(
+            types,
+            vec![
+                ("name", &DataType::Utf8),
+                ("price", &DataType::Int64),
+                ("rating", &DataType::Float64),
+                ("stock", &DataType::Boolean),
+                ("tags", &DataType::Utf8),
+            ]
+        );
+        let stock = batch.column(3).as_any().downcast_ref::<BooleanArray>().unwrap();
+        assert!(stock.is_null(1));
+        let tags = batch.column(4).as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(tags.value(1), "a; b");
+    }
+
+    #[test]
+    fn writes_records_to_arrow_files() {
+        let path = std::env::temp_dir().join(format!("rust-scrapper-arrow-{}.arrow", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let records: Vec<Value> = (0..3).map(|n| json!({"n": n})).collect();
+        Exporter::records_to_arrow(&records, &path).unwrap();
+        let reader = FileReader::try_new(File::open(&path).unwrap(), None).unwrap();
+        let batches: Vec<RecordBatch> = reader.map(Result::unwrap).collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(batches.len(), 1);
+        let n = batches[0].column(0).as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(n.values().to_vec(), vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,395 @@
+//! HTTP authentication.
+//!
+//! Basic and Bearer credentials are sent with every request. Digest authentication answers
+//! the server's `401` challenge and repeats the request with the computed response. OAuth2
+//! client-credentials tokens are fetched on first use and refreshed before they expire.
+
+use base64::Engine;
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Credentials applied to requests, globally or per host.
+#[derive(Debug, Clone)]
+pub enum Auth {
+    Basic { user: String, pass: String },
+    Bearer(String),
+    Digest { user: String, pass: String },
+    OAuth2(TokenManager),
+}
+
+impl Auth {
+    /// HTTP Basic authentication.
+    pub fn basic(user: &str, pass: &str) -> Self {
+        Auth::Basic {
+            user: user.to_string(),
+            pass: pass.to_string(),
+        }
+    }
+
+    /// Bearer token authentication.
+    pub fn bearer(token: &str) -> Self {
+        Auth::Bearer(token.to_string())
+    }
+
+    /// HTTP Digest authentication (MD5 and MD5-sess, `qop=auth`). Answering challenges
+    /// requires the `digest-auth` feature.
+    pub fn digest(user: &str, pass: &str) -> Self {
+        Auth::Digest {
+            user: user.to_string(),
+            pass: pass.to_string(),
+        }
+    }
+
+    /// OAuth2 client-credentials authentication, see `TokenManager`.
+    pub fn oauth2(tokens: TokenManager) -> Self {
+        Auth::OAuth2(tokens)
+    }
+
+    /// Returns the `Authorization` header sent up front, or `None` for Digest, which can
+    /// only answer a challenge, and OAuth2, whose token has to be fetched first.
+    pub(crate) fn header(&self) -> Option<String> {
+        match self {
+            Auth::Basic { user, pass } => Some(format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", user, pass))
+            )),
+            Auth::Bearer(token) => Some(format!("Bearer {}", token)),
+            Auth::Digest { .. } | Auth::OAuth2(_) => None,
+        }
+    }
+
+    /// Answers a `WWW-Authenticate: Digest ...` challenge for a request to `uri` (path and query).
+    /// Returns `None` if this is not Digest auth or not a Digest challenge, and fails if the
+    /// challenge cannot be answered.
+    pub(crate) fn digest_response(&self, method: &str, uri: &str, challenge: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let Auth::Digest { user, pass } = self else {
+            return Ok(None);
+        };
+        let Some(params) = challenge.trim().strip_prefix("Digest") else {
+            return Ok(None);
+        };
+        let params = parse_challenge(params);
+        if param(&params, "nonce").is_none() {
+            return Err("Digest challenge has no nonce".into());
+        }
+        let algorithm = param(&params, "algorithm").unwrap_or("MD5");
+        if !algorithm.eq_ignore_ascii_case("MD5") && !algorithm.eq_ignore_ascii_case("MD5-sess") {
+            return Err(format!("Unsupported Digest algorithm: {}", algorithm).into());
+        }
+        if let Some(qop) = param(&params, "qop").filter(|qop| !qop.split(',').any(|q| q.trim() == "auth")) {
+            return Err(format!("Unsupported Digest qop \"{}\", only \"auth\" is supported", qop).into());
+        }
+        digest_header(user, pass, method, uri, &params).map(Some)
+    }
+}
+
+/// The `Authorization` header answering a validated Digest challenge.
+#[cfg(feature = "digest-auth")]
+fn digest_header(user: &str, pass: &str, method: &str, uri: &str, params: &[(String, String)]) -> Result<String, Box<dyn std::error::Error>> {
+    let realm = param(params, "realm").unwrap_or_default();
+    let nonce = param(params, "nonce").unwrap_or_default();
+    let algorithm = param(params, "algorithm").unwrap_or("MD5");
+    let qop_auth = param(params, "qop").is_some();
+
+    let cnonce = cnonce();
+    let nc = "00000001";
+    let mut ha1 = md5_hex(format!("{}:{}:{}", user, realm, pass).as_bytes());
+    if algorithm.eq_ignore_ascii_case("MD5-sess") {
+        ha1 = md5_hex(format!("{}:{}:{}", ha1, nonce, cnonce).as_bytes());
+    }
+    let ha2 = md5_hex(format!("{}:{}", method, uri).as_bytes());
+    let response = if qop_auth {
+        md5_hex(format!("{}:{}:{}:{}:auth:{}", ha1, nonce, nc, cnonce, ha2).as_bytes())
+    } else {
+        md5_hex(format!("{}:{}:{}", ha1, nonce, ha2).as_bytes())
+    };
+
+    let mut header = format!(
+        "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", algorithm={}, response=\"{}\"",
+        user, realm, nonce, uri, algorithm, response
+    );
+    if let Some(opaque) = param(params, "opaque") {
+        header.push_str(&format!(", opaque=\"{}\"", opaque));
+    }
+    if qop_auth {
+        header.push_str(&format!(", qop=auth, nc={}, cnonce=\"{}\"", nc, cnonce));
+    }
+    Ok(header)
+}
+
+#[cfg(not(feature = "digest-auth"))]
+fn digest_header(_user: &str, _pass: &str, _method: &str, _uri: &str, _params: &[(String, String)]) -> Result<String, Box<dyn std::error::Error>> {
+    Err("Digest authentication requires the `digest-auth` feature".into())
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+#[derive(Debug)]
+struct CachedToken {
+    access_token: String,
+    expires_at: Option<Instant>,
+}
+
+/// Fetches and caches OAuth2 client-credentials tokens.
+/// The token is refreshed shortly before it expires, or after the API rejected it with `401`.
+/// Cloning returns a handle to the same token cache.
+#[derive(Debug, Clone)]
+pub struct TokenManager {
+    token_url: String,
+    client_id: String,
+    client_secret: String,
+    scope: Option<String>,
+    credentials_in_body: bool,
+    refresh_margin: Duration,
+    token: Arc<Mutex<Option<CachedToken>>>,
+}
+
+impl TokenManager {
+    /// Creates a manager requesting tokens from `token_url` with the client's credentials.
+    pub fn new(token_url: &str, client_id: &str, client_secret: &str) -> Self {
+        TokenManager {
+            token_url: token_url.to_string(),
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            scope: None,
+            credentials_in_body: false,
+            refresh_margin: Duration::from_secs(60),
+            token: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Requests tokens for this space-separated scope.
+    pub fn with_scope(mut self, scope: &str) -> Self {
+        self.scope = Some(scope.to_string());
+        self
+    }
+
+    /// Sends the client credentials as form fields instead of HTTP Basic auth,
+    /// for providers that only support `client_secret_post`.
+    pub fn credentials_in_body(mut self) -> Self {
+        self.credentials_in_body = true;
+        self
+    }
+
+    /// Refreshes tokens this long before they expire (one minute by default).
+    pub fn with_refresh_margin(mut self, refresh_margin: Duration) -> Self {
+        self.refresh_margin = refresh_margin;
+        self
+    }
+
+    /// Returns a valid access token, fetching a new one if needed.
+    pub async fn token(&self) -> Result<String, Box<dyn std::error::Error>> {
+        if let Some(token) = self.cached() {
+            return Ok(token);
+        }
+        let mut request = reqwest::Client::new().post(&self.token_url);
+        if !self.credentials_in_body {
+            request = request.basic_auth(&self.client_id, Some(&self.client_secret));
+        }
+        let response: TokenResponse = request.form(&self.form()).send().await?.error_for_status()?.json().await?;
+        Ok(self.store(response))
+    }
+
+    /// Blocking variant of `token`.
+    pub fn token_blocking(&self) -> Result<String, Box<dyn std::error::Error>> {
+        if let Some(token) = self.cached() {
+            return Ok(token);
+        }
+        let mut request = reqwest::blocking::Client::new().post(&self.token_url);
+        if !self.credentials_in_body {
+            request = request.basic_auth(&self.client_id, Some(&self.client_secret));
+        }
+        let response: TokenResponse = request.form(&self.form()).send()?.error_for_status()?.json()?;
+        Ok(self.store(response))
+    }
+
+    /// Drops the cached token so the next request fetches a new one.
+    pub fn invalidate(&self) {
+        *self.token.lock().unwrap() = None;
+    }
+
+    fn form(&self) -> Vec<(&'static str, String)> {
+        let mut form = vec![("grant_type", "client_credentials".to_string())];
+        if let Some(scope) = &self.scope {
+            form.push(("scope", scope.clone()));
+        }
+        if self.credentials_in_body {
+            form.push(("client_id", self.client_id.clone()));
+            form.push(("client_secret", self.client_secret.clone()));
+        }
+        form
+    }
+
+    fn cached(&self) -> Option<String> {
+        let token = self.token.lock().unwrap();
+        let token = token.as_ref()?;
+        match token.expires_at {
+            Some(expires_at) if Instant::now() + self.refresh_margin >= expires_at => None,
+            _ => Some(token.access_token.clone()),
+        }
+    }
+
+    fn store(&self, response: TokenResponse) -> String {
+        let expires_at = response.expires_in.map(|secs| Instant::now() + Duration::from_secs(secs));
+        *self.token.lock().unwrap() = Some(CachedToken {
+            access_token: response.access_token.clone(),
+            expires_at,
+        });
+        response.access_token
+    }
+}
+
+/// Splits `key=value, key="quoted, value"` pairs.
+fn parse_challenge(params: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut chars = params.chars().peekable();
+    loop {
+        while chars.next_if(|c| *c == ',' || c.is_whitespace()).is_some() {}
+        let key: String = std::iter::from_fn(|| chars.next_if(|c| *c != '=' && *c != ',')).collect();
+        if key.is_empty() {
+            break;
+        }
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            if chars.next_if_eq(&'"').is_some() {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => value.extend(chars.next()),
+                        '"' => break,
+                        c => value.push(c),
+                    }
+                }
+            } else {
+                value = std::iter::from_fn(|| chars.next_if(|c| *c != ',')).collect();
+            }
+        }
+        pairs.push((key.trim().to_string(), value.trim().to_string()));
+    }
+    pairs
+}
+
+/// The value of the challenge parameter `name`, ignoring case.
+fn param<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    params.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+}
+
+#[cfg(feature = "digest-auth")]
+fn cnonce() -> String {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    md5_hex(format!("{}:{}", nanos, count).as_bytes())[..16].to_string()
+}
+
+/// MD5 digest as lowercase hex, as required by RFC 2617.
+#[cfg(feature = "digest-auth")]
+fn md5_hex(input: &[u8]) -> String {
+    use md5::{Digest, Md5};
+    Md5::digest(input).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "digest-auth")]
+    #[test]
+    fn md5_matches_rfc_1321_test_suite() {
+        let vectors = [
+            ("", "d41d8cd98f00b204e9800998ecf8427e"),
+            ("a", "0cc175b9c0f1b6a831c399e269772661"),
+            ("abc", "900150983cd24fb0d6963f7d28e17f72"),
+            ("message digest", "f96b697d7cb7938d525a2f31aaf161d0"),
+            ("abcdefghijklmnopqrstuvwxyz", "c3fcd3d76192e4007dfb496cca67e13b"),
+            ("ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789", "d174ab98d277d9f5a5611c2c9f419d9f"),
+            (
+                "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                "57edf4a22be3c955ac49da2e2107b67a",
+            ),
+        ];
+        for (input, digest) in vectors {
+            assert_eq!(md5_hex(input.as_bytes()), digest, "{:?}", input);
+        }
+    }
+
+    #[cfg(feature = "digest-auth")]
+    #[test]
+    fn digest_matches_rfc_2617_example() {
+        let ha1 = md5_hex(b"Mufasa:testrealm@host.com:Circle Of Life");
+        let ha2 = md5_hex(b"GET:/dir/index.html");
+        let response = md5_hex(format!("{}:dcd98b7102dd2f0e8b11d0f600bfb0c093:00000001:0a4f113b:auth:{}", ha1, ha2).as_bytes());
+        assert_eq!(response, "6629fae49393a05397450978507c4ef1");
+
+        let challenge = r#"Digest realm="testrealm@host.com", qop="auth,auth-int", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093", opaque="5ccc069c403ebaf9f0171e9517f40e41""#;
+        let header = Auth::digest("Mufasa", "Circle Of Life").digest_response("GET", "/dir/index.html", challenge).unwrap().unwrap();
+        let cnonce = header.split("cnonce=\"").nth(1).and_then(|rest| rest.split('"').next()).unwrap();
+        let expected = md5_hex(format!("{}:dcd98b7102dd2f0e8b11d0f600bfb0c093:00000001:{}:auth:{}", ha1, cnonce, ha2).as_bytes());
+        assert!(header.contains(&format!("response=\"{}\"", expected)), "{}", header);
+        assert!(header.contains(r#"opaque="5ccc069c403ebaf9f0171e9517f40e41""#));
+    }
+
+    #[test]
+    fn rejects_challenges_it_cannot_answer() {
+        let auth = Auth::digest("Mufasa", "Circle Of Life");
+        let error = auth.digest_response("GET", "/", r#"Digest realm="r", qop="auth-int", nonce="n""#).unwrap_err();
+        assert!(error.to_string().contains("auth-int"), "{}", error);
+        let error = auth.digest_response("GET", "/", r#"Digest realm="r", algorithm=SHA-256, nonce="n""#).unwrap_err();
+        assert!(error.to_string().contains("SHA-256"), "{}", error);
+        assert!(auth.digest_response("GET", "/", r#"Basic realm="r""#).unwrap().is_none());
+        #[cfg(not(feature = "digest-auth"))]
+        assert!(auth.digest_response("GET", "/", r#"Digest nonce="n""#).unwrap_err().to_string().contains("digest-auth"));
+    }
+
+    #[test]
+    fn builds_basic_and_bearer_headers() {
+        assert_eq!(Auth::basic("Aladdin", "open sesame").header().as_deref(), Some("Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="));
+        assert_eq!(Auth::bearer("t0ken").header().as_deref(), Some("Bearer t0ken"));
+        assert_eq!(Auth::digest("u", "p").header(), None);
+        assert_eq!(Auth::basic("u", "p").digest_response("GET", "/", r#"Digest nonce="n""#).unwrap(), None);
+    }
+
+    #[test]
+    fn parses_quoted_challenge_parameters() {
+        let params = parse_challenge(r#" realm="a, \"b\"", nonce=xyz,stale"#);
+        assert_eq!(
+            params,
+            vec![
+                ("realm".to_string(), "a, \"b\"".to_string()),
+                ("nonce".to_string(), "xyz".to_string()),
+                ("stale".to_string(), String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn caches_tokens_until_they_expire_or_are_invalidated() {
+        let url = crate::tests::serve_once("application/json", r#"{"access_token": "abc", "expires_in": 3600}"#);
+        let tokens = TokenManager::new(&url, "id", "secret").with_scope("read");
+        assert_eq!(tokens.form(), vec![("grant_type", "client_credentials".to_string()), ("scope", "read".to_string())]);
+        assert_eq!(tokens.token_blocking().unwrap(), "abc");
+        assert_eq!(tokens.clone().token_blocking().unwrap(), "abc");
+
+        tokens.invalidate();
+        assert!(tokens.token_blocking().is_err());
+
+        let expiring = TokenManager::new(&url, "id", "secret").with_refresh_margin(Duration::from_secs(60));
+        expiring.store(TokenResponse { access_token: "old".to_string(), expires_in: Some(30) });
+        assert_eq!(expiring.cached(), None);
+    }
+
+    #[test]
+    fn sends_credentials_in_the_body_when_asked() {
+        let form = TokenManager::new("https://auth.example.com/token", "id", "secret").credentials_in_body().form();
+        assert!(form.contains(&("client_id", "id".to_string())));
+        assert!(form.contains(&("client_secret", "secret".to_string())));
+    }
+}
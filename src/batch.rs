@@ -0,0 +1,461 @@
+//! Error handling for batch operations.
+//!
+//! Multi-page operations such as `scrape_paginated_with_policy` and `scrape_many` take an
+//! `ErrorPolicy`. Instead of aborting on the first failed page, they can skip failures, retrying
+//! them first if wanted, and return the partial results with a list of what failed.
+//! Results stay grouped by the page they came from, in the order of the input URLs.
+//! Concurrent batches such as `scrape_many_async` interleave their URLs by host.
+
+use crate::report::ScrapeReport;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+/// What a batch operation does when one of its URLs fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Aborts the batch with the first error.
+    #[default]
+    FailFast,
+    /// Skips failed URLs and reports them in `BatchResult::errors`.
+    SkipAndCollectErrors,
+    /// Retries a failed URL, waiting `backoff` times the attempt number in between,
+    /// then skips it like `SkipAndCollectErrors`.
+    RetryThenSkip { retries: u32, backoff: Duration },
+}
+
+impl ErrorPolicy {
+    /// Retries failed URLs `retries` times with a one second linear backoff before skipping them.
+    pub fn retry_then_skip(retries: u32) -> Self {
+        ErrorPolicy::RetryThenSkip {
+            retries,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+/// A URL a batch operation skipped.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchError {
+    pub url: String,
+    /// The error of the last attempt.
+    pub error: String,
+    pub attempts: u32,
+}
+
+/// The results scraped from one URL of a batch.
+#[derive(Debug, Clone, Serialize)]
+pub struct PageResult<T> {
+    pub url: String,
+    /// Position of the URL in the batch, starting at 0.
+    pub index: usize,
+    pub items: Vec<T>,
+}
+
+/// Results of a batch operation with the URLs that failed.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResult<T> {
+    /// Results per successful URL, in the order of the input URLs.
+    pub pages: Vec<PageResult<T>>,
+    pub errors: Vec<BatchError>,
+    /// Summary of the batch. Downloaded bytes are not measured for batches.
+    pub report: ScrapeReport,
+}
+
+impl<T> BatchResult<T> {
+    /// Whether every URL succeeded.
+    pub fn is_complete(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Iterates over all items tagged with the URL they came from.
+    pub fn tagged(&self) -> impl Iterator<Item = (&str, &T)> {
+        self.pages
+            .iter()
+            .flat_map(|page| page.items.iter().map(move |item| (page.url.as_str(), item)))
+    }
+
+    /// Merges the items of all pages into one list, in page order.
+    pub fn flatten(self) -> Vec<T> {
+        self.pages.into_iter().flat_map(|page| page.items).collect()
+    }
+}
+
+/// Runs `scrape` for every URL, applying the policy to failures.
+pub(crate) fn run<T>(
+    urls: impl IntoIterator<Item = String>,
+    policy: ErrorPolicy,
+    mut scrape: impl FnMut(&str) -> Result<Vec<T>, Box<dyn std::error::Error>>,
+) -> Result<BatchResult<T>, Box<dyn std::error::Error>> {
+    let started = Instant::now();
+    let mut batch = BatchResult {
+        pages: Vec::new(),
+        errors: Vec::new(),
+        report: ScrapeReport::default(),
+    };
+    for (index, url) in urls.into_iter().enumerate() {
+        let mut attempts = 0;
+        batch.report.attempted += 1;
+        loop {
+            attempts += 1;
+            match scrape(&url) {
+                Ok(items) => {
+                    batch.report.succeeded += 1;
+                    batch.report.items += items.len();
+                    batch.pages.push(PageResult {
+                        url: url.clone(),
+                        index,
+                        items,
+                    });
+                    break;
+                }
+                Err(e) => match policy {
+                    ErrorPolicy::FailFast => return Err(e),
+                    ErrorPolicy::RetryThenSkip { retries, backoff } if attempts <= retries => {
+                        log::warn!("Attempt {} for {} failed, retrying: {}", attempts, url, e);
+                        std::thread::sleep(backoff * attempts);
+                    }
+                    _ => {
+                        skip(&mut batch, &url, e.as_ref(), attempts);
+                        break;
+                    }
+                },
+            }
+        }
+    }
+    batch.report.duration_secs = started.elapsed().as_secs_f64();
+    Ok(batch)
+}
+
+/// Records a URL the batch skipped after `attempts` attempts.
+fn skip<T>(batch: &mut BatchResult<T>, url: &str, error: &(dyn std::error::Error + 'static), attempts: u32) {
+    log::error!("Skipping {} after {} attempt(s): {}", url, attempts, error);
+    batch.report.record_failure(&error.to_string());
+    batch.report.record_soft_failure(url, error);
+    batch.errors.push(BatchError {
+        url: url.to_string(),
+        error: error.to_string(),
+        attempts,
+    });
+}
+
+/// The outcome of scraping one URL of a concurrent batch: its index, result and attempts.
+type Outcome<T> = (usize, Result<Vec<T>, Box<dyn std::error::Error>>, u32);
+
+/// A URL of a concurrent batch being scraped.
+type Attempt<'a, T> = Pin<Box<dyn Future<Output = Outcome<T>> + 'a>>;
+
+/// Like `run`, scraping up to `concurrency` URLs at a time in the order of a `HostQueue`.
+pub(crate) async fn run_fair<'a, T, F, Fut>(
+    urls: &[&'a str],
+    policy: ErrorPolicy,
+    concurrency: usize,
+    scrape: F,
+) -> Result<BatchResult<T>, Box<dyn std::error::Error>>
+where
+    F: Fn(&'a str) -> Fut,
+    Fut: Future<Output = Result<Vec<T>, Box<dyn std::error::Error>>>,
+{
+    let started = Instant::now();
+    let concurrency = concurrency.max(1);
+    let mut queue = HostQueue::new(urls);
+    let mut running: Vec<Attempt<'_, T>> = Vec::new();
+    let mut outcomes: Vec<Option<Outcome<T>>> = urls.iter().map(|_| None).collect();
+    loop {
+        while running.len() < concurrency {
+            let Some(index) = queue.next(concurrency) else {
+                break;
+            };
+            running.push(Box::pin(attempt(index, urls[index], policy, &scrape)));
+        }
+        if running.is_empty() {
+            break;
+        }
+        let (index, result, attempts) = std::future::poll_fn(|cx| {
+            for slot in 0..running.len() {
+                if let Poll::Ready(outcome) = running[slot].as_mut().poll(cx) {
+                    drop(running.swap_remove(slot));
+                    return Poll::Ready(outcome);
+                }
+            }
+            Poll::Pending
+        })
+        .await;
+        queue.finish(index);
+        let result = match result {
+            Err(e) if policy == ErrorPolicy::FailFast => return Err(e),
+            result => result,
+        };
+        outcomes[index] = Some((index, result, attempts));
+    }
+    let mut batch = BatchResult {
+        pages: Vec::new(),
+        errors: Vec::new(),
+        report: ScrapeReport::default(),
+    };
+    for (index, (url, outcome)) in urls.iter().zip(outcomes).enumerate() {
+        let Some((_, result, attempts)) = outcome else {
+            continue;
+        };
+        batch.report.attempted += 1;
+        match result {
+            Ok(items) => {
+                batch.report.succeeded += 1;
+                batch.report.items += items.len();
+                batch.pages.push(PageResult {
+                    url: url.to_string(),
+                    index,
+                    items,
+                });
+            }
+            Err(e) => skip(&mut batch, url, e.as_ref(), attempts),
+        }
+    }
+    batch.report.duration_secs = started.elapsed().as_secs_f64();
+    Ok(batch)
+}
+
+/// Scrapes the URL at `index`, retrying failures as the policy says.
+async fn attempt<'a, T, F, Fut>(index: usize, url: &'a str, policy: ErrorPolicy, scrape: &F) -> Outcome<T>
+where
+    F: Fn(&'a str) -> Fut,
+    Fut: Future<Output = Result<Vec<T>, Box<dyn std::error::Error>>>,
+{
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let error = match scrape(url).await {
+            Ok(items) => return (index, Ok(items), attempts),
+            Err(e) => e,
+        };
+        match policy {
+            ErrorPolicy::RetryThenSkip { retries, backoff } if attempts <= retries => {
+                log::warn!("Attempt {} for {} failed, retrying: {}", attempts, url, error);
+                drop(error);
+                tokio::time::sleep(backoff * attempts).await;
+            }
+            _ => return (index, Err(error), attempts),
+        }
+    }
+}
+
+/// Hands out the URLs of a batch round-robin by host. While other hosts have URLs left, a
+/// host gets at most its fair share of the concurrency, `concurrency / hosts with URLs left`.
+struct HostQueue {
+    /// Hosts with URLs left, in the order they get their next turn.
+    turns: VecDeque<String>,
+    pending: HashMap<String, VecDeque<usize>>,
+    in_flight: HashMap<String, usize>,
+    /// Host of every URL by index.
+    hosts: Vec<String>,
+}
+
+impl HostQueue {
+    fn new(urls: &[&str]) -> Self {
+        let hosts: Vec<String> = urls.iter().map(|url| crate::host_of(url)).collect();
+        let mut turns = VecDeque::new();
+        let mut pending: HashMap<String, VecDeque<usize>> = HashMap::new();
+        for (index, host) in hosts.iter().enumerate() {
+            let queue = pending.entry(host.clone()).or_insert_with(|| {
+                turns.push_back(host.clone());
+                VecDeque::new()
+            });
+            queue.push_back(index);
+        }
+        HostQueue {
+            turns,
+            pending,
+            in_flight: HashMap::new(),
+            hosts,
+        }
+    }
+
+    /// The index of the next URL to scrape, or `None` if every host with URLs left has used
+    /// its share until a request finishes.
+    fn next(&mut self, concurrency: usize) -> Option<usize> {
+        let share = (concurrency / self.turns.len().max(1)).max(1);
+        for _ in 0..self.turns.len() {
+            let host = self.turns.pop_front()?;
+            let in_flight = self.in_flight.entry(host.clone()).or_default();
+            if *in_flight >= share {
+                self.turns.push_back(host);
+                continue;
+            }
+            *in_flight += 1;
+            let queue = self.pending.get_mut(&host)?;
+            let index = queue.pop_front()?;
+            if queue.is_empty() {
+                self.pending.remove(&host);
+            } else {
+                self.turns.push_back(host);
+            }
+            return Some(index);
+        }
+        None
+    }
+
+    /// Frees the slot of the URL at `index` once it was scraped.
+    fn finish(&mut self, index: usize) {
+        if let Some(in_flight) = self.in_flight.get_mut(&self.hosts[index]) {
+            *in_flight = in_flight.saturating_sub(1);
+        }
+    }
+}
+
+/// Builds the result of a batch whose URLs were scraped, in any order, into `results`, which
+/// holds the items or the error of every URL in input order. Failed URLs were attempted once.
+pub(crate) fn collect<T>(urls: &[&str], results: Vec<Result<Vec<T>, String>>, started: Instant) -> BatchResult<T> {
+    let mut batch = BatchResult {
+        pages: Vec::new(),
+        errors: Vec::new(),
+        report: ScrapeReport::default(),
+    };
+    for (index, (url, result)) in urls.iter().zip(results).enumerate() {
+        batch.report.attempted += 1;
+        match result {
+            Ok(items) => {
+                batch.report.succeeded += 1;
+                batch.report.items += items.len();
+                batch.pages.push(PageResult {
+                    url: url.to_string(),
+                    index,
+                    items,
+                });
+            }
+            Err(error) => {
+                log::error!("Skipping {}: {}", url, error);
+                batch.report.record_failure(&error);
+                batch.errors.push(BatchError {
+                    url: url.to_string(),
+                    error,
+                    attempts: 1,
+                });
+            }
+        }
+    }
+    batch.report.duration_secs = started.elapsed().as_secs_f64();
+    batch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls() -> Vec<String> {
+        vec!["a".to_string(), "b".to_string(), "c".to_string()]
+    }
+
+    fn fail_on_b(url: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        if url == "b" {
+            Err("b is down".into())
+        } else {
+            Ok(vec![url.to_uppercase()])
+        }
+    }
+
+    #[test]
+    fn fails_fast_by_default() {
+        let error = run(urls(), ErrorPolicy::default(), fail_on_b).unwrap_err();
+        assert_eq!(error.to_string(), "b is down");
+    }
+
+    #[test]
+    fn skips_and_collects_failed_urls() {
+        let batch = run(urls(), ErrorPolicy::SkipAndCollectErrors, fail_on_b).unwrap();
+        assert_eq!(batch.pages.len(), 2);
+        assert!(!batch.is_complete());
+        assert_eq!(batch.errors.len(), 1);
+        assert_eq!(batch.errors[0].url, "b");
+        assert_eq!(batch.errors[0].error, "b is down");
+        assert_eq!(batch.errors[0].attempts, 1);
+    }
+
+    #[test]
+    fn retries_before_skipping() {
+        let policy = ErrorPolicy::RetryThenSkip { retries: 2, backoff: Duration::ZERO };
+        let batch = run(urls(), policy, fail_on_b).unwrap();
+        assert_eq!(batch.pages.len(), 2);
+        assert_eq!(batch.errors[0].attempts, 3);
+
+        let mut calls = 0;
+        let batch = run(vec!["a".to_string()], policy, |url| {
+            calls += 1;
+            if calls < 2 {
+                Err("flaky".into())
+            } else {
+                Ok(vec![url.to_string()])
+            }
+        })
+        .unwrap();
+        assert!(batch.is_complete());
+        assert_eq!(batch.flatten(), vec!["a"]);
+    }
+
+    #[test]
+    fn groups_results_by_source_page() {
+        let batch = run(urls(), ErrorPolicy::SkipAndCollectErrors, |url| {
+            fail_on_b(url).map(|items| vec![items[0].clone(), format!("{}2", items[0])])
+        })
+        .unwrap();
+        let pages: Vec<_> = batch.pages.iter().map(|page| (page.url.as_str(), page.index)).collect();
+        assert_eq!(pages, vec![("a", 0), ("c", 2)]);
+        let tagged: Vec<_> = batch.tagged().map(|(url, item)| format!("{}:{}", url, item)).collect();
+        assert_eq!(tagged, vec!["a:A", "a:A2", "c:C", "c:C2"]);
+        assert_eq!(batch.flatten(), vec!["A", "A2", "C", "C2"]);
+    }
+
+    #[test]
+    fn summarizes_the_batch() {
+        let batch = run(urls(), ErrorPolicy::SkipAndCollectErrors, fail_on_b).unwrap();
+        let report = &batch.report;
+        assert_eq!((report.attempted, report.succeeded, report.failed, report.items), (3, 2, 1, 2));
+        assert_eq!(report.failures_by_reason.get("other"), Some(&1));
+    }
+
+    #[test]
+    fn collects_results_in_url_order() {
+        let results = vec![Ok(vec![1, 2]), Err("timeout".to_string()), Ok(vec![3])];
+        let batch = collect(&["a", "b", "c"], results, Instant::now());
+        let pages: Vec<_> = batch.pages.iter().map(|page| (page.url.as_str(), page.index)).collect();
+        assert_eq!(pages, vec![("a", 0), ("c", 2)]);
+        assert_eq!((batch.errors[0].url.as_str(), batch.errors[0].attempts), ("b", 1));
+        assert_eq!((batch.report.attempted, batch.report.succeeded, batch.report.items), (3, 2, 3));
+    }
+
+    #[test]
+    fn interleaves_hosts_within_their_share() {
+        let urls = ["https://a.com/1", "https://a.com/2", "https://a.com/3", "https://b.com/1", "https://c.com/1"];
+        let mut queue = HostQueue::new(&urls);
+        // Two slots for three hosts: one request per host until only a.com has URLs left.
+        assert_eq!((queue.next(2), queue.next(2)), (Some(0), Some(3)));
+        queue.finish(3);
+        assert_eq!(queue.next(2), Some(4));
+        queue.finish(4);
+        assert_eq!(queue.next(2), Some(1));
+        assert_eq!(queue.next(2), None);
+        queue.finish(0);
+        assert_eq!(queue.next(2), Some(2));
+    }
+
+    #[tokio::test]
+    async fn runs_fair_batches_with_the_error_policy() {
+        let urls = ["https://a.com/1", "https://b.com/x", "https://a.com/2"];
+        let scrape = |url: &'static str| async move {
+            if url.contains("/x") {
+                Err::<Vec<String>, Box<dyn std::error::Error>>("x is down".into())
+            } else {
+                Ok(vec![url.to_string()])
+            }
+        };
+        let policy = ErrorPolicy::RetryThenSkip { retries: 1, backoff: Duration::ZERO };
+        let batch = run_fair(&urls, policy, 2, scrape).await.unwrap();
+        assert_eq!((batch.errors[0].url.as_str(), batch.errors[0].attempts), ("https://b.com/x", 2));
+        assert_eq!(batch.flatten(), vec!["https://a.com/1", "https://a.com/2"]);
+
+        let error = run_fair(&urls, ErrorPolicy::FailFast, 2, scrape).await.unwrap_err();
+        assert_eq!(error.to_string(), "x is down");
+    }
+}
@@ -0,0 +1,134 @@
+//! Command line interface for quick scrapes and declarative jobs.
+//! Built with the `cli` feature: `cargo install rust-scrapper --features cli`.
+
+use rust_scrapper::{Exporter, JsScrapeOptions, JsScraper, RustScrapper, ScrapeConfig, Scraper};
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage:
+  rust-scrapper <url> <selector> [--format text|json|csv] [--output <file>] [--js] [--user-agent <ua>]
+  rust-scrapper --config <file>
+
+Options:
+  -f, --format <format>    Output format: text (default), json or csv
+  -o, --output <file>      Write results to a file instead of stdout (required for csv)
+      --js                 Render the page with the headless browser
+      --user-agent <ua>    User-Agent header to send
+  -c, --config <file>      Run the jobs of a TOML, YAML or JSON config file
+  -h, --help               Print this help";
+
+#[derive(Default)]
+struct Args {
+    url: Option<String>,
+    selector: Option<String>,
+    format: String,
+    output: Option<String>,
+    js: bool,
+    user_agent: Option<String>,
+    config: Option<String>,
+}
+
+fn parse_args() -> Result<Option<Args>, String> {
+    let mut args = Args {
+        format: "text".to_string(),
+        ..Args::default()
+    };
+    let mut positional = Vec::new();
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| iter.next().ok_or_else(|| format!("Missing value for {}", name));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-f" | "--format" => args.format = value(&arg)?,
+            "-o" | "--output" => args.output = Some(value(&arg)?),
+            "-c" | "--config" => args.config = Some(value(&arg)?),
+            "--user-agent" => args.user_agent = Some(value(&arg)?),
+            "--js" => args.js = true,
+            flag if flag.starts_with('-') => return Err(format!("Unknown option: {}", flag)),
+            _ => positional.push(arg),
+        }
+    }
+    let mut positional = positional.into_iter();
+    args.url = positional.next();
+    args.selector = positional.next();
+    if let Some(extra) = positional.next() {
+        return Err(format!("Unexpected argument: {}", extra));
+    }
+    Ok(Some(args))
+}
+
+fn run_config(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let config = ScrapeConfig::from_file(path)?;
+    let mut scrapper = RustScrapper::from_config(&config)?;
+    for job in &config.jobs {
+        let results = job.run(&mut scrapper)?;
+        job.export(&results)?;
+        match &job.export {
+            Some(target) => eprintln!("{}: {} results written to {}", job.name, results.len(), target.path),
+            None => println!("{}", Exporter::records_to_json(&results)?),
+        }
+    }
+    Ok(())
+}
+
+fn run_scrape(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let (url, selector) = match (&args.url, &args.selector) {
+        (Some(url), Some(selector)) => (url, selector),
+        _ => return Err(format!("A URL and a selector are required\n\n{}", USAGE).into()),
+    };
+
+    let results = if args.js {
+        let mut options = JsScrapeOptions::new();
+        if let Some(user_agent) = &args.user_agent {
+            options = options.user_agent(user_agent);
+        }
+        JsScraper::scrape_with_options(url, selector, &options)?
+    } else {
+        let mut scrapper = RustScrapper::new();
+        if let Some(user_agent) = &args.user_agent {
+            scrapper = scrapper.with_header("User-Agent", user_agent)?;
+        }
+        scrapper.scrape(url, selector)?
+    };
+
+    let output = match args.format.as_str() {
+        "text" => results.join("\n"),
+        "json" => Exporter::to_json(results),
+        "csv" => {
+            let path = args.output.as_deref().ok_or("--output is required for csv")?;
+            Exporter::to_csv(results, path)?;
+            return Ok(());
+        }
+        other => return Err(format!("Unknown format: {}", other).into()),
+    };
+    match &args.output {
+        Some(path) => std::fs::write(path, output)?,
+        None => println!("{}", output),
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match &args.config {
+        Some(path) => run_config(path),
+        None => run_scrape(&args),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
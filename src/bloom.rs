@@ -0,0 +1,143 @@
+//! Bloom filter for probabilistic URL deduplication.
+//!
+//! An exact visited set stores every URL; a `BloomFilter` stores a fixed number of bits
+//! sized for the expected item count and false-positive rate, e.g. about 1.2 MB for a
+//! million URLs at 1%. Lookups never miss an inserted item, but may report an item that
+//! was never inserted as present.
+
+use serde::{Deserialize, Serialize};
+
+/// A fixed-size bloom filter over strings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u32,
+    items: usize,
+}
+
+impl BloomFilter {
+    /// Creates a filter for `expected_items` with the given false-positive rate, e.g. 0.01.
+    /// The rate grows beyond the target once more items are inserted.
+    pub fn new(expected_items: usize, false_positive_rate: f64) -> Self {
+        let items = expected_items.max(1) as f64;
+        let rate = false_positive_rate.clamp(1e-9, 0.5);
+        let num_bits = (-items * rate.ln() / std::f64::consts::LN_2.powi(2)).ceil().max(64.0) as u64;
+        let num_hashes = ((num_bits as f64 / items) * std::f64::consts::LN_2).round().clamp(1.0, 32.0) as u32;
+        BloomFilter {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            num_hashes,
+            items: 0,
+        }
+    }
+
+    /// Adds an item. Returns false if it was (probably) present already.
+    pub fn insert(&mut self, item: &str) -> bool {
+        let mut added = false;
+        for bit in self.bit_positions(item) {
+            let (word, mask) = ((bit / 64) as usize, 1u64 << (bit % 64));
+            added |= self.bits[word] & mask == 0;
+            self.bits[word] |= mask;
+        }
+        if added {
+            self.items += 1;
+        }
+        added
+    }
+
+    /// Whether the item was (probably) inserted. Never false for an inserted item.
+    pub fn contains(&self, item: &str) -> bool {
+        self.bit_positions(item).all(|bit| self.bits[(bit / 64) as usize] & (1u64 << (bit % 64)) != 0)
+    }
+
+    /// Number of distinct items inserted, as far as the filter can tell.
+    pub fn len(&self) -> usize {
+        self.items
+    }
+
+    /// Returns true if nothing has been inserted.
+    pub fn is_empty(&self) -> bool {
+        self.items == 0
+    }
+
+    /// Size of the bit array in bytes.
+    pub fn size_bytes(&self) -> usize {
+        self.bits.len() * 8
+    }
+
+    /// Estimated false-positive rate at the current fill.
+    pub fn false_positive_rate(&self) -> f64 {
+        let fill = 1.0 - (-(self.num_hashes as f64) * self.items as f64 / self.num_bits as f64).exp();
+        fill.powi(self.num_hashes as i32)
+    }
+
+    /// Bit indices of an item, by double hashing two 64-bit hashes. The hashes are
+    /// stable across processes, so serialized filters stay valid.
+    fn bit_positions(&self, item: &str) -> impl Iterator<Item = u64> {
+        let first = fnv1a(item.as_bytes());
+        let second = splitmix64(first) | 1;
+        let num_bits = self.num_bits;
+        (0..self.num_hashes as u64).map(move |i| first.wrapping_add(i.wrapping_mul(second)) % num_bits)
+    }
+}
+
+/// 64-bit FNV-1a, a hash that is the same in every build and process.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn never_misses_inserted_items() {
+        let mut filter = BloomFilter::new(1_000, 0.01);
+        assert!(filter.is_empty());
+        let added = (0..1_000).filter(|i| filter.insert(&format!("https://a.com/{}", i))).count();
+        // An insert reports an item as present when all its bits collide, at about the target rate.
+        assert!(added > 980, "{} added", added);
+        assert!((0..1_000).all(|i| filter.contains(&format!("https://a.com/{}", i))));
+        assert!(!filter.insert("https://a.com/0"));
+        assert_eq!(filter.len(), added);
+    }
+
+    #[test]
+    fn keeps_false_positives_near_the_target_rate() {
+        let mut filter = BloomFilter::new(10_000, 0.01);
+        for i in 0..10_000 {
+            filter.insert(&format!("https://a.com/page/{}", i));
+        }
+        let false_positives = (0..10_000)
+            .filter(|i| filter.contains(&format!("https://b.org/other/{}", i)))
+            .count();
+        assert!(false_positives < 200, "{} false positives", false_positives);
+        assert!((filter.false_positive_rate() - 0.01).abs() < 0.005);
+    }
+
+    #[test]
+    fn sizes_the_bit_array_for_the_expected_items() {
+        let size = BloomFilter::new(1_000_000, 0.01).size_bytes();
+        assert!((1_100_000..1_300_000).contains(&size), "{} bytes", size);
+        assert_eq!(BloomFilter::new(0, 0.01).size_bytes(), 8);
+    }
+
+    #[test]
+    fn survives_serialization() {
+        let mut filter = BloomFilter::new(100, 0.01);
+        filter.insert("https://a.com/");
+        let restored: BloomFilter = serde_json::from_str(&serde_json::to_string(&filter).unwrap()).unwrap();
+        assert!(restored.contains("https://a.com/"));
+        assert_eq!(restored.len(), 1);
+    }
+}
@@ -0,0 +1,98 @@
+//! Cost limits for crawls.
+//!
+//! A `RequestBudget` caps what one `Crawler::run` may spend: requests sent, bytes downloaded
+//! and wall-clock time. When a limit is reached, the crawl stops before the next request and
+//! returns what it has so far, with the limit in `ScrapeReport::stopped_by`, which keeps
+//! egress costs of unattended crawls predictable.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+/// A limit of a `RequestBudget`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetLimit {
+    Requests(usize),
+    Bytes(u64),
+    Duration(Duration),
+}
+
+impl fmt::Display for BudgetLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetLimit::Requests(requests) => write!(f, "request budget of {} reached", requests),
+            BudgetLimit::Bytes(bytes) => write!(f, "download budget of {} bytes reached", bytes),
+            BudgetLimit::Duration(duration) => write!(f, "time budget of {:?} reached", duration),
+        }
+    }
+}
+
+/// Limits applied to every run with `Crawler::with_budget`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestBudget {
+    pub max_requests: Option<usize>,
+    pub max_bytes: Option<u64>,
+    pub max_duration: Option<Duration>,
+}
+
+impl RequestBudget {
+    /// Creates a budget without limits.
+    pub fn new() -> Self {
+        RequestBudget::default()
+    }
+
+    /// Sends at most this many requests per run. Every request sent counts: retries, redirects
+    /// followed from page markup, variants, CAPTCHA submissions and authentication retries.
+    /// Pages served from the cache do not count.
+    pub fn max_requests(mut self, max_requests: usize) -> Self {
+        self.max_requests = Some(max_requests);
+        self
+    }
+
+    /// Stops once this many bytes of page bodies were downloaded. The request that crosses
+    /// the limit completes, so a run may download up to one page more.
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Stops once the run took this long. Checked between requests, so a request in flight
+    /// completes first.
+    pub fn max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// The first limit a run that sent `requests` requests, downloaded `bytes` bytes and ran
+    /// for `elapsed` has reached, if any.
+    pub fn exceeded(&self, requests: usize, bytes: u64, elapsed: Duration) -> Option<BudgetLimit> {
+        if let Some(max) = self.max_requests.filter(|max| requests >= *max) {
+            return Some(BudgetLimit::Requests(max));
+        }
+        if let Some(max) = self.max_bytes.filter(|max| bytes >= *max) {
+            return Some(BudgetLimit::Bytes(max));
+        }
+        self.max_duration
+            .filter(|max| elapsed >= *max)
+            .map(BudgetLimit::Duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_the_first_limit_reached() {
+        let budget = RequestBudget::new().max_requests(10).max_bytes(1_000).max_duration(Duration::from_secs(60));
+        assert_eq!(budget.exceeded(9, 999, Duration::from_secs(59)), None);
+        assert_eq!(budget.exceeded(10, 5_000, Duration::ZERO), Some(BudgetLimit::Requests(10)));
+        assert_eq!(budget.exceeded(0, 1_000, Duration::from_secs(61)), Some(BudgetLimit::Bytes(1_000)));
+        assert_eq!(
+            budget.exceeded(0, 0, Duration::from_secs(60)).unwrap().to_string(),
+            "time budget of 60s reached"
+        );
+        assert_eq!(RequestBudget::new().exceeded(usize::MAX, u64::MAX, Duration::MAX), None);
+    }
+}
@@ -0,0 +1,941 @@
+//! Caching of fetched pages and extraction results.
+//!
+//! `ScrapeCache` builds cache keys and keeps statistics, while the entries themselves live
+//! in a `CacheBackend`. The cache is a cheap handle: cloning it shares the same backend and
+//! counters, so several scrapers can work against one cache. With `honor_cache_headers`,
+//! `Cache-Control`, `Expires` and `Vary` response headers decide whether and how long a
+//! fetched page is cached.
+
+use base64::Engine;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use reqwest::header::HeaderMap;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+const RESULT_PREFIX: &str = "result:";
+const BODY_PREFIX: &str = "body:";
+/// Marks a body stored gzip-compressed and base64-encoded. The control character cannot
+/// start a body stored as is, since bodies are decoded text.
+const GZIP_MARKER: &str = "\u{1}gzip:";
+/// Marks a body stored with the `X-Robots-Tag` values of its response, as a JSON array on
+/// the first line.
+const ROBOTS_MARKER: &str = "\u{2}robots:";
+/// Selector of the cache keys of pages rendered in the headless browser, which are cached
+/// apart from bodies fetched over HTTP.
+pub const RENDERED_SELECTOR: &str = "<rendered>";
+
+/// Selector of the cache keys of records extracted with a schema: a hash of the schema, so
+/// changing the schema misses entries extracted with the old one. The hash is stable across
+/// builds, so persistent caches keep their entries.
+pub fn schema_selector(schema: &crate::schema::Schema) -> String {
+    let schema = serde_json::to_string(schema).unwrap_or_default();
+    format!("<schema:{:016x}>", crate::bloom::fnv1a(schema.as_bytes()))
+}
+
+/// The request inputs a cache key is built from.
+pub struct CacheKey<'a> {
+    pub url: &'a str,
+    pub selector: &'a str,
+    /// Request headers the cache varies on, lowercased and sorted by name.
+    pub headers: Vec<(String, String)>,
+}
+
+/// Custom function turning a `CacheKey` into the string used to store an entry.
+pub type CacheKeyFn = Arc<dyn Fn(&CacheKey) -> String + Send + Sync>;
+
+/// Snapshot of cache usage.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CacheStats {
+    /// Number of cached extraction results.
+    pub entries: usize,
+    /// Number of cached HTML bodies.
+    pub body_entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub body_hits: u64,
+    pub body_misses: u64,
+    /// Approximate size of all cached results and bodies in bytes.
+    pub total_bytes: usize,
+    /// Time the oldest entry still in the cache was stored.
+    pub oldest_entry: Option<SystemTime>,
+}
+
+/// How a response may be cached according to its headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseCaching {
+    /// `no-store`, `no-cache`, an expired `max-age`/`Expires`, or a `Vary` on request
+    /// headers the cache key does not contain.
+    NoStore,
+    /// Fresh for this long, from `max-age` minus `Age`, or `Expires`.
+    Fresh(Duration),
+    /// The headers say nothing; the cache's own TTL applies.
+    Unspecified,
+}
+
+/// Storage for cache entries.
+/// Implementations must be thread-safe, as one backend can be shared by many scrapers.
+pub trait CacheBackend: Send + Sync {
+    /// Returns the value stored under the key, if present and not expired.
+    fn get(&self, key: &str) -> Option<String>;
+
+    /// Stores a value, optionally expiring after `ttl`.
+    fn set(&self, key: &str, value: String, ttl: Option<Duration>);
+
+    /// Removes a key. Returns whether it was present.
+    fn remove(&self, key: &str) -> bool;
+
+    /// Lists all stored keys.
+    fn keys(&self) -> Vec<String>;
+
+    /// Removes all entries.
+    fn clear(&self);
+
+    /// Size in bytes and storage time of an entry, for backends that track them.
+    fn entry_info(&self, _key: &str) -> Option<(usize, SystemTime)> {
+        None
+    }
+}
+
+struct MemoryEntry {
+    value: String,
+    stored_at: SystemTime,
+    expires_at: Option<SystemTime>,
+}
+
+impl MemoryEntry {
+    fn is_expired(&self) -> bool {
+        self.expires_at.map(|at| at <= SystemTime::now()).unwrap_or(false)
+    }
+}
+
+#[derive(Default)]
+struct MemoryEntries {
+    map: HashMap<String, MemoryEntry>,
+    /// Total size of all values.
+    bytes: usize,
+}
+
+impl MemoryEntries {
+    fn insert(&mut self, key: String, entry: MemoryEntry) {
+        self.bytes += entry.value.len();
+        if let Some(old) = self.map.insert(key, entry) {
+            self.bytes -= old.value.len();
+        }
+    }
+
+    fn remove(&mut self, key: &str) -> Option<MemoryEntry> {
+        let entry = self.map.remove(key)?;
+        self.bytes -= entry.value.len();
+        Some(entry)
+    }
+}
+
+/// In-process cache backend. This is the default backend of `ScrapeCache`.
+/// With a size limit, the oldest entries are evicted, or spilled to disk if a spill
+/// directory is set.
+#[derive(Default)]
+pub struct MemoryCache {
+    entries: Mutex<MemoryEntries>,
+    max_bytes: Option<usize>,
+    spill_dir: Option<PathBuf>,
+}
+
+impl MemoryCache {
+    /// Creates an empty in-memory backend.
+    pub fn new() -> Self {
+        MemoryCache::default()
+    }
+
+    /// Keeps at most `max_bytes` of values in memory, evicting the oldest entries beyond that.
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Moves evicted entries to files in this directory instead of dropping them.
+    pub fn with_spill_dir(mut self, dir: &str) -> Self {
+        self.spill_dir = Some(PathBuf::from(dir));
+        self
+    }
+
+    fn evict(&self, entries: &mut MemoryEntries) {
+        let Some(max_bytes) = self.max_bytes else {
+            return;
+        };
+        if entries.bytes <= max_bytes {
+            return;
+        }
+        let mut by_age = entries
+            .map
+            .iter()
+            .map(|(key, entry)| (entry.stored_at, key.clone()))
+            .collect::<Vec<_>>();
+        by_age.sort();
+        for (_, key) in by_age {
+            if entries.bytes <= max_bytes {
+                break;
+            }
+            if let Some(entry) = entries.remove(&key) {
+                if let Err(e) = self.spill(&key, &entry) {
+                    log::error!("Failed to spill cache entry {}: {}", key, e);
+                }
+            }
+        }
+    }
+
+    fn spill_path(&self, key: &str) -> Option<PathBuf> {
+        Some(self.spill_dir.as_ref()?.join(format!("{:016x}.entry", crate::bloom::fnv1a(key.as_bytes()))))
+    }
+
+    /// Writes an entry as its key, stored-at and expiry timestamps, then the value.
+    fn spill(&self, key: &str, entry: &MemoryEntry) -> std::io::Result<()> {
+        let Some(path) = self.spill_path(key) else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let secs = |time: SystemTime| time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+        let expires = entry.expires_at.map(|at| secs(at).to_string()).unwrap_or_default();
+        std::fs::write(path, format!("{}\n{}\n{}\n{}", key, secs(entry.stored_at), expires, entry.value))
+    }
+
+    fn read_spilled(&self, path: &std::path::Path) -> Option<(String, MemoryEntry)> {
+        let contents = std::fs::read_to_string(path).ok()?;
+        let mut parts = contents.splitn(4, '\n');
+        let key = parts.next()?.to_string();
+        let time = |secs: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let stored_at = time(parts.next()?.parse().ok()?);
+        let expires_at = parts.next()?.parse().ok().map(time);
+        let entry = MemoryEntry {
+            value: parts.next().unwrap_or_default().to_string(),
+            stored_at,
+            expires_at,
+        };
+        if entry.is_expired() {
+            let _ = std::fs::remove_file(path);
+            return None;
+        }
+        Some((key, entry))
+    }
+
+    fn get_spilled(&self, key: &str) -> Option<MemoryEntry> {
+        let (stored_key, entry) = self.read_spilled(&self.spill_path(key)?)?;
+        (stored_key == key).then_some(entry)
+    }
+
+    fn spilled_files(&self) -> Vec<PathBuf> {
+        let Some(dir) = &self.spill_dir else {
+            return Vec::new();
+        };
+        std::fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "entry"))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl CacheBackend for MemoryCache {
+    fn get(&self, key: &str) -> Option<String> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.map.get(key) {
+            Some(entry) if entry.is_expired() => {
+                entries.remove(key);
+                None
+            }
+            Some(entry) => Some(entry.value.clone()),
+            None => self.get_spilled(key).map(|entry| entry.value),
+        }
+    }
+
+    fn set(&self, key: &str, value: String, ttl: Option<Duration>) {
+        let stored_at = SystemTime::now();
+        let entry = MemoryEntry {
+            value,
+            stored_at,
+            expires_at: ttl.map(|ttl| stored_at + ttl),
+        };
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(key.to_string(), entry);
+        if let Some(path) = self.spill_path(key) {
+            // The new value supersedes a spilled one.
+            let _ = std::fs::remove_file(path);
+        }
+        self.evict(&mut entries);
+    }
+
+    fn remove(&self, key: &str) -> bool {
+        let in_memory = self.entries.lock().unwrap().remove(key).is_some();
+        let spilled = self.get_spilled(key).is_some()
+            && self.spill_path(key).is_some_and(|path| std::fs::remove_file(path).is_ok());
+        in_memory || spilled
+    }
+
+    fn keys(&self) -> Vec<String> {
+        let mut keys = {
+            let mut entries = self.entries.lock().unwrap();
+            let expired = entries
+                .map
+                .iter()
+                .filter(|(_, entry)| entry.is_expired())
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+            for key in expired {
+                entries.remove(&key);
+            }
+            entries.map.keys().cloned().collect::<Vec<_>>()
+        };
+        keys.extend(self.spilled_files().iter().filter_map(|path| self.read_spilled(path)).map(|(key, _)| key));
+        keys
+    }
+
+    fn clear(&self) {
+        *self.entries.lock().unwrap() = MemoryEntries::default();
+        for path in self.spilled_files() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    fn entry_info(&self, key: &str) -> Option<(usize, SystemTime)> {
+        let entries = self.entries.lock().unwrap();
+        match entries.map.get(key) {
+            Some(entry) => Some((entry.value.len(), entry.stored_at)),
+            None => self.get_spilled(key).map(|entry| (entry.value.len(), entry.stored_at)),
+        }
+    }
+}
+
+#[derive(Default)]
+struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    body_hits: AtomicU64,
+    body_misses: AtomicU64,
+}
+
+/// Struct to hold cache functionality.
+/// Responsible only for managing cached scraping data.
+#[derive(Clone)]
+pub struct ScrapeCache {
+    backend: Arc<dyn CacheBackend>,
+    key_fn: Option<CacheKeyFn>,
+    vary_headers: Vec<String>,
+    ttl: Option<Duration>,
+    compress_above: Option<usize>,
+    honor_headers: bool,
+    counters: Arc<CacheCounters>,
+}
+
+impl Default for ScrapeCache {
+    fn default() -> Self {
+        ScrapeCache::new()
+    }
+}
+
+impl ScrapeCache {
+    /// Creates a new in-memory cache.
+    /// Entries are keyed on URL, selector and the `User-Agent` and `Accept-Language` headers.
+    pub fn new() -> Self {
+        ScrapeCache::with_backend(Arc::new(MemoryCache::new()))
+    }
+
+    /// Creates a cache storing its entries in the given backend.
+    pub fn with_backend(backend: Arc<dyn CacheBackend>) -> Self {
+        ScrapeCache {
+            backend,
+            key_fn: None,
+            vary_headers: vec!["user-agent".to_string(), "accept-language".to_string()],
+            ttl: None,
+            compress_above: None,
+            honor_headers: false,
+            counters: Arc::new(CacheCounters::default()),
+        }
+    }
+
+    /// Creates a cache storing its entries under `cache/` in a storage shared with other
+    /// persistent state, see `Storage`.
+    pub fn with_storage(storage: Arc<dyn crate::storage::Storage>) -> Self {
+        ScrapeCache::with_backend(Arc::new(crate::storage::Namespace::new(storage, "cache")))
+    }
+
+    /// Sets the request headers that are part of the cache key.
+    pub fn vary_on(mut self, headers: &[&str]) -> Self {
+        self.vary_headers = headers.iter().map(|h| h.to_ascii_lowercase()).collect();
+        self
+    }
+
+    /// Replaces the default key format with a custom function.
+    pub fn with_key_fn<F>(mut self, key_fn: F) -> Self
+    where
+        F: Fn(&CacheKey) -> String + Send + Sync + 'static,
+    {
+        self.key_fn = Some(Arc::new(key_fn));
+        self
+    }
+
+    /// Expires entries after the given duration. Entries never expire by default.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Stores HTML bodies of at least `threshold_bytes` gzip-compressed. Bodies are
+    /// decompressed transparently on read; HTML typically shrinks 5-10x.
+    pub fn with_compression(mut self, threshold_bytes: usize) -> Self {
+        self.compress_above = Some(threshold_bytes);
+        self
+    }
+
+    /// Caches fetched pages as their `Cache-Control`, `Expires` and `Vary` headers allow,
+    /// see `ResponseCaching`. The TTL from the headers replaces `with_ttl` for such pages.
+    pub fn honor_cache_headers(mut self) -> Self {
+        self.honor_headers = true;
+        self
+    }
+
+    pub fn honors_cache_headers(&self) -> bool {
+        self.honor_headers
+    }
+
+    /// Reads how a response may be cached from its headers. A response varying on a header
+    /// is only stored if keys vary on it too, which a custom key function may not do.
+    pub fn caching_for(&self, headers: &HeaderMap) -> ResponseCaching {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        let vary = header("vary").unwrap_or_default().to_ascii_lowercase();
+        let uncovered_vary = vary
+            .split(',')
+            .map(str::trim)
+            // Bodies are decoded before caching, so they do not depend on the encoding.
+            .filter(|name| !name.is_empty() && *name != "accept-encoding")
+            .any(|name| name == "*" || self.key_fn.is_some() || !self.vary_headers.iter().any(|vary| vary == name));
+        if uncovered_vary {
+            return ResponseCaching::NoStore;
+        }
+
+        let cache_control = header("cache-control").unwrap_or_default().to_ascii_lowercase();
+        let mut max_age = None;
+        for directive in cache_control.split(',').map(str::trim) {
+            match directive.split_once('=') {
+                Some(("max-age", seconds)) => max_age = seconds.trim_matches('"').parse::<u64>().ok(),
+                None if directive == "no-store" || directive == "no-cache" => return ResponseCaching::NoStore,
+                _ => {}
+            }
+        }
+        if let Some(max_age) = max_age {
+            let age = header("age").and_then(|age| age.trim().parse::<u64>().ok()).unwrap_or(0);
+            return match max_age.saturating_sub(age) {
+                0 => ResponseCaching::NoStore,
+                fresh => ResponseCaching::Fresh(Duration::from_secs(fresh)),
+            };
+        }
+        if let Some(expires) = header("expires") {
+            let Ok(expires) = chrono::DateTime::parse_from_rfc2822(expires.trim()) else {
+                // Invalid dates such as "0" mean already expired.
+                return ResponseCaching::NoStore;
+            };
+            let now = header("date")
+                .and_then(|date| chrono::DateTime::parse_from_rfc2822(date.trim()).ok())
+                .map(|date| date.timestamp())
+                .unwrap_or_else(|| chrono::Utc::now().timestamp());
+            return match expires.timestamp() - now {
+                fresh if fresh > 0 => ResponseCaching::Fresh(Duration::from_secs(fresh as u64)),
+                _ => ResponseCaching::NoStore,
+            };
+        }
+        ResponseCaching::Unspecified
+    }
+
+    /// Builds the cache key for a request.
+    pub fn key_for(&self, url: &str, selector: &str, headers: &HeaderMap) -> String {
+        let mut vary = self
+            .vary_headers
+            .iter()
+            .filter_map(|name| {
+                let value = headers.get(name.as_str())?.to_str().ok()?;
+                Some((name.clone(), value.to_string()))
+            })
+            .collect::<Vec<_>>();
+        vary.sort();
+        let key = CacheKey { url, selector, headers: vary };
+
+        match &self.key_fn {
+            Some(key_fn) => key_fn(&key),
+            None => {
+                let headers = key.headers.iter().map(|(n, v)| format!("{}={}", n, v)).collect::<Vec<_>>();
+                format!("{}|{}|{}", key.url, key.selector, headers.join("&"))
+            }
+        }
+    }
+
+    /// Builds the key for a cached HTML body, which does not depend on the selector.
+    /// Custom key functions receive an empty selector for body keys.
+    pub fn body_key_for(&self, url: &str, headers: &HeaderMap) -> String {
+        self.key_for(url, "", headers)
+    }
+
+    /// Retrieves a cached HTML body if available.
+    pub fn get_body(&self, key: &str) -> Option<String> {
+        self.get_body_with_robots_tags(key).map(|(body, _)| body)
+    }
+
+    /// Retrieves a cached HTML body with the `X-Robots-Tag` values stored with it.
+    pub fn get_body_with_robots_tags(&self, key: &str) -> Option<(String, Vec<String>)> {
+        let entry = self.backend.get(&format!("{}{}", BODY_PREFIX, key)).and_then(|entry| {
+            let (robots_tags, body) = match entry.strip_prefix(ROBOTS_MARKER) {
+                Some(tagged) => {
+                    let (tags, body) = tagged.split_once('\n')?;
+                    (serde_json::from_str(tags).ok()?, body.to_string())
+                }
+                None => (Vec::new(), entry),
+            };
+            let body = match body.strip_prefix(GZIP_MARKER) {
+                Some(compressed) => decompress(compressed)?,
+                None => body,
+            };
+            Some((body, robots_tags))
+        });
+        record_lookup(entry.is_some(), &self.counters.body_hits, &self.counters.body_misses);
+        entry
+    }
+
+    /// Stores a fetched HTML body.
+    pub fn set_body(&self, key: &str, body: String) {
+        self.set_body_as(key, body, ResponseCaching::Unspecified);
+    }
+
+    /// Stores a fetched HTML body as its response headers allow, see `caching_for`.
+    pub fn set_body_as(&self, key: &str, body: String, caching: ResponseCaching) {
+        self.set_body_with_robots_tags(key, body, &[], caching);
+    }
+
+    /// Stores a fetched HTML body like `set_body_as`, together with the `X-Robots-Tag`
+    /// values of its response, so a page served from the cache keeps its directives.
+    pub fn set_body_with_robots_tags(&self, key: &str, body: String, robots_tags: &[String], caching: ResponseCaching) {
+        let ttl = match caching {
+            ResponseCaching::NoStore => return,
+            ResponseCaching::Fresh(ttl) => Some(ttl),
+            ResponseCaching::Unspecified => self.ttl,
+        };
+        let body = match self.compress_above {
+            Some(threshold) if body.len() >= threshold => compress(&body).unwrap_or(body),
+            _ => body,
+        };
+        let entry = if robots_tags.is_empty() {
+            body
+        } else {
+            format!("{}{}\n{}", ROBOTS_MARKER, serde_json::json!(robots_tags), body)
+        };
+        self.backend.set(&format!("{}{}", BODY_PREFIX, key), entry, ttl);
+    }
+
+    /// Removes a cached HTML body. Returns whether it was cached.
+    pub fn remove_body(&self, key: &str) -> bool {
+        self.backend.remove(&format!("{}{}", BODY_PREFIX, key))
+    }
+
+    /// Retrieves cached data if available.
+    pub fn get(&self, key: &str) -> Option<Vec<String>> {
+        let data = self
+            .backend
+            .get(&format!("{}{}", RESULT_PREFIX, key))
+            .and_then(|value| serde_json::from_str(&value).ok());
+        record_lookup(data.is_some(), &self.counters.hits, &self.counters.misses);
+        data
+    }
+
+    /// Sets new data into the cache.
+    pub fn set(&self, key: &str, data: Vec<String>) {
+        let value = serde_json::to_string(&data).unwrap_or_default();
+        self.backend.set(&format!("{}{}", RESULT_PREFIX, key), value, self.ttl);
+    }
+
+    /// Retrieves cached schema records if available, see `schema_selector`.
+    pub fn get_records(&self, key: &str) -> Option<Vec<serde_json::Value>> {
+        let records = self
+            .backend
+            .get(&format!("{}{}", RESULT_PREFIX, key))
+            .and_then(|value| serde_json::from_str(&value).ok());
+        record_lookup(records.is_some(), &self.counters.hits, &self.counters.misses);
+        records
+    }
+
+    /// Stores the records extracted with a schema.
+    pub fn set_records(&self, key: &str, records: &[serde_json::Value]) {
+        let value = serde_json::to_string(records).unwrap_or_default();
+        self.backend.set(&format!("{}{}", RESULT_PREFIX, key), value, self.ttl);
+    }
+
+    /// Number of result and body lookups served from the cache so far.
+    #[cfg(feature = "native")]
+    pub(crate) fn hit_count(&self) -> u64 {
+        self.counters.hits.load(Ordering::Relaxed) + self.counters.body_hits.load(Ordering::Relaxed)
+    }
+
+    /// Returns entry counts, hit/miss counters, size and age of the cache.
+    pub fn stats(&self) -> CacheStats {
+        let keys = self.backend.keys();
+        let infos = keys.iter().filter_map(|key| self.backend.entry_info(key)).collect::<Vec<_>>();
+
+        CacheStats {
+            entries: keys.iter().filter(|key| key.starts_with(RESULT_PREFIX)).count(),
+            body_entries: keys.iter().filter(|key| key.starts_with(BODY_PREFIX)).count(),
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            body_hits: self.counters.body_hits.load(Ordering::Relaxed),
+            body_misses: self.counters.body_misses.load(Ordering::Relaxed),
+            total_bytes: infos.iter().map(|(bytes, _)| bytes).sum(),
+            oldest_entry: infos.iter().map(|(_, stored_at)| *stored_at).min(),
+        }
+    }
+
+    /// Lists the keys of all cached results and bodies.
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys = self.backend.keys().iter().filter_map(|key| strip_namespace(key)).collect::<Vec<_>>();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    /// Removes every result and body cached for a URL. Returns the number of removed entries.
+    /// Only works with the default key format, where keys start with the URL.
+    pub fn invalidate_url(&self, url: &str) -> usize {
+        let prefix = format!("{}|", url);
+        self.remove_where(|key| key == url || key.starts_with(&prefix))
+    }
+
+    /// Removes every entry whose key starts with the prefix, e.g. `https://example.com/`
+    /// to drop a whole host. Returns the number of removed entries.
+    pub fn invalidate_prefix(&self, prefix: &str) -> usize {
+        self.remove_where(|key| key.starts_with(prefix))
+    }
+
+    /// Removes all entries and resets the hit/miss counters.
+    pub fn clear(&self) {
+        self.backend.clear();
+        let counters = &self.counters;
+        for counter in [&counters.hits, &counters.misses, &counters.body_hits, &counters.body_misses] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn remove_where(&self, matches: impl Fn(&str) -> bool) -> usize {
+        self.backend
+            .keys()
+            .iter()
+            .filter(|key| strip_namespace(key).map(|key| matches(&key)).unwrap_or(false))
+            .filter(|key| self.backend.remove(key))
+            .count()
+    }
+}
+
+/// Gzips a body and encodes it for storage as a string, with the `GZIP_MARKER` prefix.
+fn compress(body: &str) -> Option<String> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body.as_bytes()).ok()?;
+    let compressed = encoder.finish().ok()?;
+    Some(format!("{}{}", GZIP_MARKER, base64::engine::general_purpose::STANDARD.encode(compressed)))
+}
+
+fn decompress(encoded: &str) -> Option<String> {
+    let compressed = base64::engine::general_purpose::STANDARD.decode(encoded).ok()?;
+    let mut body = String::new();
+    GzDecoder::new(compressed.as_slice()).read_to_string(&mut body).ok()?;
+    Some(body)
+}
+
+fn strip_namespace(key: &str) -> Option<String> {
+    key.strip_prefix(RESULT_PREFIX)
+        .or_else(|| key.strip_prefix(BODY_PREFIX))
+        .map(str::to_string)
+}
+
+fn record_lookup(hit: bool, hits: &AtomicU64, misses: &AtomicU64) {
+    let counter = if hit { hits } else { misses };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Cache backend storing entries in Redis (enabled with the `redis` feature).
+/// Entry TTLs are mapped to Redis key expiry, so a fleet of scrapers can share one cache.
+#[cfg(feature = "redis")]
+pub struct RedisCache {
+    connection: Mutex<redis::Connection>,
+    prefix: String,
+}
+
+#[cfg(feature = "redis")]
+impl RedisCache {
+    /// Connects to Redis, e.g. `redis://127.0.0.1/`.
+    /// Keys are stored under the `rust-scrapper:` prefix.
+    pub fn new(url: &str) -> Result<Self, redis::RedisError> {
+        let connection = redis::Client::open(url)?.get_connection()?;
+        Ok(RedisCache {
+            connection: Mutex::new(connection),
+            prefix: "rust-scrapper:".to_string(),
+        })
+    }
+
+    /// Changes the prefix under which keys are stored.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    fn query<T: redis::FromRedisValue>(&self, cmd: &redis::Cmd) -> Option<T> {
+        let mut connection = self.connection.lock().unwrap();
+        cmd.query(&mut *connection)
+            .map_err(|e| log::error!("Redis cache error: {}", e))
+            .ok()
+    }
+}
+
+#[cfg(feature = "redis")]
+impl CacheBackend for RedisCache {
+    fn get(&self, key: &str) -> Option<String> {
+        self.query::<Option<String>>(redis::cmd("GET").arg(format!("{}{}", self.prefix, key)))
+            .flatten()
+    }
+
+    fn set(&self, key: &str, value: String, ttl: Option<Duration>) {
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(format!("{}{}", self.prefix, key)).arg(value);
+        if let Some(ttl) = ttl {
+            cmd.arg("PX").arg(ttl.as_millis().max(1) as u64);
+        }
+        self.query::<()>(&cmd);
+    }
+
+    fn remove(&self, key: &str) -> bool {
+        self.query::<u64>(redis::cmd("DEL").arg(format!("{}{}", self.prefix, key)))
+            .map(|removed| removed > 0)
+            .unwrap_or(false)
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.scan("")
+    }
+
+    fn clear(&self) {
+        for key in self.keys() {
+            self.remove(&key);
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+impl RedisCache {
+    /// Keys starting with `prefix`, found with `SCAN MATCH`.
+    pub(crate) fn scan(&self, prefix: &str) -> Vec<String> {
+        let mut connection = self.connection.lock().unwrap();
+        let mut pattern: String = format!("{}{}", self.prefix, prefix)
+            .chars()
+            .flat_map(|c| match c {
+                '*' | '?' | '[' | ']' | '\\' => vec!['\\', c],
+                c => vec![c],
+            })
+            .collect();
+        pattern.push('*');
+        let keys = redis::cmd("SCAN")
+            .cursor_arg(0)
+            .arg("MATCH")
+            .arg(pattern)
+            .clone()
+            .iter::<String>(&mut *connection)
+            .map(|keys| keys.collect::<Vec<_>>())
+            .unwrap_or_default();
+        keys.iter()
+            .filter_map(|key| key.strip_prefix(&self.prefix).map(str::to_string))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderName, HeaderValue};
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (HeaderName::from_static(name), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn keys_cache_entries_on_selector_and_varying_headers() {
+        let cache = ScrapeCache::new();
+        let mobile = headers(&[("user-agent", "mobile"), ("cookie", "a=1")]);
+        let desktop = headers(&[("user-agent", "desktop"), ("cookie", "a=1")]);
+        let key = cache.key_for("https://a.com", "h1", &mobile);
+        assert_eq!(key, "https://a.com|h1|user-agent=mobile");
+        assert_ne!(key, cache.key_for("https://a.com", "h2", &mobile));
+        assert_ne!(key, cache.key_for("https://a.com", "h1", &desktop));
+        assert_eq!(key, cache.key_for("https://a.com", "h1", &headers(&[("user-agent", "mobile"), ("cookie", "b=2")])));
+    }
+
+    #[test]
+    fn builds_keys_with_custom_headers_and_functions() {
+        let cache = ScrapeCache::new().vary_on(&["Cookie"]);
+        let key = cache.key_for("https://a.com", "h1", &headers(&[("user-agent", "mobile"), ("cookie", "a=1")]));
+        assert_eq!(key, "https://a.com|h1|cookie=a=1");
+
+        let cache = ScrapeCache::new().with_key_fn(|key| key.url.to_string());
+        assert_eq!(cache.key_for("https://a.com", "h1", &HeaderMap::new()), "https://a.com");
+    }
+
+    #[test]
+    fn tracks_stats_and_invalidates_entries() {
+        let cache = ScrapeCache::new();
+        cache.set("https://a.com/x|h1", vec!["one".to_string()]);
+        cache.set("https://a.com/y|h1", vec!["two".to_string()]);
+        cache.set_body("https://a.com/x|", "<h1>one</h1>".to_string());
+        cache.set("https://b.com|h1", vec!["three".to_string()]);
+        assert!(cache.get("https://a.com/x|h1").is_some());
+        assert!(cache.get("https://c.com|h1").is_none());
+        assert!(cache.get_body("https://a.com/x|").is_some());
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.body_entries), (3, 1));
+        assert_eq!((stats.hits, stats.misses, stats.body_hits, stats.body_misses), (1, 1, 1, 0));
+        assert_eq!(stats.total_bytes, r#"["one"]"#.len() + r#"["two"]"#.len() + r#"["three"]"#.len() + "<h1>one</h1>".len());
+        assert!(stats.oldest_entry.is_some());
+        assert_eq!(cache.keys().len(), 4);
+
+        assert_eq!(cache.invalidate_url("https://a.com/x"), 2);
+        assert_eq!(cache.invalidate_prefix("https://a.com/"), 1);
+        assert_eq!(cache.keys(), vec!["https://b.com|h1"]);
+
+        cache.clear();
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (0, 0, 0));
+        assert!(stats.oldest_entry.is_none());
+    }
+
+    #[test]
+    fn clones_share_entries_and_counters() {
+        let cache = ScrapeCache::new();
+        let shared = cache.clone();
+        shared.set("https://a.com|h1", vec!["one".to_string()]);
+        assert_eq!(cache.get("https://a.com|h1"), Some(vec!["one".to_string()]));
+        assert_eq!(shared.stats().hits, 1);
+    }
+
+    #[test]
+    fn expires_entries_after_ttl() {
+        let cache = ScrapeCache::new().with_ttl(Duration::ZERO);
+        cache.set("https://a.com|h1", vec!["one".to_string()]);
+        assert!(cache.get("https://a.com|h1").is_none());
+        assert!(cache.keys().is_empty());
+    }
+
+    #[test]
+    fn spills_the_oldest_entries_beyond_the_memory_limit() {
+        let dir = std::env::temp_dir().join(format!("rust-scrapper-spill-{}", std::process::id()));
+        let cache = MemoryCache::new().with_max_bytes(5).with_spill_dir(&dir.to_string_lossy());
+        cache.set("a", "aaaa".to_string(), None);
+        cache.set("b", "bbbb".to_string(), None);
+        assert_eq!(cache.entries.lock().unwrap().bytes, 4);
+        // Spilled entries are named by a stable hash, so a later run finds them again.
+        assert!(dir.join("af63dc4c8601ec8c.entry").exists());
+        assert_eq!(cache.get("a").as_deref(), Some("aaaa"));
+        assert_eq!(cache.get("b").as_deref(), Some("bbbb"));
+        let mut keys = cache.keys();
+        keys.sort();
+        assert_eq!(keys, vec!["a", "b"]);
+
+        assert!(cache.remove("a"));
+        assert!(cache.remove("b"));
+        assert!(cache.get("a").is_none() && cache.get("b").is_none());
+        cache.set("c", "cccc".to_string(), None);
+        cache.set("d", "dddd".to_string(), None);
+        cache.clear();
+        assert!(cache.keys().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compresses_large_bodies_transparently() {
+        let backend = Arc::new(MemoryCache::new());
+        let cache = ScrapeCache::with_backend(backend.clone()).with_compression(100);
+        let large = "<p>repeated</p>".repeat(100);
+        cache.set_body("large", large.clone());
+        cache.set_body("small", "<p>tiny</p>".to_string());
+
+        let stored = backend.get(&format!("{}large", BODY_PREFIX)).unwrap();
+        assert!(stored.starts_with(GZIP_MARKER));
+        assert!(stored.len() < large.len() / 5);
+        assert_eq!(backend.get(&format!("{}small", BODY_PREFIX)).as_deref(), Some("<p>tiny</p>"));
+        assert_eq!(cache.get_body("large"), Some(large));
+        assert_eq!(cache.get_body("small").as_deref(), Some("<p>tiny</p>"));
+    }
+
+    #[test]
+    fn reads_caching_rules_from_response_headers() {
+        let cache = ScrapeCache::new().honor_cache_headers();
+        assert!(cache.honors_cache_headers());
+        let caching = |pairs| cache.caching_for(&headers(pairs));
+
+        assert_eq!(caching(&[("cache-control", "public, max-age=600"), ("age", "100")]), ResponseCaching::Fresh(Duration::from_secs(500)));
+        assert_eq!(caching(&[("cache-control", "max-age=60"), ("age", "90")]), ResponseCaching::NoStore);
+        assert_eq!(caching(&[("cache-control", "no-store, max-age=600")]), ResponseCaching::NoStore);
+        assert_eq!(
+            caching(&[("expires", "Tue, 01 Jan 2030 00:10:00 GMT"), ("date", "Tue, 01 Jan 2030 00:00:00 GMT")]),
+            ResponseCaching::Fresh(Duration::from_secs(600))
+        );
+        assert_eq!(caching(&[("expires", "0")]), ResponseCaching::NoStore);
+        assert_eq!(caching(&[("vary", "Cookie")]), ResponseCaching::NoStore);
+        assert_eq!(caching(&[("vary", "Accept-Encoding")]), ResponseCaching::Unspecified);
+        assert_eq!(caching(&[("vary", "User-Agent")]), ResponseCaching::Unspecified);
+
+        let cache = ScrapeCache::new().with_key_fn(|key| key.url.to_string());
+        assert_eq!(cache.caching_for(&headers(&[("vary", "User-Agent")])), ResponseCaching::NoStore);
+    }
+
+    #[test]
+    fn skips_bodies_that_must_not_be_stored() {
+        let cache = ScrapeCache::new();
+        cache.set_body_as("a", "<p>a</p>".to_string(), ResponseCaching::NoStore);
+        cache.set_body_as("b", "<p>b</p>".to_string(), ResponseCaching::Fresh(Duration::from_secs(60)));
+        assert_eq!(cache.get_body("a"), None);
+        assert_eq!(cache.get_body("b").as_deref(), Some("<p>b</p>"));
+    }
+
+    #[test]
+    fn caches_records_per_schema() {
+        use crate::schema::{Field, Schema};
+        let prices = Schema::new().field(Field::new("price", ".price"));
+        let names = Schema::new().field(Field::new("name", "h1"));
+        assert_eq!(schema_selector(&prices), schema_selector(&prices.clone()));
+        assert_ne!(schema_selector(&prices), schema_selector(&names));
+        // Pinned, so a persistent cache written by another build still hits.
+        assert_eq!(schema_selector(&prices), "<schema:66a2aa31c3ac6624>");
+
+        let cache = ScrapeCache::new();
+        let key = cache.key_for("https://shop.com/", &schema_selector(&prices), &HeaderMap::new());
+        assert_eq!(cache.get_records(&key), None);
+        cache.set_records(&key, &[serde_json::json!({"price": "9.99"})]);
+        assert_eq!(cache.get_records(&key), Some(vec![serde_json::json!({"price": "9.99"})]));
+        assert_eq!((cache.stats().hits, cache.stats().misses), (1, 1));
+    }
+
+    #[test]
+    fn keeps_robots_tags_with_cached_bodies() {
+        let tags = vec!["noindex".to_string(), "googlebot: nofollow".to_string()];
+        for cache in [ScrapeCache::new(), ScrapeCache::new().with_compression(1)] {
+            cache.set_body_with_robots_tags("a", "<p>a</p>".to_string(), &tags, ResponseCaching::Unspecified);
+            cache.set_body("b", "<p>b</p>".to_string());
+            assert_eq!(cache.get_body_with_robots_tags("a"), Some(("<p>a</p>".to_string(), tags.clone())));
+            assert_eq!(cache.get_body("a").as_deref(), Some("<p>a</p>"));
+            assert_eq!(cache.get_body_with_robots_tags("b"), Some(("<p>b</p>".to_string(), Vec::new())));
+        }
+    }
+}
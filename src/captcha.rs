@@ -0,0 +1,253 @@
+//! CAPTCHA detection and solving.
+//!
+//! With detection enabled (`RustScrapper::detect_captchas`, `JsScrapeOptions::detect_captchas`),
+//! pages showing a reCAPTCHA, hCaptcha or Turnstile widget fail with
+//! `ScrapeError::CaptchaRequired` instead of being scraped as content. A `CaptchaSolver`, e.g.
+//! a client of a solving service, turns a `CaptchaChallenge` into a response token, which is
+//! then submitted with the widget's form (or passed to its callback in the browser).
+
+use crate::schema::parse_selector;
+use scraper::{ElementRef, Html};
+use serde::Serialize;
+use std::fmt;
+#[cfg(feature = "native")]
+use std::sync::Arc;
+#[cfg(feature = "native")]
+use url::Url;
+
+/// The service behind a CAPTCHA widget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum CaptchaProvider {
+    ReCaptcha,
+    HCaptcha,
+    Turnstile,
+}
+
+impl CaptchaProvider {
+    /// Name of the form field the widget puts its response token in.
+    pub fn response_field(&self) -> &'static str {
+        match self {
+            CaptchaProvider::ReCaptcha => "g-recaptcha-response",
+            CaptchaProvider::HCaptcha => "h-captcha-response",
+            CaptchaProvider::Turnstile => "cf-turnstile-response",
+        }
+    }
+}
+
+impl fmt::Display for CaptchaProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptchaProvider::ReCaptcha => write!(f, "reCAPTCHA"),
+            CaptchaProvider::HCaptcha => write!(f, "hCaptcha"),
+            CaptchaProvider::Turnstile => write!(f, "Turnstile"),
+        }
+    }
+}
+
+/// A CAPTCHA found on a page, with what a solving service needs to solve it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CaptchaChallenge {
+    pub provider: CaptchaProvider,
+    /// The widget's `data-sitekey`, if it declares one.
+    pub site_key: Option<String>,
+    pub page_url: String,
+}
+
+/// Turns a CAPTCHA into a response token, e.g. by calling a solving service.
+/// Closures taking a `&CaptchaChallenge` and returning the token implement it.
+pub trait CaptchaSolver: Send + Sync {
+    fn solve(&self, challenge: &CaptchaChallenge) -> Result<String, Box<dyn std::error::Error>>;
+}
+
+impl<F> CaptchaSolver for F
+where
+    F: Fn(&CaptchaChallenge) -> Result<String, Box<dyn std::error::Error>> + Send + Sync,
+{
+    fn solve(&self, challenge: &CaptchaChallenge) -> Result<String, Box<dyn std::error::Error>> {
+        self(challenge)
+    }
+}
+
+/// A shared solver that keeps `JsScrapeOptions` printable.
+#[cfg(feature = "native")]
+#[derive(Clone)]
+pub(crate) struct SharedSolver(pub(crate) Arc<dyn CaptchaSolver>);
+
+#[cfg(feature = "native")]
+impl fmt::Debug for SharedSolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CaptchaSolver")
+    }
+}
+
+/// Widget markup of each provider: container classes and challenge iframe URLs.
+const WIDGETS: [(CaptchaProvider, &str, &str); 3] = [
+    (CaptchaProvider::ReCaptcha, ".g-recaptcha", "iframe[src*='google.com/recaptcha/api2/anchor'], iframe[src*='recaptcha.net/recaptcha/api2/anchor']"),
+    (CaptchaProvider::HCaptcha, ".h-captcha", "iframe[src*='hcaptcha.com/captcha']"),
+    (CaptchaProvider::Turnstile, ".cf-turnstile", "iframe[src*='challenges.cloudflare.com']"),
+];
+
+/// Finds a CAPTCHA widget in a page fetched from `url`. Only visible widgets count: invisible
+/// scoring scripts such as reCAPTCHA v3 and `data-size="invisible"` widgets do not block the
+/// content.
+pub fn detect(body: &str, url: &str) -> Option<CaptchaChallenge> {
+    let document = Html::parse_document(body);
+    let (provider, widget) = find_widget(&document)?;
+    Some(CaptchaChallenge {
+        provider,
+        site_key: widget
+            .and_then(|widget| widget.value().attr("data-sitekey"))
+            .map(str::to_string),
+        page_url: url.to_string(),
+    })
+}
+
+/// The provider and, if found, the widget container of the first CAPTCHA on the page.
+fn find_widget(document: &Html) -> Option<(CaptchaProvider, Option<ElementRef<'_>>)> {
+    WIDGETS.iter().find_map(|(provider, container, iframe)| {
+        let visible = |selector: &str| {
+            let selector = parse_selector(selector).ok()?;
+            document.select(&selector).find(|element| !is_invisible(element))
+        };
+        if let Some(widget) = visible(container) {
+            return Some((*provider, Some(widget)));
+        }
+        visible(iframe).map(|_| (*provider, None))
+    })
+}
+
+/// Whether a widget is invisible, e.g. `data-size="invisible"` reCAPTCHA or hCaptcha, which
+/// runs in the background and shows a challenge only to visitors it suspects.
+fn is_invisible(element: &ElementRef) -> bool {
+    let element = element.value();
+    element.attr("data-size").is_some_and(|size| size.trim().eq_ignore_ascii_case("invisible"))
+        || element.attr("src").is_some_and(|src| src.contains("size=invisible"))
+}
+
+/// The form a CAPTCHA widget is in, filled in with the solved token.
+#[cfg(feature = "native")]
+#[derive(Debug, Clone)]
+pub(crate) struct SolvedForm {
+    /// URL to submit to. For GET forms, it carries the fields in its query.
+    pub(crate) action: String,
+    /// Fields to POST, `None` for GET forms.
+    pub(crate) body: Option<Vec<(String, String)>>,
+}
+
+/// Builds the submission of the form around the page's CAPTCHA widget with `token` as the
+/// response. `None` if the widget is not in a form.
+#[cfg(feature = "native")]
+pub(crate) fn solved_form(body: &str, url: &str, challenge: &CaptchaChallenge, token: &str) -> Option<SolvedForm> {
+    let document = Html::parse_document(body);
+    let (_, widget) = find_widget(&document)?;
+    let form = widget?.ancestors().filter_map(ElementRef::wrap).find(|element| element.value().name() == "form")?;
+    let action = Url::parse(url).ok()?.join(form.value().attr("action").unwrap_or_default()).ok()?;
+    let post = form.value().attr("method").is_some_and(|method| method.eq_ignore_ascii_case("post"));
+    let response_field = challenge.provider.response_field();
+    let inputs = parse_selector("input[name], textarea[name], select[name]").ok()?;
+    let mut fields: Vec<(String, String)> = form
+        .select(&inputs)
+        .filter_map(|input| {
+            let element = input.value();
+            let name = element.attr("name")?;
+            let kind = element.attr("type").unwrap_or_default().to_ascii_lowercase();
+            if name == response_field || matches!(kind.as_str(), "submit" | "button" | "image" | "file" | "reset") {
+                return None;
+            }
+            if matches!(kind.as_str(), "checkbox" | "radio") && element.attr("checked").is_none() {
+                return None;
+            }
+            let value = match element.name() {
+                "textarea" => input.text().collect(),
+                "select" => {
+                    let selected = parse_selector("option[selected]").ok()?;
+                    let option = input.select(&selected).next()?;
+                    option.value().attr("value").map(str::to_string).unwrap_or_else(|| option.text().collect())
+                }
+                _ => element.attr("value").unwrap_or(if kind == "checkbox" { "on" } else { "" }).to_string(),
+            };
+            Some((name.to_string(), value))
+        })
+        .collect();
+    fields.push((response_field.to_string(), token.to_string()));
+    if post {
+        return Some(SolvedForm { action: action.to_string(), body: Some(fields) });
+    }
+    let mut action = action;
+    action.set_query(None);
+    action.query_pairs_mut().extend_pairs(&fields);
+    Some(SolvedForm { action: action.to_string(), body: None })
+}
+
+/// JavaScript function applying a solved token in the browser: fills the response fields, then
+/// calls the widget's `data-callback` or submits its form. Returns "submitted" if the form was
+/// submitted, which navigates away.
+#[cfg(feature = "native")]
+pub(crate) fn apply_token_script(challenge: &CaptchaChallenge, token: &str) -> String {
+    let quote = |value: &str| serde_json::to_string(value).unwrap_or_default();
+    format!(
+        "((token, field) => {{ \
+         document.querySelectorAll('[name=\"' + field + '\"]').forEach((e) => {{ e.value = token; e.innerHTML = token; }}); \
+         const widget = document.querySelector('.g-recaptcha, .h-captcha, .cf-turnstile'); \
+         const callback = widget && widget.getAttribute('data-callback'); \
+         if (callback && typeof window[callback] === 'function') {{ window[callback](token); return 'callback'; }} \
+         const form = widget && widget.closest('form'); \
+         if (form) {{ form.submit(); return 'submitted'; }} \
+         return 'none'; }})({}, {})",
+        quote(token),
+        quote(challenge.provider.response_field())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<form action="/verify" method="post">
+        <input type="hidden" name="token" value="abc">
+        <input type="checkbox" name="remember">
+        <select name="lang"><option value="en">English</option><option value="tr" selected>Türkçe</option></select>
+        <div class="g-recaptcha" data-sitekey="6Lc_key"></div>
+        <textarea name="g-recaptcha-response"></textarea>
+        <input type="submit" name="go" value="Go">
+    </form>"#;
+
+    #[test]
+    fn detects_visible_widgets() {
+        let challenge = detect(PAGE, "https://shop.com/login").unwrap();
+        assert_eq!(challenge.provider, CaptchaProvider::ReCaptcha);
+        assert_eq!(challenge.site_key.as_deref(), Some("6Lc_key"));
+
+        let iframe = r#"<iframe src="https://challenges.cloudflare.com/cdn-cgi/challenge"></iframe>"#;
+        let challenge = detect(iframe, "https://shop.com/").unwrap();
+        assert_eq!((challenge.provider, challenge.site_key), (CaptchaProvider::Turnstile, None));
+        assert_eq!(detect(r#"<script src="https://www.google.com/recaptcha/api.js?render=key"></script>"#, "https://shop.com/"), None);
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn fills_the_widget_form_with_the_token() {
+        let challenge = detect(PAGE, "https://shop.com/login").unwrap();
+        let form = solved_form(PAGE, "https://shop.com/login", &challenge, "solved").unwrap();
+        assert_eq!(form.action, "https://shop.com/verify");
+        let fields = form.body.unwrap();
+        let pairs: Vec<_> = fields.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect();
+        assert_eq!(pairs, vec![("token", "abc"), ("lang", "tr"), ("g-recaptcha-response", "solved")]);
+
+        let get = PAGE.replace(r#" method="post""#, "");
+        let form = solved_form(&get, "https://shop.com/login", &challenge, "solved").unwrap();
+        assert!(form.body.is_none());
+        assert_eq!(form.action, "https://shop.com/verify?token=abc&lang=tr&g-recaptcha-response=solved");
+        assert!(solved_form(r#"<div class="h-captcha"></div>"#, "https://shop.com/", &challenge, "x").is_none());
+    }
+
+    #[test]
+    fn skips_invisible_widgets() {
+        let invisible = r#"<form><div class="g-recaptcha" data-sitekey="key" data-size="invisible"></div></form>"#;
+        assert_eq!(detect(invisible, "https://example.com/"), None);
+        let iframe = r#"<iframe src="https://www.google.com/recaptcha/api2/anchor?k=key&size=invisible"></iframe>"#;
+        assert_eq!(detect(iframe, "https://example.com/"), None);
+        let both = r#"<div class="h-captcha" data-size="invisible"></div><div class="h-captcha" data-sitekey="shown"></div>"#;
+        assert_eq!(detect(both, "https://example.com/").unwrap().site_key.as_deref(), Some("shown"));
+    }
+}
@@ -0,0 +1,223 @@
+//! Crawl checkpoints.
+//!
+//! A checkpointing `Crawler` writes every crawled page to a JSON Lines file and periodically
+//! saves its state: pending and visited URLs, failures and the number of fetched pages. The
+//! file is flushed to disk before the state is replaced atomically, and resuming truncates
+//! the file to its length at the checkpoint, so a crash loses at most one checkpoint interval
+//! and no page is exported twice.
+
+use crate::crawler::{CrawledPage, FailedUrl};
+use crate::export::ExportWriter;
+use crate::frontier::FrontierSnapshot;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Crawler state saved at a checkpoint.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CheckpointState {
+    /// Pending and visited URLs; `None` if the frontier persists itself, like `RedisFrontier`.
+    pub frontier: Option<FrontierSnapshot>,
+    pub failures: Vec<FailedUrl>,
+    pub fetched: usize,
+    /// Length of the JSON Lines export when the checkpoint was taken.
+    pub export_len: Option<u64>,
+}
+
+impl CheckpointState {
+    /// Reads a checkpoint, returning `None` if none has been written yet.
+    pub fn load(path: &str) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => Ok(Some(serde_json::from_str(&contents)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes the checkpoint to a temporary file and renames it over the previous one,
+    /// so a crash never leaves a partially written checkpoint.
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let temporary = format!("{}.tmp", path);
+        let mut file = File::create(&temporary)?;
+        file.write_all(serde_json::to_string(self)?.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&temporary, path)?;
+        Ok(())
+    }
+}
+
+/// Decides when the next checkpoint is due.
+pub(crate) struct Checkpointer {
+    pub(crate) path: String,
+    every_items: usize,
+    every: Duration,
+    items_since: usize,
+    last: Instant,
+}
+
+impl Checkpointer {
+    pub(crate) fn new(path: &str, every_items: usize, every: Duration) -> Self {
+        Checkpointer {
+            path: path.to_string(),
+            every_items,
+            every,
+            items_since: 0,
+            last: Instant::now(),
+        }
+    }
+
+    /// Records extracted items and returns whether a checkpoint is due.
+    pub(crate) fn record(&mut self, items: usize) -> bool {
+        self.items_since += items;
+        (self.every_items > 0 && self.items_since >= self.every_items) || (!self.every.is_zero() && self.last.elapsed() >= self.every)
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.items_since = 0;
+        self.last = Instant::now();
+    }
+}
+
+/// Appends crawled pages to a JSON Lines file, one page per line.
+pub(crate) struct JsonlSink {
+    path: PathBuf,
+    writer: Option<ExportWriter>,
+}
+
+impl JsonlSink {
+    pub(crate) fn new(path: &str) -> Self {
+        JsonlSink {
+            path: PathBuf::from(path),
+            writer: None,
+        }
+    }
+
+    pub(crate) fn write(&mut self, page: &CrawledPage) -> Result<(), Box<dyn std::error::Error>> {
+        if self.writer.is_none() {
+            self.writer = Some(ExportWriter::append(&self.path)?);
+        }
+        if let Some(writer) = &mut self.writer {
+            serde_json::to_writer(&mut *writer, page)?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Flushes written pages to disk and returns the file's length. A compressed file is
+    /// finished, so it can be truncated to this length; later pages start a new gzip member
+    /// or zstd frame.
+    pub(crate) fn flush(&mut self) -> Result<u64, Box<dyn std::error::Error>> {
+        if let Some(writer) = self.writer.take() {
+            writer.finish()?;
+        }
+        match std::fs::metadata(&self.path) {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Drops pages written after a checkpoint, before resuming from it.
+    pub(crate) fn truncate(&mut self, len: u64) -> Result<(), Box<dyn std::error::Error>> {
+        self.writer = None;
+        if self.path.exists() {
+            OpenOptions::new().write(true).open(&self.path)?.set_len(len)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::robots::RobotsDirectives;
+
+    const NO_DIRECTIVES: &str = r#""robots":{"noindex":false,"nofollow":false,"noarchive":false}"#;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("rust-scrapper-checkpoint-{}-{}", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn saves_and_loads_state() {
+        let path = temp_path("state.json");
+        assert!(CheckpointState::load(&path).unwrap().is_none());
+        let state = CheckpointState {
+            frontier: Some(FrontierSnapshot {
+                pending: vec![("https://a.com/".to_string(), 1)],
+                ..FrontierSnapshot::default()
+            }),
+            fetched: 3,
+            export_len: Some(42),
+            ..CheckpointState::default()
+        };
+        state.save(&path).unwrap();
+        let loaded = CheckpointState::load(&path).unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.frontier.unwrap().pending, vec![("https://a.com/".to_string(), 1)]);
+        assert_eq!(loaded.fetched, 3);
+        assert_eq!(loaded.export_len, Some(42));
+    }
+
+    #[test]
+    fn checkpoints_after_enough_items() {
+        let mut checkpointer = Checkpointer::new("unused", 3, Duration::ZERO);
+        assert!(!checkpointer.record(2));
+        assert!(checkpointer.record(1));
+        checkpointer.reset();
+        assert!(!checkpointer.record(1));
+        assert!(!Checkpointer::new("unused", 0, Duration::ZERO).record(100));
+    }
+
+    #[test]
+    fn truncates_pages_written_after_a_checkpoint() {
+        let path = temp_path("pages.jsonl");
+        let page = |url: &str| CrawledPage {
+            url: url.to_string(),
+            items: vec!["item".to_string()],
+            robots: RobotsDirectives::default(),
+        };
+        let mut sink = JsonlSink::new(&path);
+        sink.write(&page("https://a.com/1")).unwrap();
+        let len = sink.flush().unwrap();
+        sink.write(&page("https://a.com/2")).unwrap();
+        sink.flush().unwrap();
+        sink.truncate(len).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents, format!("{{\"url\":\"https://a.com/1\",\"items\":[\"item\"],{}}}\n", NO_DIRECTIVES));
+    }
+
+    #[test]
+    fn truncates_compressed_pages_to_whole_members() {
+        let path = temp_path("pages.jsonl.gz");
+        let page = |url: &str| CrawledPage {
+            url: url.to_string(),
+            items: Vec::new(),
+            robots: RobotsDirectives::default(),
+        };
+        let mut sink = JsonlSink::new(&path);
+        sink.write(&page("https://a.com/1")).unwrap();
+        let len = sink.flush().unwrap();
+        sink.write(&page("https://a.com/2")).unwrap();
+        sink.flush().unwrap();
+        sink.truncate(len).unwrap();
+        sink.write(&page("https://a.com/3")).unwrap();
+        sink.flush().unwrap();
+        let mut contents = String::new();
+        std::io::Read::read_to_string(&mut flate2::read::MultiGzDecoder::new(File::open(&path).unwrap()), &mut contents).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            contents,
+            format!(
+                "{{\"url\":\"https://a.com/1\",\"items\":[],{0}}}\n{{\"url\":\"https://a.com/3\",\"items\":[],{0}}}\n",
+                NO_DIRECTIVES
+            )
+        );
+    }
+}
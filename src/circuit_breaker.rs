@@ -0,0 +1,175 @@
+//! Per-host circuit breaker.
+//!
+//! After a number of consecutive failures the circuit for a host opens and requests to
+//! it are rejected immediately. Once the cooldown has passed a single probe request is
+//! let through: success closes the circuit again, failure re-opens it. A probe that records
+//! neither expires after another cooldown, and the next request becomes the probe.
+
+use crate::host_of;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// State of the circuit for one host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests flow normally.
+    Closed,
+    /// Requests are rejected until the cooldown has passed.
+    Open,
+    /// A single probe request is in flight.
+    HalfOpen,
+}
+
+struct HostCircuit {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    probe_started_at: Option<Instant>,
+}
+
+/// Per-host circuit breaker.
+/// Cloning returns a handle to the same breaker, so it can be shared between scrapers.
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    hosts: Arc<Mutex<HashMap<String, HostCircuit>>>,
+}
+
+impl CircuitBreaker {
+    /// Opens a host's circuit after `failure_threshold` consecutive failures and
+    /// probes it again once `cooldown` has passed.
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreaker {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            hosts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Checks whether a request to the URL's host may be sent.
+    /// When the cooldown of an open circuit has passed, this admits the single probe request.
+    /// A probe whose outcome was not recorded within the cooldown is replaced by a new one,
+    /// so a request that never reports back cannot keep the circuit from closing.
+    pub fn allow(&self, url: &str) -> bool {
+        let mut hosts = self.hosts.lock().unwrap();
+        let circuit = hosts.entry(host_of(url)).or_insert_with(HostCircuit::closed);
+        let since = match circuit.state {
+            CircuitState::Closed => return true,
+            CircuitState::HalfOpen => circuit.probe_started_at,
+            CircuitState::Open => circuit.opened_at,
+        };
+        let cooled_down = since.map(|at| at.elapsed() >= self.cooldown).unwrap_or(true);
+        if cooled_down {
+            circuit.state = CircuitState::HalfOpen;
+            circuit.probe_started_at = Some(Instant::now());
+        }
+        cooled_down
+    }
+
+    /// Records a successful request, closing the host's circuit.
+    pub fn record_success(&self, url: &str) {
+        let mut hosts = self.hosts.lock().unwrap();
+        hosts.insert(host_of(url), HostCircuit::closed());
+    }
+
+    /// Records a failed request, opening the circuit when the threshold is reached
+    /// or when a probe request fails.
+    pub fn record_failure(&self, url: &str) {
+        let mut hosts = self.hosts.lock().unwrap();
+        let circuit = hosts.entry(host_of(url)).or_insert_with(HostCircuit::closed);
+        circuit.consecutive_failures += 1;
+        if circuit.state == CircuitState::HalfOpen || circuit.consecutive_failures >= self.failure_threshold {
+            circuit.state = CircuitState::Open;
+            circuit.opened_at = Some(Instant::now());
+        }
+    }
+
+    /// Returns the circuit state of the URL's host.
+    pub fn state(&self, url: &str) -> CircuitState {
+        let hosts = self.hosts.lock().unwrap();
+        hosts.get(&host_of(url)).map(|circuit| circuit.state).unwrap_or(CircuitState::Closed)
+    }
+}
+
+impl HostCircuit {
+    fn closed() -> Self {
+        HostCircuit {
+            state: CircuitState::Closed,
+            consecutive_failures: 0,
+            opened_at: None,
+            probe_started_at: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://a.com/page";
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        breaker.record_failure(URL);
+        breaker.record_success(URL);
+        breaker.record_failure(URL);
+        assert_eq!(breaker.state(URL), CircuitState::Closed);
+        assert!(breaker.allow(URL));
+
+        breaker.record_failure(URL);
+        assert_eq!(breaker.state(URL), CircuitState::Open);
+        assert!(!breaker.allow(URL));
+        assert!(breaker.allow("https://b.com/"));
+    }
+
+    #[test]
+    fn admits_one_probe_after_the_cooldown() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(200));
+        breaker.record_failure(URL);
+        std::thread::sleep(Duration::from_millis(250));
+        assert!(breaker.allow(URL));
+        assert_eq!(breaker.state(URL), CircuitState::HalfOpen);
+        assert!(!breaker.allow(URL));
+    }
+
+    #[test]
+    fn closes_when_the_probe_succeeds() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        breaker.record_failure(URL);
+        assert!(breaker.allow(URL));
+        breaker.record_success(URL);
+        assert_eq!(breaker.state(URL), CircuitState::Closed);
+        assert!(breaker.allow(URL));
+    }
+
+    #[test]
+    fn reopens_when_the_probe_fails() {
+        let breaker = CircuitBreaker::new(3, Duration::from_millis(20));
+        for _ in 0..3 {
+            breaker.record_failure(URL);
+        }
+        assert!(!breaker.allow(URL));
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(breaker.allow(URL));
+        breaker.record_failure(URL);
+        assert_eq!(breaker.state(URL), CircuitState::Open);
+        assert!(!breaker.allow(URL));
+    }
+
+    #[test]
+    fn admits_a_new_probe_when_the_last_one_never_reported() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(50));
+        breaker.record_failure(URL);
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow(URL));
+        assert!(!breaker.allow(URL));
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow(URL));
+        assert_eq!(breaker.state(URL), CircuitState::HalfOpen);
+        breaker.record_success(URL);
+        assert_eq!(breaker.state(URL), CircuitState::Closed);
+    }
+}
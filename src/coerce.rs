@@ -0,0 +1,194 @@
+//! Type coercion for extracted values.
+//!
+//! Schema fields are extracted as text. A `FieldType` turns that text into a typed JSON
+//! value: numbers with either decimal separator, booleans, dates and money amounts.
+//! Text that cannot be parsed as the declared type becomes `null`.
+
+use crate::dates;
+use crate::money::Money;
+use chrono::SecondsFormat;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::OnceLock;
+
+/// The type a schema field's text is converted to.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    /// The trimmed text, unchanged.
+    #[default]
+    String,
+    /// A whole number, ignoring thousands separators, e.g. "1,234 reviews" becomes 1234.
+    Integer,
+    /// A decimal number. Without a `decimal` separator, it is guessed from the text,
+    /// so both "1,299.99" and "1.299,99" become 1299.99.
+    Float {
+        #[serde(default)]
+        decimal: Option<char>,
+    },
+    /// "true", "yes", "y", "on" or "1" and their opposites.
+    Boolean,
+    /// A date or date-time written in `format` (chrono syntax, e.g. "%d.%m.%Y"), or in any
+    /// format `dates::parse` understands without one. Normalized to RFC 3339 in UTC.
+    Date {
+        #[serde(default)]
+        format: Option<String>,
+    },
+    /// A `Money` amount with its currency, as `{"amount": 1299.99, "currency": "USD"}`.
+    Money,
+}
+
+impl FieldType {
+    /// Converts extracted text to this type, or `null` if it does not parse.
+    pub fn coerce(&self, text: &str) -> Value {
+        let text = text.trim();
+        let value = match self {
+            FieldType::String => return Value::String(text.to_string()),
+            FieldType::Integer => parse_number(text, None)
+                .filter(|number| number.fract() == 0.0)
+                .map(|number| json!(number as i64)),
+            FieldType::Float { decimal } => parse_number(text, *decimal).map(|number| json!(number)),
+            FieldType::Boolean => parse_bool(text).map(Value::Bool),
+            FieldType::Date { format } => parse_date(text, format.as_deref()).map(Value::String),
+            FieldType::Money => Money::parse(text).and_then(|money| serde_json::to_value(money).ok()),
+        };
+        value.unwrap_or(Value::Null)
+    }
+}
+
+/// Parses the first number in `text`. Without a `decimal` separator, the last of `.` and `,`
+/// is the decimal one if both appear; a single separator followed by exactly three digits
+/// is taken as a thousands separator. Spaces and apostrophes only group thousands, so
+/// "29.99 19.99" is read as 29.99.
+pub fn parse_number(text: &str, decimal: Option<char>) -> Option<f64> {
+    static NUMBER: OnceLock<Regex> = OnceLock::new();
+    let number = NUMBER.get_or_init(|| Regex::new(r"[-+]?[0-9](?:[0-9.,' \u{a0}\u{202f}]*[0-9])?").unwrap());
+    let found = leading_number(number.find(text)?.as_str());
+    let digits: String = found.chars().filter(|c| !is_group_separator(*c)).collect();
+
+    let decimal = decimal.or_else(|| guess_decimal(&digits));
+    let normalized: String = digits
+        .chars()
+        .filter_map(|c| match c {
+            c if Some(c) == decimal => Some('.'),
+            '.' | ',' => None,
+            c => Some(c),
+        })
+        .collect();
+    normalized.parse().ok()
+}
+
+fn is_group_separator(c: char) -> bool {
+    matches!(c, ' ' | '\'' | '\u{a0}' | '\u{202f}')
+}
+
+/// The start of a match up to its first space or apostrophe that does not separate
+/// thousands: one that follows a `.` or `,`, or is not between groups of digits of one to
+/// three digits before and exactly three after, as in "1 299,90" or "10'000".
+fn leading_number(found: &str) -> &str {
+    let digits_start = found.find(|c: char| c.is_ascii_digit()).unwrap_or_default();
+    let mut group_start = digits_start;
+    let mut separated = false;
+    for (index, c) in found.char_indices() {
+        if matches!(c, '.' | ',') {
+            separated = true;
+        } else if is_group_separator(c) {
+            let group = &found[group_start..index];
+            let rest = &found[index + c.len_utf8()..];
+            let next = rest.split(|c: char| !c.is_ascii_digit()).next().unwrap_or_default();
+            let group_ok = if group_start == digits_start { (1..=3).contains(&group.len()) } else { group.len() == 3 };
+            if separated || !group_ok || next.len() != 3 {
+                return &found[..index];
+            }
+            group_start = index + c.len_utf8();
+        }
+    }
+    found
+}
+
+fn guess_decimal(digits: &str) -> Option<char> {
+    let last = digits.rfind(['.', ','])?;
+    let separator = if digits[last..].starts_with('.') { '.' } else { ',' };
+    let other = if separator == '.' { ',' } else { '.' };
+    if digits.contains(other) {
+        return Some(separator);
+    }
+    if digits.matches(separator).count() > 1 {
+        return None;
+    }
+    let decimals = digits.len() - last - 1;
+    let integer = digits[..last].trim_start_matches(['-', '+']);
+    if decimals == 3 && integer != "0" {
+        None
+    } else {
+        Some(separator)
+    }
+}
+
+fn parse_bool(text: &str) -> Option<bool> {
+    match text.to_lowercase().as_str() {
+        "true" | "yes" | "y" | "on" | "1" => Some(true),
+        "false" | "no" | "n" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_date(text: &str, format: Option<&str>) -> Option<String> {
+    let datetime = match format {
+        Some(format) => dates::parse_with_format(text, format)?,
+        None => dates::parse(text)?,
+    };
+    Some(datetime.to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_numbers_with_either_decimal_separator() {
+        assert_eq!(parse_number("1,299.99", None), Some(1299.99));
+        assert_eq!(parse_number("1.299,99 €", None), Some(1299.99));
+        assert_eq!(parse_number("1,234 reviews", None), Some(1234.0));
+        assert_eq!(parse_number("0,125", None), Some(0.125));
+        assert_eq!(parse_number("1.299.999", None), Some(1299999.0));
+        assert_eq!(parse_number("12 345,5", None), Some(12345.5));
+        assert_eq!(parse_number("1,234", Some(',')), Some(1.234));
+        assert_eq!(parse_number("none", None), None);
+    }
+
+    #[test]
+    fn coerces_text_to_field_types() {
+        assert_eq!(FieldType::Integer.coerce(" 1,234 "), json!(1234));
+        assert_eq!(FieldType::Integer.coerce("12.5"), Value::Null);
+        assert_eq!(FieldType::Float { decimal: None }.coerce("-3.5"), json!(-3.5));
+        assert_eq!(FieldType::Boolean.coerce("Yes"), json!(true));
+        assert_eq!(FieldType::Boolean.coerce("maybe"), Value::Null);
+        assert_eq!(FieldType::String.coerce(" a "), json!("a"));
+    }
+
+    #[test]
+    fn normalizes_dates_and_money() {
+        let date = |format: Option<&str>| FieldType::Date { format: format.map(str::to_string) };
+        assert_eq!(date(Some("%d.%m.%Y")).coerce("03.12.2024"), json!("2024-12-03T00:00:00Z"));
+        assert_eq!(date(None).coerce("12 Aralık 2024"), json!("2024-12-12T00:00:00Z"));
+        assert_eq!(date(None).coerce("Tue, 3 Dec 2024 10:00:00 +0000"), json!("2024-12-03T10:00:00Z"));
+        assert_eq!(date(None).coerce("someday"), Value::Null);
+
+        assert_eq!(FieldType::Money.coerce("$1,299.99"), json!({"amount": 1299.99, "currency": "USD"}));
+        assert_eq!(FieldType::Money.coerce("1.299,99 TRY"), json!({"amount": 1299.99, "currency": "TRY"}));
+        assert_eq!(FieldType::Money.coerce("42"), json!({"amount": 42.0, "currency": null}));
+    }
+
+    #[test]
+    fn groups_thousands_with_spaces_only() {
+        assert_eq!(parse_number("1 299,90 €", None), Some(1299.9));
+        assert_eq!(parse_number("10\u{a0}000\u{a0}000", None), Some(10_000_000.0));
+        assert_eq!(parse_number("CHF 1'234.50", None), Some(1234.5));
+        assert_eq!(parse_number("29.99 19.99", None), Some(29.99));
+        assert_eq!(parse_number("2024 123", None), Some(2024.0));
+        assert_eq!(parse_number("3 pieces 12", None), Some(3.0));
+        assert_eq!(parse_number("12 5", None), Some(12.0));
+    }
+}
@@ -0,0 +1,449 @@
+//! Compliance guardrails for scraping.
+//!
+//! A `Compliance` set on a scrapper with `RustScrapper::with_compliance` is consulted before
+//! every request and every redirect it follows, so it cannot be bypassed by that scrapper:
+//! URLs of blocklisted domains, and, with `respect_robots`, URLs robots.txt disallows, fail
+//! with `ScrapeError::Disallowed`.
+//! Every decision is written to an audit log (who requested which URL, when, and whether it
+//! was allowed), and the robots.txt decisions are kept per domain. `export` writes all of it
+//! to a JSON file for compliance review.
+
+use crate::context::RequestContext;
+use crate::error::ScrapeError;
+use crate::export::ExportWriter;
+use crate::robots::Robots;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// robots.txt files are fetched at most this long before giving up.
+const ROBOTS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Redirects followed per request, as by reqwest's default policy.
+const MAX_REDIRECTS: usize = 10;
+
+/// One request decision of the audit log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Unix timestamp in milliseconds.
+    pub at: i64,
+    /// Who scraped: the operator the `Compliance` was created for.
+    pub operator: String,
+    pub url: String,
+    pub allowed: bool,
+    /// Why the request was refused.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Correlation ID of the scrapper's `RequestContext`, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+}
+
+/// What robots.txt said about one URL.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RobotsDecision {
+    /// Unix timestamp in milliseconds.
+    pub at: i64,
+    pub url: String,
+    pub allowed: bool,
+    /// The deciding rule, e.g. `Disallow: /private`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+}
+
+/// The robots.txt of one domain and the decisions made with it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DomainRobots {
+    pub robots_url: String,
+    /// `fetched`, `not found` (everything allowed) or `unavailable: <error>` (everything
+    /// disallowed, as RFC 9309 requires for unreachable files).
+    pub status: String,
+    /// Unix timestamp in milliseconds.
+    pub fetched_at: i64,
+    pub decisions: Vec<RobotsDecision>,
+}
+
+/// Everything a `Compliance` recorded, as written by `export`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComplianceReport {
+    pub operator: String,
+    /// Unix timestamp in milliseconds.
+    pub generated_at: i64,
+    pub blocklist: Vec<String>,
+    /// User agent robots.txt was evaluated for, if it was respected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub robots_user_agent: Option<String>,
+    pub audit_log: Vec<AuditEntry>,
+    /// robots.txt decisions by host.
+    pub robots: BTreeMap<String, DomainRobots>,
+}
+
+struct DomainState {
+    rules: Robots,
+    record: DomainRobots,
+}
+
+#[derive(Default)]
+struct ComplianceState {
+    audit_log: Vec<AuditEntry>,
+    robots: HashMap<String, DomainState>,
+    audit_file: Option<ExportWriter>,
+}
+
+/// Domain blocklist, robots.txt enforcement and audit log.
+/// Cloning returns a handle to the same state, so it can be shared between scrapers.
+#[derive(Clone)]
+pub struct Compliance {
+    operator: String,
+    blocklist: Vec<String>,
+    robots_user_agent: Option<String>,
+    state: Arc<Mutex<ComplianceState>>,
+}
+
+impl Compliance {
+    /// Guardrails for scrapes made by `operator`, e.g. a user, team or service name, which
+    /// is recorded in every audit entry.
+    pub fn new(operator: &str) -> Self {
+        Compliance {
+            operator: operator.to_string(),
+            blocklist: Vec::new(),
+            robots_user_agent: None,
+            state: Arc::new(Mutex::new(ComplianceState::default())),
+        }
+    }
+
+    /// Refuses every URL of the domain and its subdomains. Internationalized domains are
+    /// stored in their ASCII (punycode) form, as hosts appear in URLs.
+    pub fn block_domain(mut self, domain: &str) -> Self {
+        let domain = domain.trim().trim_start_matches("*.").trim_matches('.');
+        let domain = match url::Host::parse(domain) {
+            Ok(host) => host.to_string(),
+            Err(_) => domain.to_ascii_lowercase(),
+        };
+        if !domain.is_empty() && !self.blocklist.contains(&domain) {
+            self.blocklist.push(domain);
+        }
+        self
+    }
+
+    /// Reads blocked domains from a file, one per line. Empty lines and `#` comments are skipped.
+    pub fn with_blocklist_file(self, path: &str) -> std::io::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(contents
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|line| !line.is_empty())
+            .fold(self, |compliance, domain| compliance.block_domain(domain)))
+    }
+
+    /// Refuses URLs the sites' robots.txt disallows for `user_agent`, e.g. `MyCompanyBot`.
+    /// Each host's robots.txt is fetched once, on its first request.
+    pub fn respect_robots(mut self, user_agent: &str) -> Self {
+        self.robots_user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Also appends every audit entry to a JSON Lines file as it is made, so the log survives
+    /// crashes. Compressed if the name ends with `.gz` or `.zst`.
+    pub fn with_audit_file(self, path: &str) -> std::io::Result<Self> {
+        let writer = ExportWriter::append(path)?;
+        self.state.lock().unwrap().audit_file = Some(writer);
+        Ok(self)
+    }
+
+    /// The blocklisted domain `url` belongs to, if any. A trailing dot of the host, as in
+    /// `blocked.com.`, is ignored.
+    pub fn blocked_domain(&self, url: &str) -> Option<&str> {
+        let host = crate::host_of(url).trim_end_matches('.').to_ascii_lowercase();
+        self.blocklist
+            .iter()
+            .find(|domain| host == **domain || host.ends_with(&format!(".{}", domain)))
+            .map(String::as_str)
+    }
+
+    /// Checks a URL before it is requested and records the decision, fetching the host's
+    /// robots.txt first if needed. Fails with `ScrapeError::Disallowed` if it is refused.
+    pub fn check(&self, url: &str, context: Option<&RequestContext>) -> Result<(), ScrapeError> {
+        if let Some(reason) = self.blocklist_reason(url) {
+            return self.decide(url, Some(reason), context);
+        }
+        if let Some((host, robots_url)) = self.unfetched_robots(url) {
+            let fetched = reqwest::blocking::Client::builder()
+                .timeout(ROBOTS_TIMEOUT)
+                .build()
+                .and_then(|client| client.get(&robots_url).header(reqwest::header::USER_AGENT, self.agent()).send())
+                .and_then(|response| {
+                    let status = response.status();
+                    response.text().map(|text| (status, text))
+                });
+            self.store_robots(&host, &robots_url, fetched);
+        }
+        let reason = self.robots_reason(url);
+        self.decide(url, reason, context)
+    }
+
+    /// Async variant of `check`.
+    pub async fn check_async(&self, url: &str, context: Option<&RequestContext>) -> Result<(), ScrapeError> {
+        if let Some(reason) = self.blocklist_reason(url) {
+            return self.decide(url, Some(reason), context);
+        }
+        if let Some((host, robots_url)) = self.unfetched_robots(url) {
+            let fetched = async {
+                let client = reqwest::Client::builder().timeout(ROBOTS_TIMEOUT).build()?;
+                let response = client.get(&robots_url).header(reqwest::header::USER_AGENT, self.agent()).send().await?;
+                let status = response.status();
+                Ok::<_, reqwest::Error>((status, response.text().await?))
+            }
+            .await;
+            self.store_robots(&host, &robots_url, fetched);
+        }
+        let reason = self.robots_reason(url);
+        self.decide(url, reason, context)
+    }
+
+    /// A redirect policy checking every hop with `check`, for the HTTP clients of a scrapper
+    /// with these guardrails. Refused hops fail the request with `ScrapeError::Disallowed`
+    /// as its source.
+    pub fn redirect_policy(&self, context: Option<RequestContext>) -> reqwest::redirect::Policy {
+        let compliance = self.clone();
+        reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error("too many redirects");
+            }
+            let url = attempt.url().to_string();
+            // The policy runs on the HTTP client's runtime, where fetching robots.txt with a
+            // blocking client would panic, so an unfetched robots.txt is fetched on its own thread.
+            let checked = if compliance.unfetched_robots(&url).is_some() {
+                std::thread::scope(|scope| scope.spawn(|| compliance.check(&url, context.as_ref())).join())
+                    .unwrap_or_else(|_| Err(ScrapeError::Disallowed {
+                        url: url.clone(),
+                        reason: "the compliance check failed".to_string(),
+                    }))
+            } else {
+                compliance.check(&url, context.as_ref())
+            };
+            match checked {
+                Ok(()) => attempt.follow(),
+                Err(e) => attempt.error(e),
+            }
+        })
+    }
+
+    /// The audit log so far.
+    pub fn audit_log(&self) -> Vec<AuditEntry> {
+        self.state.lock().unwrap().audit_log.clone()
+    }
+
+    /// Everything recorded so far.
+    pub fn report(&self) -> ComplianceReport {
+        let state = self.state.lock().unwrap();
+        ComplianceReport {
+            operator: self.operator.clone(),
+            generated_at: chrono::Utc::now().timestamp_millis(),
+            blocklist: self.blocklist.clone(),
+            robots_user_agent: self.robots_user_agent.clone(),
+            audit_log: state.audit_log.clone(),
+            robots: state
+                .robots
+                .iter()
+                .map(|(host, domain)| (host.clone(), domain.record.clone()))
+                .collect(),
+        }
+    }
+
+    /// Writes the report to a JSON file, compressed if its name ends with `.gz` or `.zst`.
+    pub fn export(&self, file_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = ExportWriter::create(file_name)?;
+        serde_json::to_writer_pretty(&mut file, &self.report())?;
+        file.finish()?;
+        Ok(())
+    }
+
+    fn agent(&self) -> &str {
+        self.robots_user_agent.as_deref().unwrap_or("*")
+    }
+
+    fn blocklist_reason(&self, url: &str) -> Option<String> {
+        self.blocked_domain(url).map(|domain| format!("domain {} is blocklisted", domain))
+    }
+
+    /// The host and robots.txt URL of `url`, if robots.txt is respected and not fetched yet.
+    fn unfetched_robots(&self, url: &str) -> Option<(String, String)> {
+        self.robots_user_agent.as_ref()?;
+        let parsed = url::Url::parse(url).ok()?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return None;
+        }
+        let host = parsed.host_str()?.to_ascii_lowercase();
+        if self.state.lock().unwrap().robots.contains_key(&host) {
+            return None;
+        }
+        let robots_url = format!("{}/robots.txt", parsed.origin().ascii_serialization());
+        Some((host, robots_url))
+    }
+
+    /// Stores the rules of a fetched robots.txt. Other requests may have fetched it meanwhile;
+    /// the first result is kept.
+    fn store_robots(&self, host: &str, robots_url: &str, fetched: Result<(reqwest::StatusCode, String), reqwest::Error>) {
+        let (rules, status) = match fetched {
+            Ok((status, text)) if status.is_success() => (Robots::parse(&text), "fetched".to_string()),
+            Ok((status, _)) if status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                (Robots::allow_all(), "not found".to_string())
+            }
+            Ok((status, _)) => (Robots::disallow_all(), format!("unavailable: HTTP {}", status.as_u16())),
+            Err(e) => (Robots::disallow_all(), format!("unavailable: {}", e)),
+        };
+        log::info!("robots.txt of {}: {}", host, status);
+        self.state.lock().unwrap().robots.entry(host.to_string()).or_insert_with(|| DomainState {
+            rules,
+            record: DomainRobots {
+                robots_url: robots_url.to_string(),
+                status,
+                fetched_at: chrono::Utc::now().timestamp_millis(),
+                decisions: Vec::new(),
+            },
+        });
+    }
+
+    /// Why robots.txt refuses `url`, recording the decision for its host.
+    fn robots_reason(&self, url: &str) -> Option<String> {
+        let agent = self.robots_user_agent.as_ref()?;
+        let parsed = url::Url::parse(url).ok()?;
+        let host = parsed.host_str()?.to_ascii_lowercase();
+        let path = match parsed.query() {
+            Some(query) => format!("{}?{}", parsed.path(), query),
+            None => parsed.path().to_string(),
+        };
+        let mut state = self.state.lock().unwrap();
+        let domain = state.robots.get_mut(&host)?;
+        let verdict = domain.rules.check(agent, &path);
+        domain.record.decisions.push(RobotsDecision {
+            at: chrono::Utc::now().timestamp_millis(),
+            url: url.to_string(),
+            allowed: verdict.allowed,
+            rule: verdict.rule.clone(),
+        });
+        if verdict.allowed {
+            return None;
+        }
+        Some(match verdict.rule {
+            Some(rule) => format!("robots.txt disallows it ({})", rule),
+            None => "robots.txt disallows it".to_string(),
+        })
+    }
+
+    /// Records the decision in the audit log and turns a refusal into an error.
+    fn decide(&self, url: &str, reason: Option<String>, context: Option<&RequestContext>) -> Result<(), ScrapeError> {
+        let entry = AuditEntry {
+            at: chrono::Utc::now().timestamp_millis(),
+            operator: self.operator.clone(),
+            url: url.to_string(),
+            allowed: reason.is_none(),
+            reason: reason.clone(),
+            correlation_id: context.map(|context| context.correlation_id.clone()),
+        };
+        let mut state = self.state.lock().unwrap();
+        if let Some(file) = &mut state.audit_file {
+            let written = serde_json::to_string(&entry)
+                .map_err(std::io::Error::from)
+                .and_then(|line| writeln!(file, "{}", line))
+                .and_then(|_| file.flush());
+            if let Err(e) = written {
+                log::error!("Failed to write the audit log: {}", e);
+            }
+        }
+        state.audit_log.push(entry);
+        match reason {
+            Some(reason) => {
+                log::warn!("Refusing to scrape {}: {}", url, reason);
+                Err(ScrapeError::Disallowed {
+                    url: url.to_string(),
+                    reason,
+                })
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_blocklisted_domains_and_subdomains() {
+        let compliance = Compliance::new("team").block_domain("*.Tracker.com.").block_domain("tracker.com");
+        assert_eq!(compliance.blocked_domain("https://ads.tracker.com/x"), Some("tracker.com"));
+        assert_eq!(compliance.blocked_domain("http://tracker.com./"), Some("tracker.com"));
+        assert_eq!(compliance.blocked_domain("https://nottracker.com/"), None);
+
+        let error = compliance.check("https://tracker.com/", None).unwrap_err();
+        assert_eq!(error.to_string(), "https://tracker.com/ may not be scraped: domain tracker.com is blocklisted");
+        compliance.check("https://shop.com/", None).unwrap();
+        let log = compliance.audit_log();
+        assert_eq!(log.iter().map(|entry| entry.allowed).collect::<Vec<_>>(), vec![false, true]);
+        assert_eq!(log[0].operator, "team");
+        assert_eq!(compliance.report().blocklist, vec!["tracker.com"]);
+    }
+
+    #[test]
+    fn reads_blocklist_files() {
+        let path = std::env::temp_dir().join(format!("rust-scrapper-blocklist-{}.txt", std::process::id()));
+        std::fs::write(&path, "# ad networks\nads.com\n\n  tracker.net # and subdomains\n").unwrap();
+        let compliance = Compliance::new("team").with_blocklist_file(&path.to_string_lossy()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(compliance.report().blocklist, vec!["ads.com", "tracker.net"]);
+    }
+
+    #[test]
+    fn enforces_robots_txt_fetched_once_per_host() {
+        // The server answers only the robots.txt request.
+        let origin = crate::tests::serve_once("text/plain", "User-agent: *\nDisallow: /private\n");
+        let compliance = Compliance::new("team").respect_robots("MyBot");
+        let error = compliance.check(&format!("{}private/a", origin), None).unwrap_err();
+        assert!(error.to_string().ends_with("robots.txt disallows it (Disallow: /private)"));
+        compliance.check(&format!("{}public", origin), None).unwrap();
+
+        let report = compliance.report();
+        let domain = &report.robots["127.0.0.1"];
+        assert_eq!(domain.status, "fetched");
+        assert_eq!(domain.decisions.len(), 2);
+        assert_eq!(report.robots_user_agent.as_deref(), Some("MyBot"));
+    }
+
+    #[test]
+    fn disallows_hosts_whose_robots_txt_is_unreachable() {
+        let compliance = Compliance::new("team").respect_robots("MyBot");
+        assert!(compliance.check("http://127.0.0.1:1/page", None).is_err());
+        assert!(compliance.report().robots["127.0.0.1"].status.starts_with("unavailable"));
+        compliance.check("data:text/html,<p>local</p>", None).unwrap();
+    }
+
+    #[test]
+    fn writes_the_audit_log_and_report() {
+        let dir = std::env::temp_dir();
+        let audit = dir.join(format!("rust-scrapper-audit-{}.jsonl", std::process::id()));
+        let report = dir.join(format!("rust-scrapper-compliance-{}.json", std::process::id()));
+        let compliance = Compliance::new("team").block_domain("ads.com").with_audit_file(&audit.to_string_lossy()).unwrap();
+        let context = RequestContext::new("run-1");
+        let _ = compliance.check("https://ads.com/", Some(&context));
+        compliance.export(&report.to_string_lossy()).unwrap();
+        let lines = std::fs::read_to_string(&audit).unwrap();
+        let exported: ComplianceReport = serde_json::from_str(&std::fs::read_to_string(&report).unwrap()).unwrap();
+        std::fs::remove_file(&audit).unwrap();
+        std::fs::remove_file(&report).unwrap();
+        let entry: AuditEntry = serde_json::from_str(lines.trim()).unwrap();
+        assert_eq!(entry.correlation_id.as_deref(), Some("run-1"));
+        assert!(!entry.allowed);
+        assert_eq!(exported.audit_log, vec![entry]);
+    }
+
+    #[test]
+    fn matches_internationalized_domains_in_punycode() {
+        let compliance = Compliance::new("tests").block_domain("bücher.example");
+        assert_eq!(compliance.blocked_domain("https://xn--bcher-kva.example/"), Some("xn--bcher-kva.example"));
+        assert_eq!(compliance.blocked_domain("https://bücher.example/"), Some("xn--bcher-kva.example"));
+    }
+}
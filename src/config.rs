@@ -0,0 +1,456 @@
+//! Declarative scraper configuration.
+//!
+//! A `ScrapeConfig` holds scraper settings and a list of jobs (URLs, selector or schema,
+//! pagination and export target). It can be loaded from TOML, YAML or JSON files, so scrape
+//! jobs can be defined without writing Rust. A `ScrapeJob` bundles one job with its settings
+//! for job queues. An example configuration:
+//!
+//! ```toml
+//! [scraper]
+//! user_agent = "my-bot/1.0"
+//! min_delay_ms = 500
+//!
+//! [[jobs]]
+//! name = "products"
+//! urls = ["https://example.com/products"]
+//! pagination = { param = "page", pages = 5 }
+//! export = { format = "json", path = "products.json" }
+//!
+//! [jobs.schema]
+//! root = ".product"
+//! fields = [
+//!     { name = "title", selector = "h2" },
+//!     { name = "link", selector = "a", attr = "href" },
+//! ]
+//! ```
+
+use crate::context::RequestContext;
+use crate::locale::Locale;
+use crate::pagination::with_query;
+use crate::proxy::Proxy;
+use crate::schema::Schema;
+use crate::shard::ShardedExport;
+use crate::Exporter;
+#[cfg(feature = "native")]
+use crate::{RustScrapper, Scraper};
+use serde_json::Value;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Settings applied to the scraper itself.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScraperSettings {
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// Headers sent with every request.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Enables adaptive rate limiting with this minimum delay between requests to a host.
+    #[serde(default)]
+    pub min_delay_ms: Option<u64>,
+    /// Upper bound of the adaptive delay (60 seconds by default).
+    #[serde(default)]
+    pub max_delay_ms: Option<u64>,
+    /// Imports cookies from a Netscape `cookies.txt` file or a browser extension's JSON export.
+    #[serde(default)]
+    pub cookies_file: Option<String>,
+    /// Expires cache entries after this many seconds.
+    #[serde(default)]
+    pub cache_ttl_secs: Option<u64>,
+    /// Serves pages from the cache only, failing with `ScrapeError::CacheMiss` instead of
+    /// fetching, see `RustScrapper::offline`.
+    #[serde(default)]
+    pub offline: bool,
+    /// Language pages are requested in, see `Locale`.
+    #[serde(default)]
+    pub locale: Option<Locale>,
+    /// Proxies requests go through in rotation, see `ProxyPool`.
+    #[serde(default)]
+    pub proxies: Vec<Proxy>,
+    /// Region requests are made from, through a proxy of `proxies` tagged with it.
+    #[serde(default)]
+    pub region: Option<String>,
+}
+
+/// Query-parameter pagination, as used by `scrape_paginated`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaginationConfig {
+    pub param: String,
+    pub pages: usize,
+}
+
+/// Output format of an export target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+/// Where and how the results of a job are written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportTarget {
+    pub format: ExportFormat,
+    /// With `shard_by`, a path template with `{key}` standing for the shard key.
+    pub path: String,
+    /// Writes one file per value of this record field, see `ShardedExport`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shard_by: Option<String>,
+}
+
+/// One declarative scrape job. Either `selector` or `schema` should be set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JobConfig {
+    #[serde(default)]
+    pub name: String,
+    pub urls: Vec<String>,
+    #[serde(default)]
+    pub selector: Option<String>,
+    #[serde(default)]
+    pub schema: Option<Schema>,
+    #[serde(default)]
+    pub pagination: Option<PaginationConfig>,
+    #[serde(default)]
+    pub export: Option<ExportTarget>,
+    /// Correlation ID and attributes the job's requests, errors and results are tagged with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<RequestContext>,
+}
+
+/// Scraper settings and jobs loaded from a configuration file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScrapeConfig {
+    #[serde(default)]
+    pub scraper: ScraperSettings,
+    #[serde(default)]
+    pub jobs: Vec<JobConfig>,
+}
+
+/// A self-contained scrape job: one job with the scraper settings it runs with.
+/// Jobs serialize to JSON, so one service can define them and workers can run them from a queue.
+///
+/// ```json
+/// {
+///   "scraper": { "user_agent": "my-bot/1.0", "min_delay_ms": 500 },
+///   "name": "products",
+///   "urls": ["https://example.com/products"],
+///   "selector": ".product h2",
+///   "export": { "format": "json", "path": "products.json" }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScrapeJob {
+    #[serde(default)]
+    pub scraper: ScraperSettings,
+    #[serde(flatten)]
+    pub job: JobConfig,
+}
+
+impl ScrapeJob {
+    /// Creates a job running with default scraper settings.
+    pub fn new(job: JobConfig) -> Self {
+        ScrapeJob {
+            scraper: ScraperSettings::default(),
+            job,
+        }
+    }
+
+    /// Runs the job with these scraper settings.
+    pub fn with_settings(mut self, scraper: ScraperSettings) -> Self {
+        self.scraper = scraper;
+        self
+    }
+
+    /// Serializes the job to JSON.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    /// Parses a job serialized with `to_json`.
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Writes the job to a JSON file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Reads a job from a JSON file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Builds a scraper from the job's settings, runs the job and writes its export target.
+    #[cfg(feature = "native")]
+    pub fn execute(&self) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+        let config = ScrapeConfig {
+            scraper: self.scraper.clone(),
+            jobs: Vec::new(),
+        };
+        let mut scrapper = RustScrapper::from_config(&config)?;
+        scrapper.set_context(self.job.context.clone());
+        let results = self.job.run(&mut scrapper)?;
+        self.job.export(&results)?;
+        Ok(results)
+    }
+}
+
+impl ScrapeConfig {
+    /// Splits the configuration into self-contained jobs sharing its scraper settings.
+    pub fn scrape_jobs(&self) -> Vec<ScrapeJob> {
+        self.jobs
+            .iter()
+            .map(|job| ScrapeJob::new(job.clone()).with_settings(self.scraper.clone()))
+            .collect()
+    }
+
+    /// Loads a configuration file. The format is chosen by extension:
+    /// `.toml` (requires the `toml` feature), `.yaml`/`.yml` (requires the `yaml` feature) or `.json`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        match extension.as_str() {
+            #[cfg(feature = "toml")]
+            "toml" => Ok(toml::from_str(&contents)?),
+            #[cfg(feature = "yaml")]
+            "yaml" | "yml" => Ok(serde_yaml::from_str(&contents)?),
+            "json" => Ok(serde_json::from_str(&contents)?),
+            other => Err(format!("Unsupported config format: {:?}", other).into()),
+        }
+    }
+}
+
+impl JobConfig {
+    /// Lists the URLs the job fetches, expanding pagination. The page parameter is added to
+    /// the URL's query, replacing a page parameter it already has.
+    pub fn expanded_urls(&self) -> Vec<String> {
+        match &self.pagination {
+            Some(pagination) => self
+                .urls
+                .iter()
+                .flat_map(|url| (1..=pagination.pages).map(move |page| with_query(url, &pagination.param, &page.to_string())))
+                .collect(),
+            None => self.urls.clone(),
+        }
+    }
+
+    /// Runs the job. Selector jobs yield JSON strings, schema jobs one object per record.
+    #[cfg(feature = "native")]
+    pub fn run(&self, scrapper: &mut RustScrapper) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+        let mut results = Vec::new();
+        for url in self.expanded_urls() {
+            match (&self.schema, &self.selector) {
+                (Some(schema), _) => results.extend(scrapper.scrape_structured(&url, schema)?),
+                (None, Some(selector)) => results.extend(scrapper.scrape(&url, selector)?.into_iter().map(Value::String)),
+                (None, None) => return Err(format!("Job {:?} has neither a selector nor a schema", self.name).into()),
+            }
+        }
+        Ok(results)
+    }
+
+    /// Runs the job asynchronously, see `run`.
+    #[cfg(feature = "native")]
+    pub async fn run_async(&self, scrapper: &mut RustScrapper) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+        let mut results = Vec::new();
+        for url in self.expanded_urls() {
+            match (&self.schema, &self.selector) {
+                (Some(schema), _) => results.extend(scrapper.scrape_structured_async(&url, schema).await?),
+                (None, Some(selector)) => results.extend(scrapper.scrape_async(&url, selector).await?.into_iter().map(Value::String)),
+                (None, None) => return Err(format!("Job {:?} has neither a selector nor a schema", self.name).into()),
+            }
+        }
+        Ok(results)
+    }
+
+    /// Writes results to the job's export target, if one is configured.
+    pub fn export(&self, results: &[Value]) -> Result<(), Box<dyn std::error::Error>> {
+        let target = match &self.export {
+            Some(target) => target,
+            None => return Ok(()),
+        };
+        if let Some(field) = &target.shard_by {
+            let export = ShardedExport::by_field(&target.path, field);
+            match target.format {
+                ExportFormat::Json => export.write_json(results)?,
+                ExportFormat::Csv => export.write_csv(results)?,
+            };
+            return Ok(());
+        }
+        match target.format {
+            ExportFormat::Json => Exporter::records_to_json_file(results, &target.path)?,
+            ExportFormat::Csv => Exporter::records_to_csv(results, &target.path)?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(name: &str, contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("rust-scrapper-config-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn loads_json_configs() {
+        let path = write_config(
+            "jobs.json",
+            r#"{"scraper": {"min_delay_ms": 500}, "jobs": [{"urls": ["https://a.com"], "selector": "h1"}]}"#,
+        );
+        let config = ScrapeConfig::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(config.scraper.min_delay_ms, Some(500));
+        assert_eq!(config.jobs.len(), 1);
+        assert_eq!(config.jobs[0].selector.as_deref(), Some("h1"));
+        assert!(config.jobs[0].export.is_none());
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn loads_toml_configs() {
+        let path = write_config(
+            "jobs.toml",
+            r#"
+            [scraper]
+            user_agent = "my-bot/1.0"
+
+            [[jobs]]
+            name = "products"
+            urls = ["https://example.com/products"]
+            pagination = { param = "page", pages = 5 }
+            export = { format = "csv", path = "products.csv" }
+
+            [jobs.schema]
+            root = ".product"
+            fields = [{ name = "link", selector = "a", attr = "href" }]
+            "#,
+        );
+        let config = ScrapeConfig::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(config.scraper.user_agent.as_deref(), Some("my-bot/1.0"));
+        let job = &config.jobs[0];
+        assert_eq!(job.pagination.as_ref().map(|p| p.pages), Some(5));
+        assert_eq!(job.export.as_ref().map(|e| e.format), Some(ExportFormat::Csv));
+        assert_eq!(job.schema.as_ref().unwrap().fields[0].attr.as_deref(), Some("href"));
+    }
+
+    #[test]
+    fn rejects_unknown_formats() {
+        let path = write_config("jobs.ini", "");
+        let error = ScrapeConfig::from_file(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(error.to_string().contains("Unsupported config format"));
+    }
+
+    #[test]
+    fn expands_paginated_urls() {
+        let job = JobConfig {
+            urls: vec!["https://a.com/list".to_string()],
+            pagination: Some(PaginationConfig { param: "page".to_string(), pages: 2 }),
+            ..JobConfig::default()
+        };
+        assert_eq!(job.expanded_urls(), vec!["https://a.com/list?page=1", "https://a.com/list?page=2"]);
+
+        let job = JobConfig {
+            urls: vec!["https://a.com/list?sort=new&page=5".to_string()],
+            ..job
+        };
+        assert_eq!(job.expanded_urls(), vec!["https://a.com/list?sort=new&page=1", "https://a.com/list?sort=new&page=2"]);
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn rejects_jobs_without_selector_or_schema() {
+        let job = JobConfig {
+            name: "empty".to_string(),
+            urls: vec!["https://a.com".to_string()],
+            ..JobConfig::default()
+        };
+        let error = job.run(&mut RustScrapper::new()).unwrap_err();
+        assert!(error.to_string().contains("neither a selector nor a schema"));
+    }
+
+    #[test]
+    fn exports_results_to_the_job_target() {
+        let path = std::env::temp_dir().join(format!("rust-scrapper-config-{}-export.json", std::process::id()));
+        let job = JobConfig {
+            export: Some(ExportTarget { format: ExportFormat::Json, path: path.to_string_lossy().into_owned(), shard_by: None }),
+            ..JobConfig::default()
+        };
+        job.export(&[Value::String("a".to_string())]).unwrap();
+        let written: Vec<Value> = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, vec![Value::String("a".to_string())]);
+    }
+
+    #[test]
+    fn round_trips_scrape_jobs_through_json() {
+        let job = ScrapeJob::from_json(
+            r#"{"scraper": {"user_agent": "my-bot/1.0"}, "name": "products", "urls": ["https://a.com"], "selector": "h2"}"#,
+        )
+        .unwrap();
+        assert_eq!(job.scraper.user_agent.as_deref(), Some("my-bot/1.0"));
+        assert_eq!(job.job.name, "products");
+        assert_eq!(job.job.selector.as_deref(), Some("h2"));
+
+        let path = write_config("job.json", "");
+        job.save(&path).unwrap();
+        let loaded = ScrapeJob::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.to_json().unwrap(), job.to_json().unwrap());
+    }
+
+    #[test]
+    fn splits_configs_into_scrape_jobs() {
+        let config = ScrapeConfig {
+            scraper: ScraperSettings {
+                min_delay_ms: Some(250),
+                ..ScraperSettings::default()
+            },
+            jobs: vec![
+                JobConfig {
+                    name: "a".to_string(),
+                    ..JobConfig::default()
+                },
+                JobConfig {
+                    name: "b".to_string(),
+                    ..JobConfig::default()
+                },
+            ],
+        };
+        let jobs = config.scrape_jobs();
+        assert_eq!(jobs.iter().map(|job| job.job.name.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+        assert!(jobs.iter().all(|job| job.scraper.min_delay_ms == Some(250)));
+    }
+
+    #[test]
+    fn shards_exports_by_a_record_field() {
+        let dir = std::env::temp_dir().join(format!("rust-scrapper-config-{}-shards", std::process::id()));
+        let job = JobConfig {
+            export: Some(ExportTarget {
+                format: ExportFormat::Json,
+                path: dir.join("{key}.json").to_string_lossy().into_owned(),
+                shard_by: Some("site".to_string()),
+            }),
+            ..JobConfig::default()
+        };
+        job.export(&[serde_json::json!({"site": "a"}), serde_json::json!({"site": "b"})]).unwrap();
+        let a: Vec<Value> = serde_json::from_str(&std::fs::read_to_string(dir.join("a.json")).unwrap()).unwrap();
+        let b_exists = dir.join("b.json").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(a, vec![serde_json::json!({"site": "a"})]);
+        assert!(b_exists);
+    }
+}
@@ -0,0 +1,106 @@
+//! Request contexts for tracing scrapes back to their origin.
+//!
+//! A `RequestContext` carries an opaque correlation ID, e.g. the ID of the order or product
+//! a scrape was requested for. Set on a scrapper, it tags log lines, errors (as a
+//! `ContextError`), crawl events, snapshots and worker results, so every outcome can be
+//! traced back to the upstream entity that requested it.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+/// A correlation ID with optional attributes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestContext {
+    pub correlation_id: String,
+    /// Free-form attributes, e.g. the tenant or the requesting service.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl RequestContext {
+    pub fn new(correlation_id: &str) -> Self {
+        RequestContext {
+            correlation_id: correlation_id.to_string(),
+            attributes: BTreeMap::new(),
+        }
+    }
+
+    /// Adds an attribute.
+    pub fn with_attribute(mut self, name: &str, value: &str) -> Self {
+        self.attributes.insert(name.to_string(), value.to_string());
+        self
+    }
+}
+
+impl fmt::Display for RequestContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.correlation_id)
+    }
+}
+
+/// An error of a request made in a context. Displays as `[<correlation ID>] <error>`;
+/// the original error is its `source`.
+#[derive(Debug)]
+pub struct ContextError {
+    pub context: RequestContext,
+    pub error: Box<dyn Error>,
+}
+
+impl fmt::Display for ContextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.context, self.error)
+    }
+}
+
+impl Error for ContextError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
+
+/// The context of the request that caused an error, if it was made in one.
+pub fn context_of<'a>(error: &'a (dyn Error + 'static)) -> Option<&'a RequestContext> {
+    find_cause::<ContextError>(error).map(|e| &e.context)
+}
+
+/// The first error of type `T` in the chain of `error` and its sources.
+pub(crate) fn find_cause<'a, T: Error + 'static>(error: &'a (dyn Error + 'static)) -> Option<&'a T> {
+    let mut current = Some(error);
+    while let Some(error) = current {
+        if let Some(cause) = error.downcast_ref::<T>() {
+            return Some(cause);
+        }
+        current = error.source();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_context_in_an_error_chain() {
+        let context = RequestContext::new("order-42").with_attribute("tenant", "acme");
+        let error: Box<dyn Error> = Box::new(ContextError {
+            context: context.clone(),
+            error: "connection refused".into(),
+        });
+        assert_eq!(error.to_string(), "[order-42] connection refused");
+        assert_eq!(context_of(error.as_ref()), Some(&context));
+        assert_eq!(error.source().unwrap().to_string(), "connection refused");
+
+        let plain: Box<dyn Error> = "timeout".into();
+        assert!(context_of(plain.as_ref()).is_none());
+    }
+
+    #[test]
+    fn serializes_attributes_only_when_present() {
+        let json = serde_json::to_string(&RequestContext::new("order-42")).unwrap();
+        assert_eq!(json, r#"{"correlation_id":"order-42"}"#);
+        let context: RequestContext = serde_json::from_str(r#"{"correlation_id":"a","attributes":{"tenant":"acme"}}"#).unwrap();
+        assert_eq!(context.attributes["tenant"], "acme");
+    }
+}
@@ -0,0 +1,331 @@
+//! Cookie store with browser import.
+//!
+//! A `CookieJar` holds the cookies sent with every request of a scraper and records the
+//! cookies servers set in responses. Cookies can be imported from a Netscape `cookies.txt`
+//! file or from the JSON export of browser extensions, so a session established manually in
+//! a browser can be reused by the scraper.
+
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+/// A single cookie.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// Host the cookie belongs to, without a leading dot.
+    pub domain: String,
+    /// Also sends the cookie to subdomains of `domain`.
+    pub include_subdomains: bool,
+    pub path: String,
+    /// Only sends the cookie over HTTPS.
+    pub secure: bool,
+    /// Expiry as a Unix timestamp; `None` for session cookies.
+    pub expires: Option<i64>,
+}
+
+impl Cookie {
+    fn matches(&self, url: &Url, now: i64) -> bool {
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        let domain_matches = host == self.domain
+            || (self.include_subdomains && host.ends_with(&format!(".{}", self.domain)));
+        let path = url.path();
+        let path_matches = path == self.path
+            || (path.starts_with(&self.path) && (self.path.ends_with('/') || path[self.path.len()..].starts_with('/')));
+        domain_matches
+            && path_matches
+            && (!self.secure || url.scheme() == "https")
+            && self.expires.is_none_or(|expires| expires > now)
+    }
+}
+
+/// Cookie as exported by browser extensions such as Cookie-Editor or EditThisCookie.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonCookie {
+    name: String,
+    value: String,
+    domain: String,
+    #[serde(default)]
+    path: Option<String>,
+    #[serde(default)]
+    secure: bool,
+    #[serde(default)]
+    host_only: Option<bool>,
+    #[serde(default, alias = "expires")]
+    expiration_date: Option<f64>,
+}
+
+/// Shared cookie store.
+/// Cloning returns a handle to the same jar, so several scrapers can share a session.
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar {
+    cookies: Arc<Mutex<Vec<Cookie>>>,
+}
+
+impl CookieJar {
+    /// Creates an empty jar.
+    pub fn new() -> Self {
+        CookieJar::default()
+    }
+
+    /// Adds a cookie, replacing one with the same name, domain and path.
+    pub fn add(&self, mut cookie: Cookie) {
+        cookie.domain = cookie.domain.trim_start_matches('.').to_ascii_lowercase();
+        if cookie.path.is_empty() {
+            cookie.path = "/".to_string();
+        }
+        let mut cookies = self.cookies.lock().unwrap();
+        cookies.retain(|c| !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path));
+        cookies.push(cookie);
+    }
+
+    /// Returns a copy of all cookies in the jar.
+    pub fn cookies(&self) -> Vec<Cookie> {
+        self.cookies.lock().unwrap().clone()
+    }
+
+    /// Removes all cookies.
+    pub fn clear(&self) {
+        self.cookies.lock().unwrap().clear();
+    }
+
+    /// Imports a cookie file, detecting whether it is a JSON export or a Netscape `cookies.txt`.
+    /// Returns the number of imported cookies.
+    pub fn import_file(&self, path: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        let contents = std::fs::read_to_string(path)?;
+        if contents.trim_start().starts_with('[') {
+            self.import_json(&contents)
+        } else {
+            Ok(self.import_netscape(&contents))
+        }
+    }
+
+    /// Imports cookies in the Netscape `cookies.txt` format used by curl and browser extensions.
+    /// Malformed lines are skipped. Returns the number of imported cookies.
+    pub fn import_netscape(&self, contents: &str) -> usize {
+        let mut imported = 0;
+        for line in contents.lines() {
+            // curl marks HttpOnly cookies with this prefix instead of commenting them out.
+            let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = line.split('\t').collect::<Vec<_>>();
+            if fields.len() < 7 {
+                continue;
+            }
+            let expires = fields[4].trim().parse::<i64>().unwrap_or(0);
+            self.add(Cookie {
+                name: fields[5].to_string(),
+                value: fields[6].trim_end_matches(['\r', '\n']).to_string(),
+                domain: fields[0].to_string(),
+                include_subdomains: fields[1].eq_ignore_ascii_case("TRUE"),
+                path: fields[2].to_string(),
+                secure: fields[3].eq_ignore_ascii_case("TRUE"),
+                expires: (expires > 0).then_some(expires),
+            });
+            imported += 1;
+        }
+        imported
+    }
+
+    /// Imports a JSON array of cookies as exported by browser extensions.
+    /// Returns the number of imported cookies.
+    pub fn import_json(&self, contents: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        let cookies: Vec<JsonCookie> = serde_json::from_str(contents)?;
+        let imported = cookies.len();
+        for cookie in cookies {
+            self.add(Cookie {
+                include_subdomains: !cookie.host_only.unwrap_or(!cookie.domain.starts_with('.')),
+                name: cookie.name,
+                value: cookie.value,
+                domain: cookie.domain,
+                path: cookie.path.unwrap_or_default(),
+                secure: cookie.secure,
+                expires: cookie.expiration_date.map(|expires| expires as i64),
+            });
+        }
+        Ok(imported)
+    }
+
+    /// Builds the `Cookie` header for a request to the URL, or `None` if no cookie applies.
+    pub fn header_for(&self, url: &str) -> Option<String> {
+        let url = Url::parse(url).ok()?;
+        let now = chrono::Utc::now().timestamp();
+        let mut cookies = self.cookies.lock().unwrap();
+        cookies.retain(|c| c.expires.is_none_or(|expires| expires > now));
+        let header = cookies
+            .iter()
+            .filter(|c| c.matches(&url, now))
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        (!header.is_empty()).then_some(header)
+    }
+
+    /// Stores a cookie from a `Set-Cookie` response header of a request to the URL. Cookies
+    /// whose `Domain` attribute the URL's host does not belong to are ignored, so a site
+    /// cannot set cookies for another (RFC 6265, section 5.3).
+    pub fn store_set_cookie(&self, url: &str, set_cookie: &str) {
+        let Ok(url) = Url::parse(url) else {
+            return;
+        };
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        let mut parts = set_cookie.split(';');
+        let Some((name, value)) = parts.next().and_then(|pair| pair.split_once('=')) else {
+            return;
+        };
+        let mut cookie = Cookie {
+            name: name.trim().to_string(),
+            value: value.trim().to_string(),
+            domain: host.clone(),
+            include_subdomains: false,
+            path: default_path(url.path()),
+            secure: false,
+            expires: None,
+        };
+        let mut max_age = None;
+        for attribute in parts {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "domain" if !value.trim_start_matches('.').is_empty() => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    if !domain_matches(&host, &domain) || (is_public_suffix(&domain) && domain != host) {
+                        log::warn!("Ignoring cookie {} of {} set for domain {}", cookie.name, host, domain);
+                        return;
+                    }
+                    // A public suffix only ever names the host itself.
+                    cookie.include_subdomains = !is_public_suffix(&domain);
+                    cookie.domain = domain;
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                "secure" => cookie.secure = true,
+                "max-age" => max_age = value.parse::<i64>().ok(),
+                "expires" => {
+                    cookie.expires = chrono::DateTime::parse_from_rfc2822(value)
+                        .ok()
+                        .map(|date| date.timestamp())
+                }
+                _ => {}
+            }
+        }
+        // Max-Age takes precedence over Expires.
+        if let Some(max_age) = max_age {
+            cookie.expires = Some(chrono::Utc::now().timestamp() + max_age);
+        }
+        self.add(cookie);
+    }
+}
+
+/// Whether `host` domain-matches `domain`: equals it or is a subdomain of it. IP addresses
+/// only match themselves.
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain || (host.ends_with(&format!(".{}", domain)) && host.parse::<std::net::IpAddr>().is_err())
+}
+
+/// Suffixes under which anyone can register a domain, beyond single labels like `com`.
+const PUBLIC_SUFFIXES: &[&str] = &[
+    "co.uk", "org.uk", "ac.uk", "gov.uk", "me.uk", "com.au", "net.au", "org.au", "co.nz", "co.jp", "ne.jp", "or.jp", "co.kr",
+    "com.br", "com.cn", "com.mx", "com.tr", "com.ar", "co.in", "co.za", "com.sg", "com.hk", "com.tw", "github.io", "herokuapp.com",
+];
+
+/// Whether cookies must not be shared across `domain` because it is a top-level domain or a
+/// well-known public suffix.
+fn is_public_suffix(domain: &str) -> bool {
+    !domain.contains('.') || PUBLIC_SUFFIXES.contains(&domain)
+}
+
+/// Default cookie path of a request path: its directory.
+fn default_path(path: &str) -> String {
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(end) => path[..end].to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imports_netscape_cookie_files() {
+        let jar = CookieJar::new();
+        let file = "# Netscape HTTP Cookie File\n\
+            .example.com\tTRUE\t/\tFALSE\t0\tsession\tabc\n\
+            #HttpOnly_shop.example.com\tFALSE\t/cart\tTRUE\t4102444800\tcart\t42\n\
+            broken line\n";
+        assert_eq!(jar.import_netscape(file), 2);
+        assert_eq!(jar.header_for("http://www.example.com/"), Some("session=abc".to_string()));
+        assert_eq!(jar.header_for("https://shop.example.com/cart/items"), Some("session=abc; cart=42".to_string()));
+        assert_eq!(jar.header_for("http://shop.example.com/cart"), Some("session=abc".to_string()));
+        assert_eq!(jar.header_for("https://shop.example.com/cartel"), Some("session=abc".to_string()));
+        assert_eq!(jar.header_for("https://example.org/"), None);
+    }
+
+    #[test]
+    fn imports_browser_json_exports() {
+        let jar = CookieJar::new();
+        let json = r#"[
+            {"name": "a", "value": "1", "domain": ".example.com", "path": "/"},
+            {"name": "b", "value": "2", "domain": "example.com", "hostOnly": true, "expirationDate": 1.0}
+        ]"#;
+        assert_eq!(jar.import_json(json).unwrap(), 2);
+        assert_eq!(jar.header_for("https://sub.example.com/"), Some("a=1".to_string()));
+        assert_eq!(jar.cookies().len(), 1);
+        assert!(jar.import_json("{}").is_err());
+    }
+
+    #[test]
+    fn stores_set_cookie_headers() {
+        let jar = CookieJar::new();
+        jar.store_set_cookie("https://example.com/account/login", "id=7; Path=/account; Secure; Max-Age=60");
+        jar.store_set_cookie("https://example.com/account/login", "theme=dark; Domain=.example.com");
+        jar.store_set_cookie("https://example.com/", "gone=1; Max-Age=0");
+        jar.store_set_cookie("https://example.com/", "invalid");
+
+        assert_eq!(jar.header_for("https://example.com/account/settings"), Some("id=7; theme=dark".to_string()));
+        assert_eq!(jar.header_for("http://example.com/account"), Some("theme=dark".to_string()));
+        assert_eq!(jar.header_for("https://www.example.com/account/x"), Some("theme=dark".to_string()));
+
+        jar.store_set_cookie("https://example.com/account/login", "id=8; Path=/account");
+        assert_eq!(jar.cookies().iter().filter(|c| c.name == "id").count(), 1);
+        jar.clear();
+        assert!(jar.cookies().is_empty());
+    }
+
+    #[test]
+    fn accepts_cookies_for_the_host_and_its_parent_domains() {
+        let jar = CookieJar::new();
+        jar.store_set_cookie("https://shop.example.com/cart", "a=1; Domain=.example.com; Path=/");
+        jar.store_set_cookie("https://shop.example.com/cart", "b=2; Domain=SHOP.example.com; Path=/");
+        assert_eq!(jar.header_for("https://www.example.com/").as_deref(), Some("a=1"));
+        assert_eq!(jar.header_for("https://shop.example.com/").as_deref(), Some("a=1; b=2"));
+    }
+
+    #[test]
+    fn ignores_cookies_for_other_domains() {
+        let jar = CookieJar::new();
+        jar.store_set_cookie("https://evil.com/", "session=stolen; Domain=victim.com; Path=/");
+        jar.store_set_cookie("https://notexample.com/", "c=3; Domain=example.com; Path=/");
+        jar.store_set_cookie("http://10.0.0.1/", "d=4; Domain=0.0.1; Path=/");
+        assert_eq!(jar.header_for("https://victim.com/"), None);
+        assert_eq!(jar.header_for("https://example.com/"), None);
+        assert!(jar.cookies().is_empty());
+    }
+
+    #[test]
+    fn ignores_cookies_for_public_suffixes() {
+        let jar = CookieJar::new();
+        jar.store_set_cookie("https://shop.example.com/", "a=1; Domain=com; Path=/");
+        jar.store_set_cookie("https://shop.example.co.uk/", "b=2; Domain=.co.uk; Path=/");
+        jar.store_set_cookie("http://localhost/", "c=3; Domain=localhost; Path=/");
+        assert_eq!(jar.header_for("https://other.com/"), None);
+        assert_eq!(jar.header_for("https://other.co.uk/"), None);
+        assert_eq!(jar.header_for("http://localhost/").as_deref(), Some("c=3"));
+        assert_eq!(jar.cookies().len(), 1);
+        assert!(!jar.cookies()[0].include_subdomains);
+    }
+}
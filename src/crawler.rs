@@ -0,0 +1,902 @@
+//! Multi-page crawling.
+//!
+//! A `Crawler` starts from seed URLs, extracts items from every page it visits and
+//! optionally follows links it discovers. Pending URLs wait in a `RequestQueue` and are
+//! fetched highest priority first. The queue and visited set can be moved to a shared
+//! `Frontier` so several crawlers cooperate on one crawl. Long crawls can save checkpoints
+//! to resume after a crash.
+
+use crate::budget::RequestBudget;
+use crate::checkpoint::{CheckpointState, Checkpointer, JsonlSink};
+use crate::context::{find_cause, RequestContext};
+use crate::events::{CrawlEvent, EventBus, EventSubscriber};
+use crate::frontier::{Frontier, MemoryFrontier};
+use crate::memory::MemoryBudget;
+use crate::report::ScrapeReport;
+use crate::robots::{self, RobotsDirectives};
+use crate::soft_failure::SoftFailure;
+use crate::RustScrapper;
+use log::{error, info};
+use scraper::{Html, Selector};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::broadcast;
+use tokio::time::sleep;
+use url::Url;
+
+#[derive(PartialEq, Eq)]
+struct QueuedRequest {
+    priority: i32,
+    seq: u64,
+    url: String,
+}
+
+impl Ord for QueuedRequest {
+    /// Higher priority first; equal priorities are served in insertion order.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for QueuedRequest {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    heap: BinaryHeap<QueuedRequest>,
+    /// Current priority of every queued URL. Heap entries that disagree with it are stale.
+    priorities: HashMap<String, (i32, u64)>,
+    next_seq: u64,
+}
+
+/// Priority queue of URLs waiting to be fetched.
+/// Cloning the queue returns a handle to the same queue, so URLs can be added or
+/// re-prioritized from other tasks while a crawl is running.
+#[derive(Clone, Default)]
+pub struct RequestQueue {
+    state: Arc<Mutex<QueueState>>,
+}
+
+impl RequestQueue {
+    /// Creates an empty queue.
+    pub fn new() -> Self {
+        RequestQueue::default()
+    }
+
+    /// Adds a URL with the given priority. Higher priorities are fetched first.
+    /// If the URL is already queued, its priority is raised when the new one is higher.
+    pub fn enqueue(&self, url: &str, priority: i32) {
+        let mut state = self.state.lock().unwrap();
+        if let Some((current, _)) = state.priorities.get(url) {
+            if *current >= priority {
+                return;
+            }
+        }
+        push(&mut state, url, priority);
+    }
+
+    /// Changes the priority of a queued URL. Returns false if the URL is not queued.
+    pub fn reprioritize(&self, url: &str, priority: i32) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if !state.priorities.contains_key(url) {
+            return false;
+        }
+        push(&mut state, url, priority);
+        true
+    }
+
+    /// Removes and returns the URL with the highest priority.
+    pub fn pop(&self) -> Option<(String, i32)> {
+        let mut state = self.state.lock().unwrap();
+        while let Some(request) = state.heap.pop() {
+            if state.priorities.get(&request.url) == Some(&(request.priority, request.seq)) {
+                state.priorities.remove(&request.url);
+                return Some((request.url, request.priority));
+            }
+        }
+        None
+    }
+
+    /// Number of queued URLs.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().priorities.len()
+    }
+
+    /// Returns true if no URLs are queued.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes up to `count` of the lowest-priority URLs and returns them.
+    pub(crate) fn drain_lowest(&self, count: usize) -> Vec<(String, i32)> {
+        let mut state = self.state.lock().unwrap();
+        let mut queued = state
+            .priorities
+            .iter()
+            .map(|(url, (priority, seq))| (*priority, *seq, url.clone()))
+            .collect::<Vec<_>>();
+        // Lowest priority first, and among equal priorities the most recently queued.
+        queued.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| b.1.cmp(&a.1)));
+        queued.truncate(count);
+        for (_, _, url) in &queued {
+            state.priorities.remove(url);
+        }
+        let priorities = std::mem::take(&mut state.priorities);
+        state.heap.retain(|request| priorities.get(&request.url) == Some(&(request.priority, request.seq)));
+        state.priorities = priorities;
+        queued.into_iter().map(|(priority, _, url)| (url, priority)).collect()
+    }
+
+    /// Lists the queued URLs with their priorities, in insertion order.
+    pub fn snapshot(&self) -> Vec<(String, i32)> {
+        let state = self.state.lock().unwrap();
+        let mut queued = state.priorities.iter().collect::<Vec<_>>();
+        queued.sort_by_key(|(_, (_, seq))| *seq);
+        queued
+            .into_iter()
+            .map(|(url, (priority, _))| (url.clone(), *priority))
+            .collect()
+    }
+}
+
+fn push(state: &mut QueueState, url: &str, priority: i32) {
+    let seq = state.next_seq;
+    state.next_seq += 1;
+    state.priorities.insert(url.to_string(), (priority, seq));
+    state.heap.push(QueuedRequest {
+        priority,
+        seq,
+        url: url.to_string(),
+    });
+}
+
+/// Items extracted from one crawled page.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CrawledPage {
+    pub url: String,
+    pub items: Vec<String>,
+    /// The page's meta robots and `X-Robots-Tag` directives. Header directives of cached pages
+    /// are those cached with the body, so bodies cached outside a crawl have none.
+    pub robots: RobotsDirectives,
+}
+
+/// One failed attempt at fetching or processing a URL.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FailedAttempt {
+    pub attempt: u32,
+    pub error: String,
+    pub at: SystemTime,
+}
+
+/// A URL that failed permanently, after all retries were used up.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FailedUrl {
+    pub url: String,
+    pub priority: i32,
+    /// The error of the last attempt.
+    pub error: String,
+    pub attempts: Vec<FailedAttempt>,
+}
+
+/// Assigns a priority to a discovered link.
+pub type PriorityFn = Box<dyn Fn(&str) -> i32 + Send + Sync>;
+
+/// Crawls pages starting from seed URLs, extracting items with a CSS selector.
+pub struct Crawler {
+    scrapper: RustScrapper,
+    item_selector: String,
+    link_selector: Option<String>,
+    priority_fn: PriorityFn,
+    queue: RequestQueue,
+    frontier: Arc<dyn Frontier>,
+    fetched: usize,
+    max_pages: usize,
+    delay: Duration,
+    same_host: bool,
+    max_retries: u32,
+    retry_delay: Duration,
+    failures: Vec<FailedUrl>,
+    events: EventBus,
+    checkpointer: Option<Checkpointer>,
+    sink: Option<JsonlSink>,
+    max_items: Option<usize>,
+    spill: Option<JsonlSink>,
+    budget: RequestBudget,
+    respect_robots_directives: bool,
+    report: ScrapeReport,
+}
+
+impl Crawler {
+    /// Creates a crawler extracting items matching `item_selector` from every page.
+    /// Requests go through the given scrapper, so its headers and cache are used.
+    pub fn new(mut scrapper: RustScrapper, item_selector: &str) -> Self {
+        let queue = RequestQueue::new();
+        scrapper.capture_robots_tags = true;
+        Crawler {
+            scrapper,
+            item_selector: item_selector.to_string(),
+            link_selector: None,
+            priority_fn: Box::new(|_| 0),
+            frontier: Arc::new(MemoryFrontier::with_queue(queue.clone())),
+            queue,
+            fetched: 0,
+            max_pages: 100,
+            delay: Duration::from_secs(0),
+            same_host: true,
+            max_retries: 2,
+            retry_delay: Duration::from_secs(1),
+            failures: Vec::new(),
+            events: EventBus::default(),
+            checkpointer: None,
+            sink: None,
+            max_items: None,
+            spill: None,
+            budget: RequestBudget::new(),
+            respect_robots_directives: false,
+            report: ScrapeReport::default(),
+        }
+    }
+
+    /// Adds a start URL with the given priority.
+    pub fn seed(self, url: &str, priority: i32) -> Self {
+        self.frontier.enqueue(url, priority);
+        self
+    }
+
+    /// Keeps pending and visited URLs in a shared frontier, e.g. a `RedisFrontier`, so several
+    /// crawlers can work on one crawl without fetching a URL twice. Call before `seed`.
+    pub fn with_frontier(mut self, frontier: Arc<dyn Frontier>) -> Self {
+        self.frontier = frontier;
+        self
+    }
+
+    /// Tags the crawl's requests, log lines, errors and events with a correlation ID.
+    pub fn with_context(mut self, context: RequestContext) -> Self {
+        self.scrapper.set_context(Some(context));
+        self
+    }
+
+    /// Follows the `href` of every element matching the selector, e.g. `a[href]`.
+    pub fn follow_links(mut self, selector: &str) -> Self {
+        self.link_selector = Some(selector.to_string());
+        self
+    }
+
+    /// Honors the pages' robots directives: nothing is extracted from `noindex` pages, the
+    /// links of `nofollow` pages are not followed, and neither are links marked
+    /// `rel="nofollow"`. Directives addressed to the scrapper's `User-Agent` apply too.
+    pub fn respect_robots_directives(mut self) -> Self {
+        self.respect_robots_directives = true;
+        self
+    }
+
+    /// Sets the function assigning priorities to discovered links (0 by default).
+    pub fn with_priority_fn<F>(mut self, priority_fn: F) -> Self
+    where
+        F: Fn(&str) -> i32 + Send + Sync + 'static,
+    {
+        self.priority_fn = Box::new(priority_fn);
+        self
+    }
+
+    /// Stops after this crawler has fetched this many pages (100 by default).
+    pub fn max_pages(mut self, max_pages: usize) -> Self {
+        self.max_pages = max_pages;
+        self
+    }
+
+    /// Waits this long between requests.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Allows following links to other hosts. Only same-host links are followed by default.
+    pub fn allow_other_hosts(mut self) -> Self {
+        self.same_host = false;
+        self
+    }
+
+    /// Retries a failed fetch up to `max_retries` times, doubling `retry_delay` after each attempt.
+    /// Two retries starting at one second are used by default.
+    pub fn retries(mut self, max_retries: u32, retry_delay: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    /// URLs that failed permanently, with their error and attempt history.
+    /// Export them with `Exporter::records_to_json(crawler.failures())`.
+    pub fn failures(&self) -> &[FailedUrl] {
+        &self.failures
+    }
+
+    /// Summary of the last `run`.
+    pub fn report(&self) -> &ScrapeReport {
+        &self.report
+    }
+
+    /// Moves all failed URLs back into the queue, so the next `run` retries only them.
+    pub fn requeue_failures(&mut self) {
+        for failure in self.failures.drain(..) {
+            self.frontier.unmark_visited(&failure.url);
+            self.frontier.enqueue(&failure.url, failure.priority);
+        }
+    }
+
+    /// Registers a subscriber that is called for every crawl event.
+    pub fn subscribe<S: EventSubscriber + 'static>(mut self, subscriber: S) -> Self {
+        self.events.subscribe(subscriber);
+        self
+    }
+
+    /// Returns a broadcast receiver of crawl events, e.g. to feed a dashboard from another task.
+    pub fn events(&self) -> broadcast::Receiver<CrawlEvent> {
+        self.events.receiver()
+    }
+
+    /// Bounds the memory used by the crawl, spilling results, pending URLs and cached pages
+    /// to disk beyond the budget's limits. Call before `seed` and `with_frontier`.
+    pub fn with_memory_budget(mut self, budget: MemoryBudget) -> Self {
+        if let Err(e) = std::fs::create_dir_all(&budget.spill_dir) {
+            error!("Failed to create spill directory {}: {}", budget.spill_dir.display(), e);
+        }
+        if budget.max_frontier.is_some() || budget.visited_filter.is_some() {
+            let mut frontier = MemoryFrontier::with_queue(self.queue.clone());
+            if let Some(max_frontier) = budget.max_frontier {
+                frontier = frontier.with_spill(max_frontier, &budget.frontier_path().to_string_lossy());
+            }
+            if let Some((expected_urls, false_positive_rate)) = budget.visited_filter {
+                frontier = frontier.with_bloom_filter(expected_urls, false_positive_rate);
+            }
+            self.frontier = Arc::new(frontier);
+        }
+        if let Some(cache) = budget.cache() {
+            self.scrapper = self.scrapper.with_cache(cache);
+        }
+        if let Some(max_items) = budget.max_items {
+            self.max_items = Some(max_items);
+            self.spill = Some(JsonlSink::new(&budget.spilled_pages_path().to_string_lossy()));
+        }
+        self
+    }
+
+    /// Limits the requests, downloaded bytes and duration of every `run`. A run that reaches
+    /// a limit stops before its next request and returns the pages crawled so far; the limit
+    /// is reported in `ScrapeReport::stopped_by` and pending URLs stay queued for the next run.
+    pub fn with_budget(mut self, budget: RequestBudget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Appends every crawled page to a JSON Lines file as soon as it is crawled, compressed
+    /// if its name ends with `.gz` or `.zst`.
+    pub fn export_jsonl(mut self, path: &str) -> Self {
+        self.sink = Some(JsonlSink::new(path));
+        self
+    }
+
+    /// Saves a checkpoint to `path` after every `every_items` extracted items or `every`,
+    /// whichever comes first (0 disables either trigger), and when the crawl finishes.
+    /// The `export_jsonl` file is flushed to disk first. Restore the state with `resume`.
+    pub fn checkpoint(mut self, path: &str, every_items: usize, every: Duration) -> Self {
+        self.checkpointer = Some(Checkpointer::new(path, every_items, every));
+        self
+    }
+
+    /// Restores the state of the last checkpoint, if one exists, and drops pages exported
+    /// after it. Returns whether a checkpoint was found. Call before `run`, instead of seeding.
+    pub fn resume(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        let path = self.checkpointer.as_ref().ok_or("No checkpoint configured")?.path.clone();
+        let state = match CheckpointState::load(&path)? {
+            Some(state) => state,
+            None => return Ok(false),
+        };
+        if let Some(snapshot) = state.frontier {
+            self.frontier.restore(snapshot);
+        }
+        if let (Some(sink), Some(len)) = (&mut self.sink, state.export_len) {
+            sink.truncate(len)?;
+        }
+        self.failures = state.failures;
+        self.fetched = state.fetched;
+        info!("Resumed crawl from {} after {} pages", path, self.fetched);
+        Ok(true)
+    }
+
+    /// Moves the collected pages to the memory budget's spill file.
+    fn spill_pages(&mut self, pages: &mut Vec<CrawledPage>) {
+        let Some(spill) = &mut self.spill else {
+            return;
+        };
+        let result = pages
+            .iter()
+            .try_for_each(|page| spill.write(page))
+            .and_then(|_| spill.flush().map(drop));
+        match result {
+            Ok(()) => pages.clear(),
+            Err(e) => error!("Failed to spill crawled pages: {}", e),
+        }
+    }
+
+    /// Flushes the export and saves the crawler state.
+    fn save_checkpoint(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(checkpointer) = &mut self.checkpointer else {
+            return Ok(());
+        };
+        let export_len = match &mut self.sink {
+            Some(sink) => Some(sink.flush()?),
+            None => None,
+        };
+        let state = CheckpointState {
+            frontier: self.frontier.snapshot(),
+            failures: self.failures.clone(),
+            fetched: self.fetched,
+            export_len,
+        };
+        state.save(&checkpointer.path)?;
+        checkpointer.reset();
+        Ok(())
+    }
+
+    /// Returns a handle to the request queue, to enqueue or re-prioritize URLs while crawling.
+    /// With a custom frontier, enqueue URLs through the frontier instead.
+    pub fn queue(&self) -> RequestQueue {
+        self.queue.clone()
+    }
+
+    /// Runs the crawl until the queue is empty or the page limit is reached.
+    /// With a memory budget, pages over its item limit are in its spill file instead.
+    pub async fn run(&mut self) -> Vec<CrawledPage> {
+        let started = Instant::now();
+        self.report = ScrapeReport::default();
+        let mut pages = Vec::new();
+        let mut crawled = 0;
+        let mut items_in_memory = 0;
+        let requests_before = self.scrapper.requests_sent();
+        while self.fetched < self.max_pages {
+            let requests = self.scrapper.requests_sent() - requests_before;
+            let exceeded = self.budget.exceeded(requests, self.report.bytes, started.elapsed());
+            if let Some(limit) = exceeded.filter(|_| !self.frontier.is_empty()) {
+                info!("Stopping the crawl: {}{}", limit, self.scrapper.log_tag());
+                self.report.stopped_by = Some(limit);
+                break;
+            }
+            let (url, priority) = match self.frontier.pop() {
+                Some(next) => next,
+                None => break,
+            };
+            if !self.frontier.mark_visited(&url) {
+                continue;
+            }
+            self.fetched += 1;
+            self.report.attempted += 1;
+            if crawled > 0 && !self.delay.is_zero() {
+                sleep(self.delay).await;
+            }
+
+            info!("Crawling {} (priority {}){}", url, priority, self.scrapper.log_tag());
+            let mut attempts = Vec::new();
+            let mut soft_failure = None;
+            let result = loop {
+                let attempt = attempts.len() as u32 + 1;
+                self.events.emit(CrawlEvent::RequestStarted {
+                    url: url.clone(),
+                    attempt,
+                    correlation_id: self.correlation_id(),
+                });
+                let request_started = Instant::now();
+                let fetched = self.scrapper.fetch_body_traced_async(&url, false).await;
+                let error = match fetched {
+                    Ok((body, cached)) => {
+                        if cached {
+                            self.report.cache_hits += 1;
+                        } else {
+                            self.report.bytes += body.len() as u64;
+                        }
+                        self.events.emit(CrawlEvent::ResponseReceived {
+                            url: url.clone(),
+                            bytes: body.len(),
+                            elapsed: request_started.elapsed(),
+                            correlation_id: self.correlation_id(),
+                        });
+                        let robots_tags = self.scrapper.take_robots_tags(&url);
+                        match self.process(&url, &body, &robots_tags) {
+                            Ok(page) => break Ok(page),
+                            Err(e) => format!("Failed to process: {}", e),
+                        }
+                    }
+                    Err(e) => {
+                        soft_failure = find_cause::<SoftFailure>(e.as_ref()).cloned();
+                        format!("Failed to fetch: {}", e)
+                    }
+                };
+                error!("{} (attempt {}): {}", url, attempt, error);
+                self.events.emit(CrawlEvent::ErrorOccurred {
+                    url: url.clone(),
+                    error: error.clone(),
+                    attempt,
+                    correlation_id: self.correlation_id(),
+                });
+                attempts.push(FailedAttempt {
+                    attempt,
+                    error,
+                    at: SystemTime::now(),
+                });
+                let requests = self.scrapper.requests_sent() - requests_before;
+                if attempt > self.max_retries || self.budget.exceeded(requests, self.report.bytes, started.elapsed()).is_some() {
+                    break Err(attempts);
+                }
+                sleep(self.retry_delay * 2u32.saturating_pow(attempt - 1)).await;
+            };
+
+            match result {
+                Ok(page) => {
+                    let skipped = self.respect_robots_directives && page.robots.noindex;
+                    if let Some(drift) = self.scrapper.drift.as_ref().filter(|_| !skipped) {
+                        if let Some(warning) = drift.record(&self.item_selector, &page.url, page.items.len()) {
+                            self.events.emit(CrawlEvent::SelectorDrift { warning });
+                        }
+                    }
+                    for item in &page.items {
+                        self.events.emit(CrawlEvent::ItemExtracted {
+                            url: page.url.clone(),
+                            item: item.clone(),
+                            correlation_id: self.correlation_id(),
+                        });
+                    }
+                    if let Some(sink) = &mut self.sink {
+                        if let Err(e) = sink.write(&page) {
+                            error!("Failed to export {}: {}", page.url, e);
+                        }
+                    }
+                    let items = page.items.len();
+                    self.report.succeeded += 1;
+                    self.report.items += items;
+                    crawled += 1;
+                    items_in_memory += items;
+                    pages.push(page);
+                    if self.max_items.is_some_and(|max| items_in_memory > max) {
+                        self.spill_pages(&mut pages);
+                        items_in_memory = 0;
+                    }
+                    if self.checkpointer.as_mut().is_some_and(|c| c.record(items)) {
+                        if let Err(e) = self.save_checkpoint() {
+                            error!("Failed to save checkpoint: {}", e);
+                        }
+                    }
+                }
+                Err(attempts) => {
+                    let error = attempts.last().map(|a| a.error.clone()).unwrap_or_default();
+                    self.report.record_failure(&error);
+                    if let Some(failure) = soft_failure {
+                        self.report.soft_failures.insert(url.clone(), failure);
+                    }
+                    self.failures.push(FailedUrl {
+                        url,
+                        priority,
+                        error,
+                        attempts,
+                    });
+                }
+            }
+        }
+        if let Err(e) = self.save_checkpoint() {
+            error!("Failed to save checkpoint: {}", e);
+        }
+        self.report.duration_secs = started.elapsed().as_secs_f64();
+        self.events.emit(CrawlEvent::CrawlFinished {
+            pages: crawled,
+            failures: self.failures.len(),
+            elapsed: started.elapsed(),
+            correlation_id: self.correlation_id(),
+        });
+        pages
+    }
+
+    fn correlation_id(&self) -> Option<String> {
+        self.scrapper.context().map(|context| context.correlation_id.clone())
+    }
+
+    /// Extracts items from a fetched page and queues the links it contains. `robots_tags` are
+    /// the page's `X-Robots-Tag` header values.
+    fn process(&self, url: &str, body: &str, robots_tags: &[String]) -> Result<CrawledPage, Box<dyn std::error::Error>> {
+        let document = Html::parse_document(body);
+        let user_agent = self.scrapper.user_agent();
+        let robots = robots_tags
+            .iter()
+            .map(|tag| RobotsDirectives::from_header(tag, user_agent))
+            .fold(RobotsDirectives::from_html(&document, user_agent), RobotsDirectives::merge);
+        let selector = Selector::parse(&self.item_selector).map_err(|e| format!("Selector parse error: {:?}", e))?;
+        let items = if self.respect_robots_directives && robots.noindex {
+            info!("Not extracting from {}: noindex{}", url, self.scrapper.log_tag());
+            Vec::new()
+        } else {
+            document.select(&selector).map(|elem| elem.inner_html()).collect()
+        };
+
+        let follow = !(self.respect_robots_directives && robots.nofollow);
+        if let Some(link_selector) = self.link_selector.as_ref().filter(|_| follow) {
+            let link_selector = Selector::parse(link_selector).map_err(|e| format!("Selector parse error: {:?}", e))?;
+            let base = Url::parse(url)?;
+            let links = document
+                .select(&link_selector)
+                .filter(|elem| !(self.respect_robots_directives && robots::is_nofollow(elem.value().attr("rel"))))
+                .filter_map(|elem| elem.value().attr("href"));
+            for href in links {
+                let mut link = match base.join(href) {
+                    Ok(link) => link,
+                    Err(_) => continue,
+                };
+                link.set_fragment(None);
+                if !matches!(link.scheme(), "http" | "https") || (self.same_host && link.host_str() != base.host_str()) {
+                    continue;
+                }
+                let link = link.to_string();
+                if !self.frontier.is_visited(&link) {
+                    self.frontier.enqueue(&link, (self.priority_fn)(&link));
+                }
+            }
+        }
+
+        Ok(CrawledPage {
+            url: url.to_string(),
+            items,
+            robots,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::BudgetLimit;
+    use crate::{CircuitBreaker, ScrapeCache};
+    use reqwest::header::HeaderMap;
+
+    #[test]
+    fn pops_highest_priority_first_in_insertion_order() {
+        let queue = RequestQueue::new();
+        queue.enqueue("https://a.com/low", 1);
+        queue.enqueue("https://a.com/first", 5);
+        queue.enqueue("https://a.com/second", 5);
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.pop(), Some(("https://a.com/first".to_string(), 5)));
+        assert_eq!(queue.pop(), Some(("https://a.com/second".to_string(), 5)));
+        assert_eq!(queue.pop(), Some(("https://a.com/low".to_string(), 1)));
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn reprioritizes_queued_urls() {
+        let queue = RequestQueue::new();
+        queue.enqueue("https://a.com/a", 1);
+        queue.enqueue("https://a.com/b", 2);
+        queue.enqueue("https://a.com/a", 0);
+        assert_eq!(queue.len(), 2);
+
+        assert!(queue.reprioritize("https://a.com/a", 3));
+        assert!(queue.reprioritize("https://a.com/b", -1));
+        assert!(!queue.reprioritize("https://a.com/missing", 9));
+        assert_eq!(queue.pop(), Some(("https://a.com/a".to_string(), 3)));
+        assert_eq!(queue.pop(), Some(("https://a.com/b".to_string(), -1)));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn queues_same_host_links_with_their_priority() {
+        let crawler = Crawler::new(RustScrapper::new(), "h1")
+            .follow_links("a[href]")
+            .with_priority_fn(|url| if url.contains("/item/") { 10 } else { 0 });
+        let body = r##"<h1>Title</h1>
+            <a href="/item/1#top">item</a>
+            <a href="/about">about</a>
+            <a href="https://other.com/x">other</a>
+            <a href="mailto:me@a.com">mail</a>"##;
+        let page = crawler.process("https://a.com/", body, &[]).unwrap();
+        assert_eq!(page.items, vec!["Title"]);
+
+        let queue = crawler.queue();
+        assert_eq!(queue.pop(), Some(("https://a.com/item/1".to_string(), 10)));
+        assert_eq!(queue.pop(), Some(("https://a.com/about".to_string(), 0)));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[tokio::test]
+    async fn retries_failed_urls_and_records_them() {
+        let cache = ScrapeCache::new();
+        cache.set_body(&cache.body_key_for("https://up.com/", &HeaderMap::new()), "<h1>Up</h1>".to_string());
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(60));
+        breaker.record_failure("https://down.com/");
+        let scrapper = RustScrapper::new().with_cache(cache).with_circuit_breaker(breaker);
+
+        let mut crawler = Crawler::new(scrapper, "h1")
+            .seed("https://up.com/", 0)
+            .seed("https://down.com/", 1)
+            .retries(2, Duration::ZERO);
+        let pages = crawler.run().await;
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].items, vec!["Up"]);
+
+        let failures = crawler.failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].url, "https://down.com/");
+        assert_eq!(failures[0].priority, 1);
+        assert_eq!(failures[0].attempts.len(), 3);
+        assert!(failures[0].error.starts_with("Failed to fetch: Circuit open"));
+
+        crawler.requeue_failures();
+        assert!(crawler.failures().is_empty());
+        assert_eq!(crawler.queue().pop(), Some(("https://down.com/".to_string(), 1)));
+    }
+
+    #[tokio::test]
+    async fn emits_lifecycle_events() {
+        let cache = ScrapeCache::new();
+        cache.set_body(&cache.body_key_for("https://a.com/", &HeaderMap::new()), "<h1>A</h1><h1>B</h1>".to_string());
+        let mut crawler = Crawler::new(RustScrapper::new().with_cache(cache), "h1").seed("https://a.com/", 0);
+        let mut events = crawler.events();
+        crawler.run().await;
+
+        let mut names = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            names.push(format!("{:?}", event).split([' ', '{']).next().unwrap().to_string());
+        }
+        assert_eq!(
+            names,
+            vec!["RequestStarted", "ResponseReceived", "ItemExtracted", "ItemExtracted", "CrawlFinished"]
+        );
+    }
+
+    #[tokio::test]
+    async fn shares_a_frontier_between_crawlers() {
+        let cache = ScrapeCache::new();
+        for (url, body) in [("https://a.com/1", "<h1>One</h1>"), ("https://a.com/2", "<h1>Two</h1>")] {
+            cache.set_body(&cache.body_key_for(url, &HeaderMap::new()), body.to_string());
+        }
+        let frontier: Arc<dyn Frontier> = Arc::new(MemoryFrontier::new());
+        frontier.enqueue("https://a.com/1", 1);
+        frontier.enqueue("https://a.com/2", 0);
+
+        let mut first = Crawler::new(RustScrapper::new().with_cache(cache.clone()), "h1")
+            .with_frontier(frontier.clone())
+            .max_pages(1);
+        let mut second = Crawler::new(RustScrapper::new().with_cache(cache), "h1").with_frontier(frontier.clone());
+        assert_eq!(first.run().await[0].items, vec!["One"]);
+        let pages = second.run().await;
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].items, vec!["Two"]);
+        assert!(frontier.is_empty());
+    }
+
+    #[tokio::test]
+    async fn resumes_from_the_last_checkpoint() {
+        let dir = std::env::temp_dir();
+        let state = dir.join(format!("rust-scrapper-crawl-{}.json", std::process::id()));
+        let export = dir.join(format!("rust-scrapper-crawl-{}.jsonl", std::process::id()));
+        let (state, export) = (state.to_str().unwrap(), export.to_str().unwrap());
+        let cache = ScrapeCache::new();
+        for (url, body) in [("https://a.com/1", "<h1>One</h1>"), ("https://a.com/2", "<h1>Two</h1>")] {
+            cache.set_body(&cache.body_key_for(url, &HeaderMap::new()), body.to_string());
+        }
+
+        let mut first = Crawler::new(RustScrapper::new().with_cache(cache.clone()), "h1")
+            .seed("https://a.com/1", 1)
+            .seed("https://a.com/2", 0)
+            .max_pages(1)
+            .export_jsonl(export)
+            .checkpoint(state, 0, Duration::ZERO);
+        first.run().await;
+
+        let mut second = Crawler::new(RustScrapper::new().with_cache(cache), "h1")
+            .max_pages(2)
+            .export_jsonl(export)
+            .checkpoint(state, 0, Duration::ZERO);
+        assert!(second.resume().unwrap());
+        let pages = second.run().await;
+        let exported = std::fs::read_to_string(export).unwrap();
+        std::fs::remove_file(state).unwrap();
+        std::fs::remove_file(export).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].items, vec!["Two"]);
+        assert_eq!(exported.lines().count(), 2);
+    }
+
+    #[test]
+    fn drains_the_lowest_priority_urls() {
+        let queue = RequestQueue::new();
+        for (url, priority) in [("a", 2), ("b", 0), ("c", 1), ("d", 0)] {
+            queue.enqueue(url, priority);
+        }
+        assert_eq!(queue.drain_lowest(2), vec![("d".to_string(), 0), ("b".to_string(), 0)]);
+        assert_eq!(queue.snapshot(), vec![("a".to_string(), 2), ("c".to_string(), 1)]);
+        assert_eq!(queue.pop(), Some(("a".to_string(), 2)));
+    }
+
+    #[tokio::test]
+    async fn spills_pages_beyond_the_item_budget() {
+        let dir = std::env::temp_dir().join(format!("rust-scrapper-budget-{}", std::process::id()));
+        let budget = MemoryBudget::new(&dir.to_string_lossy()).max_items(1);
+        let spilled = budget.spilled_pages_path();
+        let cache = ScrapeCache::new();
+        for (url, body) in [("https://a.com/1", "<h1>One</h1>"), ("https://a.com/2", "<h1>Two</h1>")] {
+            cache.set_body(&cache.body_key_for(url, &HeaderMap::new()), body.to_string());
+        }
+        let mut crawler = Crawler::new(RustScrapper::new().with_cache(cache), "h1")
+            .with_memory_budget(budget)
+            .seed("https://a.com/1", 1)
+            .seed("https://a.com/2", 0);
+        let pages = crawler.run().await;
+        let exported = std::fs::read_to_string(&spilled).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(pages.is_empty());
+        assert_eq!(exported.lines().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn reports_crawl_totals() {
+        let cache = ScrapeCache::new();
+        cache.set_body(&cache.body_key_for("https://up.com/", &HeaderMap::new()), "<h1>A</h1><h1>B</h1>".to_string());
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(60));
+        breaker.record_failure("https://down.com/");
+        let scrapper = RustScrapper::new().with_cache(cache).with_circuit_breaker(breaker);
+        let mut crawler = Crawler::new(scrapper, "h1")
+            .seed("https://up.com/", 1)
+            .seed("https://down.com/", 0)
+            .retries(0, Duration::ZERO);
+        crawler.run().await;
+
+        let report = crawler.report();
+        assert_eq!((report.attempted, report.succeeded, report.failed, report.items), (2, 1, 1, 2));
+        assert_eq!(report.cache_hits, 1);
+        assert_eq!(report.bytes, 0);
+        assert_eq!(report.failures_by_reason.get("circuit open"), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn stops_when_the_request_budget_is_spent() {
+        // Local pages are read without a request; only the served pages count.
+        let mut crawler = Crawler::new(RustScrapper::new(), "b")
+            .seed(&crate::tests::serve_once("text/html", "<b>1</b>"), 3)
+            .seed(&crate::tests::serve_once("text/html", "<b>2</b>"), 2)
+            .seed("data:text/html,<b>local</b>", 4)
+            .seed(&crate::tests::serve_once("text/html", "<b>3</b>"), 1)
+            .with_budget(RequestBudget::new().max_requests(2));
+        let pages = crawler.run().await;
+
+        assert_eq!(pages.len(), 3);
+        assert_eq!(crawler.report().stopped_by, Some(BudgetLimit::Requests(2)));
+        assert!(crawler.report().to_string().contains("Stopped early:   request budget of 2 reached"));
+        assert!(!crawler.frontier.is_empty());
+    }
+
+    #[test]
+    fn respects_robots_directives_only_when_asked() {
+        let body = r#"<meta name="robots" content="noindex"><b>item</b>
+            <a href="/a">a</a><a rel="nofollow" href="/b">b</a>"#;
+        let crawler = Crawler::new(RustScrapper::new(), "b").follow_links("a");
+        let page = crawler.process("https://a.com/", body, &[]).unwrap();
+        assert!(page.robots.noindex);
+        assert_eq!(page.items, vec!["item"]);
+        assert_eq!(crawler.frontier.len(), 2);
+
+        let crawler = Crawler::new(RustScrapper::new(), "b").follow_links("a").respect_robots_directives();
+        let page = crawler.process("https://a.com/", body, &[]).unwrap();
+        assert!(page.items.is_empty());
+        assert_eq!(crawler.frontier.pop(), Some(("https://a.com/a".to_string(), 0)));
+        assert!(crawler.frontier.is_empty());
+
+        let page = crawler.process("https://a.com/", "<b>item</b><a href=\"/c\">c</a>", &["nofollow".to_string()]).unwrap();
+        assert_eq!(page.items, vec!["item"]);
+        assert!(page.robots.nofollow);
+        assert!(crawler.frontier.is_empty());
+    }
+}
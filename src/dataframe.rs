@@ -0,0 +1,71 @@
+//! Polars DataFrames of structured records.
+//!
+//! `Exporter::to_dataframe` turns the records of `scrape_structured` or a pipeline into a
+//! polars `DataFrame`, so analysis can start without a CSV round-trip. Enabled by the `polars`
+//! feature.
+
+use crate::{cell_text, column_type, column_values, record_columns, ColumnType, Exporter};
+use polars::prelude::{DataFrame, NamedFrom, Series};
+use serde_json::Value;
+
+impl Exporter {
+    /// Builds a DataFrame with the columns of `records_to_csv`. A column whose values are all
+    /// booleans, integers or numbers gets that type, with nulls where records lack the field;
+    /// other columns are strings, with arrays joined by `; ` and objects as JSON.
+    pub fn to_dataframe(records: &[Value]) -> Result<DataFrame, Box<dyn std::error::Error>> {
+        let columns = record_columns(records)
+            .iter()
+            .map(|column| column_series(column, &column_values(records, column)))
+            .collect();
+        Ok(DataFrame::new(columns)?)
+    }
+}
+
+/// A column of the values, typed by what they hold.
+fn column_series(name: &str, values: &[&Value]) -> Series {
+    match column_type(values) {
+        ColumnType::Boolean => Series::new(name, values.iter().map(|value| value.as_bool()).collect::<Vec<_>>()),
+        ColumnType::Integer => Series::new(name, values.iter().map(|value| value.as_i64()).collect::<Vec<_>>()),
+        ColumnType::Float => Series::new(name, values.iter().map(|value| value.as_f64()).collect::<Vec<_>>()),
+        ColumnType::Text => Series::new(
+            name,
+            values
+                .iter()
+                .map(|value| (!value.is_null()).then(|| cell_text(value)))
+                .collect::<Vec<_>>(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::prelude::DataType;
+    use serde_json::json;
+
+    #[test]
+    fn types_columns_by_their_values() {
+        let records = [
+            json!({"name": "Lamp", "price": 20, "rating": 4.5, "stock": true, "tags": ["a", "b"]}),
+            json!({"name": "Desk", "price": 150, "rating": 4, "note": null}),
+        ];
+        let frame = Exporter::to_dataframe(&records).unwrap();
+        assert_eq!(frame.shape(), (2, 6));
+        assert_eq!(frame.column("name").unwrap().dtype(), &DataType::String);
+        assert_eq!(frame.column("price").unwrap().dtype(), &DataType::Int64);
+        assert_eq!(frame.column("rating").unwrap().dtype(), &DataType::Float64);
+        assert_eq!(frame.column("stock").unwrap().dtype(), &DataType::Boolean);
+        assert_eq!(frame.column("stock").unwrap().null_count(), 1);
+        assert_eq!(frame.column("note").unwrap().null_count(), 2);
+        let tags = frame.column("tags").unwrap().str().unwrap();
+        assert_eq!(tags.get(0), Some("a; b"));
+        assert_eq!(tags.get(1), None);
+    }
+
+    #[test]
+    fn puts_plain_values_in_one_column() {
+        let frame = Exporter::to_dataframe(&[json!("Lamp"), json!("Desk")]).unwrap();
+        assert_eq!(frame.get_column_names(), vec!["value"]);
+        assert_eq!(frame.column("value").unwrap().str().unwrap().get(1), Some("Desk"));
+    }
+}
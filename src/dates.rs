@@ -0,0 +1,209 @@
+//! Date parsing for the formats found on web pages.
+//!
+//! `parse` understands ISO 8601, RFC 2822, numeric dates such as "12.03.2024" or
+//! "03/12/2024", dates with month names in English, Turkish, German, French or Spanish
+//! ("12 Mart 2024", "March 12, 2024 3:45 PM") and relative dates ("3 days ago",
+//! "yesterday", "2 saat önce"). Dates without a time zone are taken as UTC.
+
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use regex::Regex;
+use std::sync::OnceLock;
+
+const MONTHS: &[(&str, u32)] = &[
+    ("january", 1), ("jan", 1), ("ocak", 1), ("januar", 1), ("janvier", 1), ("enero", 1),
+    ("february", 2), ("feb", 2), ("şubat", 2), ("subat", 2), ("februar", 2), ("février", 2), ("fevrier", 2), ("febrero", 2),
+    ("march", 3), ("mar", 3), ("mart", 3), ("märz", 3), ("marz", 3), ("mars", 3), ("marzo", 3),
+    ("april", 4), ("apr", 4), ("nisan", 4), ("avril", 4), ("abril", 4),
+    ("may", 5), ("mayıs", 5), ("mayis", 5), ("mai", 5), ("mayo", 5),
+    ("june", 6), ("jun", 6), ("haziran", 6), ("juni", 6), ("juin", 6), ("junio", 6),
+    ("july", 7), ("jul", 7), ("temmuz", 7), ("juli", 7), ("juillet", 7), ("julio", 7),
+    ("august", 8), ("aug", 8), ("ağustos", 8), ("agustos", 8), ("août", 8), ("aout", 8), ("agosto", 8),
+    ("september", 9), ("sep", 9), ("sept", 9), ("eylül", 9), ("eylul", 9), ("septembre", 9), ("septiembre", 9),
+    ("october", 10), ("oct", 10), ("ekim", 10), ("oktober", 10), ("okt", 10), ("octobre", 10), ("octubre", 10),
+    ("november", 11), ("nov", 11), ("kasım", 11), ("kasim", 11), ("novembre", 11), ("noviembre", 11),
+    ("december", 12), ("dec", 12), ("aralık", 12), ("aralik", 12), ("dezember", 12), ("dez", 12), ("décembre", 12), ("decembre", 12), ("diciembre", 12),
+];
+
+/// Parses a date as written on a web page, resolving relative dates against the current time.
+pub fn parse(text: &str) -> Option<DateTime<Utc>> {
+    parse_at(text, Utc::now())
+}
+
+/// Parses a date, resolving relative dates such as "3 days ago" against `now`.
+pub fn parse_at(text: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text).or_else(|_| DateTime::parse_from_rfc2822(text)) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"] {
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(text, format) {
+            return Some(Utc.from_utc_datetime(&datetime));
+        }
+    }
+    let lower = text.to_lowercase();
+    parse_relative(&lower, now).or_else(|| parse_absolute(&lower, now))
+}
+
+/// Parses a date written in a chrono `format`, with or without a time. A time zone is
+/// only honored if the format contains one.
+pub fn parse_with_format(text: &str, format: &str) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    if let Ok(datetime) = DateTime::parse_from_str(text, format) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+    NaiveDateTime::parse_from_str(text, format)
+        .or_else(|_| NaiveDate::parse_from_str(text, format).map(|date| date.and_time(NaiveTime::MIN)))
+        .ok()
+        .map(|datetime| Utc.from_utc_datetime(&datetime))
+}
+
+fn parse_relative(text: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let start_of = |date: DateTime<Utc>| Utc.from_utc_datetime(&date.date_naive().and_time(NaiveTime::MIN));
+    match text {
+        "now" | "just now" | "şimdi" | "az önce" => return Some(now),
+        "today" | "bugün" => return Some(start_of(now)),
+        "yesterday" | "dün" => return Some(start_of(now - Duration::days(1))),
+        _ => {}
+    }
+
+    static RELATIVE: OnceLock<Regex> = OnceLock::new();
+    let relative = RELATIVE.get_or_init(|| Regex::new(r"^(\d+|an?|one)\s*(\p{L}+)\s+(?:ago|önce|once)$").unwrap());
+    let captures = relative.captures(text)?;
+    let count: u32 = captures[1].parse().unwrap_or(1);
+    let unit = captures[2].trim_end_matches('s');
+    let seconds = |seconds: i64| now.checked_sub_signed(Duration::seconds(seconds * count as i64));
+    match unit {
+        "second" | "sec" | "" | "saniye" => seconds(1),
+        "minute" | "min" | "m" | "dakika" | "dk" => seconds(60),
+        "hour" | "hr" | "h" | "saat" | "sa" => seconds(3600),
+        "day" | "d" | "gün" | "gun" => seconds(86_400),
+        "week" | "w" | "wk" | "hafta" => seconds(7 * 86_400),
+        "month" | "mo" | "ay" => now.checked_sub_months(Months::new(count)),
+        "year" | "yr" | "y" | "yıl" | "yil" => now.checked_sub_months(Months::new(count * 12)),
+        _ => None,
+    }
+}
+
+fn parse_absolute(text: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    static TIME: OnceLock<Regex> = OnceLock::new();
+    let time_re = TIME.get_or_init(|| Regex::new(r"(\d{1,2}):(\d{2})(?::(\d{2}))?\s*([ap]\.?m\.?)?").unwrap());
+    let (time, rest) = match time_re.captures(text) {
+        Some(captures) => {
+            let mut hour: u32 = captures[1].parse().ok()?;
+            let minute: u32 = captures[2].parse().ok()?;
+            let second: u32 = captures.get(3).map_or(Some(0), |s| s.as_str().parse().ok())?;
+            match captures.get(4).map(|m| m.as_str().starts_with('p')) {
+                Some(true) if hour < 12 => hour += 12,
+                Some(false) if hour == 12 => hour = 0,
+                _ => {}
+            }
+            let range = captures.get(0)?.range();
+            let rest = format!("{} {}", &text[..range.start], &text[range.end..]);
+            (NaiveTime::from_hms_opt(hour, minute, second)?, rest)
+        }
+        None => (NaiveTime::MIN, text.to_string()),
+    };
+    let date = parse_numeric_date(rest.trim()).or_else(|| parse_named_date(&rest, now.year()))?;
+    Some(Utc.from_utc_datetime(&date.and_time(time)))
+}
+
+/// "2024-03-12", "12.03.2024", "12-03-24" (day first) or "03/12/2024" (month first),
+/// unless the values only fit the other order. The date may be surrounded by other text,
+/// as in "Published on 12.03.2024".
+fn parse_numeric_date(text: &str) -> Option<NaiveDate> {
+    static NUMERIC: OnceLock<Regex> = OnceLock::new();
+    let numeric = NUMERIC.get_or_init(|| Regex::new(r"\b(\d{1,4})([./-])(\d{1,2})[./-](\d{2,4})\b").unwrap());
+    let captures = numeric.captures(text)?;
+    let first: u32 = captures[1].parse().ok()?;
+    let second: u32 = captures[3].parse().ok()?;
+    let third: i32 = captures[4].parse().ok()?;
+    if captures[1].len() == 4 {
+        return NaiveDate::from_ymd_opt(first as i32, second, third as u32);
+    }
+    let year = if captures[4].len() == 2 { 2000 + third } else { third };
+    let month_first = &captures[2] == "/";
+    let (day, month) = if (month_first && first <= 12) || second > 12 {
+        (second, first)
+    } else {
+        (first, second)
+    };
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+/// "12 Mart 2024", "March 12th, 2024", "12. Januar 2024" or "Mar 12" (in `default_year`).
+fn parse_named_date(text: &str, default_year: i32) -> Option<NaiveDate> {
+    static TOKEN: OnceLock<Regex> = OnceLock::new();
+    let token = TOKEN.get_or_init(|| Regex::new(r"\p{L}+|\d+").unwrap());
+    let mut month = None;
+    let mut day = None;
+    let mut year = None;
+    for word in token.find_iter(text).map(|m| m.as_str()) {
+        if let Ok(number) = word.parse::<u32>() {
+            if word.len() == 4 && year.is_none() {
+                year = Some(number as i32);
+            } else if word.len() <= 2 && day.is_none() {
+                day = Some(number);
+            }
+        } else if month.is_none() {
+            month = MONTHS.iter().find(|(name, _)| *name == word).map(|(_, month)| *month);
+        }
+    }
+    NaiveDate::from_ymd_opt(year.unwrap_or(default_year), month?, day?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 15, 12, 0, 0).unwrap()
+    }
+
+    fn parsed(text: &str) -> String {
+        parse_at(text, now()).map(|date| date.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default()
+    }
+
+    #[test]
+    fn parses_standard_and_numeric_dates() {
+        assert_eq!(parsed("2024-03-12T14:05:00+02:00"), "2024-03-12 12:05");
+        assert_eq!(parsed("Tue, 12 Mar 2024 14:05:00 GMT"), "2024-03-12 14:05");
+        assert_eq!(parsed("2024-03-12 14:05"), "2024-03-12 14:05");
+        assert_eq!(parsed("12.03.2024"), "2024-03-12 00:00");
+        assert_eq!(parsed("03/12/2024"), "2024-03-12 00:00");
+        assert_eq!(parsed("25/12/2024"), "2024-12-25 00:00");
+    }
+
+    #[test]
+    fn parses_month_names_in_several_languages() {
+        assert_eq!(parsed("12 Mart 2024"), "2024-03-12 00:00");
+        assert_eq!(parsed("March 12th, 2024 3:45 PM"), "2024-03-12 15:45");
+        assert_eq!(parsed("12. Januar 2024"), "2024-01-12 00:00");
+        assert_eq!(parsed("3 décembre 2023"), "2023-12-03 00:00");
+        assert_eq!(parsed("Mar 2"), "2024-03-02 00:00");
+        assert_eq!(parsed("12 Smarch 2024"), "");
+    }
+
+    #[test]
+    fn resolves_relative_dates() {
+        assert_eq!(parsed("3 days ago"), "2024-03-12 12:00");
+        assert_eq!(parsed("an hour ago"), "2024-03-15 11:00");
+        assert_eq!(parsed("2 saat önce"), "2024-03-15 10:00");
+        assert_eq!(parsed("1 month ago"), "2024-02-15 12:00");
+        assert_eq!(parsed("yesterday"), "2024-03-14 00:00");
+        assert_eq!(parsed("bugün"), "2024-03-15 00:00");
+    }
+
+    #[test]
+    fn parses_explicit_formats() {
+        let date = parse_with_format("15/03/2024 08:30", "%d/%m/%Y %H:%M").unwrap();
+        assert_eq!(date, Utc.with_ymd_and_hms(2024, 3, 15, 8, 30, 0).unwrap());
+        assert!(parse_with_format("15/03/2024", "%Y-%m-%d").is_none());
+    }
+
+    #[test]
+    fn finds_numeric_dates_within_text() {
+        assert_eq!(parsed("Published on 12.03.2024"), "2024-03-12 00:00");
+        assert_eq!(parsed("Updated: 2024-03-12, 14:05"), "2024-03-12 14:05");
+        assert_eq!(parsed("Posted 03/12/2024 by admin"), "2024-03-12 00:00");
+    }
+}
@@ -0,0 +1,215 @@
+//! Anti-bot diagnostics.
+//!
+//! Classifies a response by the blocking signals it shows (challenge pages, CAPTCHAs,
+//! bot-detection 403s, rate limiting, JavaScript shells) and recommends what to try next,
+//! e.g. switching to the headless browser or a proxy.
+
+use regex::Regex;
+use scraper::Html;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// A sign that the response is not the content a browser would see.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum BlockSignal {
+    /// Cloudflare "Just a moment..." or managed challenge page.
+    CloudflareChallenge,
+    /// Akamai Bot Manager block page.
+    AkamaiBlock,
+    /// PerimeterX / HUMAN block page.
+    PerimeterXBlock,
+    /// DataDome block page.
+    DataDomeBlock,
+    /// A CAPTCHA widget, e.g. `reCAPTCHA`, `hCaptcha` or `Turnstile`.
+    Captcha { provider: String },
+    /// A 401/403 whose body mentions bots, automation or denied access.
+    BotDetection { status: u16 },
+    /// HTTP 429 Too Many Requests.
+    RateLimited,
+    /// An HTML shell with almost no text that renders its content with JavaScript.
+    JsShell,
+}
+
+/// What to try when a page is blocked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Recommendation {
+    /// Nothing suspicious was found.
+    NoActionNeeded,
+    /// Render the page with `JsScraper`.
+    UseHeadless,
+    /// Retry from another IP address.
+    UseProxy,
+    /// A human or a solving service has to pass a CAPTCHA.
+    SolveCaptcha,
+    /// Lower the request rate.
+    SlowDown,
+}
+
+/// Result of `RustScrapper::diagnose`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnosis {
+    pub url: String,
+    pub status: u16,
+    /// Length of the body in bytes.
+    pub body_bytes: usize,
+    /// Length of the visible text, without scripts and styles.
+    pub text_chars: usize,
+    pub signals: Vec<BlockSignal>,
+    pub recommendation: Recommendation,
+}
+
+impl Diagnosis {
+    /// Whether any blocking signal was found.
+    pub fn is_blocked(&self) -> bool {
+        !self.signals.is_empty()
+    }
+}
+
+fn invisible_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?is)<script\b.*?</script>|<style\b.*?</style>|<noscript\b.*?</noscript>|<template\b.*?</template>").unwrap())
+}
+
+fn bot_marker_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"(?i)\bbots?\b|automated|unusual traffic|access denied|are you (a )?human|verify you are human|blocked").unwrap()
+    })
+}
+
+/// Classifies a response. Header names must be lowercase.
+pub fn diagnose_response(url: &str, status: u16, headers: &HashMap<String, String>, body: &str) -> Diagnosis {
+    let header = |name: &str| headers.get(name).map(|value| value.to_ascii_lowercase()).unwrap_or_default();
+    let lower = body.to_ascii_lowercase();
+    let server = header("server");
+    let text_chars = visible_text(body).chars().count();
+    let mut signals = Vec::new();
+
+    let cloudflare_page = lower.contains("cf-chl") || lower.contains("challenge-platform") || lower.contains("just a moment...");
+    if header("cf-mitigated") == "challenge" || (server.contains("cloudflare") && matches!(status, 403 | 503) && cloudflare_page) {
+        signals.push(BlockSignal::CloudflareChallenge);
+    }
+    if (server.contains("akamaighost") && status == 403) || lower.contains("errors.edgesuite.net") {
+        signals.push(BlockSignal::AkamaiBlock);
+    }
+    if lower.contains("px-captcha") || lower.contains("_pxhd") || lower.contains("perimeterx") {
+        signals.push(BlockSignal::PerimeterXBlock);
+    }
+    if headers.contains_key("x-datadome") || server.contains("datadome") || lower.contains("captcha-delivery.com") {
+        signals.push(BlockSignal::DataDomeBlock);
+    }
+    for (marker, provider) in [
+        ("g-recaptcha", "reCAPTCHA"),
+        ("recaptcha/api.js", "reCAPTCHA"),
+        ("h-captcha", "hCaptcha"),
+        ("hcaptcha.com", "hCaptcha"),
+        ("challenges.cloudflare.com/turnstile", "Turnstile"),
+        ("cf-turnstile", "Turnstile"),
+    ] {
+        let captcha = BlockSignal::Captcha {
+            provider: provider.to_string(),
+        };
+        if lower.contains(marker) && !signals.contains(&captcha) {
+            signals.push(captcha);
+        }
+    }
+    if matches!(status, 401 | 403) && signals.is_empty() && bot_marker_regex().is_match(body) {
+        signals.push(BlockSignal::BotDetection { status });
+    }
+    if status == 429 {
+        signals.push(BlockSignal::RateLimited);
+    }
+    if (200..300).contains(&status) && signals.is_empty() && is_js_shell(&lower, text_chars) {
+        signals.push(BlockSignal::JsShell);
+    }
+
+    Diagnosis {
+        url: url.to_string(),
+        status,
+        body_bytes: body.len(),
+        text_chars,
+        recommendation: recommend(&signals),
+        signals,
+    }
+}
+
+fn recommend(signals: &[BlockSignal]) -> Recommendation {
+    if signals.iter().any(|s| matches!(s, BlockSignal::Captcha { .. })) {
+        Recommendation::SolveCaptcha
+    } else if signals.contains(&BlockSignal::RateLimited) {
+        Recommendation::SlowDown
+    } else if signals.iter().any(|s| matches!(s, BlockSignal::CloudflareChallenge | BlockSignal::JsShell)) {
+        Recommendation::UseHeadless
+    } else if signals.is_empty() {
+        Recommendation::NoActionNeeded
+    } else {
+        Recommendation::UseProxy
+    }
+}
+
+/// A page with scripts, barely any text and a typical single-page-app mount point or
+/// "enable JavaScript" notice.
+fn is_js_shell(lower: &str, text_chars: usize) -> bool {
+    const MOUNT_POINTS: [&str; 5] = ["id=\"root\"", "id=\"app\"", "id=\"__next\"", "id=\"__nuxt\"", "ng-app"];
+    text_chars < 200
+        && lower.contains("<script")
+        && (MOUNT_POINTS.iter().any(|m| lower.contains(m)) || lower.contains("enable javascript"))
+}
+
+fn visible_text(body: &str) -> String {
+    let stripped = invisible_regex().replace_all(body, " ");
+    let document = Html::parse_document(&stripped);
+    document
+        .root_element()
+        .text()
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn passes_normal_pages() {
+        let body = format!("<html><body><h1>Shop</h1><p>{}</p></body></html>", "Lamps and desks. ".repeat(20));
+        let diagnosis = diagnose_response("https://a.com", 200, &HashMap::new(), &body);
+        assert!(!diagnosis.is_blocked());
+        assert_eq!(diagnosis.recommendation, Recommendation::NoActionNeeded);
+        assert_eq!(diagnosis.body_bytes, body.len());
+    }
+
+    #[test]
+    fn detects_challenges_and_captchas() {
+        let challenge = "<title>Just a moment...</title><script src=\"/cdn-cgi/challenge-platform/x.js\"></script>";
+        let diagnosis = diagnose_response("https://a.com", 503, &headers(&[("server", "cloudflare")]), challenge);
+        assert_eq!(diagnosis.signals, vec![BlockSignal::CloudflareChallenge]);
+        assert_eq!(diagnosis.recommendation, Recommendation::UseHeadless);
+
+        let captcha = "<form><div class=\"g-recaptcha\"></div><script src=\"https://www.google.com/recaptcha/api.js\"></script></form>";
+        let diagnosis = diagnose_response("https://a.com", 200, &HashMap::new(), captcha);
+        assert_eq!(diagnosis.signals, vec![BlockSignal::Captcha { provider: "reCAPTCHA".to_string() }]);
+        assert_eq!(diagnosis.recommendation, Recommendation::SolveCaptcha);
+    }
+
+    #[test]
+    fn detects_bot_blocks_rate_limits_and_js_shells() {
+        let diagnosis = diagnose_response("https://a.com", 403, &HashMap::new(), "<p>Access denied: automated traffic</p>");
+        assert_eq!(diagnosis.signals, vec![BlockSignal::BotDetection { status: 403 }]);
+        assert_eq!(diagnosis.recommendation, Recommendation::UseProxy);
+
+        let diagnosis = diagnose_response("https://a.com", 429, &HashMap::new(), "slow down");
+        assert_eq!(diagnosis.recommendation, Recommendation::SlowDown);
+
+        let shell = "<html><body><div id=\"root\"></div><script src=\"/app.js\"></script></body></html>";
+        let diagnosis = diagnose_response("https://a.com", 200, &HashMap::new(), shell);
+        assert_eq!(diagnosis.signals, vec![BlockSignal::JsShell]);
+        assert_eq!(diagnosis.text_chars, 0);
+    }
+}
@@ -0,0 +1,244 @@
+//! Differences between the items of two pages.
+//!
+//! `diff_items` compares the items one selector extracted from two pages, e.g. staging and
+//! production; `diff_records` compares schema records matched by a key field, e.g. the
+//! products of a catalog before and after a price change. Both work on fetched pages
+//! (`RustScrapper::diff_pages`), on saved HTML (`diff_html`) and on `Fixture` snapshots
+//! (`diff_fixtures`).
+
+use crate::fixtures::Fixture;
+use crate::schema::Schema;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// An item present on both pages with a different value.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ItemChange<T> {
+    /// Value of the key field for records; `None` for selector items.
+    pub key: Option<String>,
+    pub before: T,
+    pub after: T,
+    /// Names of the record fields that differ. Empty for selector items.
+    pub fields: Vec<String>,
+}
+
+/// Items added, removed and changed between two pages.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PageDiff<T> {
+    pub added: Vec<T>,
+    pub removed: Vec<T>,
+    pub changed: Vec<ItemChange<T>>,
+    /// Number of items equal on both pages.
+    pub unchanged: usize,
+}
+
+impl<T> PageDiff<T> {
+    /// Whether both pages have the same items.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compares two lists of selector items. Items found on both pages are unchanged, whatever
+/// their position; of the remaining ones, the n-th removed and n-th added item in page order
+/// are paired as a change and the rest are reported as added or removed.
+pub fn diff_items(before: &[String], after: &[String]) -> PageDiff<String> {
+    let mut unmatched: HashMap<&str, usize> = HashMap::new();
+    for item in after {
+        *unmatched.entry(item.as_str()).or_default() += 1;
+    }
+    let mut removed = Vec::new();
+    for item in before {
+        match unmatched.get_mut(item.as_str()) {
+            Some(count) if *count > 0 => *count -= 1,
+            _ => removed.push(item.clone()),
+        }
+    }
+    let mut added = Vec::new();
+    for item in after.iter().rev() {
+        if let Some(count) = unmatched.get_mut(item.as_str()).filter(|count| **count > 0) {
+            *count -= 1;
+            added.push(item.clone());
+        }
+    }
+    added.reverse();
+
+    let unchanged = before.len() - removed.len();
+    let paired = removed.len().min(added.len());
+    let changed = removed
+        .drain(..paired)
+        .zip(added.drain(..paired))
+        .map(|(before, after)| ItemChange {
+            key: None,
+            before,
+            after,
+            fields: Vec::new(),
+        })
+        .collect();
+    PageDiff {
+        added,
+        removed,
+        changed,
+        unchanged,
+    }
+}
+
+/// Compares two lists of records matched by the value of `key`. Records without the key
+/// field are compared as a whole.
+pub fn diff_records(before: &[Value], after: &[Value], key: &str) -> PageDiff<Value> {
+    let key_of = |record: &Value| key_text(&record[key]);
+    let mut after_by_key: HashMap<String, &Value> = HashMap::new();
+    let mut after_unkeyed = Vec::new();
+    for record in after {
+        match key_of(record) {
+            Some(value) => {
+                after_by_key.entry(value).or_insert(record);
+            }
+            None => after_unkeyed.push(record),
+        }
+    }
+
+    let mut diff = PageDiff {
+        added: Vec::new(),
+        removed: Vec::new(),
+        changed: Vec::new(),
+        unchanged: 0,
+    };
+    for record in before {
+        let Some(value) = key_of(record) else {
+            match after_unkeyed.iter().position(|other| *other == record) {
+                Some(i) => {
+                    after_unkeyed.remove(i);
+                    diff.unchanged += 1;
+                }
+                None => diff.removed.push(record.clone()),
+            }
+            continue;
+        };
+        match after_by_key.remove(&value) {
+            Some(other) if other == record => diff.unchanged += 1,
+            Some(other) => diff.changed.push(ItemChange {
+                fields: changed_fields(record, other),
+                key: Some(value),
+                before: record.clone(),
+                after: other.clone(),
+            }),
+            None => diff.removed.push(record.clone()),
+        }
+    }
+    // Keep the added records in page order.
+    diff.added = after
+        .iter()
+        .filter(|record| match key_of(record) {
+            Some(value) => after_by_key.get(&value).is_some_and(|other| std::ptr::eq(*other, *record)),
+            None => after_unkeyed.iter().any(|other| std::ptr::eq(*other, *record)),
+        })
+        .cloned()
+        .collect();
+    diff
+}
+
+/// Extracts `selector` from two HTML documents and compares the items.
+pub fn diff_html(before: &str, after: &str, selector: &str) -> Result<PageDiff<String>, Box<dyn std::error::Error>> {
+    Ok(diff_items(&crate::extract(before, selector)?, &crate::extract(after, selector)?))
+}
+
+/// Compares the records saved in two fixtures of a page, e.g. from two days.
+/// With a schema, the records are extracted from the saved HTML again instead.
+pub fn diff_fixtures(
+    before: &Fixture,
+    after: &Fixture,
+    schema: Option<&Schema>,
+    key: &str,
+) -> Result<PageDiff<Value>, Box<dyn std::error::Error>> {
+    match schema {
+        Some(schema) => Ok(diff_records(&schema.extract(&before.html)?, &schema.extract(&after.html)?, key)),
+        None => Ok(diff_records(&before.results, &after.results, key)),
+    }
+}
+
+/// A key field value as text, `None` for a missing key.
+pub(crate) fn key_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(text) => Some(text.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+/// Names of the fields whose values differ between two records, in order of first appearance.
+fn changed_fields(before: &Value, after: &Value) -> Vec<String> {
+    let empty = serde_json::Map::new();
+    let before = before.as_object().unwrap_or(&empty);
+    let after = after.as_object().unwrap_or(&empty);
+    let mut fields = Vec::new();
+    for name in before.keys().chain(after.keys()) {
+        if before.get(name) != after.get(name) && !fields.contains(name) {
+            fields.push(name.clone());
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    fn items(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn diffs_selector_items_regardless_of_position() {
+        let diff = diff_items(&items(&["a", "b", "c", "d"]), &items(&["c", "a", "x", "y", "z"]));
+        assert_eq!(diff.unchanged, 2);
+        assert_eq!(
+            diff.changed,
+            vec![
+                ItemChange { key: None, before: "b".to_string(), after: "x".to_string(), fields: Vec::new() },
+                ItemChange { key: None, before: "d".to_string(), after: "y".to_string(), fields: Vec::new() },
+            ]
+        );
+        assert_eq!(diff.added, vec!["z"]);
+        assert!(diff.removed.is_empty());
+        assert!(diff_items(&items(&["a", "a"]), &items(&["a", "a"])).is_empty());
+    }
+
+    #[test]
+    fn diffs_records_by_key() {
+        let before = vec![
+            json!({"name": "Lamp", "price": 5, "stock": 1}),
+            json!({"name": "Desk", "price": 10}),
+            json!({"name": null, "price": 1}),
+        ];
+        let after = vec![
+            json!({"name": "Chair", "price": 7}),
+            json!({"name": "Lamp", "price": 6, "stock": 1}),
+            json!({"name": null, "price": 1}),
+        ];
+        let diff = diff_records(&before, &after, "name");
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].key.as_deref(), Some("Lamp"));
+        assert_eq!(diff.changed[0].fields, vec!["price"]);
+        assert_eq!(diff.removed, vec![json!({"name": "Desk", "price": 10})]);
+        assert_eq!(diff.added, vec![json!({"name": "Chair", "price": 7})]);
+    }
+
+    #[test]
+    fn diffs_html_and_fixtures() {
+        let diff = diff_html("<li>a</li><li>b</li>", "<li>a</li>", "li").unwrap();
+        assert_eq!(diff.removed, vec!["b"]);
+
+        let before = Fixture::new("https://a.com/", "<b>5</b>", vec![json!({"id": 1, "price": "4"})]);
+        let after = Fixture::new("https://a.com/", "<b>6</b>", vec![json!({"id": 1, "price": "4"})]);
+        assert!(diff_fixtures(&before, &after, None, "id").unwrap().is_empty());
+        let schema = Schema::new().field(crate::schema::Field::new("price", "b"));
+        let diff = diff_fixtures(&before, &after, Some(&schema), "id").unwrap();
+        assert_eq!(diff.removed, vec![json!({"price": "5"})]);
+        assert_eq!(diff.added, vec![json!({"price": "6"})]);
+    }
+}
@@ -0,0 +1,145 @@
+//! Partial and resumable downloads.
+//!
+//! `RustScrapper::fetch_prefix` downloads only the first bytes of a resource, enough to sniff
+//! its type or title. `RustScrapper::download` writes a resource to a file and, when the file
+//! already holds the beginning of it from an interrupted download, requests only the rest
+//! with a `Range` header, and an `If-Range` header so a changed resource is sent in full.
+
+use crate::schema::parse_selector;
+use reqwest::header::{HeaderMap, ETAG, LAST_MODIFIED};
+use scraper::Html;
+use serde::Serialize;
+
+/// The first bytes of a resource.
+#[derive(Debug, Clone, Serialize)]
+pub struct PartialBody {
+    pub url: String,
+    /// 206 if the server honored the range, 200 if it sent the whole body, which was cut off.
+    pub status: u16,
+    pub content_type: Option<String>,
+    /// Size of the full resource, if the server declares it.
+    pub total_length: Option<u64>,
+    pub bytes: Vec<u8>,
+}
+
+impl PartialBody {
+    /// The bytes as text; a multi-byte character cut off at the end is replaced.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.bytes).into_owned()
+    }
+
+    /// Whether the bytes are the whole resource.
+    pub fn is_complete(&self) -> bool {
+        self.total_length.is_some_and(|total| total <= self.bytes.len() as u64)
+    }
+
+    /// The `<title>` of an HTML document, if it is within the downloaded bytes.
+    pub fn title(&self) -> Option<String> {
+        let document = Html::parse_document(&self.text());
+        let title = document.select(&parse_selector("title").ok()?).next()?;
+        let title = title.text().collect::<String>().split_whitespace().collect::<Vec<_>>().join(" ");
+        Some(title).filter(|title| !title.is_empty())
+    }
+}
+
+/// The outcome of `RustScrapper::download`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Download {
+    pub path: String,
+    /// Bytes written by this call.
+    pub bytes_written: u64,
+    /// Size of the file after the download.
+    pub file_size: u64,
+    /// Whether an interrupted download was continued instead of started over.
+    pub resumed: bool,
+}
+
+/// The total size in a `Content-Range` header such as "bytes 0-99/12345", and the first
+/// byte of the range.
+pub(crate) fn parse_content_range(value: &str) -> (Option<u64>, Option<u64>) {
+    let range = value.trim().strip_prefix("bytes").unwrap_or(value).trim();
+    let (span, total) = range.split_once('/').unwrap_or((range, "*"));
+    let start = span.split_once('-').and_then(|(start, _)| start.trim().parse().ok());
+    (start, total.trim().parse().ok())
+}
+
+/// The file keeping the validator of an unfinished download to `path`: `<path>.validator`.
+pub fn validator_path(path: &str) -> String {
+    format!("{}.validator", path)
+}
+
+/// The `If-Range` validator of an unfinished download to `path`, if one was stored.
+pub(crate) fn read_validator(path: &str) -> Option<String> {
+    let validator = std::fs::read_to_string(validator_path(path)).ok()?;
+    Some(validator.trim().to_string()).filter(|validator| !validator.is_empty())
+}
+
+/// Stores the response's strong `ETag`, or else its `Last-Modified` date, for resuming the
+/// download to `path`. Weak ETags cannot be used in `If-Range`; without a validator, an
+/// interrupted download starts over.
+pub(crate) fn store_validator(path: &str, headers: &HeaderMap) -> std::io::Result<()> {
+    let header = |name| headers.get(name).and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok());
+    let validator = header(ETAG).filter(|etag| !etag.starts_with("W/")).or_else(|| header(LAST_MODIFIED));
+    match validator {
+        Some(validator) => std::fs::write(validator_path(path), validator),
+        None => {
+            remove_validator(path);
+            Ok(())
+        }
+    }
+}
+
+pub(crate) fn remove_validator(path: &str) {
+    let _ = std::fs::remove_file(validator_path(path));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partial(bytes: &str, total_length: Option<u64>) -> PartialBody {
+        PartialBody {
+            url: "https://a.com/".to_string(),
+            status: 206,
+            content_type: None,
+            total_length,
+            bytes: bytes.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn parses_content_ranges() {
+        assert_eq!(parse_content_range("bytes 100-199/12345"), (Some(100), Some(12345)));
+        assert_eq!(parse_content_range("bytes 0-99/*"), (Some(0), None));
+        assert_eq!(parse_content_range("bytes */500"), (None, Some(500)));
+    }
+
+    #[test]
+    fn reads_titles_from_the_first_bytes() {
+        let body = partial("<html><head><title>\n  Lamp   Shop </title></head><body><p>Lo", Some(4096));
+        assert_eq!(body.title().as_deref(), Some("Lamp Shop"));
+        assert!(!body.is_complete());
+        assert!(partial("<p>Hi</p>", Some(9)).is_complete());
+        assert_eq!(partial("<p>Hi</p>", None).title(), None);
+    }
+
+    #[test]
+    fn stores_only_strong_validators() {
+        let path = std::env::temp_dir().join(format!("rust-scrapper-validator-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, "\"v1\"".parse().unwrap());
+        store_validator(path, &headers).unwrap();
+        assert_eq!(read_validator(path).as_deref(), Some("\"v1\""));
+
+        headers.insert(ETAG, "W/\"v2\"".parse().unwrap());
+        store_validator(path, &headers).unwrap();
+        assert_eq!(read_validator(path), None);
+
+        headers.insert(LAST_MODIFIED, "Wed, 21 Oct 2026 07:28:00 GMT".parse().unwrap());
+        store_validator(path, &headers).unwrap();
+        assert_eq!(read_validator(path).as_deref(), Some("Wed, 21 Oct 2026 07:28:00 GMT"));
+        remove_validator(path);
+        assert_eq!(read_validator(path), None);
+    }
+}
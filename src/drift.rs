@@ -0,0 +1,196 @@
+//! Selector drift detection.
+//!
+//! A `DriftDetector` remembers how many elements each selector matched on recent pages.
+//! When a selector suddenly matches nothing, or far more or fewer elements than usual,
+//! it raises a `DriftWarning`, typically because the site changed its markup.
+
+use log::warn;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// How a selector's match count deviates from its history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum DriftKind {
+    /// The selector used to match elements and now matches none.
+    ZeroMatches,
+    /// The selector matches a count outside its usual range.
+    CountChanged,
+}
+
+/// A selector matching an unusual number of elements on a page.
+#[derive(Debug, Clone, Serialize)]
+pub struct DriftWarning {
+    pub selector: String,
+    pub url: String,
+    pub kind: DriftKind,
+    /// Elements matched on this page.
+    pub count: usize,
+    /// Average count over the recorded history.
+    pub baseline: f64,
+    /// Range of counts considered normal.
+    pub expected_min: usize,
+    pub expected_max: usize,
+}
+
+type DriftCallback = Box<dyn Fn(&DriftWarning) + Send + Sync>;
+
+/// Tracks selector match counts and reports drift. Clones share the same history.
+#[derive(Clone)]
+pub struct DriftDetector {
+    history: Arc<Mutex<HashMap<String, VecDeque<usize>>>>,
+    subscribers: Arc<Mutex<Vec<DriftCallback>>>,
+    window: usize,
+    min_samples: usize,
+    tolerance: f64,
+}
+
+impl DriftDetector {
+    /// Creates a detector comparing against the last 20 counts per selector, once at least
+    /// 5 are known, allowing counts 50% outside the observed range.
+    pub fn new() -> Self {
+        DriftDetector {
+            history: Arc::new(Mutex::new(HashMap::new())),
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+            window: 20,
+            min_samples: 5,
+            tolerance: 0.5,
+        }
+    }
+
+    /// Number of recent counts kept per selector.
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    /// Number of counts needed before a selector is checked.
+    pub fn with_min_samples(mut self, min_samples: usize) -> Self {
+        self.min_samples = min_samples.max(1);
+        self
+    }
+
+    /// Fraction of the average count by which the observed range is widened, e.g. 0.5.
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance.max(0.0);
+        self
+    }
+
+    /// Calls `callback` for every warning, e.g. to alert a monitoring system.
+    pub fn on_drift<F>(self, callback: F) -> Self
+    where
+        F: Fn(&DriftWarning) + Send + Sync + 'static,
+    {
+        self.subscribers.lock().unwrap().push(Box::new(callback));
+        self
+    }
+
+    /// Records the number of elements `selector` matched on `url` and returns a warning
+    /// if it drifted. The count joins the history either way, so a lasting change becomes
+    /// the new normal once it fills the window.
+    pub fn record(&self, selector: &str, url: &str, count: usize) -> Option<DriftWarning> {
+        let warning = {
+            let mut history = self.history.lock().unwrap();
+            let counts = history.entry(selector.to_string()).or_default();
+            let warning = self.check(counts, selector, url, count);
+            counts.push_back(count);
+            while counts.len() > self.window {
+                counts.pop_front();
+            }
+            warning
+        }?;
+        warn!(
+            "Selector {:?} matched {} elements on {}, expected {}..={}",
+            warning.selector, warning.count, warning.url, warning.expected_min, warning.expected_max
+        );
+        for subscriber in self.subscribers.lock().unwrap().iter() {
+            subscriber(&warning);
+        }
+        Some(warning)
+    }
+
+    /// Clears the history of a selector, e.g. after fixing it.
+    pub fn reset(&self, selector: &str) {
+        self.history.lock().unwrap().remove(selector);
+    }
+
+    fn check(&self, counts: &VecDeque<usize>, selector: &str, url: &str, count: usize) -> Option<DriftWarning> {
+        if counts.len() < self.min_samples {
+            return None;
+        }
+        let baseline = counts.iter().sum::<usize>() as f64 / counts.len() as f64;
+        let slack = baseline * self.tolerance;
+        let min = *counts.iter().min()?;
+        let max = *counts.iter().max()?;
+        let expected_min = (min as f64 - slack).max(0.0).floor() as usize;
+        let expected_max = (max as f64 + slack).ceil() as usize;
+        let kind = if count == 0 && min > 0 {
+            DriftKind::ZeroMatches
+        } else if count < expected_min || count > expected_max {
+            DriftKind::CountChanged
+        } else {
+            return None;
+        };
+        Some(DriftWarning {
+            selector: selector.to_string(),
+            url: url.to_string(),
+            kind,
+            count,
+            baseline,
+            expected_min,
+            expected_max,
+        })
+    }
+}
+
+impl Default for DriftDetector {
+    fn default() -> Self {
+        DriftDetector::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn trained(detector: &DriftDetector) {
+        for count in [8, 10, 12] {
+            assert!(detector.record("h2", "https://a.com/", count).is_none());
+        }
+    }
+
+    #[test]
+    fn warns_when_counts_leave_the_usual_range() {
+        let detector = DriftDetector::new().with_min_samples(3);
+        trained(&detector);
+        assert!(detector.record("h2", "https://a.com/", 16).is_none());
+
+        let warning = detector.record("h2", "https://a.com/", 0).unwrap();
+        assert_eq!(warning.kind, DriftKind::ZeroMatches);
+        assert_eq!((warning.expected_min, warning.expected_max), (2, 22));
+
+        let warning = detector.record("h2", "https://a.com/", 40).unwrap();
+        assert_eq!(warning.kind, DriftKind::CountChanged);
+        assert_eq!(warning.selector, "h2");
+    }
+
+    #[test]
+    fn notifies_subscribers_and_forgets_reset_selectors() {
+        let warnings = Arc::new(AtomicUsize::new(0));
+        let counter = warnings.clone();
+        let detector = DriftDetector::new()
+            .with_min_samples(3)
+            .on_drift(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            });
+        trained(&detector);
+        detector.record("h2", "https://a.com/", 0);
+        assert_eq!(warnings.load(Ordering::SeqCst), 1);
+
+        detector.reset("h2");
+        assert!(detector.record("h2", "https://a.com/", 0).is_none());
+        assert_eq!(warnings.load(Ordering::SeqCst), 1);
+    }
+}
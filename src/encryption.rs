@@ -0,0 +1,261 @@
+//! At-rest encryption of persistent state.
+//!
+//! Cached pages, snapshots and saved sessions can contain personal data and credentials. An
+//! `EncryptedStorage` wraps any `Storage` and encrypts every value with AES-256-GCM under a
+//! user-supplied `EncryptionKey` before it reaches the backend, so nothing is written to disk
+//! or Redis in plaintext. Keys (URLs and cache keys) stay readable, as prefix scans need them,
+//! and are bound to their values as associated data, so a value moved to another key fails to
+//! decrypt. Enabled by the `encryption` feature.
+
+use crate::cache::CacheBackend;
+use crate::storage::Storage;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::Engine;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Marks encrypted values, so plaintext entries written before encryption was enabled are
+/// recognized instead of failing to decrypt.
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+
+/// A 256-bit AES-GCM key. Keep it outside the storage it protects, e.g. in an environment
+/// variable or a secrets manager; data encrypted under a lost key cannot be recovered.
+#[derive(Clone)]
+pub struct EncryptionKey {
+    bytes: [u8; 32],
+    cipher: Aes256Gcm,
+}
+
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+impl EncryptionKey {
+    /// A new random key. Save `to_base64` of it, as it is needed to read the data again.
+    pub fn generate() -> Self {
+        EncryptionKey::from_bytes(&Aes256Gcm::generate_key(OsRng).into())
+    }
+
+    pub fn from_bytes(bytes: &[u8; 32]) -> Self {
+        EncryptionKey {
+            bytes: *bytes,
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(bytes)),
+        }
+    }
+
+    /// Reads a key from 32 base64-encoded bytes, as printed by `to_base64`.
+    pub fn from_base64(encoded: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let bytes = base64::engine::general_purpose::STANDARD.decode(encoded.trim())?;
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| "encryption key must be 32 bytes")?;
+        Ok(EncryptionKey::from_bytes(&bytes))
+    }
+
+    /// Reads a base64 key from an environment variable, e.g. `SCRAPER_KEY`.
+    pub fn from_env(name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let encoded = std::env::var(name).map_err(|_| format!("environment variable {} is not set", name))?;
+        EncryptionKey::from_base64(&encoded)
+    }
+
+    /// The key as base64, for `from_base64`.
+    pub fn to_base64(&self) -> String {
+        base64::engine::general_purpose::STANDARD.encode(self.bytes)
+    }
+
+    /// Encrypts `plaintext` under a random nonce. The result is the nonce followed by the
+    /// ciphertext and its authentication tag.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        self.encrypt_with(plaintext, &[])
+    }
+
+    /// Like `encrypt`, authenticating `associated_data` too, e.g. the name the data is stored
+    /// under: decryption only succeeds with the same associated data.
+    pub fn encrypt_with(&self, plaintext: &[u8], associated_data: &[u8]) -> Vec<u8> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: plaintext,
+            aad: associated_data,
+        };
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, payload)
+            .expect("AES-GCM encryption of in-memory data cannot fail");
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+
+    /// Decrypts data sealed by `encrypt`. Fails if the data was encrypted under another key or
+    /// has been tampered with.
+    pub fn decrypt(&self, sealed: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        self.decrypt_with(sealed, &[])
+    }
+
+    /// Decrypts data sealed by `encrypt_with`. Fails unless `associated_data` is the same.
+    pub fn decrypt_with(&self, sealed: &[u8], associated_data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if sealed.len() < NONCE_LEN {
+            return Err("encrypted data is truncated".into());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: associated_data,
+        };
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| "decryption failed: wrong key, wrong associated data or corrupted data".into())
+    }
+
+    /// Encrypts a string to printable text, for values of text stores.
+    pub fn encrypt_string(&self, plaintext: &str) -> String {
+        self.encrypt_string_with(plaintext, "")
+    }
+
+    /// Like `encrypt_string`, authenticating `associated_data` too, see `encrypt_with`.
+    pub fn encrypt_string_with(&self, plaintext: &str, associated_data: &str) -> String {
+        let sealed = self.encrypt_with(plaintext.as_bytes(), associated_data.as_bytes());
+        format!("{}{}", ENCRYPTED_PREFIX, base64::engine::general_purpose::STANDARD.encode(sealed))
+    }
+
+    /// Decrypts a string made by `encrypt_string`.
+    pub fn decrypt_string(&self, encrypted: &str) -> Result<String, Box<dyn std::error::Error>> {
+        self.decrypt_string_with(encrypted, "")
+    }
+
+    /// Decrypts a string made by `encrypt_string_with` with the same associated data.
+    pub fn decrypt_string_with(&self, encrypted: &str, associated_data: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let encoded = encrypted
+            .strip_prefix(ENCRYPTED_PREFIX)
+            .ok_or("value is not encrypted")?;
+        let sealed = base64::engine::general_purpose::STANDARD.decode(encoded)?;
+        Ok(String::from_utf8(self.decrypt_with(&sealed, associated_data.as_bytes())?)?)
+    }
+}
+
+/// A storage encrypting every value before it reaches the wrapped backend, with its storage
+/// key as associated data.
+///
+/// Values that cannot be decrypted (another key, tampering, a value copied from another
+/// storage key, or plaintext written before encryption was enabled) read as absent, so the
+/// cache refetches them and overwrites them encrypted.
+#[derive(Clone)]
+pub struct EncryptedStorage {
+    storage: Arc<dyn Storage>,
+    key: EncryptionKey,
+}
+
+impl EncryptedStorage {
+    pub fn new(storage: Arc<dyn Storage>, key: EncryptionKey) -> Self {
+        EncryptedStorage { storage, key }
+    }
+}
+
+impl CacheBackend for EncryptedStorage {
+    fn get(&self, key: &str) -> Option<String> {
+        let value = self.storage.get(key)?;
+        match self.key.decrypt_string_with(&value, key) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                log::warn!("Ignoring storage entry {}: {}", key, e);
+                None
+            }
+        }
+    }
+
+    fn set(&self, key: &str, value: String, ttl: Option<Duration>) {
+        self.storage.set(key, self.key.encrypt_string_with(&value, key), ttl)
+    }
+
+    fn remove(&self, key: &str) -> bool {
+        self.storage.remove(key)
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.storage.keys()
+    }
+
+    fn clear(&self) {
+        self.storage.clear()
+    }
+
+    /// Size of the encrypted entry.
+    fn entry_info(&self, key: &str) -> Option<(usize, SystemTime)> {
+        self.storage.entry_info(key)
+    }
+}
+
+impl Storage for EncryptedStorage {
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.storage.keys_with_prefix(prefix)
+    }
+
+    fn first_key(&self, prefix: &str) -> Option<String> {
+        self.storage.first_key(prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::storage::FileStorage;
+
+    #[test]
+    fn round_trips_keys_and_values() {
+        let key = EncryptionKey::generate();
+        let same = EncryptionKey::from_base64(&key.to_base64()).unwrap();
+        let sealed = key.encrypt(b"secret");
+        assert_ne!(key.encrypt(b"secret"), sealed);
+        assert_eq!(same.decrypt(&sealed).unwrap(), b"secret");
+        assert!(EncryptionKey::generate().decrypt(&sealed).is_err());
+        assert!(key.decrypt(&sealed[..4]).is_err());
+        assert!(EncryptionKey::from_base64("c2hvcnQ=").is_err());
+        assert_eq!(format!("{:?}", key), "EncryptionKey(..)");
+    }
+
+    #[test]
+    fn stores_only_encrypted_values() {
+        let backend: Arc<dyn Storage> = Arc::new(MemoryCache::new());
+        let key = EncryptionKey::generate();
+        let storage = EncryptedStorage::new(backend.clone(), key.clone());
+        storage.set("cache/page", "cookie=abc".to_string(), None);
+        let stored = backend.get("cache/page").unwrap();
+        assert!(stored.starts_with(ENCRYPTED_PREFIX));
+        assert!(!stored.contains("cookie"));
+        assert_eq!(storage.get("cache/page").as_deref(), Some("cookie=abc"));
+        assert_eq!(storage.keys_with_prefix("cache/"), vec!["cache/page"]);
+
+        backend.set("cache/plain", "written before encryption".to_string(), None);
+        assert_eq!(storage.get("cache/plain"), None);
+        let other = EncryptedStorage::new(backend, EncryptionKey::generate());
+        assert_eq!(other.get("cache/page"), None);
+    }
+
+    #[test]
+    fn round_trips_strings() {
+        let key = EncryptionKey::generate();
+        let sealed = key.encrypt_string_with("secret", "page:a");
+        assert!(sealed.starts_with(ENCRYPTED_PREFIX));
+        assert_eq!(key.decrypt_string_with(&sealed, "page:a").unwrap(), "secret");
+        assert!(key.decrypt_string_with(&sealed, "page:b").is_err());
+        assert!(EncryptionKey::generate().decrypt_string_with(&sealed, "page:a").is_err());
+    }
+
+    #[test]
+    fn values_moved_to_another_key_do_not_decrypt() {
+        let dir = std::env::temp_dir().join(format!("rust-scrapper-encryption-{}", std::process::id()));
+        let backend: Arc<dyn Storage> = Arc::new(FileStorage::open(dir.to_str().unwrap()).unwrap());
+        let storage = EncryptedStorage::new(backend.clone(), EncryptionKey::generate());
+        storage.set("body:https://a.example/", "page a".to_string(), None);
+        assert_eq!(CacheBackend::get(&storage, "body:https://a.example/").as_deref(), Some("page a"));
+        let sealed = backend.get("body:https://a.example/").unwrap();
+        backend.set("body:https://b.example/", sealed, None);
+        assert_eq!(CacheBackend::get(&storage, "body:https://b.example/"), None);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,126 @@
+//! Entity extraction helpers.
+//!
+//! Pulls emails, phone numbers and URLs out of scraped text. Every extractor returns
+//! normalized values, deduplicated in order of first appearance.
+
+use regex::Regex;
+use std::collections::HashSet;
+use std::sync::OnceLock;
+use url::Url;
+
+fn email_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?i)\b[a-z0-9._%+-]+@[a-z0-9-]+(?:\.[a-z0-9-]+)*\.[a-z]{2,}\b").unwrap())
+}
+
+fn phone_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?:\+|\b00)?\(?\d[\d\s().-]{6,}\d\b").unwrap())
+}
+
+fn date_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"^\d{4}[-./]\d{1,2}[-./]\d{1,2}$|^\d{1,2}[-./]\d{1,2}[-./]\d{4}$").unwrap())
+}
+
+fn url_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r#"(?i)\b(?:https?://|www\.)[^\s<>"'`]+"#).unwrap())
+}
+
+/// Extracts email addresses, lowercased.
+pub fn extract_emails(text: &str) -> Vec<String> {
+    dedup(
+        email_regex()
+            .find_iter(text)
+            .map(|m| m.as_str().trim_end_matches('.').to_lowercase()),
+    )
+}
+
+/// Extracts phone numbers, normalized to digits with an optional leading `+`.
+/// A leading `00` international prefix is rewritten to `+`. Numbers with fewer than
+/// 7 or more than 15 digits (the E.164 maximum) are ignored, and so are prices grouped in
+/// thousands and lists of numbers with mixed separators.
+pub fn extract_phones(text: &str) -> Vec<String> {
+    dedup(phone_regex().find_iter(text).filter_map(|m| normalize_phone(m.as_str())))
+}
+
+/// Extracts http(s) URLs. Bare `www.` hosts get an `http://` scheme and trailing
+/// punctuation picked up from the surrounding sentence is removed.
+pub fn extract_urls(text: &str) -> Vec<String> {
+    dedup(url_regex().find_iter(text).filter_map(|m| normalize_url(m.as_str())))
+}
+
+fn normalize_phone(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+    // The redaction rules tell prices such as "1.299.999" or "29.99 19.99" from phone numbers.
+    if date_regex().is_match(raw) || !crate::redact::is_phone_number(raw) {
+        return None;
+    }
+    let mut digits: String = raw.chars().filter(|c| c.is_ascii_digit()).collect();
+    let international = raw.starts_with('+') || raw.starts_with("00");
+    if raw.starts_with("00") {
+        digits = digits[2..].to_string();
+    }
+    if !(7..=15).contains(&digits.len()) {
+        return None;
+    }
+    Some(if international { format!("+{}", digits) } else { digits })
+}
+
+fn normalize_url(raw: &str) -> Option<String> {
+    let mut candidate = raw.trim_end_matches(|c: char| ".,;:!?".contains(c)).to_string();
+    // Drop an unbalanced closing parenthesis, e.g. "(see https://example.com)".
+    while candidate.ends_with(')') && candidate.matches('(').count() < candidate.matches(')').count() {
+        candidate.pop();
+    }
+    if candidate.to_lowercase().starts_with("www.") {
+        candidate = format!("http://{}", candidate);
+    }
+    Url::parse(&candidate).ok().map(|url| url.to_string())
+}
+
+fn dedup(values: impl Iterator<Item = String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    values.filter(|value| seen.insert(value.clone())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_emails_lowercased_and_deduplicated() {
+        let text = "Write to Sales@Example.COM or support@example.co.uk. Again: sales@example.com.";
+        assert_eq!(extract_emails(text), vec!["sales@example.com", "support@example.co.uk"]);
+    }
+
+    #[test]
+    fn normalizes_phone_numbers() {
+        let text = "Call +44 20 7946 0958, 0044 20 7946 0958 or (555) 123-4567.";
+        assert_eq!(extract_phones(text), vec!["+442079460958", "5551234567"]);
+    }
+
+    #[test]
+    fn extracts_urls_without_trailing_punctuation() {
+        let text = "See https://example.com/a?b=1, www.example.org. (or https://example.com/x_(y))";
+        assert_eq!(
+            extract_urls(text),
+            vec!["https://example.com/a?b=1", "http://www.example.org/", "https://example.com/x_(y)"]
+        );
+    }
+
+    #[test]
+    fn ignores_malformed_entities() {
+        assert!(extract_emails("user@ @example.com user@localhost a@b.c").is_empty());
+        assert!(extract_phones("Call 123-45, dated 2024-03-12 or 12.03.2024, id 1234567890123456").is_empty());
+        assert!(extract_urls("ftp://example.com, http//example.com, https://").is_empty());
+    }
+
+    #[test]
+    fn ignores_prices() {
+        assert!(extract_phones("Now 1.299.999 TL, was 1.499.999 TL").is_empty());
+        assert!(extract_phones("Prices: 29.99 19.99 9.99").is_empty());
+        assert_eq!(extract_phones("Price 1.299 TL, call 0532 123 45 67"), vec!["05321234567"]);
+    }
+}
@@ -0,0 +1,65 @@
+//! Typed scraping errors.
+//!
+//! Operations return `Box<dyn Error>`. Failures that callers typically handle in a specific
+//! way are reported as a `ScrapeError`, which `ScrapeError::find` locates in an error's
+//! source chain, e.g. behind a `ContextError`.
+
+use crate::captcha::CaptchaChallenge;
+use crate::context::find_cause;
+use std::error::Error;
+use std::fmt;
+
+/// A failure callers may want to react to, e.g. by solving a CAPTCHA or going online.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum ScrapeError {
+    /// The page shows a CAPTCHA and no solver is configured, or the solution was rejected.
+    CaptchaRequired(CaptchaChallenge),
+    /// The scrapper is offline and the page is not in the cache.
+    CacheMiss { url: String },
+    /// The compliance guardrails refuse the URL, e.g. as its domain is blocklisted.
+    Disallowed { url: String, reason: String },
+}
+
+impl ScrapeError {
+    /// The `ScrapeError` in the chain of `error` and its sources, if any.
+    pub fn find<'a>(error: &'a (dyn Error + 'static)) -> Option<&'a ScrapeError> {
+        find_cause::<ScrapeError>(error)
+    }
+}
+
+impl fmt::Display for ScrapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScrapeError::CaptchaRequired(challenge) => write!(f, "{} CAPTCHA required on {}", challenge.provider, challenge.page_url),
+            ScrapeError::CacheMiss { url } => write!(f, "{} is not cached and the scrapper is offline", url),
+            ScrapeError::Disallowed { url, reason } => write!(f, "{} may not be scraped: {}", url, reason),
+        }
+    }
+}
+
+impl Error for ScrapeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::captcha::CaptchaProvider;
+    use crate::context::{ContextError, RequestContext};
+
+    #[test]
+    fn finds_scrape_errors_behind_contexts() {
+        let challenge = CaptchaChallenge {
+            provider: CaptchaProvider::HCaptcha,
+            site_key: None,
+            page_url: "https://shop.com/".to_string(),
+        };
+        let error: Box<dyn Error> = Box::new(ContextError {
+            context: RequestContext::new("order-42"),
+            error: Box::new(ScrapeError::CaptchaRequired(challenge)),
+        });
+        assert_eq!(error.to_string(), "[order-42] hCaptcha CAPTCHA required on https://shop.com/");
+        assert!(matches!(ScrapeError::find(error.as_ref()), Some(ScrapeError::CaptchaRequired(_))));
+        assert!(ScrapeError::find(<Box<dyn Error>>::from("timeout").as_ref()).is_none());
+    }
+}
@@ -0,0 +1,110 @@
+//! Crawl lifecycle events.
+//!
+//! The crawler emits a `CrawlEvent` at every step. Applications can observe them either
+//! by registering an `EventSubscriber` (any `Fn(&CrawlEvent)` closure works) or by
+//! receiving from a tokio broadcast channel.
+
+use crate::drift::DriftWarning;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Something that happened during a crawl.
+/// Events carry the correlation ID of the crawl's `RequestContext`, if it has one.
+#[derive(Debug, Clone, serde::Serialize)]
+pub enum CrawlEvent {
+    RequestStarted { url: String, attempt: u32, correlation_id: Option<String> },
+    ResponseReceived { url: String, bytes: usize, elapsed: Duration, correlation_id: Option<String> },
+    ItemExtracted { url: String, item: String, correlation_id: Option<String> },
+    ErrorOccurred { url: String, error: String, attempt: u32, correlation_id: Option<String> },
+    /// The item selector matched an unusual number of elements, see `DriftDetector`.
+    SelectorDrift { warning: DriftWarning },
+    CrawlFinished { pages: usize, failures: usize, elapsed: Duration, correlation_id: Option<String> },
+}
+
+/// Receives crawl events synchronously, in the order they happen.
+pub trait EventSubscriber: Send + Sync {
+    fn on_event(&self, event: &CrawlEvent);
+}
+
+impl<F> EventSubscriber for F
+where
+    F: Fn(&CrawlEvent) + Send + Sync,
+{
+    fn on_event(&self, event: &CrawlEvent) {
+        self(event)
+    }
+}
+
+/// Dispatches events to subscribers and broadcast receivers.
+pub struct EventBus {
+    subscribers: Vec<Box<dyn EventSubscriber>>,
+    sender: broadcast::Sender<CrawlEvent>,
+}
+
+impl EventBus {
+    /// Creates a bus whose broadcast channel buffers up to `capacity` events per receiver.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        EventBus {
+            subscribers: Vec::new(),
+            sender,
+        }
+    }
+
+    /// Registers a subscriber.
+    pub fn subscribe<S: EventSubscriber + 'static>(&mut self, subscriber: S) {
+        self.subscribers.push(Box::new(subscriber));
+    }
+
+    /// Returns a new broadcast receiver. Receivers that fall behind lose the oldest events.
+    pub fn receiver(&self) -> broadcast::Receiver<CrawlEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Sends an event to all subscribers and receivers.
+    pub fn emit(&self, event: CrawlEvent) {
+        for subscriber in &self.subscribers {
+            subscriber.on_event(&event);
+        }
+        // Sending only fails when nobody is listening.
+        let _ = self.sender.send(event);
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        EventBus::new(1024)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn dispatches_to_subscribers_and_receivers() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut bus = EventBus::new(4);
+        let sink = seen.clone();
+        bus.subscribe(move |event: &CrawlEvent| sink.lock().unwrap().push(format!("{:?}", event)));
+        let mut receiver = bus.receiver();
+
+        bus.emit(CrawlEvent::RequestStarted {
+            url: "https://a.com/".to_string(),
+            attempt: 1,
+            correlation_id: None,
+        });
+        bus.emit(CrawlEvent::ItemExtracted {
+            url: "https://a.com/".to_string(),
+            item: "x".to_string(),
+            correlation_id: Some("order-42".to_string()),
+        });
+
+        assert_eq!(seen.lock().unwrap().len(), 2);
+        assert!(matches!(receiver.try_recv(), Ok(CrawlEvent::RequestStarted { attempt: 1, .. })));
+        assert!(matches!(receiver.try_recv(), Ok(CrawlEvent::ItemExtracted { .. })));
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,189 @@
+//! Compressed export files.
+//!
+//! Exports of large crawls are mostly repetitive text and compress well. Every exporter that
+//! writes a file (`Exporter::to_csv`, `records_to_csv`, `records_to_json_file`, `CsvSink`,
+//! `JsonLinesSink` and `Crawler::export_jsonl`) compresses it when its name ends with `.gz`
+//! (gzip) or `.zst` (zstd, with the `zstd` feature), e.g. `products.jsonl.gz`.
+//!
+//! Appending to a compressed file adds a new gzip member or zstd frame. Standard tools such
+//! as `zcat` and `zstdcat` read such files as one stream.
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Compression of an export file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl ExportCompression {
+    /// The compression implied by a file name: `.gz` for gzip, `.zst` or `.zstd` for zstd.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match extension.as_str() {
+            "gz" => ExportCompression::Gzip,
+            "zst" | "zstd" => ExportCompression::Zstd,
+            _ => ExportCompression::None,
+        }
+    }
+}
+
+enum Encoder {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, BufWriter<File>>),
+}
+
+/// A buffered writer to an export file, compressing as its name implies. Call `finish` to
+/// complete the file and see errors; dropping the writer finishes it too, ignoring them.
+pub struct ExportWriter {
+    encoder: Option<Encoder>,
+}
+
+impl ExportWriter {
+    /// Creates or truncates the file.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        ExportWriter::open(path.as_ref(), false)
+    }
+
+    /// Appends to the file, creating it if it does not exist.
+    pub fn append(path: impl AsRef<Path>) -> io::Result<Self> {
+        ExportWriter::open(path.as_ref(), true)
+    }
+
+    fn open(path: &Path, append: bool) -> io::Result<Self> {
+        let compression = ExportCompression::from_path(path);
+        #[cfg(not(feature = "zstd"))]
+        if compression == ExportCompression::Zstd {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "zstd exports require the `zstd` feature"));
+        }
+        let file = if append {
+            OpenOptions::new().create(true).append(true).open(path)?
+        } else {
+            File::create(path)?
+        };
+        let writer = BufWriter::new(file);
+        let encoder = match compression {
+            ExportCompression::None => Encoder::Plain(writer),
+            ExportCompression::Gzip => Encoder::Gzip(GzEncoder::new(writer, Compression::default())),
+            #[cfg(feature = "zstd")]
+            ExportCompression::Zstd => Encoder::Zstd(zstd::stream::write::Encoder::new(writer, 0)?),
+            #[cfg(not(feature = "zstd"))]
+            ExportCompression::Zstd => unreachable!(),
+        };
+        Ok(ExportWriter { encoder: Some(encoder) })
+    }
+
+    /// Writes the end of the compressed stream, flushes the file and syncs it to disk.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.complete()
+    }
+
+    fn complete(&mut self) -> io::Result<()> {
+        let writer = match self.encoder.take() {
+            Some(Encoder::Plain(writer)) => writer,
+            Some(Encoder::Gzip(encoder)) => encoder.finish()?,
+            #[cfg(feature = "zstd")]
+            Some(Encoder::Zstd(encoder)) => encoder.finish()?,
+            None => return Ok(()),
+        };
+        writer.into_inner().map_err(io::IntoInnerError::into_error)?.sync_data()
+    }
+
+    fn encoder(&mut self) -> io::Result<&mut dyn Write> {
+        match &mut self.encoder {
+            Some(Encoder::Plain(writer)) => Ok(writer),
+            Some(Encoder::Gzip(encoder)) => Ok(encoder),
+            #[cfg(feature = "zstd")]
+            Some(Encoder::Zstd(encoder)) => Ok(encoder),
+            None => Err(io::Error::new(io::ErrorKind::BrokenPipe, "Export file is finished")),
+        }
+    }
+}
+
+impl Write for ExportWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder()?.write(buf)
+    }
+
+    /// Flushes buffered data to the file. A compressed file is only complete after `finish`.
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder()?.flush()
+    }
+}
+
+impl Drop for ExportWriter {
+    fn drop(&mut self) {
+        let _ = self.complete();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("rust-scrapper-export-{}-{}", std::process::id(), name))
+    }
+
+    fn gunzip(path: &Path) -> String {
+        let mut contents = String::new();
+        MultiGzDecoder::new(File::open(path).unwrap()).read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn picks_compression_from_the_extension() {
+        assert_eq!(ExportCompression::from_path("items.jsonl.gz"), ExportCompression::Gzip);
+        assert_eq!(ExportCompression::from_path("items.CSV.ZST"), ExportCompression::Zstd);
+        assert_eq!(ExportCompression::from_path("items.zstd"), ExportCompression::Zstd);
+        assert_eq!(ExportCompression::from_path("items.json"), ExportCompression::None);
+        assert_eq!(ExportCompression::from_path("items"), ExportCompression::None);
+    }
+
+    #[test]
+    fn appends_gzip_members_read_as_one_stream() {
+        let path = temp_path("items.jsonl.gz");
+        let mut writer = ExportWriter::create(&path).unwrap();
+        writer.write_all(b"{\"a\":1}\n").unwrap();
+        writer.finish().unwrap();
+        let mut writer = ExportWriter::append(&path).unwrap();
+        writer.write_all(b"{\"a\":2}\n").unwrap();
+        drop(writer);
+        let contents = gunzip(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "{\"a\":1}\n{\"a\":2}\n");
+    }
+
+    #[test]
+    fn writes_plain_files_unchanged() {
+        let path = temp_path("items.csv");
+        let mut writer = ExportWriter::create(&path).unwrap();
+        writer.write_all(b"name\nLamp\n").unwrap();
+        writer.finish().unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "name\nLamp\n");
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn requires_the_zstd_feature_for_zst_files() {
+        let error = ExportWriter::create(temp_path("items.zst")).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+    }
+}
@@ -0,0 +1,194 @@
+//! C API, built with the `ffi` feature.
+//!
+//! Build the shared library with `cargo rustc --release --lib --crate-type cdylib --features ffi`.
+//! It exposes a small `extern "C"` surface declared in `include/rust_scrapper.h`. Results are
+//! returned as NUL-terminated JSON strings owned by the library: release them with
+//! `rust_scrapper_string_free`. Functions returning a pointer return NULL on failure, panics
+//! included; `rust_scrapper_last_error` then describes what went wrong.
+
+use crate::schema::Schema;
+use crate::{RustScrapper, Scraper};
+use std::cell::RefCell;
+use std::any::Any;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Creates a scraper. Release it with `rust_scrapper_free`.
+#[no_mangle]
+pub extern "C" fn rust_scrapper_new() -> *mut RustScrapper {
+    catch_panic(ptr::null_mut(), || Box::into_raw(Box::new(RustScrapper::new())))
+}
+
+/// Releases a scraper created by `rust_scrapper_new`.
+///
+/// # Safety
+/// `scrapper` must be NULL or a pointer returned by `rust_scrapper_new` that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn rust_scrapper_free(scrapper: *mut RustScrapper) {
+    catch_panic((), || {
+        if !scrapper.is_null() {
+            drop(Box::from_raw(scrapper));
+        }
+    })
+}
+
+/// Scrapes a page and returns the inner HTML of every match as a JSON array of strings.
+///
+/// # Safety
+/// `scrapper` must come from `rust_scrapper_new`; `url` and `selector` must be valid C strings.
+#[no_mangle]
+pub unsafe extern "C" fn rust_scrapper_scrape(
+    scrapper: *mut RustScrapper,
+    url: *const c_char,
+    selector: *const c_char,
+) -> *mut c_char {
+    with_result(|| {
+        let scrapper = scrapper.as_mut().ok_or("Scrapper is NULL")?;
+        let results = scrapper.scrape(str_arg(url)?, str_arg(selector)?)?;
+        Ok(serde_json::to_string(&results)?)
+    })
+}
+
+/// Scrapes structured records described by a JSON `Schema` and returns them as a JSON array.
+///
+/// # Safety
+/// `scrapper` must come from `rust_scrapper_new`; `url` and `schema_json` must be valid C strings.
+#[no_mangle]
+pub unsafe extern "C" fn rust_scrapper_scrape_structured(
+    scrapper: *mut RustScrapper,
+    url: *const c_char,
+    schema_json: *const c_char,
+) -> *mut c_char {
+    with_result(|| {
+        let scrapper = scrapper.as_mut().ok_or("Scrapper is NULL")?;
+        let schema: Schema = serde_json::from_str(str_arg(schema_json)?)?;
+        let records = scrapper.scrape_structured(str_arg(url)?, &schema)?;
+        Ok(serde_json::to_string(&records)?)
+    })
+}
+
+/// Returns the message of the last error on this thread, or NULL if there was none.
+/// The message must be released with `rust_scrapper_string_free`.
+#[no_mangle]
+pub extern "C" fn rust_scrapper_last_error() -> *mut c_char {
+    catch_panic(ptr::null_mut(), || {
+        LAST_ERROR.with(|last| match last.borrow().as_deref() {
+            Some(message) => to_c_string(message),
+            None => ptr::null_mut(),
+        })
+    })
+}
+
+/// Releases a string returned by this library.
+///
+/// # Safety
+/// `s` must be NULL or a string returned by this library that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn rust_scrapper_string_free(s: *mut c_char) {
+    catch_panic((), || {
+        if !s.is_null() {
+            drop(CString::from_raw(s));
+        }
+    })
+}
+
+/// Runs `f`, returning its output as a C string, or NULL after recording the error.
+fn with_result<F>(f: F) -> *mut c_char
+where
+    F: FnOnce() -> Result<String, Box<dyn std::error::Error>>,
+{
+    catch_panic(ptr::null_mut(), || match f() {
+        Ok(output) => {
+            set_last_error(None);
+            to_c_string(&output)
+        }
+        Err(e) => {
+            set_last_error(Some(e.to_string()));
+            ptr::null_mut()
+        }
+    })
+}
+
+/// Runs `f`, returning `on_panic` after recording the panic as the last error instead of
+/// unwinding into the C caller, which is undefined behavior.
+fn catch_panic<T>(on_panic: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        set_last_error(Some(format!("Panic: {}", panic_message(payload.as_ref()))));
+        on_panic
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload.downcast_ref::<String>().map_or("unknown panic", String::as_str),
+    }
+}
+
+fn set_last_error(message: Option<String>) {
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+unsafe fn str_arg<'a>(s: *const c_char) -> Result<&'a str, Box<dyn std::error::Error>> {
+    if s.is_null() {
+        return Err("String argument is NULL".into());
+    }
+    Ok(CStr::from_ptr(s).to_str()?)
+}
+
+/// Converts to a C string, dropping interior NUL bytes that C cannot represent.
+fn to_c_string(s: &str) -> *mut c_char {
+    CString::new(s.replace('\0', "")).unwrap_or_default().into_raw()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn take_string(s: *mut c_char) -> Option<String> {
+        if s.is_null() {
+            return None;
+        }
+        let value = unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned();
+        unsafe { rust_scrapper_string_free(s) };
+        Some(value)
+    }
+
+    #[test]
+    fn reports_errors_through_last_error() {
+        let scrapper = rust_scrapper_new();
+        let url = CString::new("https://a.com").unwrap();
+        let result = unsafe { rust_scrapper_scrape(scrapper, url.as_ptr(), ptr::null()) };
+        assert!(result.is_null());
+        assert_eq!(take_string(rust_scrapper_last_error()).as_deref(), Some("String argument is NULL"));
+
+        let schema = CString::new("{not json").unwrap();
+        let result = unsafe { rust_scrapper_scrape_structured(ptr::null_mut(), url.as_ptr(), schema.as_ptr()) };
+        assert!(result.is_null());
+        assert_eq!(take_string(rust_scrapper_last_error()).as_deref(), Some("Scrapper is NULL"));
+        unsafe { rust_scrapper_free(scrapper) };
+    }
+
+    #[test]
+    fn clears_the_last_error_after_success() {
+        let output = with_result(|| Ok("[\"a\"]".to_string()));
+        assert_eq!(take_string(output).as_deref(), Some("[\"a\"]"));
+        assert!(rust_scrapper_last_error().is_null());
+        assert_eq!(take_string(to_c_string("a\0b")).as_deref(), Some("ab"));
+    }
+
+    #[test]
+    fn returns_null_instead_of_unwinding_panics() {
+        let output = with_result(|| panic!("selector engine broke"));
+        assert!(output.is_null());
+        assert_eq!(take_string(rust_scrapper_last_error()).as_deref(), Some("Panic: selector engine broke"));
+        let output = with_result(|| panic!("{} pages", 3));
+        assert!(output.is_null());
+        assert_eq!(take_string(rust_scrapper_last_error()).as_deref(), Some("Panic: 3 pages"));
+    }
+}
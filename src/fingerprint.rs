@@ -0,0 +1,248 @@
+//! Coherent browser fingerprints for HTTP requests.
+//!
+//! Anti-bot systems flag requests whose headers contradict each other, e.g. a Firefox user
+//! agent sending Chrome's `sec-ch-ua` client hints. A `FingerprintProfile` is a set of headers
+//! one real browser sends together; `Fingerprints` assigns profiles to requests, per host or
+//! per session, see `RustScrapper::with_fingerprints`.
+//!
+//! Profiles cover the HTTP headers only. The TLS handshake is made by reqwest's TLS backend,
+//! which cannot imitate a browser's; scrape through `JsScraper` with
+//! `JsScrapeOptions::fingerprint` where the TLS fingerprint matters. `Accept-Encoding` is left
+//! to the HTTP client, which only accepts what it can decode.
+
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+const CHROME_ACCEPT: &str =
+    "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,image/apng,*/*;q=0.8,application/signed-exchange;v=b3;q=0.7";
+const FIREFOX_ACCEPT: &str = "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,*/*;q=0.8";
+const SAFARI_ACCEPT: &str = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+const CHROME_BRANDS: &str = "\"Not_A Brand\";v=\"8\", \"Chromium\";v=\"120\", \"Google Chrome\";v=\"120\"";
+const EDGE_BRANDS: &str = "\"Not_A Brand\";v=\"8\", \"Chromium\";v=\"120\", \"Microsoft Edge\";v=\"120\"";
+
+/// The headers one browser sends with a page request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FingerprintProfile {
+    pub name: String,
+    pub user_agent: String,
+    pub accept: String,
+    pub accept_language: String,
+    /// Further headers the browser sends, such as `sec-ch-ua` client hints and `sec-fetch-*`.
+    pub headers: Vec<(String, String)>,
+}
+
+impl FingerprintProfile {
+    pub fn new(name: &str, user_agent: &str, accept: &str, accept_language: &str) -> Self {
+        FingerprintProfile {
+            name: name.to_string(),
+            user_agent: user_agent.to_string(),
+            accept: accept.to_string(),
+            accept_language: accept_language.to_string(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Adds a header the browser sends.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sends `accept_language` instead, e.g. `tr-TR,tr;q=0.9` for a Turkish browser.
+    pub fn with_accept_language(mut self, accept_language: &str) -> Self {
+        self.accept_language = accept_language.to_string();
+        self
+    }
+
+    /// Chrome 120 on Windows 10.
+    pub fn chrome_windows() -> Self {
+        Self::chromium(
+            "chrome-windows",
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
+            CHROME_BRANDS,
+            false,
+            "Windows",
+        )
+    }
+
+    /// Chrome 120 on macOS.
+    pub fn chrome_mac() -> Self {
+        Self::chromium(
+            "chrome-mac",
+            "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
+            CHROME_BRANDS,
+            false,
+            "macOS",
+        )
+    }
+
+    /// Chrome 120 on Android.
+    pub fn chrome_android() -> Self {
+        Self::chromium(
+            "chrome-android",
+            "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Mobile Safari/537.36",
+            CHROME_BRANDS,
+            true,
+            "Android",
+        )
+    }
+
+    /// Edge 120 on Windows 10.
+    pub fn edge_windows() -> Self {
+        Self::chromium(
+            "edge-windows",
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36 Edg/120.0.0.0",
+            EDGE_BRANDS,
+            false,
+            "Windows",
+        )
+    }
+
+    /// Firefox 121 on Windows 10. Firefox sends no client hints.
+    pub fn firefox_windows() -> Self {
+        FingerprintProfile::new(
+            "firefox-windows",
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:121.0) Gecko/20100101 Firefox/121.0",
+            FIREFOX_ACCEPT,
+            "en-US,en;q=0.5",
+        )
+        .with_fetch_metadata()
+    }
+
+    /// Safari 17 on macOS. Safari sends no client hints.
+    pub fn safari_mac() -> Self {
+        FingerprintProfile::new(
+            "safari-mac",
+            "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.2 Safari/605.1.15",
+            SAFARI_ACCEPT,
+            "en-US,en;q=0.9",
+        )
+        .with_fetch_metadata()
+    }
+
+    /// The built-in profiles.
+    pub fn all() -> Vec<Self> {
+        vec![
+            Self::chrome_windows(),
+            Self::chrome_mac(),
+            Self::chrome_android(),
+            Self::edge_windows(),
+            Self::firefox_windows(),
+            Self::safari_mac(),
+        ]
+    }
+
+    /// Every header of the profile, in the order browsers send them.
+    pub fn header_pairs(&self) -> Vec<(String, String)> {
+        let mut headers = vec![
+            ("User-Agent".to_string(), self.user_agent.clone()),
+            ("Accept".to_string(), self.accept.clone()),
+            ("Accept-Language".to_string(), self.accept_language.clone()),
+        ];
+        headers.extend(self.headers.iter().cloned());
+        headers
+    }
+
+    /// A Chromium-based browser, which sends client hints matching its user agent.
+    fn chromium(name: &str, user_agent: &str, brands: &str, mobile: bool, platform: &str) -> Self {
+        FingerprintProfile::new(name, user_agent, CHROME_ACCEPT, "en-US,en;q=0.9")
+            .with_header("sec-ch-ua", brands)
+            .with_header("sec-ch-ua-mobile", if mobile { "?1" } else { "?0" })
+            .with_header("sec-ch-ua-platform", &format!("\"{}\"", platform))
+            .with_fetch_metadata()
+    }
+
+    /// The headers browsers send when navigating to a page typed into the address bar.
+    fn with_fetch_metadata(self) -> Self {
+        self.with_header("Upgrade-Insecure-Requests", "1")
+            .with_header("Sec-Fetch-Dest", "document")
+            .with_header("Sec-Fetch-Mode", "navigate")
+            .with_header("Sec-Fetch-Site", "none")
+            .with_header("Sec-Fetch-User", "?1")
+    }
+}
+
+/// How often requests switch to another profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FingerprintRotation {
+    /// Each host sees one profile, chosen at random for it.
+    PerHost,
+    /// All requests of the session use one profile, chosen at random.
+    PerSession,
+}
+
+/// Assigns fingerprint profiles to requests. Assignments are random per `Fingerprints` but
+/// stable for its lifetime, so a site sees one consistent browser.
+#[derive(Debug, Clone)]
+pub struct Fingerprints {
+    profiles: Vec<FingerprintProfile>,
+    rotation: FingerprintRotation,
+    seed: RandomState,
+}
+
+impl Fingerprints {
+    /// Rotates the profiles per host. Falls back to the built-in profiles if `profiles` is empty.
+    pub fn new(profiles: Vec<FingerprintProfile>) -> Self {
+        Fingerprints {
+            profiles: if profiles.is_empty() { FingerprintProfile::all() } else { profiles },
+            rotation: FingerprintRotation::PerHost,
+            seed: RandomState::new(),
+        }
+    }
+
+    /// Rotates the built-in profiles per host.
+    pub fn builtin() -> Self {
+        Fingerprints::new(FingerprintProfile::all())
+    }
+
+    pub fn with_rotation(mut self, rotation: FingerprintRotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    /// The profile for requests to `host`.
+    pub fn profile_for(&self, host: &str) -> &FingerprintProfile {
+        let key = match self.rotation {
+            FingerprintRotation::PerHost => host,
+            FingerprintRotation::PerSession => "",
+        };
+        let index = self.seed.hash_one(key) as usize % self.profiles.len();
+        &self.profiles[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header<'a>(profile: &'a FingerprintProfile, name: &str) -> Option<&'a str> {
+        profile.headers.iter().find(|(header, _)| header == name).map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn sends_client_hints_only_from_chromium_browsers() {
+        let android = FingerprintProfile::chrome_android();
+        assert_eq!(header(&android, "sec-ch-ua-mobile"), Some("?1"));
+        assert_eq!(header(&android, "sec-ch-ua-platform"), Some("\"Android\""));
+        assert!(header(&FingerprintProfile::edge_windows(), "sec-ch-ua").unwrap().contains("Microsoft Edge"));
+        assert_eq!(header(&FingerprintProfile::firefox_windows(), "sec-ch-ua"), None);
+
+        let pairs = FingerprintProfile::safari_mac().with_accept_language("tr-TR,tr;q=0.9").header_pairs();
+        assert_eq!(pairs[0].0, "User-Agent");
+        assert_eq!(pairs[2], ("Accept-Language".to_string(), "tr-TR,tr;q=0.9".to_string()));
+    }
+
+    #[test]
+    fn keeps_profiles_stable_per_host_or_session() {
+        let per_host = Fingerprints::builtin();
+        let hosts: Vec<String> = (0..50).map(|i| format!("shop{}.com", i)).collect();
+        assert!(hosts.iter().all(|host| per_host.profile_for(host) == per_host.profile_for(host)));
+        assert!(hosts.iter().any(|host| per_host.profile_for(host) != per_host.profile_for(&hosts[0])));
+
+        let per_session = Fingerprints::builtin().with_rotation(FingerprintRotation::PerSession);
+        assert!(hosts.iter().all(|host| per_session.profile_for(host) == per_session.profile_for("a.com")));
+        assert_eq!(Fingerprints::new(Vec::new()).profiles.len(), FingerprintProfile::all().len());
+    }
+}
@@ -0,0 +1,219 @@
+//! Response fixtures for selector regression tests.
+//!
+//! A `Fixture` stores a fetched page together with the records a schema extracted from it.
+//! `verify` re-runs a schema against a saved fixture and reports the fields whose values
+//! disappeared or changed, so broken selectors show up before the data does.
+
+use crate::schema::Schema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A saved page and the records extracted from it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fixture {
+    pub url: String,
+    /// Unix timestamp of when the fixture was saved.
+    pub saved_at: i64,
+    pub html: String,
+    pub results: Vec<Value>,
+}
+
+impl Fixture {
+    /// Creates a fixture saved now.
+    pub fn new(url: &str, html: &str, results: Vec<Value>) -> Self {
+        Fixture {
+            url: url.to_string(),
+            saved_at: chrono::Utc::now().timestamp(),
+            html: html.to_string(),
+            results,
+        }
+    }
+
+    /// Reads a fixture from a JSON file.
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Writes the fixture to a JSON file.
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Writes the fixture into `dir` under a file name derived from its URL and returns the path.
+    pub fn save_in(&self, dir: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(fixture_file_name(&self.url));
+        self.save(&path.to_string_lossy())?;
+        Ok(path)
+    }
+}
+
+/// How one field of a schema fares against a fixture.
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldReport {
+    pub name: String,
+    /// Records with a value for this field in the fixture.
+    pub expected: usize,
+    /// Records with a value for this field now.
+    pub found: usize,
+    /// Records where both have a value but it differs.
+    pub changed: usize,
+}
+
+impl FieldReport {
+    /// Whether the field lost values or extracts different ones.
+    pub fn is_broken(&self) -> bool {
+        self.found < self.expected || self.changed > 0
+    }
+}
+
+/// Result of re-running a schema against a fixture.
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyReport {
+    pub url: String,
+    pub expected_records: usize,
+    pub found_records: usize,
+    pub fields: Vec<FieldReport>,
+}
+
+impl VerifyReport {
+    /// Fields that lost values or extract different ones.
+    pub fn broken(&self) -> impl Iterator<Item = &FieldReport> {
+        self.fields.iter().filter(|field| field.is_broken())
+    }
+
+    /// Whether the schema still extracts what the fixture recorded.
+    pub fn is_ok(&self) -> bool {
+        self.found_records >= self.expected_records && self.broken().next().is_none()
+    }
+}
+
+/// Re-runs `schema` against the fixture file at `url_fixture` and reports which fields broke.
+pub fn verify(url_fixture: &str, schema: &Schema) -> Result<VerifyReport, Box<dyn std::error::Error>> {
+    let fixture = Fixture::load(url_fixture)?;
+    let records = schema.extract(&fixture.html)?;
+    let fields = schema
+        .fields
+        .iter()
+        .map(|field| compare_field(&field.name, &fixture.results, &records))
+        .collect();
+    Ok(VerifyReport {
+        url: fixture.url,
+        expected_records: fixture.results.len(),
+        found_records: records.len(),
+        fields,
+    })
+}
+
+/// Verifies every fixture in a directory, in file name order.
+pub fn verify_dir(dir: &str, schema: &Schema) -> Result<Vec<VerifyReport>, Box<dyn std::error::Error>> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "json"));
+    paths.sort();
+    paths.iter().map(|path| verify(&path.to_string_lossy(), schema)).collect()
+}
+
+fn compare_field(name: &str, expected: &[Value], found: &[Value]) -> FieldReport {
+    let value = |record: &Value| record.get(name).filter(|value| has_value(value)).cloned();
+    let mut report = FieldReport {
+        name: name.to_string(),
+        expected: expected.iter().filter_map(value).count(),
+        found: found.iter().filter_map(value).count(),
+        changed: 0,
+    };
+    for (old, new) in expected.iter().zip(found) {
+        if let (Some(old), Some(new)) = (value(old), value(new)) {
+            if old != new {
+                report.changed += 1;
+            }
+        }
+    }
+    report
+}
+
+fn has_value(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::String(s) => !s.is_empty(),
+        Value::Array(values) => !values.is_empty(),
+        _ => true,
+    }
+}
+
+/// Builds a stable, filesystem-safe file name from a URL. The readable part is lossy and
+/// truncated, so a hash of the full URL keeps URLs that only differ in it apart.
+fn fixture_file_name(url: &str) -> String {
+    let trimmed = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let name: String = trimmed
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .take(150)
+        .collect();
+    format!("{}-{:016x}.json", name, crate::bloom::fnv1a(url.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::schema::Field;
+
+    const HTML: &str = r#"<div class="p"><h2>Lamp</h2><b>5</b></div><div class="p"><h2>Desk</h2><b>10</b></div>"#;
+
+    fn schema(price: &str) -> Schema {
+        Schema::new()
+            .root(".p")
+            .field(Field::new("title", "h2"))
+            .field(Field::new("price", price))
+    }
+
+    #[test]
+    fn verifies_schemas_against_saved_fixtures() {
+        let dir = std::env::temp_dir().join(format!("rust-scrapper-fixtures-{}", std::process::id()));
+        let fixture = Fixture::new("https://shop.com/list?page=1", HTML, schema("b").extract(HTML).unwrap());
+        let path = fixture.save_in(&dir).unwrap();
+        assert_eq!(path.file_name().unwrap().to_string_lossy(), fixture_file_name("https://shop.com/list?page=1"));
+        assert!(path.to_string_lossy().contains("shop.com_list_page_1-"));
+
+        let report = verify(&path.to_string_lossy(), &schema("b")).unwrap();
+        assert!(report.is_ok());
+
+        let reports = verify_dir(&dir.to_string_lossy(), &schema(".price")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(reports.len(), 1);
+        assert!(!reports[0].is_ok());
+        let broken: Vec<_> = reports[0].broken().map(|field| (field.name.as_str(), field.expected, field.found)).collect();
+        assert_eq!(broken, vec![("price", 2, 0)]);
+    }
+
+    #[test]
+    fn counts_changed_values() {
+        let expected = vec![serde_json::json!({"title": "Lamp"}), serde_json::json!({"title": "Desk"})];
+        let found = vec![serde_json::json!({"title": "Lamp"}), serde_json::json!({"title": "Chair"})];
+        let report = compare_field("title", &expected, &found);
+        assert_eq!((report.expected, report.found, report.changed), (2, 2, 1));
+        assert!(report.is_broken());
+    }
+
+    #[test]
+    fn keeps_similar_urls_apart() {
+        let names = [
+            fixture_file_name("https://example.com/a?b"),
+            fixture_file_name("https://example.com/a_b"),
+            fixture_file_name("http://example.com/a_b"),
+            fixture_file_name(&format!("https://example.com/{}1", "x".repeat(200))),
+            fixture_file_name(&format!("https://example.com/{}2", "x".repeat(200))),
+        ];
+        for (i, name) in names.iter().enumerate() {
+            assert!(name.ends_with(".json"));
+            assert!(names[i + 1..].iter().all(|other| other != name), "{}", name);
+        }
+        assert!(names[0].starts_with("example.com_a_b-"));
+        assert_eq!(fixture_file_name("https://example.com/products"), "example.com_products-793966adf9c31288.json");
+    }
+}
@@ -0,0 +1,569 @@
+//! Crawl frontier: the URLs waiting to be fetched and the set of visited URLs.
+//!
+//! A `Crawler` keeps its frontier in memory by default. Backing it with a shared store such as
+//! `RedisFrontier` lets several processes or machines work on one crawl: each URL is fetched
+//! by only one of them, and links any of them discovers are queued for all.
+
+use crate::bloom::BloomFilter;
+use crate::cache::CacheBackend;
+use crate::crawler::RequestQueue;
+use crate::storage::{Namespace, Storage};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Pending and visited URLs of a frontier, saved in crawl checkpoints.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FrontierSnapshot {
+    pub pending: Vec<(String, i32)>,
+    pub visited: Vec<String>,
+    /// Visited set of a frontier using a bloom filter, whose URLs cannot be listed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visited_filter: Option<BloomFilter>,
+    /// URLs in `visited_filter` that were unmarked afterwards and count as not visited.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unvisited: Vec<String>,
+}
+
+/// Storage for the pending and visited URLs of a crawl.
+/// Implementations must be thread-safe, as one frontier can be shared by many crawlers.
+pub trait Frontier: Send + Sync {
+    /// Queues a URL with the given priority. Higher priorities are fetched first.
+    /// If the URL is already queued, its priority is raised when the new one is higher.
+    fn enqueue(&self, url: &str, priority: i32);
+
+    /// Removes and returns the URL with the highest priority.
+    fn pop(&self) -> Option<(String, i32)>;
+
+    /// Number of queued URLs.
+    fn len(&self) -> usize;
+
+    /// Returns true if no URLs are queued.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Marks a URL as visited. Returns false if it was visited before, e.g. by another worker,
+    /// in which case the caller must not fetch it.
+    fn mark_visited(&self, url: &str) -> bool;
+
+    /// Whether the URL has been visited.
+    fn is_visited(&self, url: &str) -> bool;
+
+    /// Forgets that a URL was visited, so it can be fetched again.
+    fn unmark_visited(&self, url: &str);
+
+    /// Copies the pending and visited URLs for a checkpoint. Frontiers kept in a persistent
+    /// store return `None`, as they survive a crash on their own.
+    fn snapshot(&self) -> Option<FrontierSnapshot> {
+        None
+    }
+
+    /// Restores the URLs of a checkpoint.
+    fn restore(&self, _snapshot: FrontierSnapshot) {}
+}
+
+/// Pending URLs moved out of memory, stored as JSON lines.
+struct Spill {
+    max_pending: usize,
+    path: PathBuf,
+    /// Number of URLs in the spill file.
+    count: usize,
+}
+
+/// Visited URLs, stored exactly or in a bloom filter.
+enum Visited {
+    Exact(HashSet<String>),
+    /// URLs unmarked after insertion are kept in `forgotten`, as bloom filters cannot remove.
+    Bloom { filter: BloomFilter, forgotten: HashSet<String> },
+}
+
+impl Default for Visited {
+    fn default() -> Self {
+        Visited::Exact(HashSet::new())
+    }
+}
+
+impl Visited {
+    fn insert(&mut self, url: &str) -> bool {
+        match self {
+            Visited::Exact(urls) => urls.insert(url.to_string()),
+            Visited::Bloom { filter, forgotten } => filter.insert(url) || forgotten.remove(url),
+        }
+    }
+
+    fn contains(&self, url: &str) -> bool {
+        match self {
+            Visited::Exact(urls) => urls.contains(url),
+            Visited::Bloom { filter, forgotten } => filter.contains(url) && !forgotten.contains(url),
+        }
+    }
+
+    fn remove(&mut self, url: &str) {
+        match self {
+            Visited::Exact(urls) => {
+                urls.remove(url);
+            }
+            Visited::Bloom { filter, forgotten } => {
+                if filter.contains(url) {
+                    forgotten.insert(url.to_string());
+                }
+            }
+        }
+    }
+}
+
+/// In-process frontier built on a `RequestQueue`.
+/// With a pending limit, the lowest-priority URLs beyond it are spilled to a file and
+/// loaded back once the queue runs empty. Spilled URLs come back in the order they were
+/// spilled, so priorities are only approximately respected across spills.
+#[derive(Default)]
+pub struct MemoryFrontier {
+    queue: RequestQueue,
+    visited: Mutex<Visited>,
+    spill: Mutex<Option<Spill>>,
+}
+
+impl MemoryFrontier {
+    /// Creates an empty frontier.
+    pub fn new() -> Self {
+        MemoryFrontier::default()
+    }
+
+    /// Creates a frontier taking its pending URLs from the queue.
+    pub fn with_queue(queue: RequestQueue) -> Self {
+        MemoryFrontier {
+            queue,
+            ..MemoryFrontier::default()
+        }
+    }
+
+    /// Keeps at most `max_pending` URLs in memory and spills the lowest-priority ones to `path`.
+    pub fn with_spill(self, max_pending: usize, path: &str) -> Self {
+        *self.spill.lock().unwrap() = Some(Spill {
+            max_pending: max_pending.max(1),
+            path: PathBuf::from(path),
+            count: 0,
+        });
+        self
+    }
+
+    /// Tracks visited URLs in a bloom filter sized for `expected_urls` at the given
+    /// false-positive rate instead of an exact set. A false positive skips a URL that was
+    /// never fetched, so pick the rate by how many missed pages the crawl can tolerate.
+    pub fn with_bloom_filter(self, expected_urls: usize, false_positive_rate: f64) -> Self {
+        *self.visited.lock().unwrap() = Visited::Bloom {
+            filter: BloomFilter::new(expected_urls, false_positive_rate),
+            forgotten: HashSet::new(),
+        };
+        self
+    }
+
+    /// Moves the lowest-priority half of the queue to the spill file once it exceeds the limit.
+    fn spill_excess(&self) {
+        let mut spill = self.spill.lock().unwrap();
+        let Some(spill) = spill.as_mut() else {
+            return;
+        };
+        let len = self.queue.len();
+        if len <= spill.max_pending {
+            return;
+        }
+        let evicted = self.queue.drain_lowest(len - spill.max_pending / 2);
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&spill.path)
+            .and_then(|mut file| {
+                let lines = evicted
+                    .iter()
+                    .map(|entry| serde_json::to_string(entry).map(|line| line + "\n"))
+                    .collect::<Result<String, _>>()?;
+                file.write_all(lines.as_bytes())
+            });
+        match written {
+            Ok(()) => spill.count += evicted.len(),
+            Err(e) => {
+                log::error!("Failed to spill the frontier to {}: {}", spill.path.display(), e);
+                for (url, priority) in evicted {
+                    self.queue.enqueue(&url, priority);
+                }
+            }
+        }
+    }
+
+    /// Moves up to the pending limit of URLs from the spill file back into the queue.
+    /// The file is streamed, so memory use stays bounded however many URLs were spilled.
+    fn load_spilled(&self) {
+        let mut spill = self.spill.lock().unwrap();
+        let Some(spill) = spill.as_mut().filter(|spill| spill.count > 0) else {
+            return;
+        };
+        let remaining = spill.path.with_extension("rest");
+        let result = (|| -> std::io::Result<usize> {
+            let reader = BufReader::new(File::open(&spill.path)?);
+            let mut rest = BufWriter::new(File::create(&remaining)?);
+            let mut loaded = 0;
+            let mut kept = 0;
+            for line in reader.lines() {
+                let line = line?;
+                if loaded < spill.max_pending {
+                    if let Ok((url, priority)) = serde_json::from_str::<(String, i32)>(&line) {
+                        self.queue.enqueue(&url, priority);
+                        loaded += 1;
+                    }
+                } else {
+                    writeln!(rest, "{}", line)?;
+                    kept += 1;
+                }
+            }
+            rest.flush()?;
+            std::fs::rename(&remaining, &spill.path)?;
+            Ok(kept)
+        })();
+        match result {
+            Ok(kept) => spill.count = kept,
+            Err(e) => log::error!("Failed to load spilled URLs from {}: {}", spill.path.display(), e),
+        }
+    }
+}
+
+impl Frontier for MemoryFrontier {
+    fn enqueue(&self, url: &str, priority: i32) {
+        self.queue.enqueue(url, priority);
+        self.spill_excess();
+    }
+
+    fn pop(&self) -> Option<(String, i32)> {
+        if self.queue.is_empty() {
+            self.load_spilled();
+        }
+        self.queue.pop()
+    }
+
+    fn len(&self) -> usize {
+        self.queue.len() + self.spill.lock().unwrap().as_ref().map_or(0, |spill| spill.count)
+    }
+
+    fn mark_visited(&self, url: &str) -> bool {
+        self.visited.lock().unwrap().insert(url)
+    }
+
+    fn is_visited(&self, url: &str) -> bool {
+        self.visited.lock().unwrap().contains(url)
+    }
+
+    fn unmark_visited(&self, url: &str) {
+        self.visited.lock().unwrap().remove(url);
+    }
+
+    fn snapshot(&self) -> Option<FrontierSnapshot> {
+        let mut pending = self.queue.snapshot();
+        if let Some(spill) = self.spill.lock().unwrap().as_ref().filter(|spill| spill.count > 0) {
+            let contents = std::fs::read_to_string(&spill.path).unwrap_or_default();
+            pending.extend(contents.lines().filter_map(|line| serde_json::from_str::<(String, i32)>(line).ok()));
+        }
+        let mut snapshot = FrontierSnapshot {
+            pending,
+            ..FrontierSnapshot::default()
+        };
+        match &*self.visited.lock().unwrap() {
+            Visited::Exact(urls) => snapshot.visited = urls.iter().cloned().collect(),
+            Visited::Bloom { filter, forgotten } => {
+                snapshot.visited_filter = Some(filter.clone());
+                snapshot.unvisited = forgotten.iter().cloned().collect();
+            }
+        }
+        Some(snapshot)
+    }
+
+    fn restore(&self, snapshot: FrontierSnapshot) {
+        for (url, priority) in snapshot.pending {
+            self.enqueue(&url, priority);
+        }
+        let mut visited = self.visited.lock().unwrap();
+        if let Some(filter) = snapshot.visited_filter {
+            *visited = Visited::Bloom {
+                filter,
+                forgotten: snapshot.unvisited.into_iter().collect(),
+            };
+        }
+        for url in snapshot.visited {
+            visited.insert(&url);
+        }
+    }
+}
+
+/// Frontier persisted in a `Storage`, so a crawl continues where it stopped after a restart
+/// without checkpoints. Pending URLs are stored under keys ordered by priority, then by the
+/// time they were queued; visited URLs under keys of their own. The pending keys are also
+/// indexed in memory, read from the storage once, so popping never lists the storage.
+/// Operations are atomic within one process only: share a storage between processes with
+/// `RedisFrontier` instead.
+pub struct StorageFrontier {
+    store: Namespace,
+    /// The pending keys in order, loaded on first use.
+    pending: Mutex<Option<BTreeSet<String>>>,
+}
+
+impl StorageFrontier {
+    /// Keeps the frontier of the named crawl under `frontier/<crawl>/` in the storage.
+    pub fn new(storage: Arc<dyn Storage>, crawl: &str) -> Self {
+        StorageFrontier {
+            store: Namespace::new(storage, "frontier").namespace(crawl),
+            pending: Mutex::new(None),
+        }
+    }
+
+    /// Deletes the crawl's pending and visited URLs, to start it over.
+    pub fn reset(&self) {
+        let mut pending = self.pending.lock().unwrap();
+        self.store.clear();
+        *pending = Some(BTreeSet::new());
+    }
+
+    /// Runs `f` on the pending index, reading the pending keys from the storage the first time.
+    fn with_pending<T>(&self, f: impl FnOnce(&mut BTreeSet<String>) -> T) -> T {
+        let mut pending = self.pending.lock().unwrap();
+        let index = pending.get_or_insert_with(|| self.store.keys_with_prefix("pending:").into_iter().collect());
+        f(index)
+    }
+
+    /// Key of a pending URL: the priority inverted, so higher priorities sort first, and the
+    /// time it was queued in nanoseconds, zero-padded so keys sort numerically.
+    fn pending_key(url: &str, priority: i32) -> String {
+        let rank = i32::MAX as i64 - priority as i64;
+        let queued_at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos();
+        format!("pending:{:010}:{:020}:{}", rank, queued_at, url)
+    }
+
+    /// Priority of a pending key.
+    fn priority(key: &str) -> Option<i32> {
+        let rank: i64 = key.strip_prefix("pending:")?.split(':').next()?.parse().ok()?;
+        Some((i32::MAX as i64 - rank) as i32)
+    }
+}
+
+impl Frontier for StorageFrontier {
+    fn enqueue(&self, url: &str, priority: i32) {
+        self.with_pending(|index| {
+            let queued_key = format!("queued:{}", url);
+            // `queued:<url>` points to the URL's pending key, to raise its priority.
+            if let Some(pending) = self.store.get(&queued_key) {
+                if StorageFrontier::priority(&pending).is_some_and(|queued| queued >= priority) {
+                    return;
+                }
+                self.store.remove(&pending);
+                index.remove(&pending);
+            }
+            let pending = StorageFrontier::pending_key(url, priority);
+            self.store.set(&pending, url.to_string(), None);
+            self.store.set(&queued_key, pending.clone(), None);
+            index.insert(pending);
+        })
+    }
+
+    fn pop(&self) -> Option<(String, i32)> {
+        self.with_pending(|index| {
+            // Keys whose entry is gone, e.g. expired, are dropped from the index.
+            while let Some(pending) = index.pop_first() {
+                if let Some(url) = self.store.get(&pending) {
+                    self.store.remove(&pending);
+                    self.store.remove(&format!("queued:{}", url));
+                    return Some((url, StorageFrontier::priority(&pending).unwrap_or_default()));
+                }
+            }
+            None
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.with_pending(|index| index.len())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.with_pending(|index| index.is_empty())
+    }
+
+    fn mark_visited(&self, url: &str) -> bool {
+        let _lock = self.pending.lock().unwrap();
+        let key = format!("visited:{}", url);
+        if self.store.get(&key).is_some() {
+            return false;
+        }
+        self.store.set(&key, String::new(), None);
+        true
+    }
+
+    fn is_visited(&self, url: &str) -> bool {
+        self.store.get(&format!("visited:{}", url)).is_some()
+    }
+
+    fn unmark_visited(&self, url: &str) {
+        self.store.remove(&format!("visited:{}", url));
+    }
+}
+
+/// Frontier shared through Redis, requires the `redis` feature and Redis 6.2 or newer.
+/// Pending URLs are kept in a sorted set scored by priority, visited URLs in a set.
+#[cfg(feature = "redis")]
+pub struct RedisFrontier {
+    connection: Mutex<redis::Connection>,
+    queue_key: String,
+    visited_key: String,
+}
+
+#[cfg(feature = "redis")]
+impl RedisFrontier {
+    /// Connects to Redis, e.g. `redis://127.0.0.1/`. Crawlers using the same crawl name
+    /// share one frontier.
+    pub fn new(url: &str, crawl: &str) -> Result<Self, redis::RedisError> {
+        let connection = redis::Client::open(url)?.get_connection()?;
+        Ok(RedisFrontier {
+            connection: Mutex::new(connection),
+            queue_key: format!("rust-scrapper:crawl:{}:queue", crawl),
+            visited_key: format!("rust-scrapper:crawl:{}:visited", crawl),
+        })
+    }
+
+    /// Deletes the crawl's queue and visited set, to start it over.
+    pub fn reset(&self) {
+        self.query::<()>(redis::cmd("DEL").arg(&self.queue_key).arg(&self.visited_key));
+    }
+
+    fn query<T: redis::FromRedisValue>(&self, cmd: &redis::Cmd) -> Option<T> {
+        let mut connection = self.connection.lock().unwrap();
+        cmd.query(&mut *connection)
+            .map_err(|e| log::error!("Redis frontier error: {}", e))
+            .ok()
+    }
+}
+
+#[cfg(feature = "redis")]
+impl Frontier for RedisFrontier {
+    fn enqueue(&self, url: &str, priority: i32) {
+        if self.is_visited(url) {
+            return;
+        }
+        // GT adds new members and only ever raises the score of existing ones.
+        self.query::<()>(redis::cmd("ZADD").arg(&self.queue_key).arg("GT").arg(priority).arg(url));
+    }
+
+    fn pop(&self) -> Option<(String, i32)> {
+        self.query::<Vec<(String, f64)>>(redis::cmd("ZPOPMAX").arg(&self.queue_key))?
+            .into_iter()
+            .next()
+            .map(|(url, score)| (url, score as i32))
+    }
+
+    fn len(&self) -> usize {
+        self.query::<usize>(redis::cmd("ZCARD").arg(&self.queue_key)).unwrap_or(0)
+    }
+
+    fn mark_visited(&self, url: &str) -> bool {
+        // Only the worker whose SADD adds the URL gets to fetch it.
+        self.query::<u64>(redis::cmd("SADD").arg(&self.visited_key).arg(url))
+            .map(|added| added > 0)
+            .unwrap_or(false)
+    }
+
+    fn is_visited(&self, url: &str) -> bool {
+        self.query::<bool>(redis::cmd("SISMEMBER").arg(&self.visited_key).arg(url))
+            .unwrap_or(false)
+    }
+
+    fn unmark_visited(&self, url: &str) {
+        self.query::<()>(redis::cmd("SREM").arg(&self.visited_key).arg(url));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_pending_and_visited_urls() {
+        let frontier = MemoryFrontier::new();
+        frontier.enqueue("https://a.com/a", 1);
+        frontier.enqueue("https://a.com/b", 2);
+        assert_eq!(frontier.len(), 2);
+        assert_eq!(frontier.pop(), Some(("https://a.com/b".to_string(), 2)));
+
+        assert!(frontier.mark_visited("https://a.com/b"));
+        assert!(!frontier.mark_visited("https://a.com/b"));
+        assert!(frontier.is_visited("https://a.com/b"));
+        frontier.unmark_visited("https://a.com/b");
+        assert!(!frontier.is_visited("https://a.com/b"));
+
+        assert_eq!(frontier.pop(), Some(("https://a.com/a".to_string(), 1)));
+        assert!(frontier.is_empty());
+    }
+
+    #[test]
+    fn spills_the_lowest_priority_urls_beyond_the_limit() {
+        let path = std::env::temp_dir().join(format!("rust-scrapper-frontier-{}.jsonl", std::process::id()));
+        let frontier = MemoryFrontier::new().with_spill(2, &path.to_string_lossy());
+        for (url, priority) in [("a", 4), ("b", 3), ("c", 2), ("d", 1)] {
+            frontier.enqueue(url, priority);
+        }
+        assert_eq!(frontier.len(), 4);
+        assert_eq!(frontier.snapshot().unwrap().pending.len(), 4);
+
+        let mut popped = Vec::new();
+        while let Some((url, _)) = frontier.pop() {
+            popped.push(url);
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(popped, vec!["a", "d", "b", "c"]);
+        assert!(frontier.is_empty());
+    }
+
+    #[test]
+    fn tracks_visited_urls_in_a_bloom_filter() {
+        let frontier = MemoryFrontier::new().with_bloom_filter(100, 0.01);
+        assert!(frontier.mark_visited("https://a.com/"));
+        assert!(!frontier.mark_visited("https://a.com/"));
+        frontier.unmark_visited("https://a.com/");
+        assert!(!frontier.is_visited("https://a.com/"));
+        assert!(frontier.mark_visited("https://a.com/"));
+        frontier.mark_visited("https://a.com/b");
+        frontier.unmark_visited("https://a.com/b");
+
+        let snapshot = frontier.snapshot().unwrap();
+        assert!(snapshot.visited.is_empty());
+        assert_eq!(snapshot.unvisited, vec!["https://a.com/b"]);
+        let restored = MemoryFrontier::new();
+        restored.restore(snapshot);
+        assert!(restored.is_visited("https://a.com/"));
+        assert!(!restored.is_visited("https://a.com/b"));
+    }
+
+    #[test]
+    fn persists_pending_urls_in_storage() {
+        let storage: Arc<dyn Storage> = Arc::new(crate::cache::MemoryCache::new());
+        let frontier = StorageFrontier::new(storage.clone(), "catalog");
+        frontier.enqueue("https://a.com/low", -1);
+        frontier.enqueue("https://a.com/first", 1);
+        frontier.enqueue("https://a.com/second", 1);
+        frontier.enqueue("https://a.com/low", 5);
+        frontier.enqueue("https://a.com/low", 2);
+        assert_eq!(frontier.len(), 3);
+        assert!(frontier.mark_visited("https://a.com/first"));
+
+        let restarted = StorageFrontier::new(storage.clone(), "catalog");
+        assert!(restarted.is_visited("https://a.com/first"));
+        assert!(!restarted.mark_visited("https://a.com/first"));
+        assert_eq!(restarted.pop(), Some(("https://a.com/low".to_string(), 5)));
+        assert_eq!(restarted.pop(), Some(("https://a.com/first".to_string(), 1)));
+        assert_eq!(restarted.pop(), Some(("https://a.com/second".to_string(), 1)));
+        assert!(restarted.is_empty());
+
+        assert!(StorageFrontier::new(storage.clone(), "other").is_empty());
+        restarted.reset();
+        assert!(!restarted.is_visited("https://a.com/first"));
+    }
+}
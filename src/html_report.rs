@@ -0,0 +1,198 @@
+//! HTML reports of scrape runs.
+//!
+//! `ScrapeReport::to_html` (and `Exporter::to_html_report`) renders a run as one HTML page
+//! without external resources: the summary, the failures by reason and soft failures, and
+//! the extracted records in a table that can be sorted by clicking a column and filtered by
+//! text. The page can be mailed or opened from disk by people who never read JSON.
+
+use crate::report::{format_bytes, ScrapeReport};
+use crate::{cell_text, record_columns};
+use serde_json::Value;
+use std::fmt::Write;
+
+const STYLE: &str = "body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+h1 { margin-bottom: 0.2em; } .generated { color: #777; margin-top: 0; }
+table { border-collapse: collapse; margin: 1em 0; } th, td { border: 1px solid #ddd; padding: 0.35em 0.7em; text-align: left; vertical-align: top; }
+th { background: #f4f4f4; } #records th { cursor: pointer; user-select: none; white-space: nowrap; }
+#records th.asc::after { content: ' \\25B2'; } #records th.desc::after { content: ' \\25BC'; }
+#records tbody tr:nth-child(even) { background: #fafafa; } .summary th { width: 12em; }
+.failed { color: #b00020; } .stopped { color: #a05a00; } #filter { padding: 0.4em; width: 24em; max-width: 100%; }";
+
+const SCRIPT: &str = "(() => {
+  const table = document.getElementById('records');
+  if (!table) return;
+  const body = table.tBodies[0];
+  const rows = Array.from(body.rows);
+  const count = document.getElementById('shown');
+  const filter = document.getElementById('filter');
+  filter.addEventListener('input', () => {
+    const needle = filter.value.toLowerCase();
+    let shown = 0;
+    rows.forEach((row) => {
+      const match = row.textContent.toLowerCase().includes(needle);
+      row.style.display = match ? '' : 'none';
+      if (match) shown++;
+    });
+    count.textContent = shown;
+  });
+  const headers = Array.from(table.tHead.rows[0].cells);
+  headers.forEach((header, column) => header.addEventListener('click', () => {
+    const ascending = !header.classList.contains('asc');
+    headers.forEach((other) => other.classList.remove('asc', 'desc'));
+    header.classList.add(ascending ? 'asc' : 'desc');
+    const key = (row) => row.cells[column].textContent.trim();
+    rows.sort((a, b) => {
+      const [x, y] = [key(a), key(b)];
+      const [m, n] = [parseFloat(x), parseFloat(y)];
+      const order = !isNaN(m) && !isNaN(n) && String(m) === x && String(n) === y ? m - n : x.localeCompare(y, undefined, { numeric: true });
+      return ascending ? order : -order;
+    });
+    rows.forEach((row) => body.appendChild(row));
+  }));
+})();";
+
+impl ScrapeReport {
+    /// Renders the report and the records of the run as a self-contained HTML page.
+    /// Record columns are those of `Exporter::records_to_csv`; URLs become links.
+    pub fn to_html(&self, records: &[Value]) -> String {
+        let mut html = String::new();
+        let _ = self.write_html(&mut html, records);
+        html
+    }
+
+    fn write_html(&self, html: &mut String, records: &[Value]) -> std::fmt::Result {
+        writeln!(html, "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">")?;
+        writeln!(html, "<title>Scrape report</title>\n<style>\n{}\n</style>\n</head>\n<body>", STYLE)?;
+        writeln!(html, "<h1>Scrape report</h1>")?;
+        writeln!(html, "<p class=\"generated\">Generated {}</p>", chrono::Local::now().format("%Y-%m-%d %H:%M"))?;
+
+        writeln!(html, "<h2>Summary</h2>\n<table class=\"summary\">")?;
+        let rows = [
+            ("URLs attempted", self.attempted.to_string()),
+            ("Succeeded", format!("{} ({:.1}%)", self.succeeded, self.success_rate() * 100.0)),
+            ("Failed", self.failed.to_string()),
+            ("Items extracted", self.items.to_string()),
+            ("Duration", format!("{:.1}s", self.duration_secs)),
+            ("Downloaded", format_bytes(self.bytes)),
+            ("Cache hits", self.cache_hits.to_string()),
+        ];
+        for (label, value) in rows {
+            let class = if label == "Failed" && self.failed > 0 { " class=\"failed\"" } else { "" };
+            writeln!(html, "<tr><th>{}</th><td{}>{}</td></tr>", label, class, escape(&value))?;
+        }
+        if let Some(limit) = &self.stopped_by {
+            writeln!(html, "<tr><th>Stopped early</th><td class=\"stopped\">{}</td></tr>", escape(&limit.to_string()))?;
+        }
+        writeln!(html, "</table>")?;
+
+        if !self.failures_by_reason.is_empty() {
+            writeln!(html, "<h2>Failures</h2>\n<table>\n<thead><tr><th>Reason</th><th>URLs</th></tr></thead>\n<tbody>")?;
+            for (reason, count) in &self.failures_by_reason {
+                writeln!(html, "<tr><td>{}</td><td>{}</td></tr>", escape(reason), count)?;
+            }
+            writeln!(html, "</tbody>\n</table>")?;
+        }
+        if !self.soft_failures.is_empty() {
+            writeln!(html, "<h2>Soft failures</h2>\n<table>\n<thead><tr><th>URL</th><th>Classification</th></tr></thead>\n<tbody>")?;
+            for (url, failure) in &self.soft_failures {
+                writeln!(html, "<tr><td>{}</td><td>{}</td></tr>", cell(url), escape(&failure.to_string()))?;
+            }
+            writeln!(html, "</tbody>\n</table>")?;
+        }
+
+        writeln!(html, "<h2>Records</h2>")?;
+        if records.is_empty() {
+            writeln!(html, "<p>No records were extracted.</p>")?;
+        } else {
+            writeln!(
+                html,
+                "<p><input id=\"filter\" type=\"search\" placeholder=\"Filter records\"> <span id=\"shown\">{}</span> of {} records</p>",
+                records.len(),
+                records.len()
+            )?;
+            let columns = record_columns(records);
+            write!(html, "<table id=\"records\">\n<thead><tr>")?;
+            for column in &columns {
+                write!(html, "<th>{}</th>", escape(column))?;
+            }
+            writeln!(html, "</tr></thead>\n<tbody>")?;
+            for record in records {
+                write!(html, "<tr>")?;
+                for column in &columns {
+                    let value = match record {
+                        Value::Object(object) => object.get(column).unwrap_or(&Value::Null),
+                        other => other,
+                    };
+                    write!(html, "<td>{}</td>", cell(&cell_text(value)))?;
+                }
+                writeln!(html, "</tr>")?;
+            }
+            writeln!(html, "</tbody>\n</table>")?;
+        }
+        writeln!(html, "<script>\n{}\n</script>\n</body>\n</html>", SCRIPT)
+    }
+}
+
+/// A table cell's content: the escaped text, as a link if it is an HTTP URL.
+fn cell(text: &str) -> String {
+    let escaped = escape(text);
+    if (text.starts_with("http://") || text.starts_with("https://")) && !text.contains(char::is_whitespace) {
+        format!("<a href=\"{}\">{}</a>", escaped, escaped)
+    } else {
+        escaped
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn renders_the_summary_failures_and_records() {
+        let mut report = ScrapeReport {
+            attempted: 3,
+            succeeded: 2,
+            failed: 1,
+            items: 2,
+            ..ScrapeReport::default()
+        };
+        report.failures_by_reason.insert("timeout".to_string(), 1);
+        let records = [
+            json!({"name": "<Lamp>", "link": "https://a.com/lamp"}),
+            json!({"name": "Desk", "tags": ["wood", "oak"]}),
+        ];
+        let html = report.to_html(&records);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<tr><th>Failed</th><td class=\"failed\">1</td></tr>"));
+        assert!(html.contains("<tr><td>timeout</td><td>1</td></tr>"));
+        assert!(html.contains("<tr><th>link</th><th>name</th><th>tags</th></tr>"));
+        assert!(html.contains(
+            "<tr><td><a href=\"https://a.com/lamp\">https://a.com/lamp</a></td><td>&lt;Lamp&gt;</td><td></td></tr>"
+        ));
+        assert!(html.contains("<tr><td></td><td>Desk</td><td>wood; oak</td></tr>"));
+        assert!(html.contains("<span id=\"shown\">2</span> of 2 records"));
+        assert!(!html.contains("<h2>Soft failures</h2>"));
+    }
+
+    #[test]
+    fn says_when_no_records_were_extracted() {
+        let html = ScrapeReport::default().to_html(&[]);
+        assert!(html.contains("<p>No records were extracted.</p>"));
+        assert!(!html.contains("id=\"records\""));
+    }
+
+    #[test]
+    fn links_only_http_urls() {
+        assert_eq!(cell("javascript:alert(1)"), "javascript:alert(1)");
+        assert_eq!(cell("https://a.com/?q=\"x\""), "<a href=\"https://a.com/?q=&quot;x&quot;\">https://a.com/?q=&quot;x&quot;</a>");
+        assert_eq!(cell("https://a.com/ and more"), "https://a.com/ and more");
+    }
+}
@@ -0,0 +1,281 @@
+//! Image metadata scraping.
+//!
+//! Collects every `<img>` on a page together with its absolutized source, alt text and
+//! declared dimensions. When the images are downloaded, the real pixel dimensions and
+//! basic EXIF tags are read from the image bytes as well.
+
+use scraper::{Html, Selector};
+use serde::Serialize;
+use std::collections::HashMap;
+use url::Url;
+
+/// Metadata describing a single image found on a page.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImageInfo {
+    pub src: String,
+    pub alt: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub actual_width: Option<u32>,
+    pub actual_height: Option<u32>,
+    pub exif: HashMap<String, String>,
+}
+
+impl ImageInfo {
+    /// Downloads the image through `scrapper`, honoring its offline mode, compliance
+    /// guardrails, rate limits and proxies, and fills in its real dimensions and EXIF tags.
+    /// `file://` and `data:` sources are not read; pass their bytes to `apply_bytes`.
+    #[cfg(feature = "native")]
+    pub fn fetch_metadata(&mut self, scrapper: &crate::RustScrapper) -> Result<(), Box<dyn std::error::Error>> {
+        if crate::local::is_local_url(&self.src) {
+            return Err(format!("Not reading local image source {}", self.src).into());
+        }
+        self.apply_bytes(&scrapper.fetch_bytes(&self.src)?);
+        Ok(())
+    }
+
+    /// Like `fetch_metadata`, but reads `file://` and `data:` sources directly if the page
+    /// the image was found on, `page_url`, is local as well.
+    #[cfg(feature = "native")]
+    pub(crate) fn fetch_metadata_on(&mut self, scrapper: &crate::RustScrapper, page_url: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if !crate::local::is_local_url(&self.src) {
+            return self.fetch_metadata(scrapper);
+        }
+        crate::local::check_linked(&self.src, page_url)?;
+        self.apply_bytes(&crate::local::fetch(&self.src)?);
+        Ok(())
+    }
+
+    /// Fills in real dimensions and EXIF tags from already downloaded image bytes.
+    pub fn apply_bytes(&mut self, bytes: &[u8]) {
+        if let Some((width, height)) = image_dimensions(bytes) {
+            self.actual_width = Some(width);
+            self.actual_height = Some(height);
+        }
+        self.exif = read_exif(bytes);
+    }
+}
+
+/// Extracts all images from an HTML document.
+/// Relative `src` values are resolved against `base_url`.
+pub fn extract_images(html: &str, base_url: &str) -> Vec<ImageInfo> {
+    images_in(&Html::parse_document(html), base_url)
+}
+
+/// Extracts all images from a parsed document.
+pub(crate) fn images_in(document: &Html, base_url: &str) -> Vec<ImageInfo> {
+    let selector = Selector::parse("img[src]").unwrap();
+    let base = Url::parse(base_url).ok();
+
+    document
+        .select(&selector)
+        .filter_map(|elem| {
+            let attrs = elem.value();
+            let raw_src = attrs.attr("src")?.trim();
+            let src = match &base {
+                Some(base) => base.join(raw_src).map(|u| u.to_string()).unwrap_or_else(|_| raw_src.to_string()),
+                None => raw_src.to_string(),
+            };
+            Some(ImageInfo {
+                src,
+                alt: attrs.attr("alt").map(|alt| alt.trim().to_string()),
+                width: attrs.attr("width").and_then(|w| w.trim().parse().ok()),
+                height: attrs.attr("height").and_then(|h| h.trim().parse().ok()),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Reads pixel dimensions from PNG, GIF or JPEG headers.
+fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") && bytes.len() >= 24 {
+        let width = u32::from_be_bytes(bytes[16..20].try_into().ok()?);
+        let height = u32::from_be_bytes(bytes[20..24].try_into().ok()?);
+        return Some((width, height));
+    }
+    if (bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a")) && bytes.len() >= 10 {
+        let width = u16::from_le_bytes([bytes[6], bytes[7]]) as u32;
+        let height = u16::from_le_bytes([bytes[8], bytes[9]]) as u32;
+        return Some((width, height));
+    }
+    if bytes.starts_with(&[0xFF, 0xD8]) {
+        for (marker, segment) in jpeg_segments(bytes) {
+            // SOF0..SOF15, excluding DHT (C4), JPG (C8) and DAC (CC).
+            if (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker) && segment.len() >= 5 {
+                let height = u16::from_be_bytes([segment[1], segment[2]]) as u32;
+                let width = u16::from_be_bytes([segment[3], segment[4]]) as u32;
+                return Some((width, height));
+            }
+        }
+    }
+    None
+}
+
+/// Iterates over the marker segments of a JPEG file, stopping at start of scan.
+fn jpeg_segments(bytes: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut segments = Vec::new();
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() && bytes[pos] == 0xFF {
+        let marker = bytes[pos + 1];
+        let len = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        if len < 2 || pos + 2 + len > bytes.len() {
+            break;
+        }
+        segments.push((marker, &bytes[pos + 4..pos + 2 + len]));
+        if marker == 0xDA {
+            break;
+        }
+        pos += 2 + len;
+    }
+    segments
+}
+
+/// Reads the common IFD0 tags from a JPEG's EXIF (APP1) segment.
+fn read_exif(bytes: &[u8]) -> HashMap<String, String> {
+    let mut tags = HashMap::new();
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return tags;
+    }
+    let tiff = match jpeg_segments(bytes)
+        .into_iter()
+        .find(|(marker, segment)| *marker == 0xE1 && segment.starts_with(b"Exif\0\0"))
+    {
+        Some((_, segment)) => &segment[6..],
+        None => return tags,
+    };
+    if tiff.len() < 8 {
+        return tags;
+    }
+    let little_endian = &tiff[0..2] == b"II";
+    let u16_at = |pos: usize| -> Option<u16> {
+        let b = tiff.get(pos..pos + 2)?;
+        Some(if little_endian { u16::from_le_bytes([b[0], b[1]]) } else { u16::from_be_bytes([b[0], b[1]]) })
+    };
+    let u32_at = |pos: usize| -> Option<u32> {
+        let b: [u8; 4] = tiff.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    };
+
+    let ifd = match u32_at(4) {
+        Some(offset) => offset as usize,
+        None => return tags,
+    };
+    let count = u16_at(ifd).unwrap_or(0) as usize;
+    for i in 0..count {
+        let entry = ifd + 2 + i * 12;
+        let (Some(tag), Some(kind), Some(len)) = (u16_at(entry), u16_at(entry + 2), u32_at(entry + 4)) else {
+            break;
+        };
+        let name = match tag {
+            0x010F => "Make",
+            0x0110 => "Model",
+            0x0112 => "Orientation",
+            0x0131 => "Software",
+            0x0132 => "DateTime",
+            0x013B => "Artist",
+            0x8298 => "Copyright",
+            _ => continue,
+        };
+        let value = match kind {
+            // ASCII: stored inline when it fits in four bytes, otherwise at an offset.
+            2 => {
+                let len = len as usize;
+                let start = if len <= 4 { entry + 8 } else { u32_at(entry + 8).unwrap_or(0) as usize };
+                tiff.get(start..start + len)
+                    .map(|raw| String::from_utf8_lossy(raw).trim_end_matches('\0').trim().to_string())
+            }
+            3 => u16_at(entry + 8).map(|v| v.to_string()),
+            4 => u32_at(entry + 8).map(|v| v.to_string()),
+            _ => None,
+        };
+        if let Some(value) = value {
+            tags.insert(name.to_string(), value);
+        }
+    }
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A JPEG with an EXIF `Make` tag and a 640x480 frame header.
+    fn jpeg() -> Vec<u8> {
+        let mut tiff = b"II*\0\x08\0\0\0".to_vec();
+        tiff.extend_from_slice(&[1, 0]);
+        tiff.extend_from_slice(&[0x0F, 0x01, 2, 0, 4, 0, 0, 0]);
+        tiff.extend_from_slice(b"Cam\0");
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend_from_slice(&tiff);
+
+        let mut bytes = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        bytes.extend_from_slice(&((app1.len() + 2) as u16).to_be_bytes());
+        bytes.extend_from_slice(&app1);
+        bytes.extend_from_slice(&[0xFF, 0xC0, 0, 11, 8, 0x01, 0xE0, 0x02, 0x80, 1, 1, 0x11, 0]);
+        bytes.extend_from_slice(&[0xFF, 0xDA, 0, 2]);
+        bytes
+    }
+
+    #[test]
+    fn extracts_images_with_absolute_sources() {
+        let html = r#"<img src="/a.png" alt=" Logo " width="10" height="x"><img alt="no source"><img src="https://cdn.example.com/b.gif">"#;
+        let images = extract_images(html, "https://example.com/page/");
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].src, "https://example.com/a.png");
+        assert_eq!(images[0].alt.as_deref(), Some("Logo"));
+        assert_eq!((images[0].width, images[0].height), (Some(10), None));
+        assert_eq!(images[1].src, "https://cdn.example.com/b.gif");
+    }
+
+    #[test]
+    fn reads_dimensions_from_image_headers() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.extend_from_slice(&[0, 0, 1, 0, 0, 0, 0, 200]);
+        assert_eq!(image_dimensions(&png), Some((256, 200)));
+        assert_eq!(image_dimensions(b"GIF89a\x20\x00\x10\x00"), Some((32, 16)));
+        assert_eq!(image_dimensions(&jpeg()), Some((640, 480)));
+        assert_eq!(image_dimensions(b"not an image"), None);
+    }
+
+    #[test]
+    fn reads_exif_tags_from_jpegs() {
+        let mut image = ImageInfo::default();
+        image.apply_bytes(&jpeg());
+        assert_eq!(image.exif.get("Make").map(String::as_str), Some("Cam"));
+        assert_eq!((image.actual_width, image.actual_height), (Some(640), Some(480)));
+        assert!(read_exif(b"GIF89a").is_empty());
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn fetches_metadata_through_the_scrapper() {
+        let mut image = ImageInfo {
+            src: crate::tests::serve_once("image/gif", "GIF89a\x03\x00\x02\x00"),
+            ..ImageInfo::default()
+        };
+        image.fetch_metadata(&crate::RustScrapper::new()).unwrap();
+        assert_eq!((image.actual_width, image.actual_height), (Some(3), Some(2)));
+
+        let mut image = ImageInfo {
+            src: "http://127.0.0.1:1/a.gif".to_string(),
+            ..ImageInfo::default()
+        };
+        assert!(image.fetch_metadata(&crate::RustScrapper::new().offline(true)).is_err());
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn reads_local_images_only_for_local_pages() {
+        let scrapper = crate::RustScrapper::new();
+        let mut image = ImageInfo {
+            src: "data:image/gif;base64,R0lGODlhAwACAA==".to_string(),
+            ..ImageInfo::default()
+        };
+        assert!(image.fetch_metadata(&scrapper).is_err());
+        assert!(image.fetch_metadata_on(&scrapper, "https://example.com/").is_err());
+        assert_eq!(image.actual_width, None);
+        image.fetch_metadata_on(&scrapper, "data:text/html,<img>").unwrap();
+        assert_eq!((image.actual_width, image.actual_height), (Some(3), Some(2)));
+    }
+}
@@ -0,0 +1,1700 @@
+//! Headless browser options for `JsScraper`.
+//!
+//! The default headless Chrome fingerprint (a `HeadlessChrome` user agent, `navigator.webdriver`
+//! set to true, a UTC timezone and an 800x600 window) is blocked by many sites. `JsScrapeOptions`
+//! configures the user agent, language, timezone and viewport, hides the automation flag and
+//! passes custom flags to Chrome. It can also block requests by resource type or URL pattern to
+//! speed up rendering, and capture the XHR/fetch responses the page loads.
+
+use crate::batch::{self, BatchResult};
+use crate::captcha::{self, CaptchaSolver, SharedSolver};
+use crate::cookies::Cookie;
+use crate::error::ScrapeError;
+use crate::fingerprint::FingerprintProfile;
+use crate::storage_state::StorageState;
+use crate::JsScraper;
+use base64::Engine;
+use headless_chrome::browser::tab::RequestInterceptionDecision;
+use headless_chrome::protocol::network::{self, events::ResourceType as CdpResourceType};
+use headless_chrome::protocol::page;
+use headless_chrome::protocol::Method;
+use headless_chrome::{Browser, LaunchOptionsBuilder, Tab};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Declares a Chrome DevTools Protocol method that headless_chrome does not wrap.
+macro_rules! cdp_method {
+    ($name:ident, $method:literal { $($field:ident : $ty:ty),* $(,)? }) => {
+        #[derive(Debug, Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct $name {
+            $($field: $ty),*
+        }
+
+        impl Method for $name {
+            const NAME: &'static str = $method;
+            type ReturnObject = serde_json::Value;
+        }
+    };
+}
+
+cdp_method!(SetDeviceMetricsOverride, "Emulation.setDeviceMetricsOverride" {
+    width: u32,
+    height: u32,
+    device_scale_factor: f64,
+    mobile: bool,
+});
+cdp_method!(SetTouchEmulationEnabled, "Emulation.setTouchEmulationEnabled" {
+    enabled: bool,
+    max_touch_points: u32,
+});
+cdp_method!(SetTimezoneOverride, "Emulation.setTimezoneOverride" { timezone_id: String });
+cdp_method!(AddScriptToEvaluateOnNewDocument, "Page.addScriptToEvaluateOnNewDocument" { source: String });
+cdp_method!(SetDownloadBehavior, "Page.setDownloadBehavior" { behavior: String, download_path: String });
+cdp_method!(GetAllCookies, "Network.getAllCookies" {});
+cdp_method!(SetCookies, "Network.setCookies" { cookies: Vec<Value> });
+cdp_method!(CreateIsolatedWorld, "Page.createIsolatedWorld" { frame_id: String, world_name: String });
+cdp_method!(EvaluateInContext, "Runtime.evaluate" {
+    expression: String,
+    context_id: u64,
+    return_by_value: bool,
+});
+
+/// Flags that keep cross-origin iframes in the page's renderer process, where their documents
+/// can be read.
+const SAME_PROCESS_FRAME_ARGS: [&str; 2] = ["--disable-site-isolation-trials", "--disable-features=IsolateOrigins,site-per-process"];
+
+/// Time given a page to act on a solved CAPTCHA passed to its callback, e.g. to load the
+/// protected content, before the wait conditions are checked again.
+const CAPTCHA_CALLBACK_SETTLE: Duration = Duration::from_secs(2);
+
+/// JavaScript function returning the elements matching a selector in the document and in
+/// every open shadow root below it.
+const DEEP_QUERY_ALL: &str = "((selector) => { const found = []; \
+     const visit = (root) => { found.push(...root.querySelectorAll(selector)); \
+     root.querySelectorAll('*').forEach((e) => e.shadowRoot && visit(e.shadowRoot)); }; \
+     visit(document); return found; })";
+
+/// JavaScript expression serializing the document with the content of open shadow roots
+/// inlined into their hosts, so CSS selectors run on the HTML match inside web components.
+const DEEP_OUTER_HTML: &str = "(() => { \
+     const VOID = new Set(['area', 'base', 'br', 'col', 'embed', 'hr', 'img', 'input', 'link', 'meta', 'source', 'track', 'wbr']); \
+     const RAW = new Set(['SCRIPT', 'STYLE']); \
+     const escape = (s, quotes) => { s = s.replace(/&/g, '&amp;').replace(/</g, '&lt;').replace(/>/g, '&gt;'); \
+       return quotes ? s.replace(/\"/g, '&quot;') : s; }; \
+     const children = (node) => Array.from(node.childNodes).map(serialize).join(''); \
+     const serialize = (node) => { \
+       if (node.nodeType === Node.TEXT_NODE) return node.parentNode && RAW.has(node.parentNode.nodeName) ? node.data : escape(node.data, false); \
+       if (node.nodeType === Node.COMMENT_NODE) return '<!--' + node.data + '-->'; \
+       if (node.nodeType !== Node.ELEMENT_NODE) return ''; \
+       const tag = node.localName; \
+       const attrs = Array.from(node.attributes).map((a) => ' ' + a.name + '=\"' + escape(a.value, true) + '\"').join(''); \
+       if (VOID.has(tag)) return '<' + tag + attrs + '>'; \
+       const shadow = node.shadowRoot ? children(node.shadowRoot) : ''; \
+       return '<' + tag + attrs + '>' + shadow + children(node.content || node) + '</' + tag + '>'; }; \
+     return serialize(document.documentElement); })()";
+
+/// JavaScript expression collecting `PageMetrics` from the Navigation, Paint and Resource
+/// Timing APIs, as JSON.
+pub(crate) const METRICS_SCRIPT: &str = "JSON.stringify((() => { \
+     const nav = performance.getEntriesByType('navigation')[0]; \
+     const resources = performance.getEntriesByType('resource'); \
+     const paint = performance.getEntriesByName('first-contentful-paint')[0]; \
+     const at = (time) => time > 0 ? time : null; \
+     return { dom_content_loaded_ms: nav ? at(nav.domContentLoadedEventEnd) : null, \
+       load_ms: nav ? at(nav.loadEventEnd) : null, \
+       first_contentful_paint_ms: paint ? paint.startTime : null, \
+       request_count: resources.length + (nav ? 1 : 0), \
+       transferred_bytes: resources.concat(nav ? [nav] : []).reduce((sum, e) => sum + (e.transferSize || 0), 0) }; })())";
+
+/// Browser that renders pages for `JsScraper::scrape_with_options`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BrowserBackend {
+    /// Chrome or Chromium driven over the DevTools protocol.
+    Chrome,
+    /// Any browser behind a WebDriver server such as geckodriver or chromedriver,
+    /// e.g. `http://localhost:4444`. Requires the `webdriver` feature.
+    WebDriver(String),
+}
+
+/// Kind of resource a page requests, as reported by Chrome.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ResourceType {
+    Document,
+    Stylesheet,
+    Image,
+    Media,
+    Font,
+    Script,
+    Xhr,
+    Fetch,
+    WebSocket,
+    Other,
+}
+
+impl ResourceType {
+    fn cdp_name(self) -> &'static str {
+        match self {
+            ResourceType::Document => "Document",
+            ResourceType::Stylesheet => "Stylesheet",
+            ResourceType::Image => "Image",
+            ResourceType::Media => "Media",
+            ResourceType::Font => "Font",
+            ResourceType::Script => "Script",
+            ResourceType::Xhr => "XHR",
+            ResourceType::Fetch => "Fetch",
+            ResourceType::WebSocket => "WebSocket",
+            ResourceType::Other => "Other",
+        }
+    }
+}
+
+/// Hosts of common analytics and ad scripts blocked by `JsScrapeOptions::block_analytics`.
+const ANALYTICS_PATTERNS: [&str; 8] = [
+    "*google-analytics.com*",
+    "*googletagmanager.com*",
+    "*doubleclick.net*",
+    "*connect.facebook.net*",
+    "*hotjar.com*",
+    "*segment.io*",
+    "*mixpanel.com*",
+    "*clarity.ms*",
+];
+
+/// An XHR or fetch response captured while rendering a page.
+#[derive(Debug, Clone, Serialize)]
+pub struct CapturedResponse {
+    pub url: String,
+    pub status: u32,
+    pub mime_type: String,
+    pub body: String,
+}
+
+impl CapturedResponse {
+    /// Parses the body as JSON.
+    pub fn json(&self) -> Option<Value> {
+        serde_json::from_str(&self.body).ok()
+    }
+}
+
+/// A frame of a rendered page.
+#[derive(Debug, Clone, Serialize)]
+pub struct FrameInfo {
+    pub id: String,
+    /// Frame containing this one; `None` for the top document.
+    pub parent_id: Option<String>,
+    /// Value of the iframe's `name` attribute.
+    pub name: Option<String>,
+    pub url: String,
+}
+
+/// Chooses the iframe `JsScraper` extracts from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameSelector {
+    /// The iframe element matching a CSS selector in the top document.
+    Element(String),
+    /// The first iframe whose URL matches a pattern, where `*` matches any characters.
+    Url(String),
+}
+
+impl FrameSelector {
+    /// Selects the iframe element matching the CSS selector, e.g. `iframe#reviews`.
+    pub fn element(selector: &str) -> Self {
+        FrameSelector::Element(selector.to_string())
+    }
+
+    /// Selects the first iframe whose URL matches the pattern, e.g. `*widgets.example.com*`.
+    pub fn url(pattern: &str) -> Self {
+        FrameSelector::Url(pattern.to_string())
+    }
+}
+
+/// Result of `JsScraper::render`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderedPage {
+    pub url: String,
+    /// Rendered HTML of the page, or of the selected frame if `JsScrapeOptions::in_frame` is set.
+    pub html: String,
+    /// XHR and fetch responses matching the options' capture patterns.
+    pub responses: Vec<CapturedResponse>,
+    /// All frames of the page, the top document first. Empty for the WebDriver backend.
+    pub frames: Vec<FrameInfo>,
+    /// Load timing and request counts of the top document.
+    pub metrics: PageMetrics,
+}
+
+/// Load timing and request statistics of a rendered page. Times are in milliseconds since
+/// the navigation started.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PageMetrics {
+    /// `None` if the event had not fired when the HTML was extracted.
+    pub dom_content_loaded_ms: Option<f64>,
+    /// `None` if the page was still loading when the HTML was extracted, e.g. a hanging request.
+    pub load_ms: Option<f64>,
+    pub first_contentful_paint_ms: Option<f64>,
+    /// Requests made by the page, including the document itself.
+    pub request_count: usize,
+    /// Bytes transferred over the network. Cached resources and cross-origin resources without
+    /// a `Timing-Allow-Origin` header count as 0.
+    pub transferred_bytes: u64,
+    /// Wall-clock time of the whole render, including launching the browser and the wait conditions.
+    #[serde(default)]
+    pub render_ms: u64,
+}
+
+impl PageMetrics {
+    /// Whether the page finished loading before the HTML was extracted.
+    pub fn is_loaded(&self) -> bool {
+        self.load_ms.is_some()
+    }
+
+    /// Parses the result of the metrics script.
+    pub(crate) fn from_script_result(value: Option<&Value>) -> PageMetrics {
+        let metrics = value.and_then(Value::as_str).map(serde_json::from_str::<PageMetrics>);
+        match metrics {
+            Some(Ok(metrics)) => metrics,
+            Some(Err(e)) => {
+                warn!("Could not read page metrics: {}", e);
+                PageMetrics::default()
+            }
+            None => PageMetrics::default(),
+        }
+    }
+}
+
+/// A file the browser downloaded, see `JsScraper::download`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadedFile {
+    /// Where the file was saved, in a directory of its own inside the options' download
+    /// directory.
+    pub path: PathBuf,
+    /// The file name the site suggested.
+    pub file_name: String,
+    pub bytes: u64,
+}
+
+impl DownloadedFile {
+    /// Reads the file.
+    pub fn contents(&self) -> std::io::Result<Vec<u8>> {
+        std::fs::read(&self.path)
+    }
+
+    /// Reads the file as text, e.g. a CSV export.
+    pub fn text(&self) -> std::io::Result<String> {
+        std::fs::read_to_string(&self.path)
+    }
+}
+
+/// A device to emulate: its user agent, screen and input capabilities.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub user_agent: String,
+    /// Viewport width in CSS pixels.
+    pub width: u32,
+    /// Viewport height in CSS pixels.
+    pub height: u32,
+    pub device_scale_factor: f64,
+    /// Emulates a mobile browser: meta viewport handling and overlay scrollbars.
+    pub mobile: bool,
+    /// Enables touch events.
+    pub touch: bool,
+}
+
+impl Device {
+    /// Apple iPhone 15.
+    pub fn iphone_15() -> Self {
+        Device::phone(
+            "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.0 Mobile/15E148 Safari/604.1",
+            393,
+            852,
+            3.0,
+        )
+    }
+
+    /// Apple iPhone SE, a small screen.
+    pub fn iphone_se() -> Self {
+        Device::phone(
+            "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.0 Mobile/15E148 Safari/604.1",
+            375,
+            667,
+            2.0,
+        )
+    }
+
+    /// Google Pixel 8.
+    pub fn pixel_8() -> Self {
+        Device::phone(
+            "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Mobile Safari/537.36",
+            412,
+            915,
+            2.625,
+        )
+    }
+
+    /// Samsung Galaxy S23.
+    pub fn galaxy_s23() -> Self {
+        Device::phone(
+            "Mozilla/5.0 (Linux; Android 14; SM-S911B) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Mobile Safari/537.36",
+            360,
+            780,
+            3.0,
+        )
+    }
+
+    /// Apple iPad Air.
+    pub fn ipad_air() -> Self {
+        Device::phone(
+            "Mozilla/5.0 (iPad; CPU OS 17_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.0 Mobile/15E148 Safari/604.1",
+            820,
+            1180,
+            2.0,
+        )
+    }
+
+    fn phone(user_agent: &str, width: u32, height: u32, device_scale_factor: f64) -> Self {
+        Device {
+            user_agent: user_agent.to_string(),
+            width,
+            height,
+            device_scale_factor,
+            mobile: true,
+            touch: true,
+        }
+    }
+}
+
+/// A condition awaited after the page loaded and before the HTML is extracted.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WaitFor {
+    /// An element matching the selector is rendered and visible.
+    Visible(String),
+    /// At least this many elements match the selector.
+    Count(String, usize),
+    /// The JavaScript expression evaluates to true.
+    Predicate(String),
+    /// No new resource has been loaded for this long.
+    NetworkIdle(Duration),
+    /// A fixed delay.
+    Delay(Duration),
+}
+
+impl WaitFor {
+    /// Waits until an element matching the selector is visible.
+    pub fn visible(selector: &str) -> Self {
+        WaitFor::Visible(selector.to_string())
+    }
+
+    /// Waits until at least `count` elements match the selector.
+    pub fn count(selector: &str, count: usize) -> Self {
+        WaitFor::Count(selector.to_string(), count)
+    }
+
+    /// Waits until the JavaScript expression is true, e.g. `window.dataLoaded === true`.
+    pub fn predicate(expression: &str) -> Self {
+        WaitFor::Predicate(expression.to_string())
+    }
+
+    /// Waits until no resource has been loaded for `idle`.
+    pub fn network_idle(idle: Duration) -> Self {
+        WaitFor::NetworkIdle(idle)
+    }
+
+    /// Sleeps for a fixed time. Prefer a condition where possible.
+    pub fn delay(delay: Duration) -> Self {
+        WaitFor::Delay(delay)
+    }
+
+    /// JavaScript expression polled until it evaluates to true. For `NetworkIdle` it evaluates to
+    /// the number of loaded resources instead, which has to stay the same for the idle period.
+    /// With `pierce_shadow_roots`, selectors also match inside open shadow roots.
+    pub(crate) fn script(&self, pierce_shadow_roots: bool) -> Option<String> {
+        match self {
+            WaitFor::Visible(selector) => Some(format!(
+                "(() => {{ const e = {}[0]; if (!e) return false; \
+                 const r = e.getBoundingClientRect(); const s = getComputedStyle(e); \
+                 return r.width > 0 && r.height > 0 && s.visibility !== 'hidden' && s.display !== 'none'; }})()",
+                query_all(selector, pierce_shadow_roots)
+            )),
+            WaitFor::Count(selector, count) => Some(format!("{}.length >= {}", query_all(selector, pierce_shadow_roots), count)),
+            WaitFor::Predicate(expression) => Some(format!("Boolean({})", expression)),
+            WaitFor::NetworkIdle(_) => Some("performance.getEntriesByType('resource').length".to_string()),
+            WaitFor::Delay(_) => None,
+        }
+    }
+
+    /// Whether the value of the polled script meets the condition. `last_change` tracks when
+    /// the value last changed, for `NetworkIdle`.
+    pub(crate) fn is_met(&self, value: Value, last_change: &mut (Value, Instant)) -> bool {
+        match self {
+            WaitFor::NetworkIdle(idle) => {
+                if value != last_change.0 {
+                    *last_change = (value, Instant::now());
+                }
+                last_change.1.elapsed() >= *idle
+            }
+            _ => value == Value::Bool(true),
+        }
+    }
+}
+
+/// JavaScript expression evaluating to the elements matching the selector, including those in
+/// open shadow roots if `pierce_shadow_roots` is set.
+pub(crate) fn query_all(selector: &str, pierce_shadow_roots: bool) -> String {
+    let selector = serde_json::to_string(selector).unwrap_or_default();
+    if pierce_shadow_roots {
+        format!("{}({})", DEEP_QUERY_ALL, selector)
+    } else {
+        format!("document.querySelectorAll({})", selector)
+    }
+}
+
+/// JavaScript expression evaluating to the HTML of the document, with the content of open
+/// shadow roots inlined if `pierce_shadow_roots` is set.
+pub(crate) fn outer_html_script(pierce_shadow_roots: bool) -> &'static str {
+    if pierce_shadow_roots {
+        DEEP_OUTER_HTML
+    } else {
+        "document.documentElement.outerHTML"
+    }
+}
+
+/// Runs the wait conditions one after another, evaluating scripts with `evaluate`.
+/// All conditions together have to be met within `timeout`.
+pub(crate) fn run_waits(
+    waits: &[WaitFor],
+    timeout: Duration,
+    pierce_shadow_roots: bool,
+    mut evaluate: impl FnMut(&str) -> Result<Value, Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+    let deadline = Instant::now() + timeout;
+    for wait in waits {
+        if let WaitFor::Delay(delay) = wait {
+            std::thread::sleep(*delay);
+            continue;
+        }
+        let script = wait.script(pierce_shadow_roots).unwrap_or_default();
+        let mut last_change = (Value::Null, Instant::now());
+        loop {
+            let value = evaluate(&script)?;
+            if wait.is_met(value, &mut last_change) {
+                break;
+            }
+            if Instant::now() >= deadline {
+                return Err(format!("Timed out waiting for {:?}", wait).into());
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+    Ok(())
+}
+
+/// Launch and page options for `JsScraper::scrape_with_options`.
+#[derive(Debug, Clone)]
+pub struct JsScrapeOptions {
+    pub(crate) backend: BrowserBackend,
+    pub(crate) user_agent: Option<String>,
+    pub(crate) language: Option<String>,
+    pub(crate) timezone: Option<String>,
+    pub(crate) viewport: Option<(u32, u32)>,
+    pub(crate) device_scale_factor: f64,
+    pub(crate) mobile: bool,
+    pub(crate) touch: bool,
+    pub(crate) hide_webdriver: bool,
+    pub(crate) storage_state: Option<StorageState>,
+    pub(crate) headless: bool,
+    pub(crate) chrome_path: Option<PathBuf>,
+    pub(crate) chrome_args: Vec<String>,
+    pub(crate) blocked_types: Vec<ResourceType>,
+    pub(crate) blocked_urls: Vec<String>,
+    pub(crate) capture_patterns: Vec<String>,
+    pub(crate) frame: Option<FrameSelector>,
+    pub(crate) pierce_shadow_roots: bool,
+    pub(crate) download_dir: Option<PathBuf>,
+    pub(crate) detect_captchas: bool,
+    pub(crate) captcha_solver: Option<SharedSolver>,
+    pub(crate) waits: Vec<WaitFor>,
+    pub(crate) wait_timeout: Duration,
+}
+
+impl Default for JsScrapeOptions {
+    fn default() -> Self {
+        JsScrapeOptions {
+            backend: BrowserBackend::Chrome,
+            user_agent: None,
+            language: None,
+            timezone: None,
+            viewport: None,
+            device_scale_factor: 1.0,
+            mobile: false,
+            touch: false,
+            hide_webdriver: false,
+            storage_state: None,
+            headless: true,
+            chrome_path: None,
+            chrome_args: Vec::new(),
+            blocked_types: Vec::new(),
+            blocked_urls: Vec::new(),
+            capture_patterns: Vec::new(),
+            frame: None,
+            pierce_shadow_roots: false,
+            download_dir: None,
+            detect_captchas: false,
+            captcha_solver: None,
+            waits: Vec::new(),
+            wait_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl JsScrapeOptions {
+    /// Creates options matching a plain headless Chrome launch.
+    pub fn new() -> Self {
+        JsScrapeOptions::default()
+    }
+
+    /// Common stealth settings: hides `navigator.webdriver`, removes `HeadlessChrome` from the
+    /// user agent and disables Blink's automation features.
+    pub fn stealth() -> Self {
+        JsScrapeOptions::new()
+            .hide_webdriver()
+            .chrome_arg("--disable-blink-features=AutomationControlled")
+    }
+
+    /// Selects the browser backend. Chrome is used by default.
+    pub fn backend(mut self, backend: BrowserBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Overrides the user agent.
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Sets `Accept-Language` and `navigator.languages`, e.g. `en-US,en`.
+    pub fn language(mut self, language: &str) -> Self {
+        self.language = Some(language.to_string());
+        self
+    }
+
+    /// Uses the user agent and languages of a fingerprint profile, e.g. to match the profile
+    /// the HTTP engine uses for the same host. Client hints are those of the real browser.
+    pub fn fingerprint(mut self, profile: &FingerprintProfile) -> Self {
+        self.user_agent = Some(profile.user_agent.clone());
+        self.language = Some(profile.accept_language.clone());
+        self
+    }
+
+    /// Overrides the timezone with an IANA name, e.g. `Europe/Istanbul`.
+    /// Not supported by the WebDriver backend.
+    pub fn timezone(mut self, timezone: &str) -> Self {
+        self.timezone = Some(timezone.to_string());
+        self
+    }
+
+    /// Sets the viewport size in CSS pixels.
+    pub fn viewport(mut self, width: u32, height: u32) -> Self {
+        self.viewport = Some((width, height));
+        self
+    }
+
+    /// Emulates a device, e.g. `Device::iphone_15()`, to get the markup sites serve to phones.
+    /// Sets the user agent, viewport, pixel ratio and touch support.
+    pub fn device(mut self, device: Device) -> Self {
+        self.user_agent = Some(device.user_agent);
+        self.viewport = Some((device.width, device.height));
+        self.device_scale_factor = device.device_scale_factor;
+        self.mobile = device.mobile;
+        self.touch = device.touch;
+        self
+    }
+
+    /// Makes `navigator.webdriver` undefined, as in a regular browser, and removes
+    /// `HeadlessChrome` from the default user agent.
+    pub fn hide_webdriver(mut self) -> Self {
+        self.hide_webdriver = true;
+        self
+    }
+
+    /// Shows the browser window instead of running headless.
+    pub fn headful(mut self) -> Self {
+        self.headless = false;
+        self
+    }
+
+    /// Uses this Chrome or Chromium binary instead of the one found on the system.
+    pub fn chrome_path(mut self, path: &str) -> Self {
+        self.chrome_path = Some(PathBuf::from(path));
+        self
+    }
+
+    /// Passes an extra command line flag to the browser, e.g. `--proxy-server=host:port`.
+    pub fn chrome_arg(mut self, arg: &str) -> Self {
+        self.chrome_args.push(arg.to_string());
+        self
+    }
+
+    /// Blocks every request for this kind of resource, e.g. `ResourceType::Image`.
+    pub fn block_resource(mut self, resource_type: ResourceType) -> Self {
+        self.blocked_types.push(resource_type);
+        self
+    }
+
+    /// Blocks requests whose URL matches the pattern, where `*` matches any characters,
+    /// e.g. `*.woff2` or `*ads.example.com*`.
+    pub fn block_url(mut self, pattern: &str) -> Self {
+        self.blocked_urls.push(pattern.to_string());
+        self
+    }
+
+    /// Blocks images, media and fonts, which are rarely needed for scraping.
+    pub fn block_media(self) -> Self {
+        self.block_resource(ResourceType::Image)
+            .block_resource(ResourceType::Media)
+            .block_resource(ResourceType::Font)
+    }
+
+    /// Blocks common analytics and advertising scripts.
+    pub fn block_analytics(self) -> Self {
+        ANALYTICS_PATTERNS.iter().fold(self, |options, pattern| options.block_url(pattern))
+    }
+
+    /// Captures the bodies of XHR and fetch responses whose URL matches the pattern
+    /// (`*` captures all of them). They are returned by `JsScraper::render`.
+    /// Not supported by the WebDriver backend.
+    pub fn capture_responses(mut self, pattern: &str) -> Self {
+        self.capture_patterns.push(pattern.to_string());
+        self
+    }
+
+    /// Waits for a condition after the page loaded. Conditions run in the order they were added.
+    pub fn wait_for(mut self, wait: WaitFor) -> Self {
+        self.waits.push(wait);
+        self
+    }
+
+    /// Fails rendering if the wait conditions are not met within this time (30 seconds by default).
+    pub fn wait_timeout(mut self, timeout: Duration) -> Self {
+        self.wait_timeout = timeout;
+        self
+    }
+
+    /// Restores cookies and `localStorage` saved from an earlier session before the page loads.
+    /// Not supported by the WebDriver backend.
+    pub fn storage_state(mut self, state: StorageState) -> Self {
+        self.storage_state = Some(state);
+        self
+    }
+
+    /// Extracts from an iframe instead of the top document, e.g. content embedded from a
+    /// third-party site. Cross-origin frames are kept in the page's process to make them readable.
+    /// The WebDriver backend only supports `FrameSelector::Element`.
+    pub fn in_frame(mut self, frame: FrameSelector) -> Self {
+        self.frame = Some(frame);
+        self
+    }
+
+    /// Resolves selectors inside the open shadow roots of web components too, both in wait
+    /// conditions and in the extracted HTML, where the content of each shadow root is inlined
+    /// into its host element. Closed shadow roots stay invisible.
+    pub fn pierce_shadow_roots(mut self) -> Self {
+        self.pierce_shadow_roots = true;
+        self
+    }
+
+    /// Fails with `ScrapeError::CaptchaRequired` on pages showing a CAPTCHA once the wait
+    /// conditions are met, see `captcha::detect`. Chrome only.
+    pub fn detect_captchas(mut self) -> Self {
+        self.detect_captchas = true;
+        self
+    }
+
+    /// Detects CAPTCHAs and solves them with `solver`: the token is put in the widget's
+    /// response fields and passed to its `data-callback`, or its form is submitted. Pages still
+    /// showing a CAPTCHA after that fail with `ScrapeError::CaptchaRequired`. Chrome only.
+    pub fn captcha_solver<S: CaptchaSolver + 'static>(mut self, solver: S) -> Self {
+        self.detect_captchas = true;
+        self.captcha_solver = Some(SharedSolver(Arc::new(solver)));
+        self
+    }
+
+    /// Saves files the page downloads in `dir`, see `JsScraper::download`. Without it, downloads
+    /// go to a `rust-scrapper-downloads` directory in the system's temporary directory.
+    pub fn download_dir(mut self, dir: &str) -> Self {
+        self.download_dir = Some(PathBuf::from(dir));
+        self
+    }
+
+    /// The directory downloads are saved in.
+    fn downloads_path(&self) -> PathBuf {
+        self.download_dir
+            .clone()
+            .unwrap_or_else(|| std::env::temp_dir().join("rust-scrapper-downloads"))
+    }
+
+    /// A new directory in `downloads_path` for one download, so concurrent downloads cannot
+    /// take each other's files.
+    fn download_call_path(&self) -> PathBuf {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let call = CALLS.fetch_add(1, Ordering::Relaxed);
+        self.downloads_path().join(format!("{}-{}", std::process::id(), call))
+    }
+
+    /// Command line flags Chrome is launched with.
+    fn launch_args(&self) -> Vec<String> {
+        let mut args = self.chrome_args.clone();
+        if self.frame.is_some() {
+            args.extend(SAME_PROCESS_FRAME_ARGS.iter().map(|arg| arg.to_string()));
+        }
+        args
+    }
+
+    fn is_blocked(&self, url: &str, resource_type: &str) -> bool {
+        self.blocked_types.iter().any(|t| t.cdp_name() == resource_type)
+            || self.blocked_urls.iter().any(|pattern| wildcard_match(pattern, url))
+    }
+}
+
+/// Matches text against a pattern where `*` matches any run of characters.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts = parts.collect::<Vec<_>>();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// A Chrome process launched with custom flags, killed when dropped.
+struct ChromeProcess {
+    child: Child,
+    // Keeps the temporary profile alive as long as the browser runs.
+    _profile: tempfile::TempDir,
+}
+
+impl Drop for ChromeProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A running browser configured from `JsScrapeOptions`.
+pub(crate) struct BrowserSession {
+    pub(crate) browser: Browser,
+    // Declared after `browser` so the connection is closed before the process is killed.
+    _process: Option<ChromeProcess>,
+}
+
+impl BrowserSession {
+    /// Launches Chrome. Custom flags require launching the process ourselves and connecting to it.
+    pub(crate) fn launch(options: &JsScrapeOptions) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = match &options.chrome_path {
+            Some(path) => path.clone(),
+            None => headless_chrome::browser::default_executable()?,
+        };
+        let args = options.launch_args();
+        if args.is_empty() {
+            let launch_options = LaunchOptionsBuilder::default()
+                .path(Some(path))
+                .headless(options.headless)
+                .build()?;
+            return Ok(BrowserSession {
+                browser: Browser::new(launch_options)?,
+                _process: None,
+            });
+        }
+
+        let profile = tempfile::Builder::new().prefix("rust-scrapper-profile").tempdir()?;
+        let mut command = Command::new(&path);
+        command
+            .arg("--remote-debugging-port=0")
+            .arg("--no-first-run")
+            .arg(format!("--user-data-dir={}", profile.path().display()))
+            .args(&args)
+            .stderr(Stdio::piped());
+        if options.headless {
+            command.arg("--headless");
+        }
+        let mut process = ChromeProcess {
+            child: command.spawn()?,
+            _profile: profile,
+        };
+
+        let stderr = process.child.stderr.take().ok_or("Chrome has no stderr")?;
+        let mut lines = BufReader::new(stderr).lines().map_while(Result::ok);
+        let ws_url = lines
+            .find_map(|line| line.split_once("listening on ").map(|(_, url)| url.trim().to_string()))
+            .ok_or("Chrome exited without printing its DevTools URL")?;
+        // Keep draining Chrome's log output so it never blocks on a full pipe.
+        std::thread::spawn(move || lines.for_each(drop));
+        Ok(BrowserSession {
+            browser: Browser::connect(ws_url)?,
+            _process: Some(process),
+        })
+    }
+
+    /// Opens a tab with the emulation settings of the options applied.
+    pub(crate) fn open_tab(&self, options: &JsScrapeOptions) -> Result<Arc<Tab>, Box<dyn std::error::Error>> {
+        self.configure_tab(self.browser.wait_for_initial_tab()?, options)
+    }
+
+    /// Opens another tab in the same browser, configured like `open_tab`.
+    pub(crate) fn open_new_tab(&self, options: &JsScrapeOptions) -> Result<Arc<Tab>, Box<dyn std::error::Error>> {
+        self.configure_tab(self.browser.new_tab()?, options)
+    }
+
+    /// Applies the emulation settings of the options to a tab.
+    fn configure_tab(&self, tab: Arc<Tab>, options: &JsScrapeOptions) -> Result<Arc<Tab>, Box<dyn std::error::Error>> {
+
+        let user_agent = match &options.user_agent {
+            Some(user_agent) => Some(user_agent.clone()),
+            None if options.hide_webdriver || options.language.is_some() => {
+                Some(self.browser.get_version()?.user_agent.replace("HeadlessChrome", "Chrome"))
+            }
+            None => None,
+        };
+        if let Some(user_agent) = &user_agent {
+            tab.set_user_agent(user_agent, options.language.as_deref(), None)?;
+        }
+        if let Some(timezone) = &options.timezone {
+            tab.call_method(SetTimezoneOverride {
+                timezone_id: timezone.clone(),
+            })?;
+        }
+        if let Some((width, height)) = options.viewport {
+            tab.call_method(SetDeviceMetricsOverride {
+                width,
+                height,
+                device_scale_factor: options.device_scale_factor,
+                mobile: options.mobile,
+            })?;
+        }
+        if options.touch {
+            tab.call_method(SetTouchEmulationEnabled {
+                enabled: true,
+                max_touch_points: 5,
+            })?;
+        }
+
+        let mut script = String::new();
+        if options.hide_webdriver {
+            script.push_str("Object.defineProperty(Navigator.prototype, 'webdriver', { get: () => undefined });");
+        }
+        if let Some(language) = &options.language {
+            let languages = language
+                .split(',')
+                .map(|lang| lang.split(';').next().unwrap_or_default().trim())
+                .collect::<Vec<_>>();
+            script.push_str(&format!(
+                "Object.defineProperty(Navigator.prototype, 'languages', {{ get: () => {} }});",
+                serde_json::to_string(&languages)?
+            ));
+        }
+        if let Some(state) = &options.storage_state {
+            restore_cookies(&tab, &state.cookies)?;
+            for origin in &state.origins {
+                // Only fills in missing entries, so values the page updates are not reverted
+                // on the next navigation.
+                script.push_str(&format!(
+                    "if (location.origin === {}) {{ for (const [k, v] of {}) {{ if (localStorage.getItem(k) === null) localStorage.setItem(k, v); }} }}",
+                    serde_json::to_string(&origin.origin)?,
+                    serde_json::to_string(&origin.local_storage)?
+                ));
+            }
+        }
+        if !script.is_empty() {
+            tab.call_method(AddScriptToEvaluateOnNewDocument { source: script })?;
+        }
+        Ok(tab)
+    }
+
+    /// Launches the browser, opens a tab and loads the page, awaiting the wait conditions.
+    fn load(url: &str, options: &JsScrapeOptions) -> Result<(Self, Arc<Tab>, ResponseLog), Box<dyn std::error::Error>> {
+        let session = BrowserSession::launch(options)?;
+        let tab = session.open_tab(options)?;
+        let log = intercept_network(&tab, options)?;
+        navigate(&tab, url, options)?;
+        Ok((session, tab, log))
+    }
+}
+
+/// Loads a page in the tab, awaiting the wait conditions.
+pub(crate) fn navigate(tab: &Tab, url: &str, options: &JsScrapeOptions) -> Result<(), Box<dyn std::error::Error>> {
+    tab.navigate_to(url)?.wait_until_navigated()?;
+    await_page(tab, options)?;
+    if options.detect_captchas {
+        resolve_captcha(tab, url, options)?;
+    }
+    Ok(())
+}
+
+fn await_page(tab: &Tab, options: &JsScrapeOptions) -> Result<(), Box<dyn std::error::Error>> {
+    run_waits(&options.waits, options.wait_timeout, options.pierce_shadow_roots, |script| {
+        Ok(tab.evaluate(script, false)?.value.unwrap_or_default())
+    })
+}
+
+/// Fails with `ScrapeError::CaptchaRequired` if the page loaded from `url` shows a CAPTCHA,
+/// unless the options' solver solves it.
+fn resolve_captcha(tab: &Tab, url: &str, options: &JsScrapeOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(challenge) = captcha::detect(&page_html(tab, options.pierce_shadow_roots)?, url) else {
+        return Ok(());
+    };
+    let Some(SharedSolver(solver)) = &options.captcha_solver else {
+        warn!("{} shows a {} CAPTCHA", url, challenge.provider);
+        return Err(Box::new(ScrapeError::CaptchaRequired(challenge)));
+    };
+    let token = solver.solve(&challenge)?;
+    let applied = tab.evaluate(&captcha::apply_token_script(&challenge, &token), false)?.value;
+    match applied.as_ref().and_then(Value::as_str) {
+        Some("submitted") => {
+            tab.wait_until_navigated()?;
+            await_page(tab, options)?;
+        }
+        Some("callback") => {
+            std::thread::sleep(CAPTCHA_CALLBACK_SETTLE);
+            await_page(tab, options)?;
+        }
+        _ => {}
+    }
+    match captcha::detect(&page_html(tab, options.pierce_shadow_roots)?, &tab.get_url()) {
+        Some(challenge) => {
+            warn!("The {} CAPTCHA solution for {} was rejected", challenge.provider, url);
+            Err(Box::new(ScrapeError::CaptchaRequired(challenge)))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Reads the HTML, frames, captured responses and metrics of the page loaded in the tab.
+/// `render_ms` is left for the caller to fill in.
+pub(crate) fn rendered_page(tab: &Tab, log: &ResponseLog, url: &str, options: &JsScrapeOptions) -> Result<RenderedPage, Box<dyn std::error::Error>> {
+    let frames = frame_tree(tab)?;
+    let html = match &options.frame {
+        Some(frame) => frame_html(tab, &frames, frame, options.pierce_shadow_roots)?,
+        None => page_html(tab, options.pierce_shadow_roots)?,
+    };
+    let metrics = PageMetrics::from_script_result(tab.evaluate(METRICS_SCRIPT, false)?.value.as_ref());
+    Ok(RenderedPage {
+        url: url.to_string(),
+        html,
+        responses: log.collect(tab),
+        frames,
+        metrics,
+    })
+}
+
+/// Fills in the render time of a page, warning if it had not finished loading.
+pub(crate) fn finish_render(mut page: RenderedPage, started: Instant) -> RenderedPage {
+    page.metrics.render_ms = started.elapsed().as_millis() as u64;
+    if !page.metrics.is_loaded() {
+        warn!("{} had not finished loading after {} ms", page.url, page.metrics.render_ms);
+    }
+    page
+}
+
+fn restore_cookies(tab: &Tab, cookies: &[Cookie]) -> Result<(), Box<dyn std::error::Error>> {
+    if cookies.is_empty() {
+        return Ok(());
+    }
+    let cookies = cookies
+        .iter()
+        .map(|cookie| {
+            let mut param = serde_json::json!({
+                "name": cookie.name,
+                "value": cookie.value,
+                "path": cookie.path,
+                "secure": cookie.secure,
+            });
+            // A cookie set for a URL is host-only; one set for a domain includes subdomains.
+            if cookie.include_subdomains {
+                param["domain"] = Value::from(format!(".{}", cookie.domain));
+            } else {
+                let scheme = if cookie.secure { "https" } else { "http" };
+                param["url"] = Value::from(format!("{}://{}{}", scheme, cookie.domain, cookie.path));
+            }
+            if let Some(expires) = cookie.expires {
+                param["expires"] = Value::from(expires);
+            }
+            param
+        })
+        .collect();
+    tab.call_method(SetCookies { cookies })?;
+    Ok(())
+}
+
+/// Reads the browser's cookies and the `localStorage` of the page's origin.
+fn read_storage_state(tab: &Tab, mut state: StorageState) -> Result<StorageState, Box<dyn std::error::Error>> {
+    let cookies = tab.call_method(GetAllCookies {})?;
+    state.cookies = cookies["cookies"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .map(|cookie| {
+            let domain = cookie["domain"].as_str().unwrap_or_default();
+            let expires = cookie["expires"].as_f64().unwrap_or(-1.0);
+            Cookie {
+                name: cookie["name"].as_str().unwrap_or_default().to_string(),
+                value: cookie["value"].as_str().unwrap_or_default().to_string(),
+                domain: domain.trim_start_matches('.').to_string(),
+                include_subdomains: domain.starts_with('.'),
+                path: cookie["path"].as_str().unwrap_or("/").to_string(),
+                secure: cookie["secure"].as_bool().unwrap_or_default(),
+                expires: (expires > 0.0).then_some(expires as i64),
+            }
+        })
+        .collect();
+
+    let script = "JSON.stringify([location.origin, Object.entries(localStorage)])";
+    if let Some(Value::String(json)) = tab.evaluate(script, false)?.value {
+        let (origin, entries): (String, Vec<(String, String)>) = serde_json::from_str(&json)?;
+        // Opaque origins such as about:blank have no storage worth keeping.
+        if origin != "null" {
+            state.set_local_storage(&origin, entries);
+        }
+    }
+    Ok(state)
+}
+
+/// Responses recorded while a page loads. Their bodies are fetched once loading is done,
+/// as Chrome only has a body after the response has finished.
+#[derive(Clone, Default)]
+pub(crate) struct ResponseLog {
+    entries: Arc<Mutex<Vec<(String, CapturedResponse)>>>,
+}
+
+impl ResponseLog {
+    /// Fetches the bodies of the recorded responses. Responses whose body is no longer
+    /// available, e.g. after a navigation, are skipped.
+    pub(crate) fn collect(&self, tab: &Tab) -> Vec<CapturedResponse> {
+        let entries = std::mem::take(&mut *self.entries.lock().unwrap());
+        entries
+            .into_iter()
+            .filter_map(|(request_id, mut response)| {
+                let body = match tab.call_method(network::methods::GetResponseBody { request_id: &request_id }) {
+                    Ok(body) => body,
+                    Err(e) => {
+                        warn!("No response body for {}: {}", response.url, e);
+                        return None;
+                    }
+                };
+                response.body = if body.base64_encoded {
+                    let bytes = base64::engine::general_purpose::STANDARD.decode(&body.body).ok()?;
+                    String::from_utf8_lossy(&bytes).into_owned()
+                } else {
+                    body.body
+                };
+                Some(response)
+            })
+            .collect()
+    }
+}
+
+/// Installs request blocking and response capturing on the tab as configured in the options.
+pub(crate) fn intercept_network(tab: &Tab, options: &JsScrapeOptions) -> Result<ResponseLog, Box<dyn std::error::Error>> {
+    let log = ResponseLog::default();
+    if !options.capture_patterns.is_empty() {
+        let entries = Arc::clone(&log.entries);
+        let patterns = options.capture_patterns.clone();
+        tab.enable_response_handling(Box::new(move |event, _| {
+            let response = event.response;
+            if matches!(event._type, CdpResourceType::XHR | CdpResourceType::Fetch)
+                && patterns.iter().any(|pattern| wildcard_match(pattern, &response.url))
+            {
+                entries.lock().unwrap().push((
+                    event.request_id,
+                    CapturedResponse {
+                        url: response.url,
+                        status: response.status,
+                        mime_type: response.mime_type,
+                        body: String::new(),
+                    },
+                ));
+            }
+        }))?;
+    }
+    if !options.blocked_types.is_empty() || !options.blocked_urls.is_empty() {
+        tab.call_method(network::methods::Enable {})?;
+        let blocking = options.clone();
+        // Chrome's interception API cannot abort a request here, so blocked requests
+        // receive an empty response instead.
+        let empty_response = base64::engine::general_purpose::STANDARD.encode("HTTP/1.1 204 No Content\r\n\r\n");
+        tab.enable_request_interception(
+            &[network::methods::RequestPattern {
+                url_pattern: None,
+                resource_type: None,
+                interception_stage: Some("Request"),
+            }],
+            Box::new(move |_, _, request| {
+                if !request.is_navigation_request && blocking.is_blocked(&request.request.url, &request.resource_type) {
+                    RequestInterceptionDecision::Response(empty_response.clone())
+                } else {
+                    RequestInterceptionDecision::Continue
+                }
+            }),
+        )?;
+    }
+    Ok(log)
+}
+
+/// Returns the rendered HTML of the page, with open shadow roots inlined if `pierce_shadow_roots` is set.
+pub(crate) fn page_html(tab: &Tab, pierce_shadow_roots: bool) -> Result<String, Box<dyn std::error::Error>> {
+    let html = tab
+        .evaluate(outer_html_script(pierce_shadow_roots), false)?
+        .value
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default();
+    Ok(html)
+}
+
+/// Lists the frames of the page, parents before their children.
+fn frame_tree(tab: &Tab) -> Result<Vec<FrameInfo>, Box<dyn std::error::Error>> {
+    fn walk(tree: page::methods::FrameTree, frames: &mut Vec<FrameInfo>) {
+        let frame = tree.frame;
+        frames.push(FrameInfo {
+            id: frame.id,
+            parent_id: frame.parent_id,
+            name: frame.name.filter(|name| !name.is_empty()),
+            url: frame.url,
+        });
+        for child in tree.child_frames.unwrap_or_default() {
+            walk(child, frames);
+        }
+    }
+    let mut frames = Vec::new();
+    walk(tab.call_method(page::methods::GetFrameTree {})?.frame_tree, &mut frames);
+    Ok(frames)
+}
+
+/// Returns the rendered HTML of the selected frame, with open shadow roots inlined if
+/// `pierce_shadow_roots` is set.
+fn frame_html(tab: &Tab, frames: &[FrameInfo], selector: &FrameSelector, pierce_shadow_roots: bool) -> Result<String, Box<dyn std::error::Error>> {
+    let frame_id = match selector {
+        FrameSelector::Element(selector) => tab
+            .find_element(selector)?
+            .get_description()?
+            .frame_id
+            .ok_or_else(|| format!("{} is not a frame", selector))?,
+        FrameSelector::Url(pattern) => frames
+            .iter()
+            .find(|frame| frame.parent_id.is_some() && wildcard_match(pattern, &frame.url))
+            .map(|frame| frame.id.clone())
+            .ok_or_else(|| format!("No frame matches {}", pattern))?,
+    };
+    let world = tab.call_method(CreateIsolatedWorld {
+        frame_id,
+        world_name: "rust-scrapper".to_string(),
+    })?;
+    let context_id = world["executionContextId"].as_u64().ok_or("Chrome returned no frame context")?;
+    let result = tab.call_method(EvaluateInContext {
+        expression: outer_html_script(pierce_shadow_roots).to_string(),
+        context_id,
+        return_by_value: true,
+    })?;
+    Ok(result["result"]["value"].as_str().unwrap_or_default().to_string())
+}
+
+impl JsScraper {
+    /// Renders a page in Chrome launched with the given options and returns the inner HTML
+    /// of every element matching the selector.
+    pub fn scrape_with_options(
+        url: &str,
+        element: &str,
+        options: &JsScrapeOptions,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let page = JsScraper::render(url, options)?;
+        crate::extract(&page.html, element)
+    }
+
+    /// Renders a page and returns its HTML together with the XHR and fetch responses
+    /// captured while it loaded, and its load timing.
+    pub fn render(url: &str, options: &JsScrapeOptions) -> Result<RenderedPage, Box<dyn std::error::Error>> {
+        let started = Instant::now();
+        let page = match &options.backend {
+            BrowserBackend::Chrome => {
+                let (_session, tab, log) = BrowserSession::load(url, options)?;
+                rendered_page(&tab, &log, url, options)?
+            }
+            BrowserBackend::WebDriver(webdriver_url) => {
+                let (html, metrics) = render_webdriver(webdriver_url, url, options)?;
+                RenderedPage {
+                    url: url.to_string(),
+                    html,
+                    responses: Vec::new(),
+                    frames: Vec::new(),
+                    metrics,
+                }
+            }
+        };
+        Ok(finish_render(page, started))
+    }
+
+    /// Renders several pages in one browser, in up to `tabs` tabs at a time, instead of
+    /// launching a browser per page. Pages that fail to render are reported in
+    /// `BatchResult::errors`. Each page's `render_ms` covers its own tab's work only.
+    /// The WebDriver backend renders the pages one after another.
+    pub fn render_many(
+        urls: &[&str],
+        options: &JsScrapeOptions,
+        tabs: usize,
+    ) -> Result<BatchResult<RenderedPage>, Box<dyn std::error::Error>> {
+        let started = Instant::now();
+        let results = render_all(urls, options, tabs)?
+            .into_iter()
+            .map(|page| page.map(|page| vec![page]))
+            .collect();
+        Ok(batch::collect(urls, results, started))
+    }
+
+    /// Renders several pages like `render_many` and returns the inner HTML of the elements
+    /// matching the selector, grouped by page.
+    pub fn scrape_many_with_options(
+        urls: &[&str],
+        element: &str,
+        options: &JsScrapeOptions,
+        tabs: usize,
+    ) -> Result<BatchResult<String>, Box<dyn std::error::Error>> {
+        let started = Instant::now();
+        let results = render_all(urls, options, tabs)?
+            .into_iter()
+            .map(|page| page.and_then(|page| crate::extract(&page.html, element).map_err(|e| e.to_string())))
+            .collect();
+        Ok(batch::collect(urls, results, started))
+    }
+
+    /// Renders a page in a plain headless Chrome, awaits the condition and returns the
+    /// serialized DOM after JavaScript ran, e.g. to run selectors and schemas on it with
+    /// `ParsedPage`. Use `render` for custom options.
+    pub fn rendered_html(url: &str, wait: WaitFor) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(JsScraper::render(url, &JsScrapeOptions::new().wait_for(wait))?.html)
+    }
+
+    /// Loads a page, clicks the element matching `trigger`, e.g. a "Download CSV" button, and
+    /// waits until the file it downloads is complete. The file is saved in a new directory of
+    /// its own in the options' download directory and must arrive within the wait timeout.
+    /// Chrome only.
+    pub fn download(url: &str, trigger: &str, options: &JsScrapeOptions) -> Result<DownloadedFile, Box<dyn std::error::Error>> {
+        if options.backend != BrowserBackend::Chrome {
+            return Err("Downloads are only supported by the Chrome backend".into());
+        }
+        let dir = options.download_call_path();
+        std::fs::create_dir_all(&dir)?;
+        let dir = dir.canonicalize()?;
+        let (_session, tab, _) = BrowserSession::load(url, options)?;
+        tab.call_method(SetDownloadBehavior {
+            behavior: "allow".to_string(),
+            download_path: dir.display().to_string(),
+        })?;
+        let existing = list_files(&dir)?;
+        tab.wait_for_element(trigger)?.click()?;
+        wait_for_download(&dir, &existing, options.wait_timeout)
+    }
+
+    /// Loads a page and returns the browser's storage state once the wait conditions are met.
+    /// For an interactive login, use `headful` options with a condition that only holds after
+    /// logging in, such as `WaitFor::visible(".account-menu")`, and a long wait timeout.
+    /// States restored with `JsScrapeOptions::storage_state` are carried over. Chrome only.
+    pub fn storage_state(url: &str, options: &JsScrapeOptions) -> Result<StorageState, Box<dyn std::error::Error>> {
+        if options.backend != BrowserBackend::Chrome {
+            return Err("Storage state is only supported by the Chrome backend".into());
+        }
+        let (_session, tab, _) = BrowserSession::load(url, options)?;
+        read_storage_state(&tab, options.storage_state.clone().unwrap_or_default())
+    }
+
+    /// Async variant of `scrape_with_js`.
+    pub async fn scrape_with_js_async(url: &str, element: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let (url, element) = (url.to_string(), element.to_string());
+        run_blocking(move || JsScraper::scrape_with_js(&url, &element)).await
+    }
+
+    /// Async variant of `scrape_with_options`.
+    pub async fn scrape_with_options_async(
+        url: &str,
+        element: &str,
+        options: &JsScrapeOptions,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let (url, element, options) = (url.to_string(), element.to_string(), options.clone());
+        run_blocking(move || JsScraper::scrape_with_options(&url, &element, &options)).await
+    }
+
+    /// Async variant of `render`.
+    pub async fn render_async(url: &str, options: &JsScrapeOptions) -> Result<RenderedPage, Box<dyn std::error::Error>> {
+        let (url, options) = (url.to_string(), options.clone());
+        run_blocking(move || JsScraper::render(&url, &options)).await
+    }
+
+    /// Async variant of `rendered_html`.
+    pub async fn rendered_html_async(url: &str, wait: WaitFor) -> Result<String, Box<dyn std::error::Error>> {
+        let url = url.to_string();
+        run_blocking(move || JsScraper::rendered_html(&url, wait)).await
+    }
+
+    /// Async variant of `render_many`.
+    pub async fn render_many_async(
+        urls: &[&str],
+        options: &JsScrapeOptions,
+        tabs: usize,
+    ) -> Result<BatchResult<RenderedPage>, Box<dyn std::error::Error>> {
+        let (urls, options) = (urls.iter().map(|url| url.to_string()).collect::<Vec<_>>(), options.clone());
+        run_blocking(move || JsScraper::render_many(&urls.iter().map(String::as_str).collect::<Vec<_>>(), &options, tabs)).await
+    }
+
+    /// Async variant of `scrape_many_with_options`.
+    pub async fn scrape_many_with_options_async(
+        urls: &[&str],
+        element: &str,
+        options: &JsScrapeOptions,
+        tabs: usize,
+    ) -> Result<BatchResult<String>, Box<dyn std::error::Error>> {
+        let (urls, element, options) = (urls.iter().map(|url| url.to_string()).collect::<Vec<_>>(), element.to_string(), options.clone());
+        run_blocking(move || {
+            JsScraper::scrape_many_with_options(&urls.iter().map(String::as_str).collect::<Vec<_>>(), &element, &options, tabs)
+        })
+        .await
+    }
+
+    /// Async variant of `download`.
+    pub async fn download_async(url: &str, trigger: &str, options: &JsScrapeOptions) -> Result<DownloadedFile, Box<dyn std::error::Error>> {
+        let (url, trigger, options) = (url.to_string(), trigger.to_string(), options.clone());
+        run_blocking(move || JsScraper::download(&url, &trigger, &options)).await
+    }
+
+    /// Async variant of `storage_state`.
+    pub async fn storage_state_async(url: &str, options: &JsScrapeOptions) -> Result<StorageState, Box<dyn std::error::Error>> {
+        let (url, options) = (url.to_string(), options.clone());
+        run_blocking(move || JsScraper::storage_state(&url, &options)).await
+    }
+}
+
+/// Names of the files in a directory.
+fn list_files(dir: &std::path::Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        names.push(entry?.file_name().to_string_lossy().into_owned());
+    }
+    Ok(names)
+}
+
+/// Waits until a file that is not in `existing` appears in `dir` and is complete: Chrome
+/// writes downloads to a `.crdownload` file and renames it when done.
+fn wait_for_download(dir: &std::path::Path, existing: &[String], timeout: Duration) -> Result<DownloadedFile, Box<dyn std::error::Error>> {
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+    let deadline = Instant::now() + timeout;
+    let mut last_size = None;
+    loop {
+        let new_file = list_files(dir)?
+            .into_iter()
+            .find(|name| !existing.contains(name) && !name.ends_with(".crdownload") && !name.starts_with('.'));
+        if let Some(file_name) = new_file {
+            let path = dir.join(&file_name);
+            let bytes = std::fs::metadata(&path)?.len();
+            // The size has to settle once, in case the file is still being flushed.
+            if last_size == Some(bytes) {
+                return Ok(DownloadedFile { path, file_name, bytes });
+            }
+            last_size = Some(bytes);
+        }
+        if Instant::now() >= deadline {
+            return Err(format!("Timed out waiting for a download in {}", dir.display()).into());
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Renders the pages with the options' backend, returning their results in the order of the URLs.
+fn render_all(urls: &[&str], options: &JsScrapeOptions, tabs: usize) -> Result<Vec<Result<RenderedPage, String>>, Box<dyn std::error::Error>> {
+    match &options.backend {
+        BrowserBackend::Chrome => render_in_tabs(urls, options, tabs),
+        BrowserBackend::WebDriver(_) => Ok(urls
+            .iter()
+            .map(|url| JsScraper::render(url, options).map_err(|e| e.to_string()))
+            .collect()),
+    }
+}
+
+/// Renders the pages in up to `tabs` tabs of one browser, returning their results in the order
+/// of the URLs. Each tab renders one page after another until no URLs are left.
+fn render_in_tabs(urls: &[&str], options: &JsScrapeOptions, tabs: usize) -> Result<Vec<Result<RenderedPage, String>>, Box<dyn std::error::Error>> {
+    let session = BrowserSession::launch(options)?;
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(urls.iter().map(|_| None).collect::<Vec<Option<Result<RenderedPage, String>>>>());
+    std::thread::scope(|scope| {
+        for _ in 0..tabs.clamp(1, urls.len().max(1)) {
+            scope.spawn(|| {
+                let tab = session
+                    .open_new_tab(options)
+                    .and_then(|tab| Ok((intercept_network(&tab, options)?, tab)))
+                    .map_err(|e| e.to_string());
+                loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(url) = urls.get(index) else {
+                        break;
+                    };
+                    let started = Instant::now();
+                    let result = tab.clone().and_then(|(log, tab)| {
+                        navigate(&tab, url, options)
+                            .and_then(|_| rendered_page(&tab, &log, url, options))
+                            .map(|page| finish_render(page, started))
+                            .map_err(|e| e.to_string())
+                    });
+                    results.lock().unwrap()[index] = Some(result);
+                }
+            });
+        }
+    });
+    let results = results.into_inner().unwrap_or_else(|e| e.into_inner());
+    Ok(results
+        .into_iter()
+        .map(|result| result.unwrap_or_else(|| Err("Page was not rendered".to_string())))
+        .collect())
+}
+
+/// Runs a synchronous job, such as a headless_chrome call or HTML parsing, on tokio's
+/// blocking thread pool so it does not stall the runtime's worker threads.
+pub(crate) async fn run_blocking<T: Send + 'static>(
+    job: impl FnOnce() -> Result<T, Box<dyn std::error::Error>> + Send + 'static,
+) -> Result<T, Box<dyn std::error::Error>> {
+    // Errors are not `Send`, so they cross the thread boundary as messages.
+    tokio::task::spawn_blocking(move || job().map_err(|e| e.to_string()))
+        .await?
+        .map_err(Into::into)
+}
+
+/// Renders a page through WebDriver on a private runtime, as the WebDriver client is async.
+#[cfg(feature = "webdriver")]
+fn render_webdriver(
+    webdriver_url: &str,
+    url: &str,
+    options: &JsScrapeOptions,
+) -> Result<(String, PageMetrics), Box<dyn std::error::Error>> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(crate::webdriver::render(webdriver_url, url, options))
+}
+
+#[cfg(not(feature = "webdriver"))]
+fn render_webdriver(
+    _webdriver_url: &str,
+    _url: &str,
+    _options: &JsScrapeOptions,
+) -> Result<(String, PageMetrics), Box<dyn std::error::Error>> {
+    Err("The WebDriver backend requires the `webdriver` feature".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stealth_hides_automation_signals() {
+        let options = JsScrapeOptions::stealth().viewport(1280, 720).chrome_arg("--lang=tr");
+        assert!(options.hide_webdriver);
+        assert!(options.headless);
+        assert_eq!(options.viewport, Some((1280, 720)));
+        assert_eq!(options.chrome_args, vec!["--disable-blink-features=AutomationControlled", "--lang=tr"]);
+        assert!(!JsScrapeOptions::new().headful().headless);
+    }
+
+    #[cfg(not(feature = "webdriver"))]
+    #[test]
+    fn requires_the_webdriver_feature_for_the_webdriver_backend() {
+        let options = JsScrapeOptions::new().backend(BrowserBackend::WebDriver("http://localhost:4444".to_string()));
+        let error = JsScraper::scrape_with_options("https://example.com", "h1", &options).unwrap_err();
+        assert!(error.to_string().contains("`webdriver` feature"));
+    }
+
+    #[cfg(not(feature = "webdriver"))]
+    #[test]
+    fn reports_pages_failing_to_render_in_the_batch() {
+        let options = JsScrapeOptions::new().backend(BrowserBackend::WebDriver("http://localhost:4444".to_string()));
+        let batch = JsScraper::scrape_many_with_options(&["https://a.com/", "https://b.com/"], "h1", &options, 4).unwrap();
+        assert!(batch.pages.is_empty());
+        assert_eq!(batch.errors.iter().map(|error| error.url.as_str()).collect::<Vec<_>>(), vec!["https://a.com/", "https://b.com/"]);
+        assert_eq!(batch.report.attempted, 2);
+    }
+
+    #[test]
+    fn matches_wildcard_patterns() {
+        assert!(wildcard_match("https://example.com/page", "https://example.com/page"));
+        assert!(!wildcard_match("https://example.com/page", "https://example.com/page2"));
+
+        assert!(wildcard_match("*.png", "https://cdn.example.com/logo.png"));
+        assert!(!wildcard_match("*.png", "https://cdn.example.com/logo.png?v=2"));
+        assert!(wildcard_match("https://ads.example.com/*", "https://ads.example.com/banner.js"));
+        assert!(!wildcard_match("https://ads.example.com/*", "https://example.com/ads.example.com/"));
+
+        assert!(wildcard_match("*doubleclick*", "https://ad.doubleclick.net/x"));
+        assert!(wildcard_match("*://*.analytics.*/*.js", "https://www.analytics.example/collect.js"));
+        assert!(!wildcard_match("*://*.analytics.*/*.js", "https://www.analytics.example/collect.json"));
+        assert!(wildcard_match("*/a*a", "x/aba"));
+        assert!(!wildcard_match("*/a*a", "x/a"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("**", "anything"));
+    }
+
+    #[test]
+    fn blocks_by_resource_type_and_url_pattern() {
+        let options = JsScrapeOptions::new().block_media().block_analytics().block_url("*.woff2");
+        assert!(options.is_blocked("https://a.com/logo.png", "Image"));
+        assert!(options.is_blocked("https://a.com/font.woff2", "Other"));
+        assert!(options.is_blocked("https://www.google-analytics.com/analytics.js", "Script"));
+        assert!(!options.is_blocked("https://a.com/app.js", "Script"));
+        assert!(!options.is_blocked("https://a.com/api/items", "XHR"));
+    }
+
+    #[test]
+    fn parses_captured_json_bodies() {
+        let response = CapturedResponse {
+            url: "https://a.com/api".to_string(),
+            status: 200,
+            mime_type: "application/json".to_string(),
+            body: r#"{"items": [1, 2]}"#.to_string(),
+        };
+        assert_eq!(response.json().unwrap()["items"][1], 2);
+        assert!(CapturedResponse { body: "<html>".to_string(), ..response }.json().is_none());
+    }
+
+    #[test]
+    fn builds_wait_scripts() {
+        assert_eq!(
+            WaitFor::count(".item", 3).script(false).unwrap(),
+            "document.querySelectorAll(\".item\").length >= 3"
+        );
+        assert_eq!(WaitFor::predicate("window.ready").script(false).unwrap(), "Boolean(window.ready)");
+        assert!(WaitFor::visible("#a\"b").script(false).unwrap().contains("document.querySelectorAll(\"#a\\\"b\")[0]"));
+        assert_eq!(WaitFor::delay(Duration::from_secs(1)).script(false), None);
+    }
+
+    #[test]
+    fn queries_into_shadow_roots_when_piercing() {
+        assert_eq!(query_all(".price", false), "document.querySelectorAll(\".price\")");
+        let pierced = WaitFor::count(".price", 2).script(true).unwrap();
+        assert!(pierced.starts_with(DEEP_QUERY_ALL) && pierced.ends_with("(\".price\").length >= 2"));
+        assert_eq!(outer_html_script(false), "document.documentElement.outerHTML");
+        assert!(outer_html_script(true).contains("node.shadowRoot"));
+    }
+
+    #[test]
+    fn reads_page_metrics_from_the_script_result() {
+        let json = r#"{"dom_content_loaded_ms": 120.5, "load_ms": null, "first_contentful_paint_ms": 98.0,
+            "request_count": 14, "transferred_bytes": 20480}"#;
+        let metrics = PageMetrics::from_script_result(Some(&Value::from(json)));
+        assert_eq!(metrics.dom_content_loaded_ms, Some(120.5));
+        assert_eq!((metrics.request_count, metrics.transferred_bytes, metrics.render_ms), (14, 20480, 0));
+        assert!(!metrics.is_loaded());
+
+        assert_eq!(PageMetrics::from_script_result(Some(&Value::from("oops"))).request_count, 0);
+        assert_eq!(PageMetrics::from_script_result(None).load_ms, None);
+    }
+
+    #[test]
+    fn waits_for_new_complete_downloads() {
+        let dir = std::env::temp_dir().join(format!("rust-scrapper-js-downloads-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("old.csv"), "old").unwrap();
+        std::fs::write(dir.join("new.csv.crdownload"), "partial").unwrap();
+        let existing = list_files(&dir).unwrap();
+        let timed_out = wait_for_download(&dir, &existing, Duration::ZERO).unwrap_err();
+        assert!(timed_out.to_string().contains("Timed out"));
+
+        std::fs::write(dir.join("new.csv"), "a,b\n1,2\n").unwrap();
+        let file = wait_for_download(&dir, &existing, Duration::from_secs(5));
+        let text = file.as_ref().ok().map(|file| file.text().unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+        let file = file.unwrap();
+        assert_eq!((file.file_name.as_str(), file.bytes), ("new.csv", 8));
+        assert_eq!(text.unwrap(), "a,b\n1,2\n");
+    }
+
+    #[test]
+    fn runs_waits_in_order_until_met() {
+        let mut calls = Vec::new();
+        let mut ready = 0;
+        let waits = [WaitFor::count(".item", 1), WaitFor::predicate("done")];
+        run_waits(&waits, Duration::from_secs(5), false, |script| {
+            calls.push(script.to_string());
+            ready += 1;
+            Ok(Value::Bool(ready % 2 == 0))
+        })
+        .unwrap();
+        assert_eq!(calls.len(), 4);
+        assert!(calls[0].contains(".item") && calls[3] == "Boolean(done)");
+
+        let error = run_waits(&[WaitFor::predicate("never")], Duration::ZERO, false, |_| Ok(Value::Bool(false))).unwrap_err();
+        assert!(error.to_string().contains("Timed out"));
+    }
+
+    #[test]
+    fn waits_for_the_resource_count_to_settle() {
+        let wait = WaitFor::network_idle(Duration::from_millis(20));
+        let mut last_change = (Value::Null, Instant::now());
+        assert!(!wait.is_met(Value::from(3), &mut last_change));
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(wait.is_met(Value::from(3), &mut last_change));
+        assert!(!wait.is_met(Value::from(4), &mut last_change));
+    }
+
+    #[test]
+    fn emulates_devices() {
+        let options = JsScrapeOptions::new().device(Device::pixel_8());
+        assert_eq!(options.viewport, Some((412, 915)));
+        assert_eq!(options.device_scale_factor, 2.625);
+        assert!(options.mobile && options.touch);
+        assert!(options.user_agent.unwrap().contains("Pixel 8"));
+    }
+
+    #[test]
+    fn reads_storage_state_only_with_chrome() {
+        let options = JsScrapeOptions::new().backend(BrowserBackend::WebDriver("http://localhost:4444".to_string()));
+        let error = JsScraper::storage_state("https://example.com", &options).unwrap_err();
+        assert!(error.to_string().contains("only supported by the Chrome backend"));
+    }
+
+    #[test]
+    fn keeps_frames_in_process_only_when_extracting_from_one() {
+        let options = JsScrapeOptions::new().chrome_arg("--mute-audio");
+        assert_eq!(options.launch_args(), vec!["--mute-audio"]);
+        let args = options.in_frame(FrameSelector::url("*widgets*")).launch_args();
+        assert_eq!(args.len(), 1 + SAME_PROCESS_FRAME_ARGS.len());
+        assert!(args.contains(&"--disable-site-isolation-trials".to_string()));
+    }
+
+    #[tokio::test]
+    async fn runs_browser_jobs_off_the_runtime() {
+        let options = JsScrapeOptions::new().backend(BrowserBackend::WebDriver("http://localhost:4444".to_string()));
+        let error = JsScraper::storage_state_async("https://example.com", &options).await.unwrap_err();
+        assert!(error.to_string().contains("only supported by the Chrome backend"));
+        assert_eq!(run_blocking(|| Ok(1 + 1)).await.unwrap(), 2);
+    }
+
+    #[test]
+    fn gives_every_download_its_own_directory() {
+        let options = JsScrapeOptions::new().download_dir("downloads");
+        let (first, second) = (options.download_call_path(), options.download_call_path());
+        assert_ne!(first, second);
+        assert!(first.starts_with("downloads") && second.starts_with("downloads"));
+    }
+}
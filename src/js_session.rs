@@ -0,0 +1,258 @@
+//! Long-running headless browser sessions.
+//!
+//! `JsScraper::render` launches a browser per page, which is slow for long runs, while one
+//! browser kept open for hours may crash or leak memory. A `JsSession` keeps one browser
+//! open, checks its health after every failure, restarts it with exponential backoff when
+//! it died, and renders the page that was in flight again, so unattended runs survive
+//! browser crashes. It can also restart the browser every N pages to contain leaks.
+
+use crate::batch::{self, BatchResult};
+use crate::js::{self, BrowserBackend, BrowserSession, JsScrapeOptions, RenderedPage, ResponseLog};
+use headless_chrome::Tab;
+use log::{info, warn};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A running browser with its tab.
+struct Running {
+    session: BrowserSession,
+    tab: Arc<Tab>,
+    log: ResponseLog,
+}
+
+/// A browser kept open across pages that restarts itself after crashes.
+pub struct JsSession {
+    options: JsScrapeOptions,
+    running: Option<Running>,
+    max_restarts: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+    max_page_crashes: u32,
+    recycle_after: Option<usize>,
+    pages_since_launch: usize,
+    restarts: u32,
+}
+
+impl JsSession {
+    /// Creates a session rendering with the options. The browser is launched on first use.
+    /// Only the Chrome backend is supported.
+    pub fn new(options: JsScrapeOptions) -> Self {
+        JsSession {
+            options,
+            running: None,
+            max_restarts: 5,
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            max_page_crashes: 3,
+            recycle_after: None,
+            pages_since_launch: 0,
+            restarts: 0,
+        }
+    }
+
+    /// Gives up restarting the browser after this many failed launches in a row (5 by default).
+    pub fn with_max_restarts(mut self, max_restarts: u32) -> Self {
+        self.max_restarts = max_restarts;
+        self
+    }
+
+    /// Gives up on a page after it crashed the browser this many times (3 by default).
+    pub fn with_max_page_crashes(mut self, crashes: u32) -> Self {
+        self.max_page_crashes = crashes.max(1);
+        self
+    }
+
+    /// Waits `initial` before the first launch attempt of a restart, doubling the wait for
+    /// every further one up to `max` (1 second and 60 seconds by default).
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Restarts the browser after every `pages` rendered pages, releasing memory it leaked.
+    pub fn recycle_after(mut self, pages: usize) -> Self {
+        self.recycle_after = Some(pages.max(1));
+        self
+    }
+
+    /// Number of restarts after crashes so far.
+    pub fn restarts(&self) -> u32 {
+        self.restarts
+    }
+
+    /// Whether the browser is running and responding.
+    pub fn is_healthy(&self) -> bool {
+        self.running.as_ref().is_some_and(|running| {
+            running.session.browser.get_version().is_ok() && running.tab.evaluate("1", false).is_ok()
+        })
+    }
+
+    /// Renders a page. If rendering fails because the browser died, the browser is restarted
+    /// and the page rendered again, up to `max_page_crashes` times; other failures, such as
+    /// timed-out wait conditions, are returned as they are.
+    pub fn render(&mut self, url: &str) -> Result<RenderedPage, Box<dyn std::error::Error>> {
+        let mut crashes = 0;
+        loop {
+            self.ensure_running()?;
+            match self.try_render(url) {
+                Ok(page) => return Ok(page),
+                Err(e) if self.is_healthy() => return Err(e),
+                Err(e) => {
+                    warn!("Browser died while rendering {}: {}", url, e);
+                    crashes += 1;
+                    if crashes >= self.max_page_crashes {
+                        self.close();
+                        return Err(format!("Browser crashed {} times rendering the page: {}", crashes, e).into());
+                    }
+                    self.restart()?;
+                }
+            }
+        }
+    }
+
+    /// Renders a page and returns the inner HTML of the elements matching the selector.
+    pub fn scrape(&mut self, url: &str, element: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        crate::extract(&self.render(url)?.html, element)
+    }
+
+    /// Renders several pages. A page that crashes the browser is put back at the end of the
+    /// queue instead of being retried right away, so one bad page cannot stall the run;
+    /// after `max_page_crashes` crashes it is reported in `BatchResult::errors`. If the
+    /// browser cannot be (re)started, the pages not rendered yet are reported as errors.
+    pub fn render_many(&mut self, urls: &[&str]) -> Result<BatchResult<RenderedPage>, Box<dyn std::error::Error>> {
+        let started = Instant::now();
+        let mut results: Vec<Option<Result<Vec<RenderedPage>, String>>> = urls.iter().map(|_| None).collect();
+        let mut queue: VecDeque<(usize, u32)> = (0..urls.len()).map(|index| (index, 0)).collect();
+        while let Some((index, crashes)) = queue.pop_front() {
+            let url = urls[index];
+            if let Err(e) = self.ensure_running() {
+                queue.push_front((index, crashes));
+                abandon(&mut results, queue, &format!("Browser could not be started: {}", e));
+                break;
+            }
+            match self.try_render(url) {
+                Ok(page) => results[index] = Some(Ok(vec![page])),
+                Err(e) if self.is_healthy() => results[index] = Some(Err(e.to_string())),
+                Err(e) => {
+                    warn!("Browser died while rendering {}: {}", url, e);
+                    if crashes + 1 >= self.max_page_crashes {
+                        results[index] = Some(Err(format!("Browser crashed {} times rendering the page: {}", crashes + 1, e)));
+                    } else {
+                        info!("Re-queuing {}", url);
+                        queue.push_back((index, crashes + 1));
+                    }
+                    if let Err(e) = self.restart() {
+                        abandon(&mut results, queue, &e.to_string());
+                        break;
+                    }
+                }
+            }
+        }
+        let results = results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err("Page was not rendered".to_string())))
+            .collect();
+        Ok(batch::collect(urls, results, started))
+    }
+
+    /// Closes the browser. The next page launches a new one.
+    pub fn close(&mut self) {
+        self.running = None;
+        self.pages_since_launch = 0;
+    }
+
+    /// Launches the browser if it is not running, or was used for `recycle_after` pages.
+    fn ensure_running(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.recycle_after.is_some_and(|pages| self.pages_since_launch >= pages) {
+            info!("Recycling the browser after {} pages", self.pages_since_launch);
+            self.close();
+        }
+        if self.running.is_none() {
+            self.launch()?;
+        }
+        Ok(())
+    }
+
+    /// Renders a page in the running browser.
+    fn try_render(&mut self, url: &str) -> Result<RenderedPage, Box<dyn std::error::Error>> {
+        let running = self.running.as_ref().ok_or("Browser is not running")?;
+        let started = Instant::now();
+        self.pages_since_launch += 1;
+        js::navigate(&running.tab, url, &self.options)?;
+        let page = js::rendered_page(&running.tab, &running.log, url, &self.options)?;
+        Ok(js::finish_render(page, started))
+    }
+
+    fn launch(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.options.backend != BrowserBackend::Chrome {
+            return Err("Browser sessions are only supported by the Chrome backend".into());
+        }
+        let session = BrowserSession::launch(&self.options)?;
+        let tab = session.open_tab(&self.options)?;
+        let log = js::intercept_network(&tab, &self.options)?;
+        self.running = Some(Running { session, tab, log });
+        self.pages_since_launch = 0;
+        Ok(())
+    }
+
+    /// Kills the dead browser and launches a new one after the backoff, failing once
+    /// `max_restarts` launches in a row failed.
+    fn restart(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.close();
+        self.restarts += 1;
+        for attempt in 0..self.max_restarts {
+            let backoff = self.backoff.saturating_mul(2u32.saturating_pow(attempt)).min(self.max_backoff);
+            warn!("Restarting the browser in {:?} (attempt {})", backoff, attempt + 1);
+            std::thread::sleep(backoff);
+            match self.launch() {
+                Ok(()) => return Ok(()),
+                Err(e) => warn!("Failed to restart the browser: {}", e),
+            }
+        }
+        Err(format!("Browser could not be restarted after {} attempts, giving up", self.max_restarts).into())
+    }
+}
+
+/// Reports the pages left in `queue` as failed with `error`.
+fn abandon(results: &mut [Option<Result<Vec<RenderedPage>, String>>], queue: VecDeque<(usize, u32)>, error: &str) {
+    for (index, _) in queue {
+        results[index] = Some(Err(error.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_other_backends_without_restarting() {
+        let options = JsScrapeOptions::new().backend(BrowserBackend::WebDriver("http://localhost:4444".to_string()));
+        let mut session = JsSession::new(options).with_max_restarts(2);
+        assert!(!session.is_healthy());
+        let error = session.render("https://example.com").unwrap_err();
+        assert!(error.to_string().contains("Chrome backend"));
+        assert_eq!(session.restarts(), 0);
+    }
+
+    #[test]
+    fn keeps_the_backoff_cap_above_the_initial_wait() {
+        let session = JsSession::new(JsScrapeOptions::new())
+            .with_backoff(Duration::from_secs(5), Duration::from_secs(1))
+            .recycle_after(0);
+        assert_eq!(session.max_backoff, Duration::from_secs(5));
+        assert_eq!(session.recycle_after, Some(1));
+    }
+
+    #[test]
+    fn reports_every_page_when_the_browser_cannot_start() {
+        let options = JsScrapeOptions::new().backend(BrowserBackend::WebDriver("http://localhost:4444".to_string()));
+        let mut session = JsSession::new(options).with_max_page_crashes(0);
+        assert_eq!(session.max_page_crashes, 1);
+        let batch = session.render_many(&["https://a.com/", "https://b.com/"]).unwrap();
+        assert!(batch.pages.is_empty());
+        assert_eq!(batch.errors.len(), 2);
+        assert!(batch.errors.iter().all(|error| error.error.contains("could not be started")));
+    }
+}
@@ -0,0 +1,88 @@
+//! Local sources: `file://` URLs and `data:` URIs.
+//!
+//! These are read directly instead of being requested over HTTP, which makes it easy to run
+//! the scraper against fixtures or mirrored site dumps.
+
+use base64::Engine;
+use percent_encoding::percent_decode_str;
+use url::Url;
+
+/// Returns whether the URL is a `file://` URL or a `data:` URI.
+pub fn is_local_url(url: &str) -> bool {
+    let url = url.trim_start();
+    ["file:", "data:"]
+        .iter()
+        .any(|scheme| url.get(..scheme.len()).is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme)))
+}
+
+/// Reads the contents of a `file://` URL or decodes a `data:` URI.
+pub fn fetch(url: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let url = url.trim();
+    if url.get(..5).is_some_and(|prefix| prefix.eq_ignore_ascii_case("data:")) {
+        return decode_data_uri(url);
+    }
+    let path = Url::parse(url)?
+        .to_file_path()
+        .map_err(|_| format!("Not a local file URL: {}", url))?;
+    Ok(std::fs::read(path)?)
+}
+
+/// Fails if `url` is local but was found on `page_url`, a page that is not: remote pages must
+/// not make the scraper read local files.
+pub fn check_linked(url: &str, page_url: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if is_local_url(url) && !is_local_url(page_url) {
+        return Err(format!("Not reading local source {} found on {}", url, page_url).into());
+    }
+    Ok(())
+}
+
+/// Decodes `data:[<media type>][;base64],<data>`.
+fn decode_data_uri(uri: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let (header, data) = uri[5..].split_once(',').ok_or("Data URI has no comma")?;
+    let data: Vec<u8> = percent_decode_str(data).collect();
+    if header.to_ascii_lowercase().ends_with(";base64") {
+        let data: Vec<u8> = data.into_iter().filter(|b| !b.is_ascii_whitespace()).collect();
+        Ok(base64::engine::general_purpose::STANDARD.decode(data)?)
+    } else {
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_local_urls() {
+        assert!(is_local_url("file:///tmp/page.html"));
+        assert!(is_local_url(" DATA:text/html,<p>"));
+        assert!(!is_local_url("https://example.com/file:"));
+        assert!(!is_local_url("dat"));
+    }
+
+    #[test]
+    fn decodes_data_uris() {
+        assert_eq!(fetch("data:text/html,%3Cp%3Ehi%3C%2Fp%3E").unwrap(), b"<p>hi</p>");
+        assert_eq!(fetch("data:text/plain;base64,aGVs bG8=").unwrap(), b"hello");
+        assert!(fetch("data:text/plain").is_err());
+    }
+
+    #[test]
+    fn reads_file_urls() {
+        let path = std::env::temp_dir().join(format!("rust-scrapper-local-{}.html", std::process::id()));
+        std::fs::write(&path, "<h1>Local</h1>").unwrap();
+        let url = Url::from_file_path(&path).unwrap();
+        let bytes = fetch(url.as_str()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(bytes, b"<h1>Local</h1>");
+        assert!(fetch("file://remote-host/page.html").is_err());
+    }
+
+    #[test]
+    fn reads_local_links_only_from_local_pages() {
+        assert!(check_linked("file:///etc/passwd", "https://example.com/").is_err());
+        assert!(check_linked("data:text/plain,hi", "https://example.com/").is_err());
+        assert!(check_linked("file:///tmp/a.png", "file:///tmp/page.html").is_ok());
+        assert!(check_linked("https://example.com/a.png", "https://example.com/").is_ok());
+    }
+}
@@ -0,0 +1,123 @@
+//! Locale-aware scraping.
+//!
+//! Many sites serve content in the language a request asks for. A `Locale` sets the
+//! `Accept-Language` header of a scrapper's requests and, for sites that ignore it, can also
+//! select the language with a query parameter (`?lang=de`) or a cookie (`locale=de`).
+//! `RustScrapper::scrape_locales` scrapes one page in several locales and returns the results
+//! keyed by language tag.
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// The language requests ask for, and how they ask for it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Locale {
+    /// BCP 47 language tag, e.g. `de-DE` or `fr`.
+    pub tag: String,
+    /// Query parameter selecting the language, e.g. `lang`.
+    #[serde(default)]
+    pub query_param: Option<String>,
+    /// Cookie selecting the language, e.g. `locale`.
+    #[serde(default)]
+    pub cookie: Option<String>,
+    /// Value of the query parameter and cookie, if the site expects another form than the
+    /// tag, e.g. `de` or `de_DE`.
+    #[serde(default)]
+    pub value: Option<String>,
+}
+
+impl Locale {
+    /// Asks for `tag` in the `Accept-Language` header only.
+    pub fn new(tag: &str) -> Self {
+        Locale {
+            tag: tag.to_string(),
+            query_param: None,
+            cookie: None,
+            value: None,
+        }
+    }
+
+    /// Also sets the query parameter `name` to the locale, replacing a value already in the URL.
+    pub fn with_query_param(mut self, name: &str) -> Self {
+        self.query_param = Some(name.to_string());
+        self
+    }
+
+    /// Also sends the cookie `name` with the locale.
+    pub fn with_cookie(mut self, name: &str) -> Self {
+        self.cookie = Some(name.to_string());
+        self
+    }
+
+    /// Uses `value` instead of the tag for the query parameter and cookie.
+    pub fn with_value(mut self, value: &str) -> Self {
+        self.value = Some(value.to_string());
+        self
+    }
+
+    /// The `Accept-Language` header value: the tag, then its primary language as a fallback,
+    /// e.g. `de-DE,de;q=0.9`.
+    pub fn accept_language(&self) -> String {
+        match self.tag.split_once(['-', '_']) {
+            Some((language, _)) => format!("{},{};q=0.9", self.tag, language),
+            None => self.tag.clone(),
+        }
+    }
+
+    /// The URL with the locale's query parameter set, if it uses one.
+    pub fn apply_to_url(&self, url: &str) -> String {
+        let (Some(name), Ok(mut parsed)) = (&self.query_param, Url::parse(url)) else {
+            return url.to_string();
+        };
+        let pairs: Vec<(String, String)> = parsed
+            .query_pairs()
+            .filter(|(key, _)| key != name)
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        parsed
+            .query_pairs_mut()
+            .clear()
+            .extend_pairs(pairs)
+            .append_pair(name, self.value());
+        parsed.to_string()
+    }
+
+    /// The `name=value` pair of the locale's cookie, if it uses one.
+    pub fn cookie_pair(&self) -> Option<String> {
+        self.cookie.as_ref().map(|name| format!("{}={}", name, self.value()))
+    }
+
+    fn value(&self) -> &str {
+        self.value.as_deref().unwrap_or(&self.tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_to_the_primary_language() {
+        assert_eq!(Locale::new("de-DE").accept_language(), "de-DE,de;q=0.9");
+        assert_eq!(Locale::new("pt_BR").accept_language(), "pt_BR,pt;q=0.9");
+        assert_eq!(Locale::new("fr").accept_language(), "fr");
+    }
+
+    #[test]
+    fn sets_the_query_parameter_and_cookie() {
+        let locale = Locale::new("de-DE").with_query_param("lang").with_cookie("locale").with_value("de");
+        assert_eq!(locale.apply_to_url("https://shop.com/p?lang=en&id=1"), "https://shop.com/p?id=1&lang=de");
+        assert_eq!(locale.apply_to_url("not a url"), "not a url");
+        assert_eq!(locale.cookie_pair().as_deref(), Some("locale=de"));
+
+        let plain = Locale::new("fr");
+        assert_eq!(plain.apply_to_url("https://shop.com/p"), "https://shop.com/p");
+        assert_eq!(plain.cookie_pair(), None);
+    }
+
+    #[test]
+    fn reads_locales_with_defaults() {
+        let locale: Locale = serde_json::from_str(r#"{"tag": "fr", "cookie": "lang"}"#).unwrap();
+        assert_eq!(locale, Locale::new("fr").with_cookie("lang"));
+    }
+}
@@ -0,0 +1,78 @@
+//! Memory limits for large crawls.
+//!
+//! A `MemoryBudget` caps the crawl results kept in memory, the number of pending URLs and the
+//! size of the page cache, and can trade the exact visited set for a bloom filter. Data beyond a limit is spilled to files in a spill directory
+//! instead of growing without bound over a long crawl.
+
+use crate::cache::{MemoryCache, ScrapeCache};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Memory limits applied with `Crawler::with_memory_budget`.
+#[derive(Debug, Clone)]
+pub struct MemoryBudget {
+    pub(crate) spill_dir: PathBuf,
+    pub(crate) max_items: Option<usize>,
+    pub(crate) max_frontier: Option<usize>,
+    pub(crate) max_cache_bytes: Option<usize>,
+    /// Expected URL count and false-positive rate of a bloom-filter visited set.
+    pub(crate) visited_filter: Option<(usize, f64)>,
+}
+
+impl MemoryBudget {
+    /// Creates a budget without limits that spills into `spill_dir`.
+    pub fn new(spill_dir: &str) -> Self {
+        MemoryBudget {
+            spill_dir: PathBuf::from(spill_dir),
+            max_items: None,
+            max_frontier: None,
+            max_cache_bytes: None,
+            visited_filter: None,
+        }
+    }
+
+    /// Keeps at most this many extracted items in memory. Crawled pages beyond it are
+    /// written to `spilled_pages_path` and not returned by `Crawler::run`.
+    pub fn max_items(mut self, max_items: usize) -> Self {
+        self.max_items = Some(max_items);
+        self
+    }
+
+    /// Keeps at most this many pending URLs in memory; the lowest-priority ones are spilled.
+    pub fn max_frontier(mut self, max_frontier: usize) -> Self {
+        self.max_frontier = Some(max_frontier);
+        self
+    }
+
+    /// Keeps at most this many bytes of cached pages and results in memory; the oldest
+    /// entries are spilled. Replaces the cache of the crawler's scrapper.
+    pub fn max_cache_bytes(mut self, max_cache_bytes: usize) -> Self {
+        self.max_cache_bytes = Some(max_cache_bytes);
+        self
+    }
+
+    /// Tracks visited URLs in a bloom filter instead of an exact set, see
+    /// `MemoryFrontier::with_bloom_filter`.
+    pub fn probabilistic_visited(mut self, expected_urls: usize, false_positive_rate: f64) -> Self {
+        self.visited_filter = Some((expected_urls, false_positive_rate));
+        self
+    }
+
+    /// JSON Lines file receiving the crawled pages spilled over `max_items`.
+    pub fn spilled_pages_path(&self) -> PathBuf {
+        self.spill_dir.join("pages.jsonl")
+    }
+
+    pub(crate) fn frontier_path(&self) -> PathBuf {
+        self.spill_dir.join("frontier.jsonl")
+    }
+
+    /// A cache keeping `max_cache_bytes` in memory and spilling the rest.
+    pub(crate) fn cache(&self) -> Option<ScrapeCache> {
+        let max_bytes = self.max_cache_bytes?;
+        let backend = MemoryCache::new()
+            .with_max_bytes(max_bytes)
+            .with_spill_dir(&self.spill_dir.join("cache").to_string_lossy());
+        Some(ScrapeCache::with_backend(Arc::new(backend)))
+    }
+}
@@ -0,0 +1,138 @@
+//! Price and currency parsing.
+//!
+//! `Money::parse` reads the amount and currency from price strings as they appear on
+//! shop pages, e.g. "$1,299.99", "1.299,99 TL", "€ 12,-" or "CHF 1'250.00".
+
+use crate::coerce::parse_number;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// Currency symbols and abbreviations, longest first so "US$" wins over "$".
+const SYMBOLS: &[(&str, &str)] = &[
+    ("US$", "USD"),
+    ("R$", "BRL"),
+    ("C$", "CAD"),
+    ("A$", "AUD"),
+    ("NZ$", "NZD"),
+    ("HK$", "HKD"),
+    ("S$", "SGD"),
+    ("zł", "PLN"),
+    ("TL", "TRY"),
+    ("Kč", "CZK"),
+    ("$", "USD"),
+    ("€", "EUR"),
+    ("£", "GBP"),
+    ("¥", "JPY"),
+    ("₺", "TRY"),
+    ("₹", "INR"),
+    ("₽", "RUB"),
+    ("₩", "KRW"),
+    ("₴", "UAH"),
+    ("₪", "ILS"),
+    ("฿", "THB"),
+    ("₫", "VND"),
+    ("₦", "NGN"),
+];
+
+/// ISO 4217 codes recognized when written out.
+const CODES: &[&str] = &[
+    "USD", "EUR", "GBP", "JPY", "TRY", "CHF", "CAD", "AUD", "NZD", "CNY", "INR", "RUB", "KRW", "BRL", "MXN",
+    "SEK", "NOK", "DKK", "PLN", "CZK", "HUF", "RON", "BGN", "ZAR", "SGD", "HKD", "AED", "SAR", "ILS", "THB",
+    "UAH", "VND", "NGN", "IDR", "MYR", "PHP",
+];
+
+/// An amount of money with its ISO 4217 currency code, if one was written.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Money {
+    pub amount: f64,
+    pub currency: Option<String>,
+}
+
+impl Money {
+    /// Parses the first amount in `text` and the currency written next to it.
+    /// Thousands and decimal separators are told apart as in `coerce::parse_number`,
+    /// and dash placeholders for zero cents ("12,-" or "12—") are ignored.
+    pub fn parse(text: &str) -> Option<Money> {
+        Some(Money {
+            amount: parse_number(text, None)?,
+            currency: currency_code(text).map(str::to_string),
+        })
+    }
+
+    /// Parses an amount written with a known decimal separator, e.g. ',' for "1.299,99".
+    pub fn parse_with_decimal(text: &str, decimal: char) -> Option<Money> {
+        Some(Money {
+            amount: parse_number(text, Some(decimal))?,
+            currency: currency_code(text).map(str::to_string),
+        })
+    }
+}
+
+/// ISO 4217 code of the currency written in `text`, from a code or a symbol. Symbols
+/// made of letters, such as "TL", only count as a word of their own, so "TITLE" is not
+/// read as lira; they may still follow the amount directly, as in "99TL".
+pub fn currency_code(text: &str) -> Option<&'static str> {
+    static CODE: OnceLock<Regex> = OnceLock::new();
+    let code = CODE.get_or_init(|| Regex::new(r"\b[A-Z]{3}\b").unwrap());
+    let written = code
+        .find_iter(text)
+        .find_map(|found| CODES.iter().find(|code| **code == found.as_str()).copied());
+    written.or_else(|| SYMBOLS.iter().find(|(symbol, _)| contains_symbol(text, symbol)).map(|(_, code)| *code))
+}
+
+/// Whether `symbol` occurs in `text` without a letter directly before or after the
+/// letters it starts or ends with.
+fn contains_symbol(text: &str, symbol: &str) -> bool {
+    let starts_with_letter = symbol.starts_with(char::is_alphabetic);
+    let ends_with_letter = symbol.ends_with(char::is_alphabetic);
+    text.match_indices(symbol).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + symbol.len()..].chars().next();
+        let joined_before = starts_with_letter && before.is_some_and(char::is_alphabetic);
+        let joined_after = ends_with_letter && after.is_some_and(char::is_alphabetic);
+        !joined_before && !joined_after
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_prices_with_currencies() {
+        let money = |text: &str| Money::parse(text).map(|money| (money.amount, money.currency));
+        assert_eq!(money("$1,299.99"), Some((1299.99, Some("USD".to_string()))));
+        assert_eq!(money("1.299,99 TL"), Some((1299.99, Some("TRY".to_string()))));
+        assert_eq!(money("€ 12,-"), Some((12.0, Some("EUR".to_string()))));
+        assert_eq!(money("CHF 1'250.00"), Some((1250.0, Some("CHF".to_string()))));
+        assert_eq!(money("R$ 10,50"), Some((10.5, Some("BRL".to_string()))));
+        assert_eq!(money("42"), Some((42.0, None)));
+        assert_eq!(money("free"), None);
+    }
+
+    #[test]
+    fn ignores_words_that_are_not_currency_codes() {
+        assert_eq!(currency_code("NEW price 5 EUR"), Some("EUR"));
+        assert_eq!(currency_code("NEW 5"), None);
+        assert_eq!(Money::parse_with_decimal("1,234", ','), Some(Money { amount: 1.234, currency: None }));
+    }
+
+    #[test]
+    fn reads_spaces_as_thousands_separators() {
+        let money = Money::parse("1 299,90 €").unwrap();
+        assert_eq!((money.amount, money.currency.as_deref()), (1299.9, Some("EUR")));
+        assert_eq!(Money::parse("29.99 19.99").map(|money| money.amount), Some(29.99));
+    }
+
+    #[test]
+    fn matches_letter_symbols_as_whole_words() {
+        assert_eq!(currency_code("TITLE 42"), None);
+        assert_eq!(currency_code("Kčs or Bazłoty"), None);
+        assert_eq!(currency_code("Stock: 12 in TITLE"), None);
+        assert_eq!(currency_code("99TL"), Some("TRY"));
+        assert_eq!(currency_code("1 299 Kč"), Some("CZK"));
+        assert_eq!(currency_code("12,50 zł/szt."), Some("PLN"));
+        assert_eq!(currency_code("Fiyat: 150 TL."), Some("TRY"));
+    }
+}
@@ -0,0 +1,230 @@
+//! Parsed pages for repeated extraction.
+//!
+//! A `ParsedPage` parses a document once and serves any number of selector, schema,
+//! image and address extractions from the same DOM, instead of reparsing the HTML for
+//! every call. The `*_borrowed` methods return slices of the parsed document instead of
+//! allocating a `String` per element.
+
+use crate::address::{self, Address};
+use crate::images::{self, ImageInfo};
+use crate::robots::{self, RobotsDirectives};
+use crate::schema::{parse_selector, Extraction, Schema};
+use scraper::{ElementRef, Html};
+use serde_json::Value;
+use std::borrow::Cow;
+use url::Url;
+
+/// A parsed HTML document, optionally with the URL it was fetched from.
+pub struct ParsedPage {
+    url: Option<String>,
+    document: Html,
+}
+
+impl ParsedPage {
+    /// Parses an HTML document.
+    pub fn parse(html: &str) -> Self {
+        ParsedPage {
+            url: None,
+            document: Html::parse_document(html),
+        }
+    }
+
+    /// Sets the URL used to resolve relative links and image sources.
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_string());
+        self
+    }
+
+    /// The URL the page was fetched from, if known.
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    /// The parsed document, for direct use with the `scraper` crate.
+    pub fn document(&self) -> &Html {
+        &self.document
+    }
+
+    /// Inner HTML of every element matching the selector, like `RustScrapper::scrape`.
+    pub fn select(&self, selector: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let selector = parse_selector(selector)?;
+        Ok(self.document.select(&selector).map(|elem| elem.inner_html()).collect())
+    }
+
+    /// Trimmed text of every element matching the selector.
+    pub fn select_text(&self, selector: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let selector = parse_selector(selector)?;
+        Ok(self
+            .document
+            .select(&selector)
+            .map(|elem| elem.text().collect::<String>().trim().to_string())
+            .collect())
+    }
+
+    /// Value of `attr` on every element matching the selector that has it.
+    pub fn select_attr(&self, selector: &str, attr: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let selector = parse_selector(selector)?;
+        Ok(self
+            .document
+            .select(&selector)
+            .filter_map(|elem| elem.value().attr(attr).map(|value| value.trim().to_string()))
+            .collect())
+    }
+
+    /// Trimmed text of every element matching the selector, borrowed from the document when
+    /// the element has a single text node and only allocated when it has several.
+    pub fn select_text_borrowed(&self, selector: &str) -> Result<Vec<Cow<'_, str>>, Box<dyn std::error::Error>> {
+        let selector = parse_selector(selector)?;
+        Ok(self
+            .document
+            .select(&selector)
+            .map(borrowed_text)
+            .collect())
+    }
+
+    /// Value of `attr` on every element matching the selector that has it, borrowed from the document.
+    pub fn select_attr_borrowed(&self, selector: &str, attr: &str) -> Result<Vec<&str>, Box<dyn std::error::Error>> {
+        let selector = parse_selector(selector)?;
+        Ok(self
+            .document
+            .select(&selector)
+            .filter_map(|elem| elem.value().attr(attr).map(str::trim))
+            .collect())
+    }
+
+    /// Calls `f` with the borrowed text of every element matching the selector, without
+    /// collecting the results, for hot loops that aggregate or write values directly.
+    pub fn for_each_text<F>(&self, selector: &str, mut f: F) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: FnMut(Cow<'_, str>),
+    {
+        let selector = parse_selector(selector)?;
+        self.document.select(&selector).map(borrowed_text).for_each(&mut f);
+        Ok(())
+    }
+
+    /// The `href` of every element matching the selector, resolved against the page URL.
+    pub fn links(&self, selector: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let base = self.url.as_deref().and_then(|url| Url::parse(url).ok());
+        Ok(self
+            .select_attr(selector, "href")?
+            .into_iter()
+            .map(|href| match &base {
+                Some(base) => base.join(&href).map(|url| url.to_string()).unwrap_or(href),
+                None => href,
+            })
+            .collect())
+    }
+
+    /// Like `links`, skipping links marked `rel="nofollow"`.
+    pub fn followable_links(&self, selector: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let selector = parse_selector(selector)?;
+        let base = self.url.as_deref().and_then(|url| Url::parse(url).ok());
+        Ok(self
+            .document
+            .select(&selector)
+            .filter(|elem| !robots::is_nofollow(elem.value().attr("rel")))
+            .filter_map(|elem| elem.value().attr("href"))
+            .map(|href| match &base {
+                Some(base) => base.join(href).map(|url| url.to_string()).unwrap_or_else(|_| href.to_string()),
+                None => href.to_string(),
+            })
+            .collect())
+    }
+
+    /// The page's `<meta name="robots">` directives, and those of meta tags named after
+    /// `user_agent`'s product token.
+    pub fn robots_directives(&self, user_agent: &str) -> RobotsDirectives {
+        RobotsDirectives::from_html(&self.document, user_agent)
+    }
+
+    /// Extracts the records described by a schema. Linked pages of `follow` fields are not fetched.
+    pub fn extract(&self, schema: &Schema) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+        Ok(schema.extract_from(&self.document)?.records)
+    }
+
+    /// Extracts the records described by a schema with the selector that matched each field.
+    pub fn extract_detailed(&self, schema: &Schema) -> Result<Extraction, Box<dyn std::error::Error>> {
+        schema.extract_from(&self.document)
+    }
+
+    /// All images on the page, see `images::extract_images`.
+    pub fn images(&self) -> Vec<ImageInfo> {
+        images::images_in(&self.document, self.url.as_deref().unwrap_or_default())
+    }
+
+    /// All postal addresses on the page, see `address::extract_addresses`.
+    pub fn addresses(&self) -> Vec<Address> {
+        address::addresses_in(&self.document)
+    }
+}
+
+/// The trimmed text of an element, borrowed unless it spans several text nodes.
+fn borrowed_text(elem: ElementRef<'_>) -> Cow<'_, str> {
+    let mut chunks = elem.text();
+    match (chunks.next(), chunks.next()) {
+        (None, _) => Cow::Borrowed(""),
+        (Some(only), None) => Cow::Borrowed(only.trim()),
+        (Some(first), Some(second)) => {
+            let text = [first, second].into_iter().chain(chunks).collect::<String>();
+            Cow::Owned(text.trim().to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::schema::Field;
+
+    const HTML: &str = r#"
+        <h1> Shop </h1>
+        <a class="nav" href="/about">About</a>
+        <img src="/logo.png" alt="Logo">
+        <address>1 Main Street, Springfield 12345</address>
+        <div class="product"><b>Lamp</b></div>"#;
+
+    #[test]
+    fn runs_several_extractions_on_one_document() {
+        let page = ParsedPage::parse(HTML).with_url("https://shop.com/list");
+        assert_eq!(page.url(), Some("https://shop.com/list"));
+        assert_eq!(page.select("h1").unwrap(), vec![" Shop "]);
+        assert_eq!(page.select_text("h1").unwrap(), vec!["Shop"]);
+        assert_eq!(page.select_attr("img", "alt").unwrap(), vec!["Logo"]);
+        assert_eq!(page.links("a.nav").unwrap(), vec!["https://shop.com/about"]);
+        assert_eq!(page.images()[0].src, "https://shop.com/logo.png");
+        assert_eq!(page.addresses()[0].postal_code.as_deref(), Some("12345"));
+
+        let schema = Schema::new().root(".product").field(Field::new("name", "b"));
+        assert_eq!(page.extract(&schema).unwrap(), vec![serde_json::json!({"name": "Lamp"})]);
+        assert!(page.select("h1[").is_err());
+    }
+
+    #[test]
+    fn keeps_relative_links_without_a_url() {
+        assert_eq!(ParsedPage::parse(HTML).links("a").unwrap(), vec!["/about"]);
+    }
+
+    #[test]
+    fn borrows_text_and_attributes_from_the_document() {
+        let page = ParsedPage::parse(r#"<p> plain </p><p>a <b>bold</b> b</p><a href=" /x ">x</a>"#);
+        let texts = page.select_text_borrowed("p").unwrap();
+        assert!(matches!(texts[0], Cow::Borrowed("plain")));
+        assert!(matches!(&texts[1], Cow::Owned(text) if text == "a bold b"));
+        assert_eq!(page.select_attr_borrowed("a", "href").unwrap(), vec!["/x"]);
+
+        let mut total = 0;
+        page.for_each_text("p", |text| total += text.len()).unwrap();
+        assert_eq!(total, 13);
+    }
+
+    #[test]
+    fn skips_nofollow_links_and_reads_robots_meta() {
+        let page = ParsedPage::parse(r#"<meta name="robots" content="noindex"><a href="/a">a</a><a rel="nofollow" href="/b">b</a>"#)
+            .with_url("https://shop.com/");
+        assert_eq!(page.followable_links("a").unwrap(), vec!["https://shop.com/a"]);
+        assert_eq!(page.links("a").unwrap().len(), 2);
+        assert!(page.robots_directives("MyBot").noindex);
+    }
+}
@@ -0,0 +1,263 @@
+//! Resumable pagination.
+//!
+//! A `PaginationState` describes where a paginated scrape stands: the next page number,
+//! offset or cursor token. `RustScrapper::scrape_paginated_from` returns the state next to
+//! the results, pointing at the page that failed, so passing it back resumes the scrape
+//! there. States serialize to JSON to survive a restart.
+
+#[cfg(feature = "native")]
+use crate::schema::parse_selector;
+#[cfg(feature = "native")]
+use scraper::Html;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// The position of a paginated scrape.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PaginationState {
+    /// Numbered pages in a query parameter, e.g. `?page=3`.
+    Page {
+        base_url: String,
+        param: String,
+        next_page: usize,
+        last_page: usize,
+    },
+    /// Item offsets in a query parameter, e.g. `?offset=40`, advanced by `step` per page.
+    Offset {
+        base_url: String,
+        param: String,
+        next_offset: usize,
+        step: usize,
+        /// The scrape stops at this offset.
+        end: usize,
+    },
+    /// Opaque tokens read from each page, e.g. `?cursor=eyJpZCI6NDJ9`. The first page is
+    /// fetched without a token; the scrape stops when a page has no token.
+    Cursor {
+        base_url: String,
+        param: String,
+        /// Token of the next page, `None` for the first page.
+        cursor: Option<String>,
+        /// Selector of the element holding the next token.
+        selector: String,
+        /// Attribute holding the token; the element's text is used without one.
+        attr: Option<String>,
+        /// Pages still to fetch at most.
+        remaining: usize,
+        finished: bool,
+    },
+}
+
+impl PaginationState {
+    /// Pages 1 to `pages` in the query parameter `param`, like `scrape_paginated`.
+    pub fn pages(base_url: &str, param: &str, pages: usize) -> Self {
+        PaginationState::Page {
+            base_url: base_url.to_string(),
+            param: param.to_string(),
+            next_page: 1,
+            last_page: pages,
+        }
+    }
+
+    /// Offsets from 0 up to, but excluding, `end` in steps of `step`.
+    pub fn offsets(base_url: &str, param: &str, step: usize, end: usize) -> Self {
+        PaginationState::Offset {
+            base_url: base_url.to_string(),
+            param: param.to_string(),
+            next_offset: 0,
+            step: step.max(1),
+            end,
+        }
+    }
+
+    /// Cursor tokens read from the text of the element matching `selector`, for at most
+    /// `max_pages` pages.
+    pub fn cursor(base_url: &str, param: &str, selector: &str, max_pages: usize) -> Self {
+        PaginationState::Cursor {
+            base_url: base_url.to_string(),
+            param: param.to_string(),
+            cursor: None,
+            selector: selector.to_string(),
+            attr: None,
+            remaining: max_pages,
+            finished: false,
+        }
+    }
+
+    /// Reads cursor tokens from an attribute instead of the text, e.g. `data-cursor`.
+    pub fn with_cursor_attr(mut self, name: &str) -> Self {
+        if let PaginationState::Cursor { attr, .. } = &mut self {
+            *attr = Some(name.to_string());
+        }
+        self
+    }
+
+    /// Whether every page has been scraped.
+    pub fn is_finished(&self) -> bool {
+        match self {
+            PaginationState::Page { next_page, last_page, .. } => next_page > last_page,
+            PaginationState::Offset { next_offset, end, .. } => next_offset >= end,
+            PaginationState::Cursor { remaining, finished, .. } => *finished || *remaining == 0,
+        }
+    }
+
+    /// URL of the next page to scrape, `None` once finished.
+    pub fn next_url(&self) -> Option<String> {
+        if self.is_finished() {
+            return None;
+        }
+        match self {
+            PaginationState::Page {
+                base_url, param, next_page, ..
+            } => Some(with_query(base_url, param, &next_page.to_string())),
+            PaginationState::Offset {
+                base_url,
+                param,
+                next_offset,
+                ..
+            } => Some(with_query(base_url, param, &next_offset.to_string())),
+            PaginationState::Cursor {
+                base_url, param, cursor, ..
+            } => Some(match cursor {
+                Some(cursor) => with_query(base_url, param, cursor),
+                None => base_url.clone(),
+            }),
+        }
+    }
+
+    /// Moves past a successfully scraped page, reading the next cursor from its body.
+    /// Fails, leaving the state unchanged, if the cursor selector is invalid.
+    #[cfg(feature = "native")]
+    pub(crate) fn advance(&mut self, body: &str) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            PaginationState::Page { next_page, .. } => *next_page += 1,
+            PaginationState::Offset { next_offset, step, .. } => *next_offset += *step,
+            PaginationState::Cursor {
+                cursor,
+                selector,
+                attr,
+                remaining,
+                finished,
+                ..
+            } => {
+                let next = next_cursor(body, selector, attr.as_deref())?;
+                *remaining = remaining.saturating_sub(1);
+                // A repeated token would fetch the same page forever.
+                *finished = next.is_none() || next == *cursor;
+                if next.is_some() {
+                    *cursor = next;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Results of a resumable paginated scrape.
+#[derive(Debug, Clone, Serialize)]
+pub struct PaginatedResult<T> {
+    /// Items of the scraped pages, in page order.
+    pub items: Vec<T>,
+    /// Where the scrape stopped: the failed page, or finished.
+    pub state: PaginationState,
+    /// The error of the page the scrape stopped at.
+    pub error: Option<String>,
+}
+
+impl<T> PaginatedResult<T> {
+    /// Whether every page was scraped.
+    pub fn is_complete(&self) -> bool {
+        self.error.is_none() && self.state.is_finished()
+    }
+}
+
+/// The URL with the query parameter `param` set to `value`, replacing any value it had.
+pub(crate) fn with_query(base_url: &str, param: &str, value: &str) -> String {
+    match Url::parse(base_url) {
+        Ok(mut url) => {
+            let pairs: Vec<(String, String)> = url
+                .query_pairs()
+                .filter(|(name, _)| name != param)
+                .map(|(name, value)| (name.into_owned(), value.into_owned()))
+                .collect();
+            url.query_pairs_mut().clear().extend_pairs(pairs).append_pair(param, value);
+            url.to_string()
+        }
+        Err(_) => format!("{}?{}={}", base_url, param, value),
+    }
+}
+
+#[cfg(feature = "native")]
+fn next_cursor(body: &str, selector: &str, attr: Option<&str>) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let selector = parse_selector(selector)?;
+    let document = Html::parse_document(body);
+    let token = document.select(&selector).next().and_then(|elem| match attr {
+        Some(attr) => elem.value().attr(attr).map(|value| value.trim().to_string()),
+        None => Some(elem.text().collect::<String>().trim().to_string()),
+    });
+    Ok(token.filter(|token| !token.is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_urls_for_pages_and_offsets() {
+        let pages = PaginationState::pages("https://shop.com/list?sort=new", "page", 2);
+        assert_eq!(pages.next_url().as_deref(), Some("https://shop.com/list?sort=new&page=1"));
+        let offsets = PaginationState::offsets("https://shop.com/list", "offset", 20, 20);
+        assert_eq!(offsets.next_url().as_deref(), Some("https://shop.com/list?offset=0"));
+        let done = PaginationState::offsets("https://shop.com/list", "offset", 20, 0);
+        assert!(done.is_finished() && done.next_url().is_none());
+    }
+
+    #[test]
+    fn survives_serialization() {
+        let state = PaginationState::cursor("https://api.com/items", "cursor", ".next", 5).with_cursor_attr("data-cursor");
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(json.contains(r#""type":"cursor""#));
+        assert_eq!(serde_json::from_str::<PaginationState>(&json).unwrap(), state);
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn advances_through_pages_and_cursors() {
+        let mut pages = PaginationState::pages("https://shop.com/", "page", 2);
+        pages.advance("").unwrap();
+        assert_eq!(pages.next_url().as_deref(), Some("https://shop.com/?page=2"));
+        pages.advance("").unwrap();
+        assert!(pages.is_finished());
+
+        let mut cursor = PaginationState::cursor("https://api.com/items", "cursor", "a.next", 5).with_cursor_attr("data-cursor");
+        assert_eq!(cursor.next_url().as_deref(), Some("https://api.com/items"));
+        cursor.advance(r#"<a class="next" data-cursor="abc">Next</a>"#).unwrap();
+        assert_eq!(cursor.next_url().as_deref(), Some("https://api.com/items?cursor=abc"));
+        // The same token again would loop on one page.
+        cursor.advance(r#"<a class="next" data-cursor="abc">Next</a>"#).unwrap();
+        assert!(cursor.is_finished());
+    }
+
+    #[test]
+    fn replaces_an_existing_query_parameter() {
+        assert_eq!(with_query("https://a.com/list?page=1&sort=new", "page", "3"), "https://a.com/list?sort=new&page=3");
+        assert_eq!(with_query("https://a.com/list?sort=new", "page", "2"), "https://a.com/list?sort=new&page=2");
+        let state = PaginationState::pages("https://a.com/list?page=1", "page", 2);
+        assert_eq!(state.next_url().as_deref(), Some("https://a.com/list?page=1"));
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn fails_on_an_invalid_cursor_selector() {
+        let mut state = PaginationState::cursor("https://a.com/feed", "cursor", "a[", 5);
+        assert!(state.advance("<a>next</a>").is_err());
+        assert_eq!(state, PaginationState::cursor("https://a.com/feed", "cursor", "a[", 5));
+
+        let mut state = PaginationState::cursor("https://a.com/feed", "cursor", ".next", 5).with_cursor_attr("data-cursor");
+        state.advance(r#"<a class="next" data-cursor="abc">more</a>"#).unwrap();
+        assert_eq!(state.next_url().as_deref(), Some("https://a.com/feed?cursor=abc"));
+        state.advance("<p>end</p>").unwrap();
+        assert!(state.is_finished());
+    }
+}
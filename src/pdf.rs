@@ -0,0 +1,123 @@
+//! PDF text extraction (enabled with the `pdf` feature).
+//!
+//! PDF responses are converted into a minimal HTML document so they can be queried with
+//! the same CSS selectors as regular pages: every page becomes a `div.pdf-page` and every
+//! non-empty line a `<p>` inside it. Document metadata is exposed as `<meta>` tags.
+
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use std::collections::HashMap;
+
+/// Text content and metadata of a PDF document.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct PdfDocument {
+    pub pages: Vec<String>,
+    pub metadata: HashMap<String, String>,
+}
+
+impl PdfDocument {
+    /// Parses a PDF document from raw bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = pdf_extract::extract_text_from_mem(bytes)?;
+        let pages = text.split('\x0c').map(|page| page.trim().to_string()).collect();
+        Ok(PdfDocument {
+            pages,
+            metadata: read_metadata(bytes),
+        })
+    }
+
+    /// Returns the text of all pages joined together.
+    pub fn text(&self) -> String {
+        self.pages.join("\n\n")
+    }
+
+    /// Renders the document as HTML so it can go through the regular selector pipeline.
+    pub fn to_html(&self) -> String {
+        let mut html = String::from("<html><head>");
+        for (name, value) in &self.metadata {
+            html.push_str(&format!("<meta name=\"{}\" content=\"{}\">", escape(name), escape(value)));
+        }
+        html.push_str("</head><body>");
+        for page in &self.pages {
+            html.push_str("<div class=\"pdf-page\">");
+            for line in page.lines().map(str::trim).filter(|line| !line.is_empty()) {
+                html.push_str(&format!("<p>{}</p>", escape(line)));
+            }
+            html.push_str("</div>");
+        }
+        html.push_str("</body></html>");
+        html
+    }
+}
+
+/// Checks whether a response declares a PDF body.
+pub fn is_pdf(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_ascii_lowercase().starts_with("application/pdf"))
+        .unwrap_or(false)
+}
+
+/// Reads the entries of the document information dictionary (Title, Author, ...).
+fn read_metadata(bytes: &[u8]) -> HashMap<String, String> {
+    let mut metadata = HashMap::new();
+    let document = match lopdf::Document::load_mem(bytes) {
+        Ok(document) => document,
+        Err(_) => return metadata,
+    };
+    let info = document
+        .trailer
+        .get(b"Info")
+        .and_then(|info| info.as_reference())
+        .and_then(|id| document.get_object(id))
+        .and_then(|object| object.as_dict());
+    if let Ok(info) = info {
+        for (key, value) in info.iter() {
+            if let Ok(value) = value.as_str() {
+                metadata.insert(
+                    String::from_utf8_lossy(key).to_string(),
+                    String::from_utf8_lossy(value).to_string(),
+                );
+            }
+        }
+    }
+    metadata
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn renders_pages_as_html() {
+        let document = PdfDocument {
+            pages: vec!["Title\n\n  a < b & c  ".to_string(), "Second".to_string()],
+            metadata: HashMap::from([("Author".to_string(), "\"Ada\"".to_string())]),
+        };
+        assert_eq!(
+            document.to_html(),
+            "<html><head><meta name=\"Author\" content=\"&quot;Ada&quot;\"></head><body>\
+             <div class=\"pdf-page\"><p>Title</p><p>a &lt; b &amp; c</p></div>\
+             <div class=\"pdf-page\"><p>Second</p></div></body></html>"
+        );
+        assert_eq!(document.text(), "Title\n\n  a < b & c  \n\nSecond");
+    }
+
+    #[test]
+    fn detects_pdf_content_types() {
+        let mut headers = HeaderMap::new();
+        assert!(!is_pdf(&headers));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("Application/PDF; charset=binary"));
+        assert!(is_pdf(&headers));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+        assert!(!is_pdf(&headers));
+    }
+}
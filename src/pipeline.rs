@@ -0,0 +1,369 @@
+//! Declarative scraping pipelines.
+//!
+//! A `Pipeline` chains the steps of a scraping job: where the URLs come from, how pages are
+//! fetched, the schema records are extracted with, transforms and filters applied to each
+//! record, and the sinks receiving the results.
+
+use crate::export::ExportWriter;
+use crate::redact::Redactor;
+use crate::schema::Schema;
+use crate::{Exporter, JsScraper, RustScrapper, ScrapeEngine};
+use log::{error, info};
+use regex::Regex;
+use serde_json::Value;
+use std::io::Write;
+use std::sync::OnceLock;
+
+/// Nested sitemap indexes are followed up to this depth.
+const MAX_SITEMAP_DEPTH: usize = 3;
+
+/// Where a pipeline takes its URLs from.
+#[derive(Debug, Clone)]
+pub enum Source {
+    /// A fixed list of URLs.
+    Urls(Vec<String>),
+    /// The `<loc>` entries of a sitemap. Sitemap indexes are followed.
+    Sitemap(String),
+}
+
+/// Receives the records a pipeline produces.
+pub trait RecordSink: Send {
+    /// Writes the records extracted from one page.
+    fn write(&mut self, records: &[Value]) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Called once after the last page, e.g. to flush buffered output.
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+}
+
+/// Writes records to a CSV file with a header row, see `Exporter::records_to_csv`.
+/// The columns depend on all records, so the file is written when the pipeline finishes.
+pub struct CsvSink {
+    path: String,
+    records: Vec<Value>,
+}
+
+impl CsvSink {
+    pub fn new(path: &str) -> Self {
+        CsvSink {
+            path: path.to_string(),
+            records: Vec::new(),
+        }
+    }
+}
+
+impl RecordSink for CsvSink {
+    fn write(&mut self, records: &[Value]) -> Result<(), Box<dyn std::error::Error>> {
+        self.records.extend_from_slice(records);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        Exporter::records_to_csv(&self.records, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Writes records to a JSON Lines file, one record per line, as they are extracted.
+/// An existing file is replaced unless `appending` is set. The file is compressed if its
+/// name ends with `.gz` or `.zst`, see `ExportWriter`.
+pub struct JsonLinesSink {
+    path: String,
+    append: bool,
+    writer: Option<ExportWriter>,
+}
+
+impl JsonLinesSink {
+    pub fn new(path: &str) -> Self {
+        JsonLinesSink {
+            path: path.to_string(),
+            append: false,
+            writer: None,
+        }
+    }
+
+    /// Adds the records to the end of an existing file instead of replacing it.
+    pub fn appending(mut self) -> Self {
+        self.append = true;
+        self
+    }
+}
+
+impl RecordSink for JsonLinesSink {
+    fn write(&mut self, records: &[Value]) -> Result<(), Box<dyn std::error::Error>> {
+        if self.writer.is_none() {
+            self.writer = Some(if self.append {
+                ExportWriter::append(&self.path)?
+            } else {
+                ExportWriter::create(&self.path)?
+            });
+        }
+        if let Some(writer) = &mut self.writer {
+            for record in records {
+                serde_json::to_writer(&mut *writer, record)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(writer) = self.writer.take() {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+type Transform = Box<dyn Fn(Value) -> Value + Send + Sync>;
+type Predicate = Box<dyn Fn(&Value) -> bool + Send + Sync>;
+
+/// A scraping job assembled from a source, a fetch engine, a schema, record transforms
+/// and filters, and sinks. Pages are processed one at a time, in source order.
+pub struct Pipeline {
+    scrapper: Option<RustScrapper>,
+    sources: Vec<Source>,
+    engine: ScrapeEngine,
+    schema: Option<Schema>,
+    transforms: Vec<Transform>,
+    filters: Vec<Predicate>,
+    sinks: Vec<Box<dyn RecordSink>>,
+}
+
+impl Pipeline {
+    /// Creates an empty pipeline fetching over HTTP with a default `RustScrapper`.
+    pub fn new() -> Self {
+        Pipeline {
+            scrapper: None,
+            sources: Vec::new(),
+            engine: ScrapeEngine::Http,
+            schema: None,
+            transforms: Vec::new(),
+            filters: Vec::new(),
+            sinks: Vec::new(),
+        }
+    }
+
+    /// Fetches pages with a configured scrapper, e.g. one with a rate limiter or cache.
+    pub fn with_scrapper(mut self, scrapper: RustScrapper) -> Self {
+        self.scrapper = Some(scrapper);
+        self
+    }
+
+    /// Adds a source of URLs. Sources are read in the order they were added.
+    pub fn source(mut self, source: Source) -> Self {
+        self.sources.push(source);
+        self
+    }
+
+    /// Fetches pages over HTTP (the default) or renders them in the headless browser,
+    /// with the scrapper's `JsScrapeOptions`.
+    pub fn fetch(mut self, engine: ScrapeEngine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    /// Extracts the records described by the schema from every page.
+    pub fn extract(mut self, schema: Schema) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Maps every record, e.g. to clean or rename fields. Transforms run in the order they
+    /// were added, before the filters.
+    pub fn transform<F>(mut self, transform: F) -> Self
+    where
+        F: Fn(Value) -> Value + Send + Sync + 'static,
+    {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+
+    /// Masks or drops personal data in every record before the filters and sinks, see
+    /// `Redactor`.
+    pub fn redact(self, redactor: Redactor) -> Self {
+        self.transform(move |record| redactor.redact(record))
+    }
+
+    /// Keeps only the records the predicate accepts.
+    pub fn filter<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Value) -> bool + Send + Sync + 'static,
+    {
+        self.filters.push(Box::new(predicate));
+        self
+    }
+
+    /// Sends the resulting records to a sink. Several sinks can be added.
+    pub fn sink<S: RecordSink + 'static>(mut self, sink: S) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    /// Runs the pipeline and returns all records that passed the filters.
+    /// Pages that fail to load are logged and skipped; a failing sink aborts the run.
+    pub async fn run(&mut self) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+        let schema = self.schema.clone().ok_or("Pipeline has no schema to extract")?;
+        let scrapper = self.scrapper.get_or_insert_with(RustScrapper::new);
+
+        let mut urls = Vec::new();
+        for source in &self.sources {
+            match source {
+                Source::Urls(list) => urls.extend(list.iter().cloned()),
+                Source::Sitemap(url) => urls.extend(sitemap_urls(scrapper, url, 0).await?),
+            }
+        }
+        info!("Pipeline processing {} URLs", urls.len());
+
+        let mut output = Vec::new();
+        for url in urls {
+            let extracted = match self.engine {
+                ScrapeEngine::Http => scrapper.scrape_structured_async(&url, &schema).await,
+                ScrapeEngine::Headless => match scrapper.check_compliance_async(&url).await.and_then(|()| scrapper.browser_options()) {
+                    Ok(options) => match JsScraper::render_async(&url, &options).await {
+                        Ok(page) => schema.extract(&page.html),
+                        Err(e) => Err(e),
+                    },
+                    Err(e) => Err(e),
+                },
+            };
+            let records = match extracted {
+                Ok(records) => records,
+                Err(e) => {
+                    error!("Pipeline failed to scrape {}: {}", url, e);
+                    continue;
+                }
+            };
+            let records = records
+                .into_iter()
+                .map(|record| self.transforms.iter().fold(record, |record, transform| transform(record)))
+                .filter(|record| self.filters.iter().all(|predicate| predicate(record)))
+                .collect::<Vec<_>>();
+            for sink in &mut self.sinks {
+                sink.write(&records)?;
+            }
+            output.extend(records);
+        }
+        for sink in &mut self.sinks {
+            sink.finish()?;
+        }
+        Ok(output)
+    }
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Pipeline::new()
+    }
+}
+
+/// The page URLs listed in a sitemap, following nested sitemap indexes.
+async fn sitemap_urls(scrapper: &mut RustScrapper, url: &str, depth: usize) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    static LOC: OnceLock<Regex> = OnceLock::new();
+    let loc = LOC.get_or_init(|| Regex::new(r"(?s)<loc>\s*(?:<!\[CDATA\[)?(.*?)(?:\]\]>)?\s*</loc>").unwrap());
+
+    let xml = scrapper.fetch_body_async(url).await?;
+    let locs = loc
+        .captures_iter(&xml)
+        .map(|captures| captures[1].replace("&amp;", "&"))
+        .collect::<Vec<_>>();
+    if !xml.contains("<sitemapindex") {
+        return Ok(locs);
+    }
+    if depth >= MAX_SITEMAP_DEPTH {
+        return Err(format!("Sitemap index nested too deeply at {}", url).into());
+    }
+    let mut urls = Vec::new();
+    for sitemap in locs {
+        urls.extend(Box::pin(sitemap_urls(scrapper, &sitemap, depth + 1)).await?);
+    }
+    Ok(urls)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::schema::Field;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    struct Collect(Arc<Mutex<Vec<Value>>>, Arc<Mutex<bool>>);
+
+    impl RecordSink for Collect {
+        fn write(&mut self, records: &[Value]) -> Result<(), Box<dyn std::error::Error>> {
+            self.0.lock().unwrap().extend_from_slice(records);
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+            *self.1.lock().unwrap() = true;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_records_through_transforms_filters_and_sinks() {
+        let (records, finished) = (Arc::new(Mutex::new(Vec::new())), Arc::new(Mutex::new(false)));
+        let path = std::env::temp_dir().join(format!("rust-scrapper-pipeline-{}.jsonl", std::process::id()));
+        let sitemap = "data:text/xml,<urlset><url><loc>data:text/html,<li><b>Chair</b></li></loc></url></urlset>";
+        let mut pipeline = Pipeline::new()
+            .source(Source::Urls(vec!["data:text/html,<li><b>Lamp</b></li><li><b>Desk</b></li>".to_string()]))
+            .source(Source::Sitemap(sitemap.to_string()))
+            .extract(Schema::new().root("li").field(Field::new("name", "b")))
+            .transform(|mut record| {
+                record["name"] = Value::String(record["name"].as_str().unwrap_or_default().to_uppercase());
+                record
+            })
+            .filter(|record| record["name"] != "DESK")
+            .sink(Collect(records.clone(), finished.clone()))
+            .sink(JsonLinesSink::new(&path.to_string_lossy()));
+        let output = pipeline.run().await.unwrap();
+        let lines = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let names: Vec<_> = output.iter().map(|record| record["name"].as_str().unwrap()).collect();
+        assert_eq!(names, vec!["LAMP", "CHAIR"]);
+        assert_eq!(*records.lock().unwrap(), output);
+        assert!(*finished.lock().unwrap());
+        assert_eq!(lines, "{\"name\":\"LAMP\"}\n{\"name\":\"CHAIR\"}\n");
+    }
+
+    #[tokio::test]
+    async fn requires_a_schema() {
+        let error = Pipeline::new().run().await.unwrap_err();
+        assert_eq!(error.to_string(), "Pipeline has no schema to extract");
+    }
+
+    #[tokio::test]
+    async fn redacts_records_before_the_sinks() {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let mut pipeline = Pipeline::new()
+            .source(Source::Urls(vec!["data:text/html,<li><p>call 555-123-4567</p></li><li><p>no phone</p></li>".to_string()]))
+            .extract(Schema::new().root("li").field(Field::new("text", "p")))
+            .redact(Redactor::new().phone_numbers().drop_matches())
+            .sink(Collect(records.clone(), Arc::new(Mutex::new(false))));
+        pipeline.run().await.unwrap();
+        let texts: Vec<Value> = records.lock().unwrap().iter().map(|record| record["text"].clone()).collect();
+        assert_eq!(texts, vec![Value::Null, Value::String("no phone".to_string())]);
+    }
+
+    #[test]
+    fn replaces_json_lines_files_unless_appending() {
+        let path = std::env::temp_dir().join(format!("rust-scrapper-pipeline-append-{}.jsonl", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        for _ in 0..2 {
+            let mut sink = JsonLinesSink::new(&path);
+            sink.write(&[json!({"n": 1})]).unwrap();
+            sink.finish().unwrap();
+        }
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{\"n\":1}\n");
+
+        let mut sink = JsonLinesSink::new(&path).appending();
+        sink.write(&[json!({"n": 2})]).unwrap();
+        sink.finish().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{\"n\":1}\n{\"n\":2}\n");
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,98 @@
+//! Cheap URL checks without downloading bodies.
+//!
+//! `RustScrapper::probe` sends a HEAD request, or a GET for the first byte when the server
+//! does not support HEAD, and reports what the headers say about the resource: status,
+//! content type, size and last modification. Useful to pre-filter large URL lists.
+
+use reqwest::header::{HeaderMap, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, LAST_MODIFIED};
+use serde::Serialize;
+
+/// `Range` header value requesting only the first byte.
+pub(crate) const FIRST_BYTE: &str = "bytes=0-0";
+
+/// What a probe found out about a URL.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProbeResult {
+    pub url: String,
+    /// The URL after redirects.
+    pub final_url: String,
+    /// Status of the HEAD request, or of the ranged GET, which is usually 206.
+    pub status: u16,
+    pub content_type: Option<String>,
+    /// Size of the full body in bytes, if the server declares it.
+    pub content_length: Option<u64>,
+    /// The `Last-Modified` header as sent, e.g. "Wed, 21 Oct 2015 07:28:00 GMT".
+    pub last_modified: Option<String>,
+    pub etag: Option<String>,
+}
+
+impl ProbeResult {
+    pub(crate) fn from_response(url: &str, final_url: &str, status: u16, headers: &HeaderMap) -> Self {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok()).map(|value| value.trim().to_string());
+        // A ranged response's Content-Length is the range's; the full size follows the slash
+        // in "Content-Range: bytes 0-0/12345".
+        let content_length = match header(CONTENT_RANGE) {
+            Some(range) => range.rsplit_once('/').and_then(|(_, total)| total.parse().ok()),
+            None => header(CONTENT_LENGTH).and_then(|length| length.parse().ok()),
+        };
+        ProbeResult {
+            url: url.to_string(),
+            final_url: final_url.to_string(),
+            status,
+            content_type: header(CONTENT_TYPE),
+            content_length,
+            last_modified: header(LAST_MODIFIED),
+            etag: header(ETAG),
+        }
+    }
+
+    /// Whether the status is 2xx.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Whether the content type is HTML or XHTML.
+    pub fn is_html(&self) -> bool {
+        self.content_type
+            .as_deref()
+            .is_some_and(|content_type| content_type.contains("text/html") || content_type.contains("application/xhtml"))
+    }
+
+    /// The `Last-Modified` header as a Unix timestamp in seconds.
+    pub fn last_modified_timestamp(&self) -> Option<i64> {
+        chrono::DateTime::parse_from_rfc2822(self.last_modified.as_deref()?).ok().map(|date| date.timestamp())
+    }
+}
+
+/// Whether a HEAD response status means the server does not support HEAD.
+pub(crate) fn head_unsupported(status: u16) -> bool {
+    matches!(status, 405 | 501)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn reads_the_full_size_of_ranged_responses() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/html; charset=utf-8"));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("1"));
+        headers.insert(CONTENT_RANGE, HeaderValue::from_static("bytes 0-0/12345"));
+        headers.insert(LAST_MODIFIED, HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"));
+        let probe = ProbeResult::from_response("https://a.com/", "https://a.com/home", 206, &headers);
+
+        assert_eq!(probe.content_length, Some(12345));
+        assert!(probe.is_success() && probe.is_html());
+        assert_eq!(probe.last_modified_timestamp(), Some(1_445_412_480));
+        assert_eq!(probe.etag, None);
+    }
+
+    #[test]
+    fn falls_back_to_get_when_head_is_not_allowed() {
+        assert!(head_unsupported(405) && head_unsupported(501));
+        assert!(!head_unsupported(404));
+    }
+}
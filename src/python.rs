@@ -0,0 +1,119 @@
+//! Python bindings, built with the `python` feature.
+//!
+//! Build the extension with maturin (`maturin develop --features python`) and use it from Python:
+//!
+//! ```python
+//! import rust_scrapper
+//!
+//! scrapper = rust_scrapper.RustScrapper(user_agent="my-bot/1.0")
+//! titles = scrapper.scrape("https://example.com", "h1")
+//! products = scrapper.scrape_structured("https://example.com/products", {
+//!     "root": ".product",
+//!     "fields": [{"name": "title", "selector": "h2"}],
+//! })
+//! rust_scrapper.records_to_csv(products, "products.csv")
+//! ```
+//!
+//! Structured records cross the language boundary as JSON, so Python sees plain dicts and lists.
+//! Scrapes release the GIL while they wait on the network, so other Python threads keep running.
+
+use crate::schema::Schema;
+use crate::{Exporter, RustScrapper, Scraper};
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::PyString;
+use std::collections::HashMap;
+
+/// Python wrapper around `RustScrapper`, exposed as `rust_scrapper.RustScrapper`.
+#[pyclass(name = "RustScrapper", unsendable)]
+pub struct PyScrapper {
+    inner: RustScrapper,
+}
+
+#[pymethods]
+impl PyScrapper {
+    #[new]
+    #[pyo3(signature = (user_agent = None, headers = None))]
+    fn new(user_agent: Option<&str>, headers: Option<HashMap<String, String>>) -> PyResult<Self> {
+        let mut inner = RustScrapper::new();
+        if let Some(user_agent) = user_agent {
+            inner = inner.with_header("User-Agent", user_agent).map_err(py_err)?;
+        }
+        for (name, value) in headers.unwrap_or_default() {
+            inner = inner.with_header(&name, &value).map_err(py_err)?;
+        }
+        Ok(PyScrapper { inner })
+    }
+
+    /// Returns the inner HTML of every element matching the selector.
+    fn scrape(&mut self, py: Python<'_>, url: &str, selector: &str) -> PyResult<Vec<String>> {
+        let inner = &mut self.inner;
+        py.allow_threads(|| inner.scrape(url, selector).map_err(|e| e.to_string()))
+            .map_err(PyRuntimeError::new_err)
+    }
+
+    /// Scrapes `pages` pages by appending `?<page_param>=<n>` to the base URL.
+    fn scrape_paginated(&mut self, py: Python<'_>, base_url: &str, page_param: &str, pages: usize, selector: &str) -> PyResult<Vec<String>> {
+        let inner = &mut self.inner;
+        py.allow_threads(|| inner.scrape_paginated(base_url, page_param, pages, selector).map_err(|e| e.to_string()))
+            .map_err(PyRuntimeError::new_err)
+    }
+
+    /// Extracts one dict per record. `schema` is a dict or a JSON string in the `Schema` format.
+    fn scrape_structured(&mut self, py: Python<'_>, url: &str, schema: &PyAny) -> PyResult<PyObject> {
+        let schema: Schema = serde_json::from_str(&to_json_string(py, schema)?).map_err(|e| py_err(e.into()))?;
+        let inner = &mut self.inner;
+        let records = py
+            .allow_threads(|| {
+                let records = inner.scrape_structured(url, &schema).map_err(|e| e.to_string())?;
+                serde_json::to_string(&records).map_err(|e| e.to_string())
+            })
+            .map_err(PyRuntimeError::new_err)?;
+        from_json_string(py, &records)
+    }
+}
+
+/// Exports a list of strings to a JSON array.
+#[pyfunction]
+fn to_json(data: Vec<String>) -> String {
+    Exporter::to_json(data)
+}
+
+/// Writes a list of strings to a CSV file, one per line.
+#[pyfunction]
+fn to_csv(data: Vec<String>, file_name: &str) -> PyResult<()> {
+    Ok(Exporter::to_csv(data, file_name)?)
+}
+
+/// Writes a list of dicts to a CSV file with a header row.
+#[pyfunction]
+fn records_to_csv(py: Python<'_>, records: &PyAny, file_name: &str) -> PyResult<()> {
+    let records: Vec<serde_json::Value> =
+        serde_json::from_str(&to_json_string(py, records)?).map_err(|e| py_err(e.into()))?;
+    Ok(Exporter::records_to_csv(&records, file_name)?)
+}
+
+#[pymodule]
+fn rust_scrapper(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyScrapper>()?;
+    m.add_function(wrap_pyfunction!(to_json, m)?)?;
+    m.add_function(wrap_pyfunction!(to_csv, m)?)?;
+    m.add_function(wrap_pyfunction!(records_to_csv, m)?)?;
+    Ok(())
+}
+
+fn py_err(e: Box<dyn std::error::Error>) -> PyErr {
+    PyRuntimeError::new_err(e.to_string())
+}
+
+/// Serializes a Python object with `json.dumps`; strings are taken as JSON already.
+fn to_json_string(py: Python<'_>, value: &PyAny) -> PyResult<String> {
+    if value.is_instance_of::<PyString>() {
+        return value.extract();
+    }
+    py.import("json")?.call_method1("dumps", (value,))?.extract()
+}
+
+fn from_json_string(py: Python<'_>, json: &str) -> PyResult<PyObject> {
+    Ok(py.import("json")?.call_method1("loads", (json,))?.into())
+}
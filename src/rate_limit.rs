@@ -0,0 +1,354 @@
+//! Adaptive, per-host rate limiting.
+//!
+//! The delay between requests to a host grows when the server answers with 429/503 or
+//! responds noticeably slower than usual, honors `Retry-After` and `X-RateLimit-*` quota
+//! headers, and shrinks gradually back to the minimum while responses stay healthy.
+
+use crate::host_of;
+use reqwest::header::HeaderMap;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Request quota announced by a server in `X-RateLimit-*` or `RateLimit-*` headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitQuota {
+    pub limit: Option<u64>,
+    pub remaining: Option<u64>,
+    /// Time until the quota resets.
+    pub reset: Option<Duration>,
+}
+
+/// Tuning parameters of the `RateLimiter`.
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    /// Delay used while the server is healthy.
+    pub min_delay: Duration,
+    /// Upper bound for the delay.
+    pub max_delay: Duration,
+    /// Factor the delay is multiplied with when the server signals overload.
+    pub backoff_factor: f64,
+    /// Factor the delay is multiplied with after each healthy response (below 1.0).
+    pub recovery_factor: f64,
+    /// Latency, relative to the fastest average seen, that counts as a slowdown.
+    pub latency_threshold: f64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            min_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(60),
+            backoff_factor: 2.0,
+            recovery_factor: 0.9,
+            latency_threshold: 2.0,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// The config with `max_delay` at least `min_delay` and usable factors, as delays are
+    /// clamped between the bounds and multiplied with the factors.
+    fn validated(mut self) -> Self {
+        let defaults = RateLimitConfig::default();
+        if self.max_delay < self.min_delay {
+            log::warn!("Rate limit max_delay {:?} is below min_delay {:?}, using min_delay", self.max_delay, self.min_delay);
+            self.max_delay = self.min_delay;
+        }
+        if !(self.backoff_factor.is_finite() && self.backoff_factor >= 1.0) {
+            self.backoff_factor = defaults.backoff_factor;
+        }
+        if !(self.recovery_factor > 0.0 && self.recovery_factor <= 1.0) {
+            self.recovery_factor = defaults.recovery_factor;
+        }
+        if !(self.latency_threshold.is_finite() && self.latency_threshold > 0.0) {
+            self.latency_threshold = defaults.latency_threshold;
+        }
+        self
+    }
+}
+
+struct HostState {
+    delay: Duration,
+    next_allowed: Instant,
+    latency_avg: Option<f64>,
+    latency_baseline: Option<f64>,
+}
+
+/// Per-host adaptive rate limiter.
+/// Cloning returns a handle to the same limiter, so it can be shared between scrapers.
+#[derive(Clone)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    hosts: Arc<Mutex<HashMap<String, HostState>>>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter that waits between `min_delay` and `max_delay` between requests to a host.
+    pub fn new(min_delay: Duration, max_delay: Duration) -> Self {
+        RateLimiter::with_config(RateLimitConfig {
+            min_delay,
+            max_delay,
+            ..RateLimitConfig::default()
+        })
+    }
+
+    /// Creates a limiter with custom tuning. A `max_delay` below `min_delay` is raised to it,
+    /// and factors out of range fall back to their defaults.
+    pub fn with_config(config: RateLimitConfig) -> Self {
+        RateLimiter {
+            config: config.validated(),
+            hosts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Reserves the next request slot for the URL's host and returns how long to wait for it.
+    pub fn reserve(&self, url: &str) -> Duration {
+        let mut hosts = self.hosts.lock().unwrap();
+        let state = self.state(&mut hosts, url);
+        let now = Instant::now();
+        let start = state.next_allowed.max(now);
+        state.next_allowed = start + state.delay;
+        start - now
+    }
+
+    /// Waits until a request to the URL's host is allowed.
+    pub async fn wait(&self, url: &str) {
+        let wait = self.reserve(url);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Blocking variant of `wait`.
+    pub fn wait_blocking(&self, url: &str) {
+        let wait = self.reserve(url);
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// Feeds a response back into the limiter to adapt the host's delay. A `Retry-After`
+    /// delay is honored up to `max_delay`.
+    pub fn record(&self, url: &str, status: u16, latency: Duration, retry_after: Option<Duration>) {
+        let config = self.config.clone();
+        let mut hosts = self.hosts.lock().unwrap();
+        let state = self.state(&mut hosts, url);
+
+        let latency = latency.as_secs_f64();
+        let average = match state.latency_avg {
+            Some(average) => 0.8 * average + 0.2 * latency,
+            None => latency,
+        };
+        state.latency_avg = Some(average);
+        let baseline = state.latency_baseline.map(|b| b.min(average)).unwrap_or(average);
+        state.latency_baseline = Some(baseline);
+
+        let overloaded = status == 429 || status == 503;
+        let slow = baseline > 0.0 && average > baseline * config.latency_threshold;
+        state.delay = if overloaded || slow {
+            // Computed in seconds and bounded first, so a large factor cannot overflow.
+            let raised = state.delay.max(config.min_delay).max(Duration::from_millis(100)).as_secs_f64() * config.backoff_factor;
+            Duration::from_secs_f64(raised.min(config.max_delay.as_secs_f64()))
+        } else {
+            state.delay.mul_f64(config.recovery_factor)
+        }
+        .clamp(config.min_delay, config.max_delay);
+
+        // `Retry-After` comes from the server, so it is bounded like every other delay.
+        if let Some(retry_at) = retry_after.and_then(|retry_after| Instant::now().checked_add(retry_after.min(config.max_delay))) {
+            state.next_allowed = state.next_allowed.max(retry_at);
+        }
+    }
+
+    /// Feeds a server's quota headers into the limiter. Once the quota is used up, requests
+    /// wait for its reset, at most `max_delay`; before that, the delay is raised to spread the
+    /// remaining requests evenly until the reset.
+    pub fn record_quota(&self, url: &str, quota: &RateLimitQuota) {
+        let (Some(remaining), Some(reset)) = (quota.remaining, quota.reset) else {
+            return;
+        };
+        // Servers control the header, so the wait is bounded like every other delay.
+        let max_delay = self.config.max_delay;
+        let reset = reset.min(max_delay);
+        let mut hosts = self.hosts.lock().unwrap();
+        let state = self.state(&mut hosts, url);
+        if remaining == 0 {
+            if let Some(reset_at) = Instant::now().checked_add(reset) {
+                state.next_allowed = state.next_allowed.max(reset_at);
+            }
+        } else {
+            let pace = Duration::from_secs_f64(reset.as_secs_f64() / remaining as f64);
+            state.delay = state.delay.max(pace.min(max_delay));
+        }
+    }
+
+    /// Returns the current delay for the URL's host.
+    pub fn current_delay(&self, url: &str) -> Duration {
+        let mut hosts = self.hosts.lock().unwrap();
+        self.state(&mut hosts, url).delay
+    }
+
+    fn state<'a>(&self, hosts: &'a mut HashMap<String, HostState>, url: &str) -> &'a mut HostState {
+        let host = host_of(url);
+        hosts.entry(host).or_insert_with(|| HostState {
+            delay: self.config.min_delay,
+            next_allowed: Instant::now(),
+            latency_avg: None,
+            latency_baseline: None,
+        })
+    }
+}
+
+/// Parses a `Retry-After` header value, given either in seconds or as an HTTP date.
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let date = SystemTime::from(date);
+    Some(date.duration_since(SystemTime::now()).unwrap_or(Duration::ZERO))
+}
+
+/// Parses the `X-RateLimit-Limit`, `-Remaining` and `-Reset` headers, or their `RateLimit-*`
+/// equivalents. Resets are read as seconds from now, or as a Unix timestamp for large values.
+pub fn parse_rate_limit_headers(headers: &HeaderMap) -> Option<RateLimitQuota> {
+    let number = |name: &str| {
+        [format!("x-ratelimit-{}", name), format!("ratelimit-{}", name)]
+            .iter()
+            .find_map(|name| headers.get(name.as_str()))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+    };
+    let quota = RateLimitQuota {
+        limit: number("limit"),
+        remaining: number("remaining"),
+        reset: number("reset").map(|reset| {
+            // Smaller values cannot be timestamps, as they lie before 2001.
+            if reset > 1_000_000_000 {
+                let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+                Duration::from_secs(reset).saturating_sub(now)
+            } else {
+                Duration::from_secs(reset)
+            }
+        }),
+    };
+    (quota != RateLimitQuota { limit: None, remaining: None, reset: None }).then_some(quota)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(Duration::from_millis(100), Duration::from_secs(1))
+    }
+
+    #[test]
+    fn backs_off_on_overload_and_recovers_to_the_minimum() {
+        let limiter = limiter();
+        let url = "https://a.com/page";
+        limiter.record(url, 429, Duration::from_millis(10), None);
+        assert_eq!(limiter.current_delay(url), Duration::from_millis(200));
+        for _ in 0..5 {
+            limiter.record(url, 503, Duration::from_millis(10), None);
+        }
+        assert_eq!(limiter.current_delay(url), Duration::from_secs(1));
+        for _ in 0..50 {
+            limiter.record(url, 200, Duration::from_millis(10), None);
+        }
+        assert_eq!(limiter.current_delay(url), Duration::from_millis(100));
+        assert_eq!(limiter.current_delay("https://b.com/"), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn backs_off_when_responses_slow_down() {
+        let limiter = limiter();
+        let url = "https://a.com/";
+        limiter.record(url, 200, Duration::from_millis(10), None);
+        limiter.record(url, 200, Duration::from_millis(500), None);
+        assert!(limiter.current_delay(url) > Duration::from_millis(100));
+    }
+
+    #[test]
+    fn spaces_reservations_per_host() {
+        let limiter = limiter();
+        assert_eq!(limiter.reserve("https://a.com/1"), Duration::ZERO);
+        assert!(limiter.reserve("https://a.com/2") > Duration::from_millis(50));
+        assert_eq!(limiter.reserve("https://b.com/1"), Duration::ZERO);
+
+        // Retry-After is capped at the maximum delay of one second.
+        limiter.record("https://b.com/1", 429, Duration::from_millis(10), Some(Duration::from_secs(30)));
+        let wait = limiter.reserve("https://b.com/2");
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn parses_retry_after_seconds_and_dates() {
+        assert_eq!(parse_retry_after(" 120 "), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[test]
+    fn paces_requests_by_the_remaining_quota() {
+        let headers: HeaderMap = [("x-ratelimit-remaining", "10"), ("x-ratelimit-reset", "20"), ("ratelimit-limit", "100")]
+            .iter()
+            .map(|(name, value)| (name.parse().unwrap(), value.parse().unwrap()))
+            .collect();
+        let quota = parse_rate_limit_headers(&headers).unwrap();
+        assert_eq!(quota, RateLimitQuota { limit: Some(100), remaining: Some(10), reset: Some(Duration::from_secs(20)) });
+        assert!(parse_rate_limit_headers(&HeaderMap::new()).is_none());
+
+        let limiter = RateLimiter::new(Duration::from_millis(100), Duration::from_secs(30));
+        limiter.record_quota("https://api.com/", &quota);
+        assert_eq!(limiter.current_delay("https://api.com/"), Duration::from_secs(2));
+
+        let exhausted = RateLimitQuota { remaining: Some(0), ..quota };
+        limiter.record_quota("https://api.com/", &exhausted);
+        assert!(limiter.reserve("https://api.com/next") > Duration::from_secs(19));
+    }
+
+    fn quota(remaining: u64, reset: Duration) -> RateLimitQuota {
+        RateLimitQuota {
+            limit: None,
+            remaining: Some(remaining),
+            reset: Some(reset),
+        }
+    }
+
+    #[test]
+    fn spreads_the_remaining_quota() {
+        let limiter = RateLimiter::new(Duration::from_millis(10), Duration::from_secs(60));
+        limiter.record_quota("https://example.com/", &quota(10, Duration::from_secs(20)));
+        assert_eq!(limiter.current_delay("https://example.com/"), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn fixes_inverted_bounds() {
+        let limiter = RateLimiter::new(Duration::from_secs(5), Duration::from_secs(1));
+        limiter.record("https://example.com/", 429, Duration::from_millis(50), None);
+        assert_eq!(limiter.current_delay("https://example.com/"), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn bounds_retry_after() {
+        let limiter = RateLimiter::new(Duration::from_millis(10), Duration::from_secs(60));
+        let url = "https://example.com/";
+        limiter.record(url, 503, Duration::from_millis(50), parse_retry_after("18446744073709551615"));
+        assert!(limiter.reserve(url) <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn bounds_hostile_quota_headers() {
+        let limiter = RateLimiter::new(Duration::from_millis(10), Duration::from_secs(60));
+        let url = "https://example.com/";
+        limiter.record_quota(url, &quota(u64::MAX, Duration::from_secs(100)));
+        limiter.record_quota(url, &quota(1 << 32, Duration::from_secs(100)));
+        limiter.record_quota(url, &quota(1, Duration::MAX));
+        assert_eq!(limiter.current_delay(url), Duration::from_secs(60));
+        limiter.record_quota(url, &quota(0, Duration::MAX));
+        assert!(limiter.reserve(url) <= Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,322 @@
+//! Redaction of personal data in extracted records.
+//!
+//! A `Redactor` finds personal data in the text values of records, such as email addresses,
+//! phone numbers and national ID numbers, and masks it or drops the values holding it.
+//! Applied with `RustScrapper::with_redactor` it runs before records are cached, snapshotted
+//! or returned; `Pipeline::redact` applies it before the sinks, so exports never contain it.
+
+use regex::Regex;
+use serde_json::Value;
+use std::sync::OnceLock;
+
+/// What happens to personal data a `Redactor` finds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RedactionMode {
+    /// Replaces each match with its label, e.g. `[email]`, keeping the rest of the text.
+    #[default]
+    Mask,
+    /// Removes the whole field or array element containing a match.
+    Drop,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    label: String,
+    pattern: Regex,
+    /// Confirms a match, for patterns that also match other numbers.
+    validate: Option<fn(&str) -> bool>,
+}
+
+impl Rule {
+    fn accepts(&self, matched: &str) -> bool {
+        self.validate.is_none_or(|validate| validate(matched))
+    }
+}
+
+/// Masks or drops personal data in records. Add the kinds of data to look for with
+/// `emails`, `phone_numbers`, `national_ids`, `pattern` and `field`.
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    rules: Vec<Rule>,
+    fields: Vec<String>,
+    mode: RedactionMode,
+}
+
+impl Redactor {
+    /// A redactor finding nothing yet.
+    pub fn new() -> Self {
+        Redactor::default()
+    }
+
+    /// A redactor for email addresses, national ID numbers and phone numbers.
+    pub fn pii() -> Self {
+        Redactor::new().emails().national_ids().phone_numbers()
+    }
+
+    /// Finds email addresses, masked as `[email]`.
+    pub fn emails(self) -> Self {
+        self.rule("email", email_regex().clone(), None)
+    }
+
+    /// Finds formatted phone numbers of 7 to 15 digits, with a leading `+`, parentheses or
+    /// separators such as `+44 20 7946 0958` or `(555) 123-4567`, masked as `[phone]`.
+    /// Plain digit runs, dates, IP addresses and numbers grouped in thousands with dots, like
+    /// the price `12.500.000`, are left alone.
+    pub fn phone_numbers(self) -> Self {
+        self.rule("phone", phone_regex().clone(), Some(is_phone_number))
+    }
+
+    /// Finds US social security numbers, UK national insurance numbers and Turkish identity
+    /// numbers (checksum-validated), masked as `[national id]`. Other formats can be added
+    /// with `pattern`.
+    pub fn national_ids(self) -> Self {
+        self.rule("national id", ssn_regex().clone(), None)
+            .rule("national id", nino_regex().clone(), None)
+            .rule("national id", tckn_regex().clone(), Some(is_tckn))
+    }
+
+    /// Finds matches of a custom regex, masked as `[label]`.
+    pub fn pattern(self, label: &str, pattern: &str) -> Result<Self, regex::Error> {
+        Ok(self.rule(label, Regex::new(pattern)?, None))
+    }
+
+    /// Redacts a field entirely, whatever it holds, e.g. `customer_name`, masked as
+    /// `[redacted]`. Fields of nested objects with that name are redacted too.
+    pub fn field(mut self, name: &str) -> Self {
+        self.fields.push(name.to_string());
+        self
+    }
+
+    /// Drops the values containing personal data instead of masking the matches.
+    pub fn drop_matches(mut self) -> Self {
+        self.mode = RedactionMode::Drop;
+        self
+    }
+
+    fn rule(mut self, label: &str, pattern: Regex, validate: Option<fn(&str) -> bool>) -> Self {
+        self.rules.push(Rule {
+            label: label.to_string(),
+            pattern,
+            validate,
+        });
+        self
+    }
+
+    /// Whether the text contains personal data.
+    pub fn contains_pii(&self, text: &str) -> bool {
+        self.rules.iter().any(|rule| {
+            rule.pattern
+                .find_iter(text)
+                .any(|found| rule.accepts(found.as_str()))
+        })
+    }
+
+    /// The text with every match replaced by its label. Where matches of several rules
+    /// overlap, the one starting first wins, then the longest, then the rule added first.
+    pub fn redact_text(&self, text: &str) -> String {
+        let mut matches: Vec<(usize, usize, &str)> = self
+            .rules
+            .iter()
+            .flat_map(|rule| {
+                rule.pattern
+                    .find_iter(text)
+                    .filter(|found| rule.accepts(found.as_str()))
+                    .map(|found| (found.start(), found.end(), rule.label.as_str()))
+            })
+            .collect();
+        matches.sort_by_key(|&(start, end, _)| (start, std::cmp::Reverse(end)));
+
+        let mut redacted = String::with_capacity(text.len());
+        let mut position = 0;
+        for (start, end, label) in matches {
+            if start < position {
+                continue;
+            }
+            redacted.push_str(&text[position..start]);
+            redacted.push('[');
+            redacted.push_str(label);
+            redacted.push(']');
+            position = end;
+        }
+        redacted.push_str(&text[position..]);
+        redacted
+    }
+
+    /// The record with its personal data masked or dropped. In `Drop` mode a record that is
+    /// itself a string with personal data becomes `null`.
+    pub fn redact(&self, record: Value) -> Value {
+        self.redact_value(record).unwrap_or(Value::Null)
+    }
+
+    /// Redacts every record.
+    pub fn redact_all(&self, records: Vec<Value>) -> Vec<Value> {
+        records.into_iter().map(|record| self.redact(record)).collect()
+    }
+
+    /// The redacted value, or `None` if it is dropped.
+    fn redact_value(&self, value: Value) -> Option<Value> {
+        match value {
+            Value::String(text) => match self.mode {
+                RedactionMode::Mask => Some(Value::String(self.redact_text(&text))),
+                RedactionMode::Drop => (!self.contains_pii(&text)).then_some(Value::String(text)),
+            },
+            Value::Array(items) => Some(Value::Array(items.into_iter().filter_map(|item| self.redact_value(item)).collect())),
+            Value::Object(object) => Some(Value::Object(
+                object
+                    .into_iter()
+                    .filter_map(|(name, value)| {
+                        if self.fields.contains(&name) {
+                            return match self.mode {
+                                RedactionMode::Mask => Some((name, Value::String("[redacted]".to_string()))),
+                                RedactionMode::Drop => None,
+                            };
+                        }
+                        self.redact_value(value).map(|value| (name, value))
+                    })
+                    .collect(),
+            )),
+            other => Some(other),
+        }
+    }
+}
+
+fn email_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}").unwrap())
+}
+
+fn phone_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"(?:\+\d{1,3}[\s.-]?(?:\(\d{1,4}\)[\s.-]?)?|\(\d{1,4}\)[\s.-]?|\b)\d{2,4}(?:[\s.-]\d{2,4}){1,4}\b").unwrap()
+    })
+}
+
+fn ssn_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\b\d{3}-\d{2}-\d{4}\b").unwrap())
+}
+
+fn nino_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\b[A-CEGHJ-PR-TW-Z][A-CEGHJ-NPR-TW-Z] ?\d{2} ?\d{2} ?\d{2} ?[A-D]\b").unwrap())
+}
+
+fn tckn_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\b[1-9]\d{10}\b").unwrap())
+}
+
+/// Dates and IPv4 addresses, which look like phone numbers.
+fn not_phone_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"^(?:\d{1,4}[./-]\d{1,2}[./-]\d{1,4}|\d{1,3}(?:\.\d{1,3}){3})$").unwrap())
+}
+
+/// Numbers grouped in thousands with dots, e.g. the price `12.500.000`.
+fn thousands_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"^\d{1,3}(?:\.\d{3})+$").unwrap())
+}
+
+/// Whether a candidate is a phone number: 7 to 15 digits, and not a date or IP address.
+/// Without a leading `+` or parentheses, the digits must be grouped like a phone number:
+/// with one kind of separator, and not in thousands.
+pub(crate) fn is_phone_number(candidate: &str) -> bool {
+    let digits = candidate.chars().filter(char::is_ascii_digit).count();
+    if !(7..=15).contains(&digits) || not_phone_regex().is_match(candidate) {
+        return false;
+    }
+    if candidate.starts_with(['+', '(']) {
+        return true;
+    }
+    let mut separators = candidate.chars().filter(|c| !c.is_ascii_digit());
+    let first = separators.next();
+    separators.all(|separator| Some(separator) == first) && !thousands_regex().is_match(candidate)
+}
+
+/// Whether eleven digits form a valid Turkish identity number (T.C. Kimlik No).
+fn is_tckn(candidate: &str) -> bool {
+    let digits: Vec<u32> = candidate.chars().filter_map(|c| c.to_digit(10)).collect();
+    if digits.len() != 11 {
+        return false;
+    }
+    let odd: u32 = digits[0..9].iter().step_by(2).sum();
+    let even: u32 = digits[1..8].iter().step_by(2).sum();
+    let tenth = (odd * 7 + 10 * 9 - even) % 10;
+    let eleventh = digits[0..10].iter().sum::<u32>() % 10;
+    digits[9] == tenth && digits[10] == eleventh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn masks_emails_phones_and_national_ids() {
+        let redactor = Redactor::pii();
+        assert_eq!(
+            redactor.redact_text("Mail jane.doe@example.co.uk or call +44 20 7946 0958."),
+            "Mail [email] or call [phone]."
+        );
+        assert_eq!(redactor.redact_text("Call (555) 123-4567 today"), "Call [phone] today");
+        assert_eq!(redactor.redact_text("SSN 123-45-6789, NI AB 12 34 56 C"), "SSN [national id], NI [national id]");
+        assert_eq!(redactor.redact_text("TC 10000000146"), "TC [national id]");
+    }
+
+    #[test]
+    fn leaves_other_numbers_alone() {
+        let redactor = Redactor::pii();
+        for text in ["Order 12345678901", "On 2024-01-15", "Server 192.168.10.200", "SKU 12 34", "TC 10000000147"] {
+            assert!(!redactor.contains_pii(text), "{}", text);
+            assert_eq!(redactor.redact_text(text), text);
+        }
+    }
+
+    #[test]
+    fn redacts_nested_records_and_named_fields() {
+        let record = json!({
+            "name": "Lamp",
+            "seller": {"contact": "ann@shop.com", "customer_name": "Ann"},
+            "notes": ["ships fast", "ask bob@shop.com"],
+            "price": 20,
+        });
+        let masked = Redactor::new().emails().field("customer_name").redact(record.clone());
+        assert_eq!(
+            masked,
+            json!({
+                "name": "Lamp",
+                "seller": {"contact": "[email]", "customer_name": "[redacted]"},
+                "notes": ["ships fast", "ask [email]"],
+                "price": 20,
+            })
+        );
+        let dropped = Redactor::new().emails().field("customer_name").drop_matches().redact(record);
+        assert_eq!(dropped, json!({"name": "Lamp", "seller": {}, "notes": ["ships fast"], "price": 20}));
+        assert_eq!(Redactor::new().emails().drop_matches().redact(json!("a@b.com")), Value::Null);
+    }
+
+    #[test]
+    fn adds_custom_patterns() {
+        let redactor = Redactor::new().pattern("account", r"\bACC-\d+\b").unwrap();
+        assert_eq!(redactor.redact_text("Paid from ACC-991"), "Paid from [account]");
+        assert!(Redactor::new().pattern("bad", "(").is_err());
+    }
+
+    #[test]
+    fn masks_phone_numbers() {
+        let redactor = Redactor::new().phone_numbers();
+        for phone in ["+44 20 7946 0958", "(555) 123-4567", "555-123-4567", "0532 123 45 67", "+90 (212) 555 12 34"] {
+            assert_eq!(redactor.redact_text(&format!("Call {} now", phone)), "Call [phone] now", "{}", phone);
+        }
+    }
+
+    #[test]
+    fn leaves_prices_dates_and_addresses_alone() {
+        let redactor = Redactor::new().phone_numbers();
+        for text in ["12.500.000 TL", "Price: 1.250.000", "2024-01-15", "192.168.100.200", "Order 12345678", "12-345.678"] {
+            assert_eq!(redactor.redact_text(text), text);
+        }
+    }
+}
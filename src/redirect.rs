@@ -0,0 +1,115 @@
+//! Detection of soft redirects.
+//!
+//! Many pages redirect in the body instead of with an HTTP status: a
+//! `<meta http-equiv="refresh" content="0; url=...">` tag or a one-line script assigning
+//! `location.href`. `soft_redirect` finds the target of such pages so the fetch layer can
+//! follow it, see `RustScrapper::follow_soft_redirects`.
+
+use crate::schema::parse_selector;
+use regex::Regex;
+use scraper::Html;
+use std::sync::OnceLock;
+use url::Url;
+
+/// Scripts longer than this are page logic rather than a redirect stub.
+const MAX_REDIRECT_SCRIPT_CHARS: usize = 300;
+
+/// The absolute URL a page redirects to with a meta refresh tag or a redirect script,
+/// resolved against the page's `url`. Only `http` and `https` targets and targets with the
+/// page's own scheme are followed, so a remote page cannot redirect to a local `file:` URL.
+pub fn soft_redirect(body: &str, url: &str) -> Option<String> {
+    let document = Html::parse_document(body);
+    let target = meta_refresh_target(&document).or_else(|| script_redirect_target(&document))?;
+    let base = Url::parse(url).ok();
+    let resolved = match &base {
+        Some(base) => base.join(&target).ok()?,
+        None => Url::parse(&target).ok()?,
+    };
+    let scheme = resolved.scheme();
+    let allowed = scheme == "http" || scheme == "https" || base.is_some_and(|base| base.scheme() == scheme);
+    Some(resolved.to_string()).filter(|resolved| allowed && resolved != url)
+}
+
+/// The URL in `<meta http-equiv="refresh" content="<delay>; url=<target>">`. A refresh without
+/// a URL only reloads the page and is ignored.
+fn meta_refresh_target(document: &Html) -> Option<String> {
+    let selector = parse_selector("meta[http-equiv][content]").ok()?;
+    document
+        .select(&selector)
+        .filter(|meta| meta.value().attr("http-equiv").is_some_and(|equiv| equiv.trim().eq_ignore_ascii_case("refresh")))
+        .find_map(|meta| {
+            let content = meta.value().attr("content")?;
+            let target = meta_refresh_regex().captures(content)?.get(1)?.as_str();
+            let target = target.trim().trim_matches(|c| c == '\'' || c == '"').trim();
+            Some(target.to_string()).filter(|target| !target.is_empty())
+        })
+}
+
+/// The URL assigned to `location` in a short inline script.
+fn script_redirect_target(document: &Html) -> Option<String> {
+    let selector = parse_selector("script:not([src])").ok()?;
+    document.select(&selector).find_map(|script| {
+        let code = script.text().collect::<String>();
+        let code = code.trim();
+        if code.is_empty() || code.chars().count() > MAX_REDIRECT_SCRIPT_CHARS {
+            return None;
+        }
+        let captures = script_redirect_regex().captures(code)?;
+        let target = captures.get(1).or_else(|| captures.get(2))?.as_str();
+        Some(target.to_string()).filter(|target| !target.is_empty())
+    })
+}
+
+fn meta_refresh_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?i)^\s*\d+(?:\.\d*)?\s*[;,]\s*(?:url\s*=\s*)?(.+)$").unwrap())
+}
+
+fn script_redirect_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r#"(?:\b(?:window|document|top|self)\.)?\blocation(?:\.href)?\s*=\s*["'`]([^"'`]+)["'`]|\blocation\.(?:replace|assign)\(\s*["'`]([^"'`]+)["'`]\s*\)"#,
+        )
+        .unwrap()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_meta_refresh_targets() {
+        let body = r#"<meta http-equiv="Refresh" content="0; URL='/new?page=1'">"#;
+        assert_eq!(soft_redirect(body, "https://a.com/old").as_deref(), Some("https://a.com/new?page=1"));
+        assert_eq!(soft_redirect(r#"<meta http-equiv="refresh" content="30">"#, "https://a.com/"), None);
+    }
+
+    #[test]
+    fn finds_short_redirect_scripts_only() {
+        let body = r#"<script>window.location.href = "https://b.com/";</script>"#;
+        assert_eq!(soft_redirect(body, "https://a.com/").as_deref(), Some("https://b.com/"));
+        let body = r#"<script>location.replace('next.html')</script>"#;
+        assert_eq!(soft_redirect(body, "https://a.com/dir/").as_deref(), Some("https://a.com/dir/next.html"));
+
+        let app = format!("<script>{}\nlocation.href = '/login';</script>", "var x = 1;".repeat(40));
+        assert_eq!(soft_redirect(&app, "https://a.com/"), None);
+        assert_eq!(soft_redirect(r#"<script>location.href = "/";</script>"#, "https://a.com/"), None);
+    }
+
+    #[test]
+    fn ignores_local_targets_of_remote_pages() {
+        let meta = r#"<meta http-equiv="refresh" content="0;url=file:///etc/passwd">"#;
+        assert_eq!(soft_redirect(meta, "https://example.com/a"), None);
+        let script = r#"<script>location.replace("data:text/html,hi")</script>"#;
+        assert_eq!(soft_redirect(script, "https://example.com/a"), None);
+        assert_eq!(soft_redirect(meta, "/relative"), None);
+    }
+
+    #[test]
+    fn keeps_the_scheme_of_local_pages() {
+        let meta = r#"<meta http-equiv="refresh" content="0; url=other.html">"#;
+        assert_eq!(soft_redirect(meta, "file:///tmp/site/index.html").as_deref(), Some("file:///tmp/site/other.html"));
+    }
+}
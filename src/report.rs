@@ -0,0 +1,171 @@
+//! Summary reports of scraping sessions.
+//!
+//! `Crawler::report` and `BatchResult::report` summarize a finished crawl or batch: how many
+//! URLs were attempted and why the failed ones failed, how many items were extracted, how
+//! long it took and how much was downloaded.
+
+#[cfg(feature = "native")]
+use crate::context::find_cause;
+use crate::budget::BudgetLimit;
+use crate::soft_failure::SoftFailure;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Summary of a crawl or batch, serializable to JSON and printable with `Display`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScrapeReport {
+    /// URLs fetched at least once. Retries of a URL are not counted again.
+    pub attempted: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Failed URLs per reason, e.g. "timeout" or "connection", see `failure_reason`.
+    pub failures_by_reason: BTreeMap<String, usize>,
+    pub items: usize,
+    pub duration_secs: f64,
+    /// Bytes of page bodies downloaded; bodies served from the cache are not counted.
+    /// Only measured by crawls.
+    pub bytes: u64,
+    /// Pages or results served from the cache instead of the network.
+    pub cache_hits: u64,
+    /// Failed URLs whose response was a soft failure, with its classification.
+    #[serde(default)]
+    pub soft_failures: BTreeMap<String, SoftFailure>,
+    /// The budget limit that stopped a crawl early, see `RequestBudget`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stopped_by: Option<BudgetLimit>,
+}
+
+impl ScrapeReport {
+    /// Records a URL that failed with the given error.
+    #[cfg(feature = "native")]
+    pub(crate) fn record_failure(&mut self, error: &str) {
+        self.failed += 1;
+        *self.failures_by_reason.entry(failure_reason(error)).or_default() += 1;
+    }
+
+    /// Records the classification of a URL that failed as a soft failure.
+    #[cfg(feature = "native")]
+    pub(crate) fn record_soft_failure(&mut self, url: &str, error: &(dyn std::error::Error + 'static)) {
+        if let Some(failure) = find_cause::<SoftFailure>(error) {
+            self.soft_failures.insert(url.to_string(), failure.clone());
+        }
+    }
+
+    /// Share of attempted URLs that succeeded, between 0 and 1.
+    pub fn success_rate(&self) -> f64 {
+        if self.attempted == 0 {
+            return 0.0;
+        }
+        self.succeeded as f64 / self.attempted as f64
+    }
+}
+
+impl fmt::Display for ScrapeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "URLs attempted:  {}", self.attempted)?;
+        writeln!(f, "  succeeded:     {} ({:.1}%)", self.succeeded, self.success_rate() * 100.0)?;
+        writeln!(f, "  failed:        {}", self.failed)?;
+        for (reason, count) in &self.failures_by_reason {
+            writeln!(f, "    {}: {}", reason, count)?;
+        }
+        if !self.soft_failures.is_empty() {
+            writeln!(f, "Soft failures:")?;
+            for (url, failure) in &self.soft_failures {
+                writeln!(f, "    {}: {}", url, failure)?;
+            }
+        }
+        writeln!(f, "Items extracted: {}", self.items)?;
+        writeln!(f, "Duration:        {:.1}s", self.duration_secs)?;
+        writeln!(f, "Downloaded:      {}", format_bytes(self.bytes))?;
+        write!(f, "Cache hits:      {}", self.cache_hits)?;
+        if let Some(limit) = &self.stopped_by {
+            write!(f, "\nStopped early:   {}", limit)?;
+        }
+        Ok(())
+    }
+}
+
+/// Groups an error message into a reason: "soft failure", "HTTP <status>", "timeout", "connection",
+/// "circuit open", "invalid selector", "not found", "processing" or "other".
+pub fn failure_reason(error: &str) -> String {
+    static STATUS: OnceLock<Regex> = OnceLock::new();
+    let status = STATUS.get_or_init(|| Regex::new(r"(?i)\b(?:status|http)\D{0,3}([1-5]\d\d)\b").unwrap());
+
+    let lower = error.to_lowercase();
+    let reason = if lower.contains("soft failure") {
+        "soft failure"
+    } else if lower.contains("circuit open") {
+        "circuit open"
+    } else if lower.contains("timed out") || lower.contains("timeout") {
+        "timeout"
+    } else if lower.contains("dns") || lower.contains("connect") {
+        "connection"
+    } else if lower.contains("selector") {
+        "invalid selector"
+    } else if let Some(captures) = status.captures(error) {
+        return format!("HTTP {}", &captures[1]);
+    } else if lower.contains("not found") || lower.contains("no such file") {
+        "not found"
+    } else if lower.contains("failed to process") {
+        "processing"
+    } else {
+        "other"
+    };
+    reason.to_string()
+}
+
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_errors_by_reason() {
+        assert_eq!(failure_reason("Failed to fetch: Circuit open for a.com"), "circuit open");
+        assert_eq!(failure_reason("operation timed out"), "timeout");
+        assert_eq!(failure_reason("error trying to connect: dns error"), "connection");
+        assert_eq!(failure_reason("HTTP status 503 Service Unavailable"), "HTTP 503");
+        assert_eq!(failure_reason("No such file or directory"), "not found");
+        assert_eq!(failure_reason("Invalid selector: h1["), "invalid selector");
+        assert_eq!(failure_reason("something odd"), "other");
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn prints_a_summary() {
+        let mut report = ScrapeReport {
+            attempted: 4,
+            succeeded: 3,
+            items: 12,
+            duration_secs: 2.25,
+            bytes: 1536,
+            cache_hits: 1,
+            ..ScrapeReport::default()
+        };
+        report.record_failure("request timed out");
+        assert_eq!(report.success_rate(), 0.75);
+        assert_eq!(
+            report.to_string(),
+            "URLs attempted:  4\n  succeeded:     3 (75.0%)\n  failed:        1\n    timeout: 1\n\
+             Items extracted: 12\nDuration:        2.2s\nDownloaded:      1.5 KB\nCache hits:      1"
+        );
+        assert_eq!(ScrapeReport::default().success_rate(), 0.0);
+    }
+}
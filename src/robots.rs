@@ -0,0 +1,357 @@
+//! robots.txt rules and page-level robots directives.
+//!
+//! `Robots::parse` reads a robots.txt file and `Robots::check` answers whether a user agent may
+//! fetch a path, following RFC 9309: the group of the most specific matching user agent
+//! applies (else the `*` group), the longest matching rule wins, and `Allow` wins ties.
+//! Rules may use `*` wildcards and a trailing `$` anchor.
+//!
+//! `RobotsDirectives` are the indexing directives of one page, from `<meta name="robots">`
+//! tags and `X-Robots-Tag` headers; `is_nofollow` tells whether a link's `rel` excludes it.
+
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// The rules of one robots.txt file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Robots {
+    groups: Vec<Group>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Group {
+    /// Lowercase user agent product tokens; `*` for all.
+    agents: Vec<String>,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+/// The answer of `Robots::check`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RobotsVerdict {
+    pub allowed: bool,
+    /// The deciding rule, e.g. `Disallow: /private`; `None` if no rule matched.
+    pub rule: Option<String>,
+}
+
+impl Robots {
+    /// Rules allowing everything, for sites without a robots.txt.
+    pub fn allow_all() -> Self {
+        Robots::default()
+    }
+
+    /// Rules disallowing everything, for sites whose robots.txt cannot be fetched.
+    pub fn disallow_all() -> Self {
+        Robots {
+            groups: vec![Group {
+                agents: vec!["*".to_string()],
+                rules: vec![Rule {
+                    allow: false,
+                    pattern: "/".to_string(),
+                }],
+            }],
+        }
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut groups: Vec<Group> = Vec::new();
+        // Consecutive user-agent lines share the group that follows them.
+        let mut collecting_agents = false;
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    if !collecting_agents {
+                        groups.push(Group::default());
+                        collecting_agents = true;
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.agents.push(value.to_ascii_lowercase());
+                    }
+                }
+                directive @ ("allow" | "disallow") => {
+                    collecting_agents = false;
+                    // An empty `Disallow:` allows everything and adds no rule.
+                    if let (Some(group), false) = (groups.last_mut(), value.is_empty()) {
+                        group.rules.push(Rule {
+                            allow: directive == "allow",
+                            pattern: value.to_string(),
+                        });
+                    }
+                }
+                _ => collecting_agents = false,
+            }
+        }
+        Robots { groups }
+    }
+
+    /// Whether `user_agent` may fetch `path` (with its query, e.g. `/search?q=shoes`).
+    pub fn check(&self, user_agent: &str, path: &str) -> RobotsVerdict {
+        let path = if path.is_empty() { "/" } else { path };
+        let mut best: Option<&Rule> = None;
+        for rule in self.rules_for(user_agent) {
+            if !matches(&rule.pattern, path) {
+                continue;
+            }
+            let better = match best {
+                None => true,
+                Some(current) => {
+                    rule.pattern.len() > current.pattern.len() || (rule.pattern.len() == current.pattern.len() && rule.allow && !current.allow)
+                }
+            };
+            if better {
+                best = Some(rule);
+            }
+        }
+        RobotsVerdict {
+            allowed: best.is_none_or(|rule| rule.allow),
+            rule: best.map(|rule| format!("{}: {}", if rule.allow { "Allow" } else { "Disallow" }, rule.pattern)),
+        }
+    }
+
+    /// Whether `user_agent` may fetch `path`.
+    pub fn allows(&self, user_agent: &str, path: &str) -> bool {
+        self.check(user_agent, path).allowed
+    }
+
+    /// The rules of the groups naming the agent's product token, else of the `*` groups.
+    fn rules_for(&self, user_agent: &str) -> impl Iterator<Item = &Rule> {
+        let token = agent_token(user_agent);
+        let named = !token.is_empty() && self.groups.iter().any(|group| group.agents.contains(&token));
+        self.groups
+            .iter()
+            .filter(move |group| {
+                if named {
+                    group.agents.contains(&token)
+                } else {
+                    group.agents.iter().any(|agent| agent == "*")
+                }
+            })
+            .flat_map(|group| group.rules.iter())
+    }
+}
+
+/// Whether a rule pattern matches the path: a prefix match, with `*` matching any characters
+/// and a trailing `$` requiring the path to end there.
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (index, part) in parts.iter().enumerate() {
+        let last = index == parts.len() - 1;
+        if last && anchored {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+/// Indexing directives of a page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RobotsDirectives {
+    /// The page should not be indexed, i.e. its content not extracted.
+    pub noindex: bool,
+    /// The links of the page should not be followed.
+    pub nofollow: bool,
+    /// The page should not be cached or archived.
+    pub noarchive: bool,
+}
+
+impl RobotsDirectives {
+    /// Parses a comma-separated directive list such as `noindex, nofollow`. `none` means
+    /// `noindex, nofollow`; unknown directives are ignored.
+    pub fn parse(value: &str) -> Self {
+        let mut directives = RobotsDirectives::default();
+        for directive in value.split(',') {
+            match directive.trim().to_ascii_lowercase().as_str() {
+                "noindex" => directives.noindex = true,
+                "nofollow" => directives.nofollow = true,
+                "noarchive" => directives.noarchive = true,
+                "none" => {
+                    directives.noindex = true;
+                    directives.nofollow = true;
+                }
+                _ => {}
+            }
+        }
+        directives
+    }
+
+    /// The directives of an `X-Robots-Tag` header value that apply to `user_agent`. Values
+    /// may name the crawler they apply to, e.g. `otherbot: noindex`.
+    pub fn from_header(value: &str, user_agent: &str) -> Self {
+        let directives = match value.split_once(':') {
+            Some((agent, rest)) if !agent.contains(',') && !agent.trim().eq_ignore_ascii_case("unavailable_after") => {
+                if !agent.trim().eq_ignore_ascii_case(&agent_token(user_agent)) {
+                    return RobotsDirectives::default();
+                }
+                rest
+            }
+            _ => value,
+        };
+        RobotsDirectives::parse(directives)
+    }
+
+    /// The directives of a page's `<meta name="robots">` tags and of the meta tags named after
+    /// `user_agent`'s product token, e.g. `<meta name="mybot">`.
+    pub fn from_html(document: &Html, user_agent: &str) -> Self {
+        let token = agent_token(user_agent);
+        document
+            .select(meta_selector())
+            .filter(|meta| {
+                let name = meta.value().attr("name").unwrap_or_default().trim().to_ascii_lowercase();
+                name == "robots" || (!token.is_empty() && name == token)
+            })
+            .filter_map(|meta| meta.value().attr("content"))
+            .fold(RobotsDirectives::default(), |directives, content| directives.merge(RobotsDirectives::parse(content)))
+    }
+
+    /// The directives of both, as a page is restricted by each.
+    pub fn merge(self, other: RobotsDirectives) -> Self {
+        RobotsDirectives {
+            noindex: self.noindex || other.noindex,
+            nofollow: self.nofollow || other.nofollow,
+            noarchive: self.noarchive || other.noarchive,
+        }
+    }
+}
+
+/// Whether a link's `rel` attribute marks it `nofollow`.
+pub fn is_nofollow(rel: Option<&str>) -> bool {
+    rel.is_some_and(|rel| rel.split_ascii_whitespace().any(|value| value.eq_ignore_ascii_case("nofollow")))
+}
+
+/// The lowercase product token of a user agent, e.g. `mybot` of `MyBot/1.0`.
+fn agent_token(user_agent: &str) -> String {
+    user_agent.split(['/', ' ']).next().unwrap_or_default().to_ascii_lowercase()
+}
+
+fn meta_selector() -> &'static Selector {
+    static SELECTOR: OnceLock<Selector> = OnceLock::new();
+    SELECTOR.get_or_init(|| Selector::parse("meta[name][content]").unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = "
+User-agent: *
+Disallow: /private
+Allow: /private/open
+Disallow: /*.pdf$
+
+User-agent: MyBot
+User-agent: OtherBot
+Disallow: /
+Allow: /public # only this
+";
+
+    #[test]
+    fn applies_the_longest_matching_rule() {
+        let robots = Robots::parse(ROBOTS);
+        assert!(robots.allows("Mozilla/5.0", "/"));
+        assert_eq!(
+            robots.check("Mozilla/5.0", "/private/x"),
+            RobotsVerdict {
+                allowed: false,
+                rule: Some("Disallow: /private".to_string()),
+            }
+        );
+        assert!(robots.allows("Mozilla/5.0", "/private/open/x"));
+        assert!(!robots.allows("Mozilla/5.0", "/files/a.pdf"));
+        assert!(robots.allows("Mozilla/5.0", "/files/a.pdf?download=1"));
+    }
+
+    #[test]
+    fn uses_the_group_naming_the_agent() {
+        let robots = Robots::parse(ROBOTS);
+        assert!(!robots.allows("MyBot/1.0", "/"));
+        assert!(robots.allows("mybot", "/public/page"));
+        assert!(!robots.allows("OtherBot", "/private/open"));
+    }
+
+    #[test]
+    fn prefers_allow_on_ties_and_ignores_empty_disallows() {
+        let robots = Robots::parse("User-agent: *\nDisallow: /page\nAllow: /page\n");
+        assert!(robots.allows("*", "/page"));
+        assert!(Robots::parse("User-agent: *\nDisallow:\n").allows("*", "/anything"));
+        assert!(Robots::allow_all().allows("*", "/"));
+        assert!(!Robots::disallow_all().allows("*", ""));
+    }
+
+    #[test]
+    fn matches_wildcards_and_anchors() {
+        assert!(matches("/a*c", "/abbc/d"));
+        assert!(matches("/a*c$", "/abc"));
+        assert!(!matches("/a*c$", "/abcd"));
+        assert!(matches("/exact$", "/exact"));
+        assert!(!matches("/exact$", "/exact/"));
+        assert!(!matches("/b", "/a"));
+    }
+
+    #[test]
+    fn parses_directive_lists() {
+        let none = RobotsDirectives::parse("None");
+        assert!(none.noindex && none.nofollow && !none.noarchive);
+        assert_eq!(
+            RobotsDirectives::parse(" noarchive , max-snippet:0"),
+            RobotsDirectives {
+                noarchive: true,
+                ..RobotsDirectives::default()
+            }
+        );
+    }
+
+    #[test]
+    fn reads_header_directives_for_the_agent() {
+        assert!(RobotsDirectives::from_header("noindex, nofollow", "MyBot/1.0").nofollow);
+        assert!(RobotsDirectives::from_header("mybot: noindex", "MyBot/1.0").noindex);
+        assert!(!RobotsDirectives::from_header("otherbot: noindex", "MyBot/1.0").noindex);
+        assert!(RobotsDirectives::from_header("unavailable_after: 2030-01-01, noindex", "MyBot").noindex);
+    }
+
+    #[test]
+    fn reads_meta_directives_for_robots_and_the_agent() {
+        let document = Html::parse_document(
+            r#"<meta name="robots" content="noarchive"><meta name="MyBot" content="nofollow"><meta name="otherbot" content="noindex">"#,
+        );
+        let directives = RobotsDirectives::from_html(&document, "MyBot/1.0");
+        assert_eq!(
+            directives,
+            RobotsDirectives {
+                noindex: false,
+                nofollow: true,
+                noarchive: true,
+            }
+        );
+        assert!(!RobotsDirectives::from_html(&document, "").nofollow);
+    }
+
+    #[test]
+    fn recognizes_nofollow_links() {
+        assert!(is_nofollow(Some("noopener NoFollow")));
+        assert!(!is_nofollow(Some("nofollowed")));
+        assert!(!is_nofollow(None));
+    }
+}
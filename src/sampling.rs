@@ -0,0 +1,230 @@
+//! Sampling a page several times to detect A/B tests and personalization.
+//!
+//! Sites may serve different content to different visitors: A/B tests, personalized
+//! recommendations, prices varying by browser or location. A single fetch then shows only one
+//! of them. `RustScrapper::sample` fetches a page several times, optionally with a different
+//! fingerprint profile or proxy region per fetch, and a `SampleReport` tells how much the
+//! extracted content varies between the samples.
+
+use crate::fingerprint::FingerprintProfile;
+use serde::{Deserialize, Serialize};
+
+/// How a page is sampled.
+#[derive(Debug, Clone)]
+pub struct Sampling {
+    samples: usize,
+    fingerprints: Vec<FingerprintProfile>,
+    regions: Vec<String>,
+}
+
+impl Sampling {
+    /// Fetches the page `samples` times with the scrapper's own settings.
+    pub fn new(samples: usize) -> Self {
+        Sampling {
+            samples,
+            fingerprints: Vec::new(),
+            regions: Vec::new(),
+        }
+    }
+
+    /// Uses the profiles in turn, one per sample; the built-in profiles if `profiles` is empty.
+    pub fn rotate_fingerprints(mut self, profiles: Vec<FingerprintProfile>) -> Self {
+        self.fingerprints = if profiles.is_empty() { FingerprintProfile::all() } else { profiles };
+        self
+    }
+
+    /// Fetches through a proxy of each region in turn, one per sample, see `ProxyPool`.
+    /// Without regions, a scrapper with a proxy pool still rotates through all its proxies.
+    pub fn rotate_regions(mut self, regions: &[&str]) -> Self {
+        self.regions = regions.iter().map(|region| region.trim().to_ascii_uppercase()).collect();
+        self
+    }
+
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    /// The fingerprint profile of sample `index`, if fingerprints are rotated.
+    pub fn fingerprint(&self, index: usize) -> Option<&FingerprintProfile> {
+        (!self.fingerprints.is_empty()).then(|| &self.fingerprints[index % self.fingerprints.len()])
+    }
+
+    /// The region of sample `index`, if regions are rotated.
+    pub fn region(&self, index: usize) -> Option<&str> {
+        (!self.regions.is_empty()).then(|| self.regions[index % self.regions.len()].as_str())
+    }
+}
+
+/// The items extracted from one fetch of the page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sample {
+    pub index: usize,
+    /// Name of the fingerprint profile the sample was fetched with.
+    pub fingerprint: Option<String>,
+    /// Region of the proxy the sample was fetched through.
+    pub region: Option<String>,
+    pub items: Vec<String>,
+    /// Why the fetch or extraction failed; failed samples have no items and are left out of
+    /// the variants.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Sample {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Content seen by one or more samples.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentVariant {
+    pub items: Vec<String>,
+    /// Indices of the samples that saw it.
+    pub samples: Vec<usize>,
+}
+
+/// How the content of a page varies between samples.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SampleReport {
+    pub url: String,
+    pub selector: String,
+    pub samples: Vec<Sample>,
+    /// The distinct contents seen, the most frequent first.
+    pub variants: Vec<ContentVariant>,
+    /// Items every successful sample contains.
+    pub stable_items: Vec<String>,
+    /// Items only some samples contain, e.g. the texts of an A/B tested element.
+    pub varying_items: Vec<String>,
+    /// Number of failed samples.
+    pub failures: usize,
+}
+
+impl SampleReport {
+    pub fn new(url: &str, selector: &str, samples: Vec<Sample>) -> Self {
+        let succeeded: Vec<&Sample> = samples.iter().filter(|sample| sample.succeeded()).collect();
+        let mut variants: Vec<ContentVariant> = Vec::new();
+        for sample in &succeeded {
+            match variants.iter_mut().find(|variant| variant.items == sample.items) {
+                Some(variant) => variant.samples.push(sample.index),
+                None => variants.push(ContentVariant {
+                    items: sample.items.clone(),
+                    samples: vec![sample.index],
+                }),
+            }
+        }
+        // Stable, so equally frequent variants keep the order they were first seen in.
+        variants.sort_by_key(|variant| std::cmp::Reverse(variant.samples.len()));
+
+        let mut stable_items = Vec::new();
+        let mut varying_items = Vec::new();
+        for sample in &succeeded {
+            for item in &sample.items {
+                if stable_items.contains(item) || varying_items.contains(item) {
+                    continue;
+                }
+                if succeeded.iter().all(|other| other.items.contains(item)) {
+                    stable_items.push(item.clone());
+                } else {
+                    varying_items.push(item.clone());
+                }
+            }
+        }
+
+        let failures = samples.len() - succeeded.len();
+        SampleReport {
+            url: url.to_string(),
+            selector: selector.to_string(),
+            failures,
+            samples,
+            variants,
+            stable_items,
+            varying_items,
+        }
+    }
+
+    /// Whether every successful sample saw the same content.
+    pub fn is_consistent(&self) -> bool {
+        self.variants.len() <= 1
+    }
+
+    /// The share of successful samples that saw the most frequent content, from 0 to 1;
+    /// 1 if consistent.
+    pub fn consistency(&self) -> f64 {
+        let succeeded = self.samples.len() - self.failures;
+        match self.variants.first() {
+            Some(variant) => variant.samples.len() as f64 / succeeded as f64,
+            None => 1.0,
+        }
+    }
+
+    /// The most frequent content, the one to trust if a single result is needed.
+    pub fn majority(&self) -> Option<&[String]> {
+        self.variants.first().map(|variant| variant.items.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(index: usize, items: &[&str]) -> Sample {
+        Sample {
+            index,
+            fingerprint: None,
+            region: None,
+            items: items.iter().map(|item| item.to_string()).collect(),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn groups_samples_by_content() {
+        let report = SampleReport::new(
+            "https://shop.com/",
+            "h2",
+            vec![sample(0, &["Lamp", "Buy now"]), sample(1, &["Lamp", "Order today"]), sample(2, &["Lamp", "Order today"])],
+        );
+        assert!(!report.is_consistent());
+        assert_eq!(report.variants.len(), 2);
+        assert_eq!(report.variants[0].samples, vec![1, 2]);
+        assert_eq!(report.majority(), Some(&["Lamp".to_string(), "Order today".to_string()][..]));
+        assert!((report.consistency() - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(report.stable_items, vec!["Lamp"]);
+        assert_eq!(report.varying_items, vec!["Buy now", "Order today"]);
+    }
+
+    #[test]
+    fn reports_identical_samples_as_consistent() {
+        let report = SampleReport::new("https://shop.com/", "h2", vec![sample(0, &["Lamp"]), sample(1, &["Lamp"])]);
+        assert!(report.is_consistent());
+        assert_eq!(report.consistency(), 1.0);
+        assert!(report.varying_items.is_empty());
+        assert_eq!(SampleReport::new("https://shop.com/", "h2", Vec::new()).consistency(), 1.0);
+    }
+
+    #[test]
+    fn rotates_fingerprints_and_regions_per_sample() {
+        let sampling = Sampling::new(4).rotate_fingerprints(Vec::new()).rotate_regions(&["de", " us "]);
+        let profiles = FingerprintProfile::all();
+        assert_eq!(sampling.fingerprint(profiles.len()).unwrap().name, profiles[0].name);
+        assert_eq!(sampling.region(0), Some("DE"));
+        assert_eq!(sampling.region(3), Some("US"));
+        assert!(Sampling::new(2).fingerprint(0).is_none());
+        assert!(Sampling::new(2).region(0).is_none());
+    }
+
+    #[test]
+    fn leaves_failed_samples_out() {
+        let failed = Sample {
+            error: Some("proxy unreachable".to_string()),
+            ..sample(1, &[])
+        };
+        let samples = vec![sample(0, &["19 EUR"]), failed, sample(2, &["19 EUR"])];
+        let report = SampleReport::new("https://example.com/", ".price", samples);
+        assert!(report.is_consistent());
+        assert_eq!(report.failures, 1);
+        assert_eq!(report.consistency(), 1.0);
+        assert_eq!(report.stable_items, vec!["19 EUR"]);
+    }
+}
@@ -0,0 +1,314 @@
+//! Scheduled scraping jobs.
+//!
+//! A `Scheduler` runs named jobs at fixed intervals and persists their definitions and
+//! last-run times to a JSON file. After a restart it knows which runs were missed while the
+//! process was down and, with `with_catch_up`, runs them right away instead of silently
+//! waiting for the next interval.
+
+use crate::cache::CacheBackend;
+use crate::storage::{Namespace, Storage};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A job definition with the time of its last run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledJob {
+    pub name: String,
+    pub urls: Vec<String>,
+    pub interval_secs: u64,
+    /// Unix timestamp in milliseconds of the last run, `None` if the job never ran.
+    pub last_run: Option<i64>,
+}
+
+impl ScheduledJob {
+    fn interval_millis(&self) -> i64 {
+        (self.interval_secs as i64).saturating_mul(1000).max(1)
+    }
+
+    /// Unix timestamp in milliseconds when the job is next due. A job that never ran is due
+    /// immediately.
+    pub fn next_run(&self) -> i64 {
+        self.last_run.map_or(i64::MIN, |last| last.saturating_add(self.interval_millis()))
+    }
+
+    /// Number of scheduled runs between the last run and `until` (Unix milliseconds).
+    pub fn runs_due_by(&self, until: i64) -> u64 {
+        match self.last_run {
+            Some(last) if until > last => ((until - last) / self.interval_millis()) as u64,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SchedulerState {
+    jobs: Vec<ScheduledJob>,
+}
+
+/// Where a scheduler persists its jobs.
+enum SchedulerStore {
+    File(String),
+    Storage(Namespace),
+}
+
+/// Key of the scheduler state in its storage namespace.
+const STATE_KEY: &str = "state";
+
+/// Runs jobs at fixed intervals, persisting them with `Scheduler::open` or `with_storage`.
+pub struct Scheduler {
+    store: Option<SchedulerStore>,
+    jobs: Vec<ScheduledJob>,
+    catch_up: bool,
+    started_at: i64,
+}
+
+impl Scheduler {
+    /// Creates a scheduler keeping its jobs in memory only.
+    pub fn new() -> Self {
+        Scheduler {
+            store: None,
+            jobs: Vec::new(),
+            catch_up: false,
+            started_at: chrono::Utc::now().timestamp_millis(),
+        }
+    }
+
+    /// Opens a scheduler persisting its jobs and their last-run times in the file at `path`,
+    /// loading the jobs saved by an earlier run.
+    pub fn open(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let state: SchedulerState = match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => SchedulerState::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Scheduler {
+            store: Some(SchedulerStore::File(path.to_string())),
+            jobs: state.jobs,
+            ..Scheduler::new()
+        })
+    }
+
+    /// Opens a scheduler persisting its jobs under `scheduler/` in a storage shared with other
+    /// persistent state, see `Storage`, loading the jobs saved by an earlier run.
+    pub fn with_storage(storage: Arc<dyn Storage>) -> Result<Self, Box<dyn std::error::Error>> {
+        let store = Namespace::new(storage, "scheduler");
+        let state: SchedulerState = match store.get(STATE_KEY) {
+            Some(contents) => serde_json::from_str(&contents)?,
+            None => SchedulerState::default(),
+        };
+        Ok(Scheduler {
+            store: Some(SchedulerStore::Storage(store)),
+            jobs: state.jobs,
+            ..Scheduler::new()
+        })
+    }
+
+    /// Runs jobs that missed runs while the process was down once, as soon as the scheduler
+    /// runs pending jobs. Without catch-up, missed runs are logged and the jobs wait for
+    /// their next scheduled time.
+    pub fn with_catch_up(mut self, catch_up: bool) -> Self {
+        self.catch_up = catch_up;
+        self
+    }
+
+    /// Adds a job scraping `urls` every `interval`. A job with the same name is redefined
+    /// and keeps its last-run time.
+    pub fn add_job(&mut self, name: &str, urls: &[&str], interval: Duration) -> Result<(), Box<dyn std::error::Error>> {
+        let urls = urls.iter().map(|url| url.to_string()).collect();
+        let interval_secs = interval.as_secs().max(1);
+        match self.jobs.iter_mut().find(|job| job.name == name) {
+            Some(job) => {
+                job.urls = urls;
+                job.interval_secs = interval_secs;
+            }
+            None => self.jobs.push(ScheduledJob {
+                name: name.to_string(),
+                urls,
+                interval_secs,
+                last_run: None,
+            }),
+        }
+        self.save()
+    }
+
+    /// Removes a job, returning whether it existed.
+    pub fn remove_job(&mut self, name: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let before = self.jobs.len();
+        self.jobs.retain(|job| job.name != name);
+        self.save()?;
+        Ok(self.jobs.len() < before)
+    }
+
+    pub fn jobs(&self) -> &[ScheduledJob] {
+        &self.jobs
+    }
+
+    /// Jobs with runs missed before this scheduler was created, with the number of missed runs.
+    pub fn missed_runs(&self) -> Vec<(String, u64)> {
+        self.jobs
+            .iter()
+            .map(|job| (job.name.clone(), job.runs_due_by(self.started_at)))
+            .filter(|(_, missed)| *missed > 0)
+            .collect()
+    }
+
+    /// Runs every due job with `run` and saves its last-run time. A failed run is logged and
+    /// retried at the next interval. Returns the names of the jobs that ran.
+    pub fn run_pending<F>(&mut self, mut run: F) -> Result<Vec<String>, Box<dyn std::error::Error>>
+    where
+        F: FnMut(&ScheduledJob) -> Result<(), Box<dyn std::error::Error>>,
+    {
+        let mut ran = Vec::new();
+        for i in 0..self.jobs.len() {
+            let missed = self.jobs[i].runs_due_by(self.started_at);
+            if missed > 0 {
+                let job = &mut self.jobs[i];
+                if self.catch_up {
+                    info!("Catching up job {} after {} missed runs", job.name, missed);
+                } else {
+                    warn!("Job {} missed {} runs while the scheduler was not running", job.name, missed);
+                    // Skip to the last missed slot, keeping the job's schedule.
+                    job.last_run = job.last_run.map(|last| last + missed as i64 * job.interval_millis());
+                    self.save()?;
+                }
+            }
+
+            let now = chrono::Utc::now().timestamp_millis();
+            if self.jobs[i].next_run() > now {
+                continue;
+            }
+            let job = self.jobs[i].clone();
+            info!("Running scheduled job {}", job.name);
+            if let Err(e) = run(&job) {
+                error!("Scheduled job {} failed: {}", job.name, e);
+            }
+            self.jobs[i].last_run = Some(now);
+            self.save()?;
+            ran.push(job.name);
+        }
+        Ok(ran)
+    }
+
+    /// Runs due jobs until the process exits, sleeping until the next job is due.
+    pub fn run_forever<F>(&mut self, mut run: F) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: FnMut(&ScheduledJob) -> Result<(), Box<dyn std::error::Error>>,
+    {
+        loop {
+            self.run_pending(&mut run)?;
+            let now = chrono::Utc::now().timestamp_millis();
+            let next = self.jobs.iter().map(ScheduledJob::next_run).min().unwrap_or(now + 60_000);
+            // Wake up at least every minute to notice jobs added by another handle.
+            std::thread::sleep(Duration::from_millis((next - now).clamp(100, 60_000) as u64));
+        }
+    }
+
+    /// Writes the jobs to a temporary file and renames it over the previous state, or stores
+    /// them in the storage.
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let state = serde_json::to_string_pretty(&SchedulerState { jobs: self.jobs.clone() })?;
+        match &self.store {
+            Some(SchedulerStore::File(path)) => {
+                let temporary = format!("{}.tmp", path);
+                let mut file = File::create(&temporary)?;
+                file.write_all(state.as_bytes())?;
+                file.sync_all()?;
+                std::fs::rename(&temporary, path)?;
+            }
+            Some(SchedulerStore::Storage(store)) => store.set(STATE_KEY, state, None),
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_file(name: &str, last_run: i64) -> String {
+        let path = std::env::temp_dir().join(format!("rust-scrapper-schedule-{}-{}.json", name, std::process::id()));
+        let state = SchedulerState {
+            jobs: vec![ScheduledJob {
+                name: "prices".to_string(),
+                urls: vec!["https://shop.com/".to_string()],
+                interval_secs: 3600,
+                last_run: Some(last_run),
+            }],
+        };
+        std::fs::write(&path, serde_json::to_string(&state).unwrap()).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn counts_runs_due_since_the_last_run() {
+        let job = ScheduledJob {
+            name: "prices".to_string(),
+            urls: Vec::new(),
+            interval_secs: 10,
+            last_run: Some(1_000),
+        };
+        assert_eq!(job.next_run(), 11_000);
+        assert_eq!(job.runs_due_by(35_000), 3);
+        assert_eq!(job.runs_due_by(500), 0);
+        assert_eq!(ScheduledJob { last_run: None, ..job }.runs_due_by(35_000), 0);
+    }
+
+    #[test]
+    fn runs_new_jobs_and_persists_their_last_run() {
+        let path = std::env::temp_dir().join(format!("rust-scrapper-schedule-new-{}.json", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let mut scheduler = Scheduler::open(&path).unwrap();
+        scheduler.add_job("prices", &["https://shop.com/"], Duration::from_secs(3600)).unwrap();
+        let mut runs = 0;
+        assert_eq!(scheduler.run_pending(|_| { runs += 1; Ok(()) }).unwrap(), vec!["prices"]);
+        assert!(scheduler.run_pending(|_| { runs += 1; Ok(()) }).unwrap().is_empty());
+        assert_eq!(runs, 1);
+
+        let mut reopened = Scheduler::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(reopened.jobs()[0].last_run.is_some());
+        assert!(reopened.remove_job("prices").unwrap());
+        assert!(reopened.jobs().is_empty());
+    }
+
+    #[test]
+    fn catches_up_on_missed_runs_only_when_asked() {
+        let last_run = chrono::Utc::now().timestamp_millis() - 3_600_000 * 7 / 2;
+
+        let path = state_file("skip", last_run);
+        let mut scheduler = Scheduler::open(&path).unwrap();
+        assert_eq!(scheduler.missed_runs(), vec![("prices".to_string(), 3)]);
+        assert!(scheduler.run_pending(|_| Ok(())).unwrap().is_empty());
+        assert_eq!(scheduler.jobs()[0].last_run, Some(last_run + 3 * 3_600_000));
+        std::fs::remove_file(&path).unwrap();
+
+        let path = state_file("catch-up", last_run);
+        let mut scheduler = Scheduler::open(&path).unwrap().with_catch_up(true);
+        assert_eq!(scheduler.run_pending(|_| Ok(())).unwrap(), vec!["prices"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn persists_jobs_in_a_shared_storage() {
+        let storage: Arc<dyn Storage> = Arc::new(crate::cache::MemoryCache::new());
+        let mut scheduler = Scheduler::with_storage(storage.clone()).unwrap();
+        scheduler.add_job("prices", &["https://shop.com/"], Duration::from_secs(3600)).unwrap();
+        scheduler.run_pending(|_| Ok(())).unwrap();
+
+        let reopened = Scheduler::with_storage(storage.clone()).unwrap();
+        assert_eq!(reopened.jobs()[0].name, "prices");
+        assert!(reopened.jobs()[0].last_run.is_some());
+        assert_eq!(storage.keys(), vec!["scheduler/state"]);
+    }
+}
@@ -0,0 +1,576 @@
+//! Structured extraction schemas.
+//!
+//! A `Schema` describes the records on a page: an optional `root` selector matching one
+//! element per record, and a list of named fields selected relative to it. Extraction
+//! produces one JSON object per record. Fields can carry validation `Rule`s; records
+//! breaking them are quarantined by `Schema::validate` instead of being exported.
+
+use crate::coerce::FieldType;
+use regex::Regex;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// A named value extracted from each record.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Field {
+    pub name: String,
+    pub selector: String,
+    /// Selectors tried in order when `selector` matches nothing.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallbacks: Vec<String>,
+    /// Attribute to read instead of the element's text, e.g. `href`.
+    #[serde(default)]
+    pub attr: Option<String>,
+    /// Collects all matches into an array instead of taking the first one.
+    #[serde(default)]
+    pub multiple: bool,
+    /// Selector that must match inside the record for the field to be extracted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,
+    /// Value used when the field is not extracted or matches nothing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+    /// Schema extracted from the page this field links to. Its fields are merged into the record.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub follow: Option<Box<Schema>>,
+    /// Type the text is converted to, plain text by default.
+    #[serde(default, rename = "type", skip_serializing_if = "is_string")]
+    pub kind: FieldType,
+    /// Rules every record's value must satisfy, checked by `Schema::validate`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<Rule>,
+}
+
+/// A validation rule for a field value. Rules on a `multiple` field apply to each element.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum Rule {
+    /// The field matched at least one element. A `multiple` field with no elements and a
+    /// value equal to the field's default count as missing.
+    Required,
+    /// The value is present and not blank.
+    NonEmpty,
+    /// The value matches a regular expression.
+    Pattern { pattern: String },
+    /// The value is a number within the bounds, read like `coerce::parse_number` reads it, so
+    /// `1.299,90` and `1,299.90` are both 1299.9.
+    Range {
+        #[serde(default)]
+        min: Option<f64>,
+        #[serde(default)]
+        max: Option<f64>,
+    },
+}
+
+impl Field {
+    /// Creates a field reading the trimmed text of the first match.
+    pub fn new(name: &str, selector: &str) -> Self {
+        Field {
+            name: name.to_string(),
+            selector: selector.to_string(),
+            fallbacks: Vec::new(),
+            attr: None,
+            multiple: false,
+            when: None,
+            default: None,
+            follow: None,
+            kind: FieldType::String,
+            rules: Vec::new(),
+        }
+    }
+
+    /// Adds a selector tried when the previous ones match nothing, e.g. for A/B tested markup.
+    pub fn fallback(mut self, selector: &str) -> Self {
+        self.fallbacks.push(selector.to_string());
+        self
+    }
+
+    /// The primary selector followed by the fallbacks, in the order they are tried.
+    pub fn selectors(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.selector.as_str()).chain(self.fallbacks.iter().map(String::as_str))
+    }
+
+    /// Reads the given attribute instead of the text.
+    pub fn attr(mut self, attr: &str) -> Self {
+        self.attr = Some(attr.to_string());
+        self
+    }
+
+    /// Collects all matches into an array.
+    pub fn multiple(mut self) -> Self {
+        self.multiple = true;
+        self
+    }
+
+    /// Extracts the field only from records containing an element matching `selector`,
+    /// e.g. a sale price only when `.on-sale` is present.
+    pub fn when(mut self, selector: &str) -> Self {
+        self.when = Some(selector.to_string());
+        self
+    }
+
+    /// Uses `value` instead of `null` (or an empty array) when the field is not extracted.
+    pub fn default_value(mut self, value: impl Into<Value>) -> Self {
+        self.default = Some(value.into());
+        self
+    }
+
+    /// Follows the link this field selects (its `href` unless another attribute is set) and
+    /// merges the fields `schema` extracts from the linked page into the record, taking the
+    /// first record if the schema has a root. The field itself holds the absolute URL.
+    /// Only `RustScrapper::scrape_structured` and its variants fetch linked pages.
+    pub fn follow(mut self, schema: Schema) -> Self {
+        self.attr.get_or_insert_with(|| "href".to_string());
+        self.follow = Some(Box::new(schema));
+        self
+    }
+
+    /// Converts the text to the given type. Values that do not parse become `null`.
+    pub fn typed(mut self, kind: FieldType) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Requires the field to match.
+    pub fn required(mut self) -> Self {
+        self.rules.push(Rule::Required);
+        self
+    }
+
+    /// Requires a non-blank value.
+    pub fn non_empty(mut self) -> Self {
+        self.rules.push(Rule::NonEmpty);
+        self
+    }
+
+    /// Requires the value to match a regular expression.
+    pub fn pattern(mut self, pattern: &str) -> Self {
+        self.rules.push(Rule::Pattern {
+            pattern: pattern.to_string(),
+        });
+        self
+    }
+
+    /// Requires a number between `min` and `max`, inclusive.
+    pub fn range(mut self, min: Option<f64>, max: Option<f64>) -> Self {
+        self.rules.push(Rule::Range { min, max });
+        self
+    }
+}
+
+/// A rule a record's field failed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationError {
+    pub field: String,
+    pub message: String,
+}
+
+/// A record that failed validation, with every rule it broke.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvalidRecord {
+    pub record: Value,
+    pub errors: Vec<ValidationError>,
+}
+
+/// Extracted records together with the selector that matched each field.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Extraction {
+    pub records: Vec<Value>,
+    /// For each record, the selector that matched each field. Fields that matched nothing are absent.
+    pub matched: Vec<HashMap<String, String>>,
+}
+
+impl Extraction {
+    /// How many records each selector of `field` matched, to notice when fallbacks take over.
+    pub fn selector_counts(&self, field: &str) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for selector in self.matched.iter().filter_map(|matched| matched.get(field)) {
+            *counts.entry(selector.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+}
+
+/// Records split by validation. Quarantined records are kept for inspection rather than exported.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ValidatedRecords {
+    pub valid: Vec<Value>,
+    pub quarantined: Vec<InvalidRecord>,
+}
+
+/// Description of the records to extract from a page.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Schema {
+    /// Selector matching one element per record. Without it, the whole page is one record.
+    #[serde(default)]
+    pub root: Option<String>,
+    pub fields: Vec<Field>,
+}
+
+impl Schema {
+    /// Creates an empty schema treating the whole page as one record.
+    pub fn new() -> Self {
+        Schema::default()
+    }
+
+    /// Sets the selector matching one element per record.
+    pub fn root(mut self, selector: &str) -> Self {
+        self.root = Some(selector.to_string());
+        self
+    }
+
+    /// Adds a field.
+    pub fn field(mut self, field: Field) -> Self {
+        self.fields.push(field);
+        self
+    }
+
+    /// Extracts all records from an HTML document.
+    pub fn extract(&self, html: &str) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+        Ok(self.extract_detailed(html)?.records)
+    }
+
+    /// Extracts all records and reports which of each field's selectors matched.
+    pub fn extract_detailed(&self, html: &str) -> Result<Extraction, Box<dyn std::error::Error>> {
+        self.extract_from(&Html::parse_document(html))
+    }
+
+    /// Extracts all records from a parsed document.
+    pub(crate) fn extract_from(&self, document: &Html) -> Result<Extraction, Box<dyn std::error::Error>> {
+        let fields = self
+            .fields
+            .iter()
+            .map(|field| {
+                let selectors = field
+                    .selectors()
+                    .map(|selector| Ok((selector, parse_selector(selector)?)))
+                    .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+                let when = field.when.as_deref().map(parse_selector).transpose()?;
+                Ok(CompiledField { field, selectors, when })
+            })
+            .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+
+        let roots = match &self.root {
+            Some(root) => document.select(&parse_selector(root)?).collect::<Vec<_>>(),
+            None => vec![document.root_element()],
+        };
+        let mut extraction = Extraction::default();
+        for root in roots {
+            let (record, matched) = extract_record(root, &fields);
+            extraction.records.push(record);
+            extraction.matched.push(matched);
+        }
+        Ok(extraction)
+    }
+
+    /// Checks records against the fields' rules. Fails only if a rule's pattern is invalid.
+    pub fn validate(&self, records: Vec<Value>) -> Result<ValidatedRecords, Box<dyn std::error::Error>> {
+        let mut patterns = Vec::new();
+        for rule in self.fields.iter().flat_map(|field| &field.rules) {
+            if let Rule::Pattern { pattern } = rule {
+                patterns.push((pattern.as_str(), Regex::new(pattern)?));
+            }
+        }
+        let patterns = &patterns;
+        let mut validated = ValidatedRecords::default();
+        for record in records {
+            let errors: Vec<_> = self
+                .fields
+                .iter()
+                .flat_map(|field| {
+                    let value = record.get(&field.name).unwrap_or(&Value::Null);
+                    field.rules.iter().filter_map(move |rule| {
+                        check_rule(rule, field, value, patterns).map(|message| ValidationError {
+                            field: field.name.clone(),
+                            message,
+                        })
+                    })
+                })
+                .collect();
+            if errors.is_empty() {
+                validated.valid.push(record);
+            } else {
+                validated.quarantined.push(InvalidRecord { record, errors });
+            }
+        }
+        Ok(validated)
+    }
+
+    /// Extracts all records from an HTML document and validates them.
+    pub fn extract_validated(&self, html: &str) -> Result<ValidatedRecords, Box<dyn std::error::Error>> {
+        self.validate(self.extract(html)?)
+    }
+}
+
+/// Text of a scalar value, or of a money value's amount.
+fn value_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(value) => Some(value.to_string()),
+        Value::Object(object) => object.get("amount").and_then(value_text),
+        _ => None,
+    }
+}
+
+fn is_string(kind: &FieldType) -> bool {
+    *kind == FieldType::String
+}
+
+/// Returns why the `value` of `field` breaks `rule`, if it does.
+fn check_rule(rule: &Rule, field: &Field, value: &Value, patterns: &[(&str, Regex)]) -> Option<String> {
+    let values: Vec<String> = match value {
+        Value::Array(values) => values.iter().filter_map(value_text).collect(),
+        value => value_text(value).into_iter().collect(),
+    };
+    match rule {
+        Rule::Required if value.is_null() || (value == &missing_value(field) && (field.multiple || field.default.is_some())) => {
+            Some("is missing".to_string())
+        }
+        Rule::NonEmpty if values.iter().all(|value| value.trim().is_empty()) => Some("is empty".to_string()),
+        Rule::Pattern { pattern } => {
+            let regex = &patterns.iter().find(|(p, _)| p == pattern)?.1;
+            let bad = values.iter().find(|value| !regex.is_match(value))?;
+            Some(format!("{:?} does not match {:?}", bad, pattern))
+        }
+        Rule::Range { min, max } => values.iter().find_map(|value| {
+            let Some(number) = crate::coerce::parse_number(value, None) else {
+                return Some(format!("{:?} is not a number", value));
+            };
+            if min.is_some_and(|min| number < min) || max.is_some_and(|max| number > max) {
+                Some(format!("{} is outside {:?}..={:?}", number, min, max))
+            } else {
+                None
+            }
+        }),
+        _ => None,
+    }
+}
+
+/// A field with its selectors parsed.
+struct CompiledField<'a> {
+    field: &'a Field,
+    selectors: Vec<(&'a str, Selector)>,
+    when: Option<Selector>,
+}
+
+fn extract_record(root: ElementRef, fields: &[CompiledField]) -> (Value, HashMap<String, String>) {
+    let mut record = Map::new();
+    let mut matched = HashMap::new();
+    for CompiledField { field, selectors, when } in fields {
+        let applies = when.as_ref().is_none_or(|when| root.select(when).next().is_some());
+        let winner = selectors
+            .iter()
+            .filter(|_| applies)
+            .find(|(_, selector)| root.select(selector).any(|elem| field_value(elem, field).is_some()));
+        let Some((source, selector)) = winner else {
+            record.insert(field.name.clone(), missing_value(field));
+            continue;
+        };
+        matched.insert(field.name.clone(), source.to_string());
+        let mut values = root
+            .select(selector)
+            .filter_map(|elem| field_value(elem, field))
+            .map(|text| field.kind.coerce(&text));
+        let value = if field.multiple {
+            Value::Array(values.filter(|value| !value.is_null()).collect())
+        } else {
+            values.next().unwrap_or(Value::Null)
+        };
+        let empty = value.is_null() || value.as_array().is_some_and(Vec::is_empty);
+        record.insert(field.name.clone(), if empty { missing_value(field) } else { value });
+    }
+    (Value::Object(record), matched)
+}
+
+/// Merges the first record extracted from a followed page into `record`. Without a linked
+/// record, the followed schema's fields get their missing value.
+#[cfg(feature = "native")]
+pub(crate) fn merge_followed(record: &mut Value, schema: &Schema, linked: Option<&Value>) {
+    let Value::Object(record) = record else {
+        return;
+    };
+    for field in &schema.fields {
+        let value = linked
+            .and_then(|linked| linked.get(&field.name))
+            .cloned()
+            .unwrap_or_else(|| missing_value(field));
+        record.insert(field.name.clone(), value);
+    }
+}
+
+/// The value of a field that was not extracted: its default, or an empty value.
+fn missing_value(field: &Field) -> Value {
+    match &field.default {
+        Some(default) => default.clone(),
+        None if field.multiple => Value::Array(Vec::new()),
+        None => Value::Null,
+    }
+}
+
+fn field_value(elem: ElementRef, field: &Field) -> Option<String> {
+    match &field.attr {
+        Some(attr) => elem.value().attr(attr).map(|value| value.trim().to_string()),
+        None => Some(elem.text().collect::<String>().trim().to_string()),
+    }
+}
+
+pub(crate) fn parse_selector(selector: &str) -> Result<Selector, Box<dyn std::error::Error>> {
+    Ok(Selector::parse(selector).map_err(|e| format!("Selector parse error: {:?}", e))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn extracts_one_record_per_root() {
+        let schema = Schema::new()
+            .root(".product")
+            .field(Field::new("title", "h2"))
+            .field(Field::new("link", "a").attr("href"))
+            .field(Field::new("tags", ".tag").multiple())
+            .field(Field::new("price", ".price"));
+        let html = r#"
+            <div class="product"><h2> Lamp </h2><a href="/lamp">x</a><i class="tag">new</i><i class="tag">sale</i></div>
+            <div class="product"><h2>Desk</h2><span class="price">10</span></div>"#;
+        assert_eq!(
+            schema.extract(html).unwrap(),
+            vec![
+                json!({"title": "Lamp", "link": "/lamp", "tags": ["new", "sale"], "price": null}),
+                json!({"title": "Desk", "link": null, "tags": [], "price": "10"}),
+            ]
+        );
+    }
+
+    #[test]
+    fn treats_the_page_as_one_record_without_a_root() {
+        let schema = Schema::new().field(Field::new("title", "title"));
+        assert_eq!(schema.extract("<title>Home</title>").unwrap(), vec![json!({"title": "Home"})]);
+        assert!(Schema::new().field(Field::new("bad", "h1[")).extract("").is_err());
+    }
+
+    #[test]
+    fn quarantines_records_breaking_rules() {
+        let schema = Schema::new()
+            .root(".product")
+            .field(Field::new("title", "h2").required().non_empty())
+            .field(Field::new("sku", ".sku").pattern("^[A-Z]{2}-\\d+$"))
+            .field(Field::new("price", ".price").range(Some(0.0), Some(1000.0)));
+        let html = r#"
+            <div class="product"><h2>Lamp</h2><i class="sku">LA-1</i><b class="price">1,000</b></div>
+            <div class="product"><h2> </h2><i class="sku">la1</i><b class="price">free</b></div>
+            <div class="product"><i class="sku">DE-2</i><b class="price">2000</b></div>"#;
+        let validated = schema.extract_validated(html).unwrap();
+        assert_eq!(validated.valid.len(), 1);
+        assert_eq!(validated.valid[0]["title"], "Lamp");
+
+        let errors = |index: usize| {
+            validated.quarantined[index]
+                .errors
+                .iter()
+                .map(|error| format!("{} {}", error.field, error.message))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            errors(0),
+            vec!["title is empty", "sku \"la1\" does not match \"^[A-Z]{2}-\\\\d+$\"", "price \"free\" is not a number"]
+        );
+        assert_eq!(
+            errors(1),
+            vec!["title is missing", "title is empty", "price 2000 is outside Some(0.0)..=Some(1000.0)"]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        let schema = Schema::new().field(Field::new("title", "h1").pattern("("));
+        assert!(schema.validate(vec![json!({"title": "x"})]).is_err());
+    }
+
+    #[test]
+    fn coerces_typed_fields_before_validating_them() {
+        let schema = Schema::new()
+            .root("li")
+            .field(Field::new("price", "b").typed(FieldType::Money).range(Some(1.0), None))
+            .field(Field::new("stars", "i").typed(FieldType::Integer).multiple());
+        let html = "<li><b>€12,50</b><i>4</i><i>n/a</i></li><li><b>€0,50</b></li>";
+        let validated = schema.extract_validated(html).unwrap();
+        assert_eq!(
+            validated.valid,
+            vec![json!({"price": {"amount": 12.5, "currency": "EUR"}, "stars": [4]})]
+        );
+        assert_eq!(validated.quarantined[0].errors[0].message, "0.5 is outside Some(1.0)..=None");
+    }
+
+    #[test]
+    fn falls_back_to_later_selectors() {
+        let schema = Schema::new()
+            .root(".product")
+            .field(Field::new("price", ".price-new").fallback(".price").fallback("b"));
+        let html = r#"
+            <div class="product"><span class="price-new">5</span><span class="price">6</span></div>
+            <div class="product"><span class="price">7</span></div>
+            <div class="product"><b>8</b></div>
+            <div class="product"></div>"#;
+        let extraction = schema.extract_detailed(html).unwrap();
+        let prices: Vec<_> = extraction.records.iter().map(|record| record["price"].clone()).collect();
+        assert_eq!(prices, vec![json!("5"), json!("7"), json!("8"), Value::Null]);
+
+        let counts = extraction.selector_counts("price");
+        assert_eq!(counts.get(".price-new"), Some(&1));
+        assert_eq!(counts.get(".price"), Some(&1));
+        assert_eq!(counts.get("b"), Some(&1));
+        assert!(extraction.matched[3].is_empty());
+        assert!(Schema::new().field(Field::new("x", "h1").fallback("h1[")).extract("").is_err());
+    }
+
+    #[test]
+    fn extracts_conditional_fields_with_defaults() {
+        let schema = Schema::new()
+            .root(".product")
+            .field(Field::new("sale_price", ".price").when(".on-sale"))
+            .field(Field::new("stock", ".stock").default_value("unknown"))
+            .field(Field::new("tags", ".tag").multiple().default_value(json!(["none"])));
+        let html = r#"
+            <div class="product"><i class="on-sale"></i><b class="price">5</b><span class="stock">3</span></div>
+            <div class="product"><b class="price">9</b><i class="tag">new</i></div>"#;
+        assert_eq!(
+            schema.extract(html).unwrap(),
+            vec![
+                json!({"sale_price": "5", "stock": "3", "tags": ["none"]}),
+                json!({"sale_price": null, "stock": "unknown", "tags": ["new"]}),
+            ]
+        );
+    }
+
+    fn errors(schema: &Schema, html: &str) -> Vec<String> {
+        let validated = schema.extract_validated(html).unwrap();
+        validated
+            .quarantined
+            .iter()
+            .flat_map(|record| record.errors.iter().map(|error| format!("{} {}", error.field, error.message)))
+            .collect()
+    }
+
+    #[test]
+    fn checks_ranges_of_localized_numbers() {
+        let schema = Schema::new().field(Field::new("price", ".price").range(Some(1000.0), Some(2000.0)));
+        assert!(errors(&schema, r#"<span class="price">1.299,90 TL</span>"#).is_empty());
+        assert!(errors(&schema, r#"<span class="price">$1,299.90</span>"#).is_empty());
+        assert_eq!(errors(&schema, r#"<span class="price">12,99</span>"#), vec!["price 12.99 is outside Some(1000.0)..=Some(2000.0)"]);
+        assert_eq!(errors(&schema, r#"<span class="price">call us</span>"#), vec!["price \"call us\" is not a number"]);
+    }
+
+    #[test]
+    fn requires_multiple_and_defaulted_fields_to_match() {
+        let schema = Schema::new()
+            .field(Field::new("tags", ".tag").multiple().required())
+            .field(Field::new("stock", ".stock").default_value("unknown").required());
+        assert_eq!(errors(&schema, "<p>nothing</p>"), vec!["tags is missing", "stock is missing"]);
+        assert!(errors(&schema, r#"<b class="tag">new</b><i class="stock">3</i>"#).is_empty());
+    }
+}
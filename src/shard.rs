@@ -0,0 +1,479 @@
+//! Exports split into one file per key.
+//!
+//! Downstream consumers often want a file per site or per category instead of one export of
+//! the whole crawl. A `ShardedExport` assigns every record a key, e.g. a field's value or the
+//! host of a URL field, and writes the records of each key to a file named by a path template
+//! such as `exports/products-{key}.csv`. Keys are sanitized into safe file names first, so a
+//! record cannot write outside the export directory.
+
+use crate::export::ExportWriter;
+#[cfg(feature = "native")]
+use crate::pipeline::RecordSink;
+use crate::Exporter;
+use serde_json::Value;
+use std::collections::BTreeMap;
+#[cfg(feature = "native")]
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "native")]
+use std::io::BufRead;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use url::Url;
+
+/// Key of records without a value for the shard key.
+pub const UNKNOWN_SHARD: &str = "unknown";
+
+/// File names are cut to this many bytes, leaving room for the template around the key.
+const MAX_KEY_BYTES: usize = 100;
+
+/// Names Windows reserves for devices, in any case and with any extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2",
+    "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+type ShardKeyFn = Arc<dyn Fn(&Value) -> Option<String> + Send + Sync>;
+
+/// Turns a shard key into a safe file name: letters, digits, `-`, `_` and `.` are kept, other
+/// characters such as path separators become `_`. Leading dots are dropped, names reserved by
+/// Windows get a `_` prefix and long keys are cut. Keys that sanitize to the same name share
+/// a file.
+pub fn sanitize_file_name(key: &str) -> String {
+    let mut name: String = key
+        .trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+        .collect();
+    name = name.trim_start_matches('.').to_string();
+    if name.len() > MAX_KEY_BYTES {
+        let mut end = MAX_KEY_BYTES;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name.truncate(end);
+    }
+    let stem = name.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES.iter().any(|reserved| stem.eq_ignore_ascii_case(reserved)) {
+        name.insert(0, '_');
+    }
+    if name.is_empty() {
+        name.push('_');
+    }
+    name
+}
+
+/// Splits records into files by a key.
+#[derive(Clone)]
+pub struct ShardedExport {
+    template: String,
+    key: ShardKeyFn,
+}
+
+impl ShardedExport {
+    /// Shards by the records' keys. `template` is the path of each file with `{key}` standing
+    /// for the sanitized key, e.g. `exports/{key}.jsonl.gz`; without `{key}`, the key is put in
+    /// front of the file name. Records without a key go to the `unknown` file.
+    pub fn new<F>(template: &str, key: F) -> Self
+    where
+        F: Fn(&Value) -> Option<String> + Send + Sync + 'static,
+    {
+        ShardedExport {
+            template: template.to_string(),
+            key: Arc::new(key),
+        }
+    }
+
+    /// Shards by the value of a field, e.g. `category`.
+    pub fn by_field(template: &str, field: &str) -> Self {
+        let field = field.to_string();
+        ShardedExport::new(template, move |record| record.get(&field).and_then(key_of))
+    }
+
+    /// Shards by the host of the URL in a field, e.g. one file per domain of `link`.
+    pub fn by_host(template: &str, url_field: &str) -> Self {
+        let url_field = url_field.to_string();
+        ShardedExport::new(template, move |record| {
+            let url = Url::parse(record.get(&url_field)?.as_str()?).ok()?;
+            url.host_str().map(str::to_string)
+        })
+    }
+
+    /// The key a record is exported under, before sanitizing.
+    pub fn key_of(&self, record: &Value) -> String {
+        (self.key)(record)
+            .filter(|key| !key.trim().is_empty())
+            .unwrap_or_else(|| UNKNOWN_SHARD.to_string())
+    }
+
+    /// The file the records of `key` are written to.
+    pub fn path_for(&self, key: &str) -> PathBuf {
+        let name = sanitize_file_name(key);
+        if self.template.contains("{key}") {
+            return PathBuf::from(self.template.replace("{key}", &name));
+        }
+        let template = Path::new(&self.template);
+        let file_name = template.file_name().map(|file_name| file_name.to_string_lossy()).unwrap_or_default();
+        template.with_file_name(format!("{}-{}", name, file_name))
+    }
+
+    /// Groups records by the file they are written to, keeping their order.
+    pub fn split<'a>(&self, records: &'a [Value]) -> BTreeMap<PathBuf, Vec<&'a Value>> {
+        let mut shards: BTreeMap<PathBuf, Vec<&Value>> = BTreeMap::new();
+        for record in records {
+            shards.entry(self.path_for(&self.key_of(record))).or_default().push(record);
+        }
+        shards
+    }
+
+    /// Writes each shard to a CSV file, see `Exporter::records_to_csv`. Returns the files written.
+    pub fn write_csv(&self, records: &[Value]) -> std::io::Result<Vec<PathBuf>> {
+        let shards = self.split(records);
+        for (path, records) in &shards {
+            create_parent(path)?;
+            let records: Vec<Value> = records.iter().map(|record| (*record).clone()).collect();
+            Exporter::records_to_csv(&records, &path.to_string_lossy())?;
+        }
+        Ok(shards.into_keys().collect())
+    }
+
+    /// Writes each shard to a JSON array file. Returns the files written.
+    pub fn write_json(&self, records: &[Value]) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let shards = self.split(records);
+        for (path, records) in &shards {
+            create_parent(path)?;
+            Exporter::records_to_json_file(records, &path.to_string_lossy())?;
+        }
+        Ok(shards.into_keys().collect())
+    }
+
+    /// Appends each shard to a JSON Lines file. Returns the files written.
+    pub fn write_json_lines(&self, records: &[Value]) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let shards = self.split(records);
+        for (path, records) in &shards {
+            create_parent(path)?;
+            let mut file = ExportWriter::append(path)?;
+            for record in records {
+                serde_json::to_writer(&mut file, record)?;
+                file.write_all(b"\n")?;
+            }
+            file.finish()?;
+        }
+        Ok(shards.into_keys().collect())
+    }
+}
+
+/// Open files a sharded sink keeps by default, see `with_max_open_files`.
+#[cfg(feature = "native")]
+pub const DEFAULT_MAX_OPEN_FILES: usize = 64;
+
+/// The writers of a sharded sink's files, at most `max_open` of them open at a time. When
+/// another file is needed, the least recently written one is finished and reopened for
+/// appending when a record for it comes again.
+#[cfg(feature = "native")]
+struct WriterPool {
+    max_open: usize,
+    /// Whether files are appended to from the start, or created anew the first time.
+    append: bool,
+    writers: HashMap<PathBuf, (ExportWriter, u64)>,
+    /// Files opened before, which are appended to when reopened.
+    opened: HashSet<PathBuf>,
+    uses: u64,
+}
+
+#[cfg(feature = "native")]
+impl WriterPool {
+    fn new(append: bool) -> Self {
+        WriterPool {
+            max_open: DEFAULT_MAX_OPEN_FILES,
+            append,
+            writers: HashMap::new(),
+            opened: HashSet::new(),
+            uses: 0,
+        }
+    }
+
+    /// Writes a record as a JSON line to the file at `path`.
+    fn write_line(&mut self, path: &Path, record: &Value) -> Result<(), Box<dyn std::error::Error>> {
+        self.uses += 1;
+        if !self.writers.contains_key(path) {
+            if self.writers.len() >= self.max_open {
+                self.close_least_recent()?;
+            }
+            create_parent(path)?;
+            let writer = if self.append || self.opened.contains(path) {
+                ExportWriter::append(path)?
+            } else {
+                ExportWriter::create(path)?
+            };
+            self.opened.insert(path.to_path_buf());
+            self.writers.insert(path.to_path_buf(), (writer, 0));
+        }
+        if let Some((writer, used)) = self.writers.get_mut(path) {
+            *used = self.uses;
+            serde_json::to_writer(&mut *writer, record)?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    fn close_least_recent(&mut self) -> std::io::Result<()> {
+        let least_recent = self.writers.iter().min_by_key(|(_, (_, used))| *used).map(|(path, _)| path.clone());
+        match least_recent.and_then(|path| self.writers.remove(&path)) {
+            Some((writer, _)) => writer.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Finishes every open file. Returns the files written so far.
+    fn finish(&mut self) -> std::io::Result<Vec<PathBuf>> {
+        for (_, (writer, _)) in std::mem::take(&mut self.writers) {
+            writer.finish()?;
+        }
+        let mut files: Vec<PathBuf> = std::mem::take(&mut self.opened).into_iter().collect();
+        files.sort();
+        Ok(files)
+    }
+}
+
+/// Writes records to one CSV file per key, see `ShardedExport::write_csv`. The CSV columns
+/// are only known once all records are in, so records are streamed to a JSON Lines spool
+/// file per key (`<file>.spool.jsonl`) and converted to CSV when the pipeline finishes.
+#[cfg(feature = "native")]
+pub struct ShardedCsvSink {
+    export: ShardedExport,
+    spools: WriterPool,
+}
+
+#[cfg(feature = "native")]
+impl ShardedCsvSink {
+    pub fn new(export: ShardedExport) -> Self {
+        ShardedCsvSink {
+            export,
+            spools: WriterPool::new(false),
+        }
+    }
+
+    /// Keeps at most this many spool files open at a time (64 by default).
+    pub fn with_max_open_files(mut self, max_open: usize) -> Self {
+        self.spools.max_open = max_open.max(1);
+        self
+    }
+}
+
+#[cfg(feature = "native")]
+impl RecordSink for ShardedCsvSink {
+    fn write(&mut self, records: &[Value]) -> Result<(), Box<dyn std::error::Error>> {
+        for record in records {
+            let spool = spool_path(&self.export.path_for(&self.export.key_of(record)));
+            self.spools.write_line(&spool, record)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        for spool in self.spools.finish()? {
+            // Drops `.spool.jsonl`.
+            let path = spool.with_extension("").with_extension("");
+            spool_to_csv(&spool, &path)?;
+            std::fs::remove_file(&spool)?;
+        }
+        Ok(())
+    }
+}
+
+/// Appends records to one JSON Lines file per key as they are extracted. At most 64 files
+/// are open at a time by default, see `with_max_open_files`.
+#[cfg(feature = "native")]
+pub struct ShardedJsonLinesSink {
+    export: ShardedExport,
+    writers: WriterPool,
+}
+
+#[cfg(feature = "native")]
+impl ShardedJsonLinesSink {
+    pub fn new(export: ShardedExport) -> Self {
+        ShardedJsonLinesSink {
+            export,
+            writers: WriterPool::new(true),
+        }
+    }
+
+    /// Keeps at most this many files open at a time (64 by default). Further files close
+    /// the least recently written one, which is reopened for appending when needed again.
+    pub fn with_max_open_files(mut self, max_open: usize) -> Self {
+        self.writers.max_open = max_open.max(1);
+        self
+    }
+}
+
+#[cfg(feature = "native")]
+impl RecordSink for ShardedJsonLinesSink {
+    fn write(&mut self, records: &[Value]) -> Result<(), Box<dyn std::error::Error>> {
+        for record in records {
+            let path = self.export.path_for(&self.export.key_of(record));
+            self.writers.write_line(&path, record)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.writers.finish()?;
+        Ok(())
+    }
+}
+
+/// The spool file records of the CSV file at `path` are collected in.
+#[cfg(feature = "native")]
+fn spool_path(path: &Path) -> PathBuf {
+    let mut spool = path.as_os_str().to_owned();
+    spool.push(".spool.jsonl");
+    PathBuf::from(spool)
+}
+
+/// Converts a spool file to CSV in two passes, one for the columns and one for the rows, so
+/// only one record is in memory at a time.
+#[cfg(feature = "native")]
+fn spool_to_csv(spool: &Path, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let records = || -> std::io::Result<_> {
+        let reader = std::io::BufReader::new(std::fs::File::open(spool)?);
+        Ok(reader.lines().map(|line| -> Result<Value, Box<dyn std::error::Error>> { Ok(serde_json::from_str(&line?)?) }))
+    };
+    let mut columns = Vec::new();
+    for record in records()? {
+        crate::extend_columns(&mut columns, &record?);
+    }
+    if columns.is_empty() {
+        columns.push("value".to_string());
+    }
+    let mut file = ExportWriter::create(path)?;
+    writeln!(file, "{}", crate::csv_header(&columns))?;
+    for record in records()? {
+        writeln!(file, "{}", crate::csv_row(&columns, &record?))?;
+    }
+    file.finish()?;
+    Ok(())
+}
+
+/// A field value as a shard key. Strings are used as they are, other values as JSON.
+fn key_of(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(value) => Some(value.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+fn create_parent(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => std::fs::create_dir_all(parent),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rust-scrapper-shard-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn sanitizes_keys_into_safe_file_names() {
+        assert_eq!(sanitize_file_name("../../etc/passwd"), "_.._etc_passwd");
+        assert_eq!(sanitize_file_name(" shop.com "), "shop.com");
+        assert_eq!(sanitize_file_name("con.txt"), "_con.txt");
+        assert_eq!(sanitize_file_name("..."), "_");
+        assert_eq!(sanitize_file_name(&"é".repeat(80)).len(), MAX_KEY_BYTES);
+    }
+
+    #[test]
+    fn places_keys_in_the_path_template() {
+        let export = ShardedExport::by_field("out/{key}.csv", "category");
+        assert_eq!(export.path_for("a/b"), PathBuf::from("out/a_b.csv"));
+        let export = ShardedExport::by_field("out/items.csv", "category");
+        assert_eq!(export.path_for("lamps"), PathBuf::from("out/lamps-items.csv"));
+    }
+
+    #[test]
+    fn splits_records_by_field_and_host() {
+        let records = vec![
+            json!({"category": "lamps", "link": "https://a.com/1"}),
+            json!({"category": null, "link": "https://b.com/2"}),
+            json!({"category": "lamps", "link": "not a url"}),
+        ];
+        let by_field = ShardedExport::by_field("{key}.json", "category");
+        let shards = by_field.split(&records);
+        assert_eq!(shards[&PathBuf::from("lamps.json")], vec![&records[0], &records[2]]);
+        assert_eq!(shards[&PathBuf::from("unknown.json")], vec![&records[1]]);
+
+        let by_host = ShardedExport::by_host("{key}.json", "link");
+        let keys: Vec<String> = records.iter().map(|record| by_host.key_of(record)).collect();
+        assert_eq!(keys, vec!["a.com", "b.com", UNKNOWN_SHARD]);
+    }
+
+    #[test]
+    fn writes_one_json_lines_file_per_key() {
+        let dir = temp_dir("jsonl");
+        let export = ShardedExport::by_field(&format!("{}/{{key}}.jsonl", dir.display()), "category");
+        let records = vec![json!({"category": "a", "n": 1}), json!({"category": "b", "n": 2}), json!({"category": "a", "n": 3})];
+        let files = export.write_json_lines(&records).unwrap();
+        let a = std::fs::read_to_string(dir.join("a.jsonl")).unwrap();
+        let b = std::fs::read_to_string(dir.join("b.jsonl")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, vec![dir.join("a.jsonl"), dir.join("b.jsonl")]);
+        assert_eq!(a, "{\"category\":\"a\",\"n\":1}\n{\"category\":\"a\",\"n\":3}\n");
+        assert_eq!(b, "{\"category\":\"b\",\"n\":2}\n");
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn streams_records_to_sharded_sinks() {
+        let dir = temp_dir("sink");
+        let export = ShardedExport::by_field(&format!("{}/{{key}}.jsonl", dir.display()), "category");
+        let mut sink = ShardedJsonLinesSink::new(export);
+        sink.write(&[json!({"category": "a"}), json!({"n": 1})]).unwrap();
+        sink.write(&[json!({"category": "a", "n": 2})]).unwrap();
+        sink.finish().unwrap();
+        let a = std::fs::read_to_string(dir.join("a.jsonl")).unwrap();
+        let unknown = std::fs::read_to_string(dir.join("unknown.jsonl")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(a, "{\"category\":\"a\"}\n{\"category\":\"a\",\"n\":2}\n");
+        assert_eq!(unknown, "{\"n\":1}\n");
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn reopens_closed_json_lines_files_for_appending() {
+        let dir = temp_dir("jsonl");
+        let export = ShardedExport::by_field(&dir.join("{key}.jsonl").to_string_lossy(), "category");
+        let mut sink = ShardedJsonLinesSink::new(export).with_max_open_files(1);
+        sink.write(&[json!({"category": "a", "n": 1}), json!({"category": "b", "n": 2})]).unwrap();
+        sink.write(&[json!({"category": "a", "n": 3})]).unwrap();
+        sink.finish().unwrap();
+        let a = std::fs::read_to_string(dir.join("a.jsonl")).unwrap();
+        assert_eq!(a, "{\"category\":\"a\",\"n\":1}\n{\"category\":\"a\",\"n\":3}\n");
+        assert_eq!(std::fs::read_to_string(dir.join("b.jsonl")).unwrap().lines().count(), 1);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn streams_csv_shards_through_spool_files() {
+        let dir = temp_dir("csv");
+        let export = ShardedExport::by_field(&dir.join("{key}.csv").to_string_lossy(), "category");
+        let mut sink = ShardedCsvSink::new(export).with_max_open_files(1);
+        sink.write(&[json!({"category": "a", "n": 1}), json!({"category": "b"})]).unwrap();
+        sink.write(&[json!({"category": "a", "note": "x, y"})]).unwrap();
+        sink.finish().unwrap();
+        let a = std::fs::read_to_string(dir.join("a.csv")).unwrap();
+        assert_eq!(a, "category,n,note\na,1,\na,,\"x, y\"\n");
+        assert!(dir.join("b.csv").exists());
+        let mut names: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        names.sort();
+        assert_eq!(names, vec!["a.csv", "b.csv"]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
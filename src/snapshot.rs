@@ -0,0 +1,285 @@
+//! History of scraped records.
+//!
+//! A `SnapshotStore` keeps every scrape of a watched URL with its timestamp, so the value of
+//! a field can be followed over time, e.g. a price history. Snapshots are stored in a
+//! `Storage`: in memory, in files with `SnapshotStore::open`, or in a shared backend such
+//! as `RedisCache`. Queries only read the keys of one URL, found with a prefix scan. With
+//! `Alerts`, recording a snapshot can notify hooks of changes.
+
+use crate::alerts::{Alert, Alerts};
+use crate::cache::MemoryCache;
+use crate::context::RequestContext;
+use crate::diff::{self, PageDiff};
+use crate::storage::{Namespace, Storage};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+const SNAPSHOT_PREFIX: &str = "snapshot:";
+
+/// The records scraped from a URL at one point in time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub url: String,
+    /// Unix timestamp in milliseconds.
+    pub taken_at: i64,
+    pub records: Vec<Value>,
+    /// Context of the scrape that recorded the snapshot, see `RustScrapper::with_context`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<RequestContext>,
+}
+
+/// Stores snapshots of watched URLs and answers queries over their history.
+/// Cloning the store returns a handle to the same backend.
+#[derive(Clone)]
+pub struct SnapshotStore {
+    backend: Arc<dyn Storage>,
+    alerts: Option<Alerts>,
+}
+
+impl SnapshotStore {
+    /// Creates a store keeping snapshots in the given backend.
+    pub fn new(backend: Arc<dyn Storage>) -> Self {
+        SnapshotStore { backend, alerts: None }
+    }
+
+    /// Creates a store keeping snapshots under `snapshots/` in a storage shared with other
+    /// persistent state, see `Storage`.
+    pub fn with_storage(storage: Arc<dyn Storage>) -> Self {
+        SnapshotStore::new(Arc::new(Namespace::new(storage, "snapshots")))
+    }
+
+    /// Creates a store that keeps snapshots in memory for the lifetime of the process.
+    pub fn in_memory() -> Self {
+        SnapshotStore::new(Arc::new(MemoryCache::new()))
+    }
+
+    /// Opens a store persisting every snapshot as a file in `dir`. Snapshots recorded by
+    /// earlier runs are available again.
+    pub fn open(dir: &str) -> Self {
+        SnapshotStore::new(Arc::new(MemoryCache::new().with_max_bytes(0).with_spill_dir(dir)))
+    }
+
+    /// Checks every recorded snapshot against the previous one of its URL and notifies the
+    /// alerts' hooks, e.g. on a price drop.
+    pub fn with_alerts(mut self, alerts: Alerts) -> Self {
+        self.alerts = Some(alerts);
+        self
+    }
+
+    /// Records the records scraped from a URL now.
+    pub fn record(&self, url: &str, records: Vec<Value>) -> Result<Snapshot, Box<dyn std::error::Error>> {
+        self.record_in_context(url, records, None)
+    }
+
+    /// Records a snapshot with an explicit timestamp in Unix milliseconds, e.g. when importing
+    /// older data. A snapshot with the same URL and timestamp is replaced.
+    pub fn record_at(&self, url: &str, records: Vec<Value>, taken_at: i64) -> Result<Snapshot, Box<dyn std::error::Error>> {
+        self.store(Snapshot {
+            url: url.to_string(),
+            taken_at,
+            records,
+            context: None,
+        })
+    }
+
+    /// Records the records scraped from a URL now, tagged with the context of the scrape.
+    pub fn record_in_context(
+        &self,
+        url: &str,
+        records: Vec<Value>,
+        context: Option<RequestContext>,
+    ) -> Result<Snapshot, Box<dyn std::error::Error>> {
+        self.store(Snapshot {
+            url: url.to_string(),
+            taken_at: chrono::Utc::now().timestamp_millis(),
+            records,
+            context,
+        })
+    }
+
+    fn store(&self, snapshot: Snapshot) -> Result<Snapshot, Box<dyn std::error::Error>> {
+        let (url, taken_at) = (snapshot.url.as_str(), snapshot.taken_at);
+        let previous = self.alerts.as_ref().and_then(|_| self.latest(url));
+        self.backend.set(&snapshot_key(url, taken_at), serde_json::to_string(&snapshot)?, None);
+        if let Some(alerts) = &self.alerts {
+            alerts.check(previous.as_ref(), &snapshot);
+        }
+        Ok(snapshot)
+    }
+
+    /// Checks the two most recent snapshots of a URL against alert rules without notifying
+    /// any hooks, e.g. to test rules on recorded history.
+    pub fn alerts_for_latest(&self, url: &str, alerts: &Alerts) -> Vec<Alert> {
+        let snapshots = self.snapshots(url);
+        match snapshots.as_slice() {
+            [.., before, after] => alerts.evaluate(Some(before), after),
+            [only] => alerts.evaluate(None, only),
+            [] => Vec::new(),
+        }
+    }
+
+    /// All snapshots of a URL, oldest first.
+    pub fn snapshots(&self, url: &str) -> Vec<Snapshot> {
+        self.snapshot_keys(url)
+            .iter()
+            .filter_map(|key| self.snapshot(key))
+            .collect()
+    }
+
+    /// The keys of a URL's snapshots, oldest first.
+    fn snapshot_keys(&self, url: &str) -> Vec<String> {
+        let mut keys = self.backend.keys_with_prefix(&url_prefix(url));
+        // Timestamps are zero-padded, so keys sort chronologically.
+        keys.sort();
+        keys
+    }
+
+    fn snapshot(&self, key: &str) -> Option<Snapshot> {
+        serde_json::from_str(&self.backend.get(key)?).ok()
+    }
+
+    /// Snapshots of a URL taken between `from` and `to` (inclusive, Unix milliseconds), oldest first.
+    pub fn between(&self, url: &str, from: i64, to: i64) -> Vec<Snapshot> {
+        self.snapshots(url)
+            .into_iter()
+            .filter(|snapshot| (from..=to).contains(&snapshot.taken_at))
+            .collect()
+    }
+
+    /// The most recent snapshot of a URL.
+    pub fn latest(&self, url: &str) -> Option<Snapshot> {
+        self.snapshot_keys(url).iter().rev().find_map(|key| self.snapshot(key))
+    }
+
+    /// The URLs with at least one snapshot, sorted.
+    pub fn urls(&self) -> Vec<String> {
+        let mut urls = self
+            .backend
+            .keys_with_prefix(SNAPSHOT_PREFIX)
+            .iter()
+            .filter_map(|key| key.strip_prefix(SNAPSHOT_PREFIX)?.rsplit_once('|').map(|(url, _)| url.to_string()))
+            .collect::<Vec<_>>();
+        urls.sort();
+        urls.dedup();
+        urls
+    }
+
+    /// Value of `field` in the first record of every snapshot, oldest first, for pages with
+    /// one record such as a product page. Snapshots without records are skipped.
+    pub fn history(&self, url: &str, field: &str) -> Vec<(i64, Value)> {
+        self.snapshots(url)
+            .into_iter()
+            .filter_map(|snapshot| Some((snapshot.taken_at, snapshot.records.first()?.get(field)?.clone())))
+            .collect()
+    }
+
+    /// Value of `field` over time in the record whose `key` field equals `key_value`, for pages
+    /// listing several records, e.g. the price of one product on a category page.
+    pub fn history_of(&self, url: &str, key: &str, key_value: &str, field: &str) -> Vec<(i64, Value)> {
+        self.snapshots(url)
+            .into_iter()
+            .filter_map(|snapshot| {
+                let record = snapshot
+                    .records
+                    .iter()
+                    .find(|record| diff::key_text(&record[key]).is_some_and(|text| text == key_value))?;
+                Some((snapshot.taken_at, record.get(field)?.clone()))
+            })
+            .collect()
+    }
+
+    /// Compares the two most recent snapshots of a URL by the `key` field.
+    /// Returns `None` until two snapshots exist.
+    pub fn diff_latest(&self, url: &str, key: &str) -> Option<PageDiff<Value>> {
+        let snapshots = self.snapshots(url);
+        let [.., before, after] = snapshots.as_slice() else {
+            return None;
+        };
+        Some(diff::diff_records(&before.records, &after.records, key))
+    }
+
+    /// Deletes all snapshots of a URL.
+    pub fn forget(&self, url: &str) {
+        for key in self.snapshot_keys(url) {
+            self.backend.remove(&key);
+        }
+    }
+}
+
+fn url_prefix(url: &str) -> String {
+    format!("{}{}|", SNAPSHOT_PREFIX, url)
+}
+
+fn snapshot_key(url: &str, taken_at: i64) -> String {
+    format!("{}{:020}", url_prefix(url), taken_at)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn queries_the_history_of_a_url() {
+        let store = SnapshotStore::in_memory();
+        let url = "https://shop.com/lamp";
+        store.record_at(url, vec![json!({"price": 12})], 2_000).unwrap();
+        store.record_at(url, vec![json!({"price": 10})], 1_000).unwrap();
+        store.record_at(url, vec![], 3_000).unwrap();
+        store.record_at("https://shop.com/desk", vec![json!({"price": 50})], 1_000).unwrap();
+
+        assert_eq!(store.history(url, "price"), vec![(1_000, json!(10)), (2_000, json!(12))]);
+        assert_eq!(store.between(url, 1_500, 3_000).len(), 2);
+        assert_eq!(store.latest(url).unwrap().taken_at, 3_000);
+        assert_eq!(store.urls(), vec!["https://shop.com/desk", url]);
+
+        store.forget(url);
+        assert!(store.snapshots(url).is_empty());
+        assert_eq!(store.urls(), vec!["https://shop.com/desk"]);
+    }
+
+    #[test]
+    fn follows_keyed_records_and_diffs_the_latest_snapshots() {
+        let store = SnapshotStore::in_memory();
+        let url = "https://shop.com/lamps";
+        store.record_at(url, vec![json!({"name": "Lamp", "price": 12})], 1).unwrap();
+        assert!(store.diff_latest(url, "name").is_none());
+        store
+            .record_at(url, vec![json!({"name": "Desk", "price": 5}), json!({"name": "Lamp", "price": 9})], 2)
+            .unwrap();
+
+        assert_eq!(store.history_of(url, "name", "Lamp", "price"), vec![(1, json!(12)), (2, json!(9))]);
+        let diff = store.diff_latest(url, "name").unwrap();
+        assert_eq!(diff.changed[0].fields, vec!["price"]);
+        assert_eq!(diff.added, vec![json!({"name": "Desk", "price": 5})]);
+    }
+
+    #[test]
+    fn persists_snapshots_in_a_directory() {
+        let dir = std::env::temp_dir().join(format!("rust-scrapper-snapshots-{}", std::process::id()));
+        let dir = dir.to_string_lossy();
+        SnapshotStore::open(&dir).record_at("https://a.com/", vec![json!({"n": 1})], 7).unwrap();
+        let reopened = SnapshotStore::open(&dir);
+        let latest = reopened.latest("https://a.com/");
+        std::fs::remove_dir_all(&*dir).unwrap();
+        assert_eq!(latest.unwrap().records, vec![json!({"n": 1})]);
+    }
+
+    #[test]
+    fn reads_only_the_snapshots_of_a_url() {
+        let store = SnapshotStore::in_memory();
+        store.record_at("https://a.com/x", vec![json!({"price": 2})], 2_000).unwrap();
+        store.record_at("https://a.com/x", vec![json!({"price": 1})], 1_000).unwrap();
+        store.record_at("https://a.com/x2", vec![json!({"price": 9})], 3_000).unwrap();
+
+        assert_eq!(store.latest("https://a.com/x").unwrap().taken_at, 2_000);
+        assert_eq!(store.history("https://a.com/x", "price"), vec![(1_000, json!(1)), (2_000, json!(2))]);
+        assert_eq!(store.urls(), vec!["https://a.com/x", "https://a.com/x2"]);
+
+        store.forget("https://a.com/x");
+        assert!(store.latest("https://a.com/x").is_none());
+        assert_eq!(store.snapshots("https://a.com/x2").len(), 1);
+    }
+}
@@ -0,0 +1,237 @@
+//! Detection of soft failures.
+//!
+//! Many sites answer missing pages, expired sessions and errors with `200 OK` and an error
+//! page. A `SoftFailureDetector` recognizes such responses by their body (empty or tiny
+//! bodies, "page not found" titles, login forms) so they are reported as failures instead
+//! of being scraped as content.
+
+use crate::schema::parse_selector;
+use scraper::Html;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+use url::Url;
+
+const NOT_FOUND_PHRASES: &[&str] = &[
+    "404",
+    "page not found",
+    "not found",
+    "no longer available",
+    "does not exist",
+    "sayfa bulunamadı",
+    "seite nicht gefunden",
+    "page introuvable",
+    "página no encontrada",
+];
+
+const LOGIN_PHRASES: &[&str] = &["log in", "login", "sign in", "signin", "giriş yap", "anmelden", "connexion", "iniciar sesión"];
+
+const LOGIN_PATHS: &[&str] = &["/login", "/signin", "/sign-in", "/sign_in", "/auth", "/account/login", "/giris"];
+
+/// Why a successful response was classified as a failure.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SoftFailure {
+    EmptyBody,
+    /// The body is shorter than the detector's minimum.
+    TinyBody { bytes: usize },
+    /// The title or main heading says the page was not found.
+    NotFound { phrase: String },
+    /// A login form instead of the requested page, e.g. after a redirect for an expired session.
+    LoginPage,
+    /// A check added with `SoftFailureDetector::with_check`.
+    Custom { name: String },
+}
+
+impl fmt::Display for SoftFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SoftFailure::EmptyBody => write!(f, "soft failure: empty body"),
+            SoftFailure::TinyBody { bytes } => write!(f, "soft failure: tiny body ({} bytes)", bytes),
+            SoftFailure::NotFound { phrase } => write!(f, "soft failure: not-found page (\"{}\")", phrase),
+            SoftFailure::LoginPage => write!(f, "soft failure: login page"),
+            SoftFailure::Custom { name } => write!(f, "soft failure: {}", name),
+        }
+    }
+}
+
+impl std::error::Error for SoftFailure {}
+
+type Check = Arc<dyn Fn(&str, &str) -> bool + Send + Sync>;
+
+/// Classifies response bodies as soft failures with configurable heuristics.
+/// Enable it with `RustScrapper::with_soft_failure_detector`.
+#[derive(Clone)]
+pub struct SoftFailureDetector {
+    min_body_bytes: usize,
+    not_found_phrases: Vec<String>,
+    login_pages: bool,
+    login_paths: Vec<String>,
+    checks: Vec<(String, Check)>,
+}
+
+impl SoftFailureDetector {
+    /// Creates a detector flagging bodies under 200 bytes, not-found pages in English,
+    /// Turkish, German, French and Spanish, and login pages.
+    pub fn new() -> Self {
+        SoftFailureDetector {
+            min_body_bytes: 200,
+            not_found_phrases: NOT_FOUND_PHRASES.iter().map(|phrase| phrase.to_string()).collect(),
+            login_pages: true,
+            login_paths: LOGIN_PATHS.iter().map(|path| path.to_string()).collect(),
+            checks: Vec::new(),
+        }
+    }
+
+    /// Flags bodies shorter than `bytes`, after trimming whitespace. 0 only flags empty bodies.
+    pub fn with_min_body_bytes(mut self, bytes: usize) -> Self {
+        self.min_body_bytes = bytes;
+        self
+    }
+
+    /// Adds a phrase that marks a not-found page when it appears in the title or `<h1>`.
+    pub fn with_not_found_phrase(mut self, phrase: &str) -> Self {
+        self.not_found_phrases.push(phrase.to_lowercase());
+        self
+    }
+
+    /// Adds a URL path that identifies the site's login page, e.g. "/kullanici/giris".
+    pub fn with_login_path(mut self, path: &str) -> Self {
+        self.login_paths.push(path.to_lowercase());
+        self
+    }
+
+    /// Stops flagging login pages, e.g. when scraping the login page itself.
+    pub fn without_login_detection(mut self) -> Self {
+        self.login_pages = false;
+        self
+    }
+
+    /// Adds a custom check called with the URL and body, reported as `SoftFailure::Custom`
+    /// with the given name when it returns true.
+    pub fn with_check<F>(mut self, name: &str, check: F) -> Self
+    where
+        F: Fn(&str, &str) -> bool + Send + Sync + 'static,
+    {
+        self.checks.push((name.to_string(), Arc::new(check)));
+        self
+    }
+
+    /// Classifies the body fetched from `url`. Returns `None` for a regular page.
+    pub fn classify(&self, url: &str, body: &str) -> Option<SoftFailure> {
+        let bytes = body.trim().len();
+        if bytes == 0 {
+            return Some(SoftFailure::EmptyBody);
+        }
+        if bytes < self.min_body_bytes {
+            return Some(SoftFailure::TinyBody { bytes });
+        }
+        if let Some((name, _)) = self.checks.iter().find(|(_, check)| check(url, body)) {
+            return Some(SoftFailure::Custom { name: name.clone() });
+        }
+
+        let document = Html::parse_document(body);
+        let text_of = |selector: &str| {
+            let selector = parse_selector(selector).ok()?;
+            let elem = document.select(&selector).next()?;
+            Some(elem.text().collect::<String>().trim().to_lowercase())
+        };
+        let headings = [text_of("title"), text_of("h1")].into_iter().flatten().collect::<Vec<_>>();
+        let not_found = self
+            .not_found_phrases
+            .iter()
+            .find(|phrase| headings.iter().any(|heading| contains_phrase(heading, phrase)));
+        if let Some(phrase) = not_found {
+            return Some(SoftFailure::NotFound { phrase: phrase.clone() });
+        }
+
+        if self.login_pages && !self.is_login_url(url) {
+            let has_password = parse_selector(r#"input[type="password"]"#).is_ok_and(|selector| document.select(&selector).next().is_some());
+            let canonical = ["link[rel=canonical]", r#"meta[property="og:url"]"#]
+                .iter()
+                .filter_map(|selector| parse_selector(selector).ok())
+                .filter_map(|selector| {
+                    let elem = document.select(&selector).next()?;
+                    elem.value().attr("href").or_else(|| elem.value().attr("content")).map(str::to_string)
+                })
+                .any(|canonical| self.is_login_url(&canonical));
+            let login_heading = headings.iter().any(|heading| LOGIN_PHRASES.iter().any(|phrase| heading.contains(phrase)));
+            if canonical || (has_password && login_heading) {
+                return Some(SoftFailure::LoginPage);
+            }
+        }
+        None
+    }
+
+    fn is_login_url(&self, url: &str) -> bool {
+        let path = Url::parse(url).map(|url| url.path().to_lowercase()).unwrap_or_else(|_| url.to_lowercase());
+        self.login_paths
+            .iter()
+            .any(|login| path.strip_prefix(login.as_str()).is_some_and(|rest| !rest.starts_with(char::is_alphanumeric)))
+    }
+}
+
+impl Default for SoftFailureDetector {
+    fn default() -> Self {
+        SoftFailureDetector::new()
+    }
+}
+
+/// Whether `phrase` appears in `text` as whole words, so "404" does not match "14045".
+fn contains_phrase(text: &str, phrase: &str) -> bool {
+    text.match_indices(phrase).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + phrase.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(title: &str, body: &str) -> String {
+        let filler = "<p>filler text</p>".repeat(20);
+        format!("<html><head><title>{}</title></head><body>{}{}</body></html>", title, body, filler)
+    }
+
+    #[test]
+    fn flags_empty_and_tiny_bodies() {
+        let detector = SoftFailureDetector::new();
+        assert_eq!(detector.classify("https://a.com/", "  \n"), Some(SoftFailure::EmptyBody));
+        assert_eq!(detector.classify("https://a.com/", "<p>oops</p>"), Some(SoftFailure::TinyBody { bytes: 11 }));
+        assert_eq!(detector.clone().with_min_body_bytes(0).classify("https://a.com/", "<p>oops</p>"), None);
+    }
+
+    #[test]
+    fn flags_not_found_pages_by_heading() {
+        let detector = SoftFailureDetector::new();
+        assert_eq!(
+            detector.classify("https://a.com/x", &page("Sayfa bulunamadı", "")),
+            Some(SoftFailure::NotFound { phrase: "sayfa bulunamadı".to_string() })
+        );
+        assert_eq!(detector.classify("https://a.com/x", &page("Order 14045", "")), None);
+        let custom = detector.with_not_found_phrase("gone fishing");
+        assert!(matches!(custom.classify("https://a.com/x", &page("Gone fishing", "")), Some(SoftFailure::NotFound { .. })));
+    }
+
+    #[test]
+    fn flags_login_pages_except_at_login_urls() {
+        let detector = SoftFailureDetector::new();
+        let login = page("Sign in", r#"<form><input type="password"></form>"#);
+        assert_eq!(detector.classify("https://a.com/orders", &login), Some(SoftFailure::LoginPage));
+        assert_eq!(detector.classify("https://a.com/login", &login), None);
+        assert_eq!(detector.clone().without_login_detection().classify("https://a.com/orders", &login), None);
+
+        let canonical = page("Welcome", r#"<link rel="canonical" href="https://a.com/giris?next=/orders">"#);
+        assert_eq!(detector.classify("https://a.com/orders", &canonical), Some(SoftFailure::LoginPage));
+        assert_eq!(detector.classify("https://a.com/authors", &page("Authors", "")), None);
+    }
+
+    #[test]
+    fn runs_custom_checks() {
+        let detector = SoftFailureDetector::new().with_check("maintenance", |_, body| body.contains("maintenance"));
+        let failure = detector.classify("https://a.com/", &page("Shop", "down for maintenance")).unwrap();
+        assert_eq!(failure, SoftFailure::Custom { name: "maintenance".to_string() });
+        assert_eq!(failure.to_string(), "soft failure: maintenance");
+    }
+}
@@ -0,0 +1,590 @@
+//! One persistence backend for all persistent state.
+//!
+//! The cache, snapshot history, crawl frontier and scheduler each keep state that should
+//! survive restarts. A `Storage` is a key-value store they can all share: each takes its own
+//! `Namespace` (key prefix) of it through `with_storage` constructors, so one configured
+//! backend holds everything. Backends: `FileStorage` (one file per key), `MemoryCache`,
+//! `SledStorage` (`sled` feature), `SqliteStorage` (`sqlite` feature) and `RedisCache`
+//! (`redis` feature).
+
+use crate::cache::{CacheBackend, MemoryCache};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Encoded keys longer than this are stored in files named by a hash of the key, as file
+/// systems limit names to 255 bytes.
+const MAX_FILE_NAME: usize = 200;
+const ENTRY_EXTENSION: &str = "entry";
+/// Prefix of the files of hashed keys; `~` never starts an encoded key.
+const HASHED_PREFIX: char = '~';
+
+/// A key-value store shared by all persistent state. Every `CacheBackend` is one; backends with
+/// ordered or indexed keys override the prefix scans.
+pub trait Storage: CacheBackend {
+    /// Keys starting with `prefix`.
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.keys().into_iter().filter(|key| key.starts_with(prefix)).collect()
+    }
+
+    /// The smallest key starting with `prefix`.
+    fn first_key(&self, prefix: &str) -> Option<String> {
+        self.keys_with_prefix(prefix).into_iter().min()
+    }
+}
+
+impl Storage for MemoryCache {}
+
+/// The keys of a storage under a prefix, e.g. `cache/`. Keys are stored with the prefix and
+/// seen without it, so features sharing one storage cannot overwrite each other's entries.
+#[derive(Clone)]
+pub struct Namespace {
+    storage: Arc<dyn Storage>,
+    prefix: String,
+}
+
+impl Namespace {
+    /// The keys under `name/`.
+    pub fn new(storage: Arc<dyn Storage>, name: &str) -> Self {
+        Namespace {
+            storage,
+            prefix: format!("{}/", name),
+        }
+    }
+
+    /// A namespace nested in this one, e.g. `frontier/catalog/`.
+    pub fn namespace(&self, name: &str) -> Namespace {
+        Namespace {
+            storage: self.storage.clone(),
+            prefix: format!("{}{}/", self.prefix, name),
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    fn strip(&self, keys: Vec<String>) -> Vec<String> {
+        keys.into_iter()
+            .filter_map(|key| key.strip_prefix(&self.prefix).map(str::to_string))
+            .collect()
+    }
+}
+
+impl CacheBackend for Namespace {
+    fn get(&self, key: &str) -> Option<String> {
+        self.storage.get(&self.key(key))
+    }
+
+    fn set(&self, key: &str, value: String, ttl: Option<Duration>) {
+        self.storage.set(&self.key(key), value, ttl)
+    }
+
+    fn remove(&self, key: &str) -> bool {
+        self.storage.remove(&self.key(key))
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.strip(self.storage.keys_with_prefix(&self.prefix))
+    }
+
+    /// Removes the entries of this namespace only.
+    fn clear(&self) {
+        for key in self.storage.keys_with_prefix(&self.prefix) {
+            self.storage.remove(&key);
+        }
+    }
+
+    fn entry_info(&self, key: &str) -> Option<(usize, SystemTime)> {
+        self.storage.entry_info(&self.key(key))
+    }
+}
+
+impl Storage for Namespace {
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.strip(self.storage.keys_with_prefix(&self.key(prefix)))
+    }
+
+    fn first_key(&self, prefix: &str) -> Option<String> {
+        self.storage
+            .first_key(&self.key(prefix))
+            .and_then(|key| key.strip_prefix(&self.prefix).map(str::to_string))
+    }
+}
+
+/// Storage keeping each entry in a file of a directory. Files are replaced atomically, so a
+/// crash never leaves a partially written entry.
+pub struct FileStorage {
+    dir: PathBuf,
+}
+
+impl FileStorage {
+    /// Opens the storage in `dir`, creating the directory if needed.
+    pub fn open(dir: &str) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(FileStorage { dir: PathBuf::from(dir) })
+    }
+
+    /// The file of a key: the key percent-encoded, or its FNV-1a hash for long keys, stable across
+    /// builds and processes. Uppercase letters are encoded too, so keys stay apart on
+    /// case-insensitive file systems.
+    fn path(&self, key: &str) -> PathBuf {
+        let mut name = String::new();
+        for byte in key.bytes() {
+            match byte {
+                b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' => name.push(byte as char),
+                _ => name.push_str(&format!("%{:02X}", byte)),
+            }
+        }
+        if name.len() > MAX_FILE_NAME {
+            name = format!("{}{:016x}", HASHED_PREFIX, crate::bloom::fnv1a(key.as_bytes()));
+        }
+        self.dir.join(format!("{}.{}", name, ENTRY_EXTENSION))
+    }
+
+    /// Reads an entry as its key, stored-at and expiry timestamps and value. Expired entries
+    /// are removed.
+    fn read(&self, path: &Path) -> Option<(String, SystemTime, String)> {
+        let contents = std::fs::read_to_string(path).ok()?;
+        let mut parts = contents.splitn(4, '\n');
+        let key = parts.next()?.to_string();
+        let time = |secs: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let stored_at = time(parts.next()?.parse().ok()?);
+        let expires_at = parts.next()?.parse().ok().map(time);
+        if expires_at.is_some_and(|at| at <= SystemTime::now()) {
+            let _ = std::fs::remove_file(path);
+            return None;
+        }
+        Some((key, stored_at, parts.next().unwrap_or_default().to_string()))
+    }
+
+    /// The entry of a key, checking the stored key in case of a hash collision.
+    fn entry(&self, key: &str) -> Option<(SystemTime, String)> {
+        let (stored_key, stored_at, value) = self.read(&self.path(key))?;
+        (stored_key == key).then_some((stored_at, value))
+    }
+
+    fn files(&self) -> Vec<PathBuf> {
+        std::fs::read_dir(&self.dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension().is_some_and(|ext| ext == ENTRY_EXTENSION))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl CacheBackend for FileStorage {
+    fn get(&self, key: &str) -> Option<String> {
+        self.entry(key).map(|(_, value)| value)
+    }
+
+    fn set(&self, key: &str, value: String, ttl: Option<Duration>) {
+        let path = self.path(key);
+        let secs = |time: SystemTime| time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+        let now = SystemTime::now();
+        let expires = ttl.map(|ttl| secs(now + ttl).to_string()).unwrap_or_default();
+        if let Err(e) = write_atomically(&path, &format!("{}\n{}\n{}\n{}", key, secs(now), expires, value)) {
+            log::error!("Failed to store {} in {}: {}", key, self.dir.display(), e);
+        }
+    }
+
+    fn remove(&self, key: &str) -> bool {
+        self.entry(key).is_some() && std::fs::remove_file(self.path(key)).is_ok()
+    }
+
+    /// Lists the keys by their file names; only entries of long keys are read.
+    fn keys(&self) -> Vec<String> {
+        self.files()
+            .iter()
+            .filter_map(|path| {
+                let name = path.file_stem()?.to_str()?;
+                if name.starts_with(HASHED_PREFIX) {
+                    return self.read(path).map(|(key, _, _)| key);
+                }
+                decode_file_name(name)
+            })
+            .collect()
+    }
+
+    fn clear(&self) {
+        for path in self.files() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    fn entry_info(&self, key: &str) -> Option<(usize, SystemTime)> {
+        self.entry(key).map(|(stored_at, value)| (value.len(), stored_at))
+    }
+}
+
+impl Storage for FileStorage {}
+
+/// Writes a file through a temporary file of its own, synced to disk before it is renamed
+/// over `path`, so concurrent writers never share a temporary file and a crash leaves either
+/// the old or the new contents.
+fn write_atomically(path: &Path, contents: &str) -> std::io::Result<()> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let unique = format!("{}-{}.tmp", std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed));
+    let temporary = path.with_extension(unique);
+    let result = std::fs::File::create(&temporary)
+        .and_then(|mut file| {
+            file.write_all(contents.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&temporary, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temporary);
+    }
+    result
+}
+
+fn decode_file_name(name: &str) -> Option<String> {
+    let bytes = name.as_bytes();
+    let mut key = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            key.push(u8::from_str_radix(name.get(index + 1..index + 3)?, 16).ok()?);
+            index += 3;
+        } else {
+            key.push(bytes[index]);
+            index += 1;
+        }
+    }
+    String::from_utf8(key).ok()
+}
+
+/// Milliseconds since the Unix epoch, as stored by the sled and SQLite backends.
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+fn unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_millis() as i64
+}
+
+/// Storage in an embedded sled database (enabled with the `sled` feature).
+/// Keys are ordered, so prefix scans do not read other entries.
+#[cfg(feature = "sled")]
+pub struct SledStorage {
+    db: sled::Db,
+    /// Serializes read-modify-write sequences such as removing an expired entry.
+    lock: Mutex<()>,
+}
+
+#[cfg(feature = "sled")]
+impl SledStorage {
+    /// Opens or creates the database in the directory at `path`.
+    pub fn open(path: &str) -> Result<Self, sled::Error> {
+        Ok(SledStorage {
+            db: sled::open(path)?,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Decodes an entry stored as its stored-at and expiry times (0 for none) in big-endian
+    /// milliseconds, then the value. `None` if it expired.
+    fn decode(bytes: &[u8]) -> Option<(SystemTime, String)> {
+        let millis = |range: std::ops::Range<usize>| -> Option<u64> { Some(u64::from_be_bytes(bytes.get(range)?.try_into().ok()?)) };
+        let stored_at = SystemTime::UNIX_EPOCH + Duration::from_millis(millis(0..8)?);
+        let expires_at = millis(8..16)?;
+        if expires_at != 0 && expires_at as i64 <= unix_millis(SystemTime::now()) {
+            return None;
+        }
+        Some((stored_at, String::from_utf8(bytes.get(16..)?.to_vec()).ok()?))
+    }
+
+    fn entry(&self, key: &str) -> Option<(SystemTime, String)> {
+        let bytes = self
+            .db
+            .get(key)
+            .map_err(|e| log::error!("Sled storage error: {}", e))
+            .ok()??;
+        let entry = SledStorage::decode(&bytes);
+        if entry.is_none() {
+            let _lock = self.lock.lock().unwrap();
+            let _ = self.db.compare_and_swap(key, Some(bytes), None as Option<&[u8]>);
+        }
+        entry
+    }
+
+    fn scan(&self, prefix: &str) -> impl Iterator<Item = String> + '_ {
+        self.db.scan_prefix(prefix).filter_map(|entry| {
+            let (key, value) = entry.ok()?;
+            SledStorage::decode(&value)?;
+            String::from_utf8(key.to_vec()).ok()
+        })
+    }
+}
+
+#[cfg(feature = "sled")]
+impl CacheBackend for SledStorage {
+    fn get(&self, key: &str) -> Option<String> {
+        self.entry(key).map(|(_, value)| value)
+    }
+
+    fn set(&self, key: &str, value: String, ttl: Option<Duration>) {
+        let now = SystemTime::now();
+        let expires_at = ttl.map_or(0, |ttl| unix_millis(now + ttl) as u64);
+        let mut bytes = Vec::with_capacity(16 + value.len());
+        bytes.extend_from_slice(&(unix_millis(now) as u64).to_be_bytes());
+        bytes.extend_from_slice(&expires_at.to_be_bytes());
+        bytes.extend_from_slice(value.as_bytes());
+        let _lock = self.lock.lock().unwrap();
+        if let Err(e) = self.db.insert(key, bytes) {
+            log::error!("Sled storage error: {}", e);
+        }
+    }
+
+    fn remove(&self, key: &str) -> bool {
+        let _lock = self.lock.lock().unwrap();
+        self.db
+            .remove(key)
+            .map_err(|e| log::error!("Sled storage error: {}", e))
+            .ok()
+            .flatten()
+            .is_some_and(|bytes| SledStorage::decode(&bytes).is_some())
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.scan("").collect()
+    }
+
+    fn clear(&self) {
+        if let Err(e) = self.db.clear() {
+            log::error!("Sled storage error: {}", e);
+        }
+    }
+
+    fn entry_info(&self, key: &str) -> Option<(usize, SystemTime)> {
+        self.entry(key).map(|(stored_at, value)| (value.len(), stored_at))
+    }
+}
+
+#[cfg(feature = "sled")]
+impl Storage for SledStorage {
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.scan(prefix).collect()
+    }
+
+    fn first_key(&self, prefix: &str) -> Option<String> {
+        self.scan(prefix).next()
+    }
+}
+
+/// Storage in a SQLite database file (enabled with the `sqlite` feature). Entries live in
+/// one `storage` table keyed by a primary key index, which serves the prefix scans.
+#[cfg(feature = "sqlite")]
+pub struct SqliteStorage {
+    connection: Mutex<rusqlite::Connection>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStorage {
+    /// Opens or creates the database at `path`.
+    pub fn open(path: &str) -> Result<Self, rusqlite::Error> {
+        let connection = rusqlite::Connection::open(path)?;
+        connection.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS storage (
+                 key TEXT PRIMARY KEY,
+                 value TEXT NOT NULL,
+                 stored_at INTEGER NOT NULL,
+                 expires_at INTEGER
+             );",
+        )?;
+        Ok(SqliteStorage {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn query<T>(&self, run: impl FnOnce(&rusqlite::Connection, i64) -> rusqlite::Result<T>) -> Option<T> {
+        let connection = self.connection.lock().unwrap();
+        run(&connection, unix_millis(SystemTime::now()))
+            .map_err(|e| log::error!("SQLite storage error: {}", e))
+            .ok()
+    }
+
+    /// Live keys starting with `prefix` in order, at most `limit` of them. The prefix is
+    /// matched as a key range, so SQLite seeks the primary key index instead of scanning.
+    fn scan(&self, prefix: &str, limit: i64) -> Vec<String> {
+        self.query(|connection, now| {
+            let mut statement = connection.prepare(
+                "SELECT key FROM storage WHERE key >= ?1 AND key < ?1 || char(0x10FFFF)
+                 AND (expires_at IS NULL OR expires_at > ?2) ORDER BY key LIMIT ?3",
+            )?;
+            let keys: rusqlite::Result<Vec<String>> =
+                statement.query_map(rusqlite::params![prefix, now, limit], |row| row.get(0))?.collect();
+            keys
+        })
+        .unwrap_or_default()
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl CacheBackend for SqliteStorage {
+    fn get(&self, key: &str) -> Option<String> {
+        use rusqlite::OptionalExtension;
+        self.query(|connection, now| {
+            connection
+                .query_row(
+                    "SELECT value FROM storage WHERE key = ?1 AND (expires_at IS NULL OR expires_at > ?2)",
+                    rusqlite::params![key, now],
+                    |row| row.get(0),
+                )
+                .optional()
+        })
+        .flatten()
+    }
+
+    fn set(&self, key: &str, value: String, ttl: Option<Duration>) {
+        self.query(|connection, now| {
+            let expires_at = ttl.map(|ttl| now + ttl.as_millis() as i64);
+            connection.execute(
+                "INSERT OR REPLACE INTO storage (key, value, stored_at, expires_at) VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![key, value, now, expires_at],
+            )
+        });
+    }
+
+    fn remove(&self, key: &str) -> bool {
+        self.query(|connection, now| {
+            connection.execute(
+                "DELETE FROM storage WHERE key = ?1 AND (expires_at IS NULL OR expires_at > ?2)",
+                rusqlite::params![key, now],
+            )
+        })
+        .is_some_and(|removed| removed > 0)
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.scan("", -1)
+    }
+
+    fn clear(&self) {
+        self.query(|connection, _| connection.execute("DELETE FROM storage", []));
+    }
+
+    fn entry_info(&self, key: &str) -> Option<(usize, SystemTime)> {
+        use rusqlite::OptionalExtension;
+        let (bytes, stored_at) = self
+            .query(|connection, now| {
+                connection
+                    .query_row(
+                        "SELECT length(CAST(value AS BLOB)), stored_at FROM storage
+                         WHERE key = ?1 AND (expires_at IS NULL OR expires_at > ?2)",
+                        rusqlite::params![key, now],
+                        |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+                    )
+                    .optional()
+            })
+            .flatten()?;
+        Some((bytes as usize, SystemTime::UNIX_EPOCH + Duration::from_millis(stored_at as u64)))
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Storage for SqliteStorage {
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.scan(prefix, -1)
+    }
+
+    fn first_key(&self, prefix: &str) -> Option<String> {
+        self.scan(prefix, 1).into_iter().next()
+    }
+}
+
+#[cfg(feature = "redis")]
+impl Storage for crate::cache::RedisCache {
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.scan(prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("rust-scrapper-storage-{}-{}", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn keeps_namespaces_apart() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryCache::new());
+        let cache = Namespace::new(storage.clone(), "cache");
+        let frontier = Namespace::new(storage.clone(), "frontier").namespace("crawl");
+        cache.set("a", "1".to_string(), None);
+        frontier.set("a", "2".to_string(), None);
+        frontier.set("b", "3".to_string(), None);
+        assert_eq!(cache.get("a").as_deref(), Some("1"));
+        assert_eq!(frontier.get("a").as_deref(), Some("2"));
+        assert_eq!(storage.get("frontier/crawl/b").as_deref(), Some("3"));
+        assert_eq!(frontier.first_key(""), Some("a".to_string()));
+
+        frontier.clear();
+        assert!(frontier.keys().is_empty());
+        assert_eq!(cache.keys(), vec!["a"]);
+    }
+
+    #[test]
+    fn stores_entries_in_files_named_by_their_keys() {
+        let dir = temp_dir("files");
+        let storage = FileStorage::open(&dir).unwrap();
+        let long_key = "k".repeat(300);
+        storage.set("Cache/page:1", "a\nb".to_string(), None);
+        storage.set(&long_key, "long".to_string(), None);
+        storage.set("gone", "x".to_string(), Some(Duration::ZERO));
+
+        let reopened = FileStorage::open(&dir).unwrap();
+        assert_eq!(reopened.get("Cache/page:1").as_deref(), Some("a\nb"));
+        assert_eq!(reopened.get(&long_key).as_deref(), Some("long"));
+        assert_eq!(reopened.get("gone"), None);
+        let mut keys = reopened.keys_with_prefix("");
+        keys.sort();
+        assert_eq!(keys, vec!["Cache/page:1".to_string(), long_key.clone()]);
+        assert!(reopened.path("Cache/page:1").ends_with("%43ache%2Fpage%3A1.entry"));
+
+        assert!(reopened.remove(&long_key));
+        assert!(!reopened.remove(&long_key));
+        reopened.clear();
+        assert!(reopened.keys().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn temp_storage(name: &str) -> (FileStorage, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("rust-scrapper-storage-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        (FileStorage::open(dir.to_str().unwrap()).unwrap(), dir)
+    }
+
+    #[test]
+    fn names_long_keys_by_a_stable_hash() {
+        let (storage, dir) = temp_storage("long");
+        let key = format!("cache/{}", "https://example.com/".repeat(20));
+        let name = format!("{}{:016x}.{}", HASHED_PREFIX, crate::bloom::fnv1a(key.as_bytes()), ENTRY_EXTENSION);
+        assert_eq!(storage.path(&key), dir.join(name));
+        storage.set(&key, "value".to_string(), None);
+        assert_eq!(storage.get(&key).as_deref(), Some("value"));
+        assert_eq!(storage.keys(), vec![key]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn leaves_no_temporary_files() {
+        let (storage, dir) = temp_storage("atomic");
+        storage.set("a", "1".to_string(), None);
+        storage.set("a", "2".to_string(), None);
+        let names: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(names, vec!["a.entry"]);
+        assert_eq!(storage.get("a").as_deref(), Some("2"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,127 @@
+//! Browser storage state.
+//!
+//! A `StorageState` holds the cookies and `localStorage` entries of a browser session. It is
+//! captured after logging in with `JsScraper::storage_state`, saved to a JSON file and restored
+//! in later headless runs with `JsScrapeOptions::storage_state`, so the login happens only once.
+
+use crate::cookies::{Cookie, CookieJar};
+#[cfg(feature = "encryption")]
+use crate::encryption::EncryptionKey;
+use serde::{Deserialize, Serialize};
+
+/// `localStorage` entries of one origin.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct OriginStorage {
+    /// Origin the entries belong to, e.g. `https://example.com`.
+    pub origin: String,
+    pub local_storage: Vec<(String, String)>,
+}
+
+/// Cookies and `localStorage` of a browser session.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct StorageState {
+    pub cookies: Vec<Cookie>,
+    pub origins: Vec<OriginStorage>,
+}
+
+impl StorageState {
+    /// Reads a state saved with `save`.
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Writes the state to a JSON file. The file contains session secrets; keep it private.
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Reads a state saved with `save_encrypted`.
+    #[cfg(feature = "encryption")]
+    pub fn load_encrypted(path: &str, key: &EncryptionKey) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_slice(&key.decrypt(&std::fs::read(path)?)?)?)
+    }
+
+    /// Writes the state to a file encrypted under `key`, so the session cookies are not
+    /// readable from disk.
+    #[cfg(feature = "encryption")]
+    pub fn save_encrypted(&self, path: &str, key: &EncryptionKey) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, key.encrypt(&serde_json::to_vec(self)?))?;
+        Ok(())
+    }
+
+    /// Adds the `localStorage` entries of an origin, replacing earlier entries of that origin.
+    pub fn set_local_storage(&mut self, origin: &str, entries: Vec<(String, String)>) {
+        self.origins.retain(|o| o.origin != origin);
+        self.origins.push(OriginStorage {
+            origin: origin.to_string(),
+            local_storage: entries,
+        });
+    }
+
+    /// Returns a cookie jar with the state's cookies, so `RustScrapper` can reuse the session.
+    pub fn cookie_jar(&self) -> CookieJar {
+        let jar = CookieJar::new();
+        for cookie in &self.cookies {
+            jar.add(cookie.clone());
+        }
+        jar
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> StorageState {
+        let mut state = StorageState {
+            cookies: vec![Cookie {
+                name: "session".to_string(),
+                value: "abc".to_string(),
+                domain: "example.com".to_string(),
+                include_subdomains: true,
+                path: "/".to_string(),
+                secure: true,
+                expires: None,
+            }],
+            origins: Vec::new(),
+        };
+        state.set_local_storage("https://example.com", vec![("theme".to_string(), "dark".to_string())]);
+        state.set_local_storage("https://example.com", vec![("token".to_string(), "t".to_string())]);
+        state
+    }
+
+    #[test]
+    fn replaces_local_storage_per_origin() {
+        let state = state();
+        assert_eq!(state.origins.len(), 1);
+        assert_eq!(state.origins[0].local_storage, vec![("token".to_string(), "t".to_string())]);
+    }
+
+    #[test]
+    fn saves_loads_and_exports_cookies() {
+        let path = std::env::temp_dir().join(format!("rust-scrapper-state-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        state().save(path).unwrap();
+        let loaded = StorageState::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded, state());
+        assert_eq!(loaded.cookie_jar().header_for("https://www.example.com/"), Some("session=abc".to_string()));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn saves_and_loads_encrypted_states() {
+        let path = std::env::temp_dir().join(format!("rust-scrapper-state-{}.enc", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let key = EncryptionKey::generate();
+        state().save_encrypted(&path, &key).unwrap();
+        let raw = std::fs::read(&path).unwrap();
+        let loaded = StorageState::load_encrypted(&path, &key);
+        let wrong_key = StorageState::load_encrypted(&path, &EncryptionKey::generate());
+        std::fs::remove_file(&path).unwrap();
+        assert!(!String::from_utf8_lossy(&raw).contains("session"));
+        assert_eq!(loaded.unwrap().cookies[0].value, "abc");
+        assert!(wrong_key.is_err());
+    }
+}
@@ -0,0 +1,177 @@
+//! Exports rendered through user templates.
+//!
+//! A `RecordTemplate` renders every record through a Jinja-style template (MiniJinja), with an
+//! optional header and footer around them, to produce Markdown tables, HTML reports or any
+//! custom text format straight from scrape results. Enabled by the `templates` feature.
+//!
+//! The record template sees the record as `record` and its position as `index`; the header
+//! and footer see all records as `records` and their number as `count`.
+
+use crate::export::ExportWriter;
+#[cfg(feature = "native")]
+use crate::pipeline::RecordSink;
+use minijinja::{context, AutoEscape, Environment};
+use serde_json::Value;
+use std::io::Write;
+
+/// Templates rendering records to text.
+#[derive(Debug, Clone)]
+pub struct RecordTemplate {
+    record: String,
+    header: Option<String>,
+    footer: Option<String>,
+    escape_html: bool,
+}
+
+impl RecordTemplate {
+    /// Renders each record through `record`, e.g. `"- [{{ record.title }}]({{ record.link }})\n"`.
+    /// Records are concatenated, so the template should end with the separator it wants.
+    pub fn new(record: &str) -> Self {
+        RecordTemplate {
+            record: record.to_string(),
+            header: None,
+            footer: None,
+            escape_html: false,
+        }
+    }
+
+    /// Reads the record template from a file.
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(RecordTemplate::new(&std::fs::read_to_string(path)?))
+    }
+
+    /// Renders `header` once before the records, e.g. a table head.
+    pub fn with_header(mut self, header: &str) -> Self {
+        self.header = Some(header.to_string());
+        self
+    }
+
+    /// Renders `footer` once after the records.
+    pub fn with_footer(mut self, footer: &str) -> Self {
+        self.footer = Some(footer.to_string());
+        self
+    }
+
+    /// Escapes HTML in every value the templates print, for HTML reports of untrusted page
+    /// content. Use the `safe` filter for values that are HTML already.
+    pub fn escape_html(mut self) -> Self {
+        self.escape_html = true;
+        self
+    }
+
+    /// Renders one record.
+    pub fn render(&self, record: &Value, index: usize) -> Result<String, Box<dyn std::error::Error>> {
+        let environment = self.environment();
+        Ok(environment.template_from_str(&self.record)?.render(context! { record => record, index => index })?)
+    }
+
+    /// Renders the header, every record and the footer.
+    pub fn render_all(&self, records: &[Value]) -> Result<String, Box<dyn std::error::Error>> {
+        let mut output = Vec::new();
+        self.render_to(records, &mut output)?;
+        Ok(String::from_utf8(output)?)
+    }
+
+    /// Renders the records to a file, compressed if its name ends with `.gz` or `.zst`.
+    pub fn write(&self, records: &[Value], file_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = ExportWriter::create(file_name)?;
+        self.render_to(records, &mut file)?;
+        file.finish()?;
+        Ok(())
+    }
+
+    fn render_to(&self, records: &[Value], output: &mut dyn Write) -> Result<(), Box<dyn std::error::Error>> {
+        let environment = self.environment();
+        let around = context! { records => records, count => records.len() };
+        if let Some(header) = &self.header {
+            output.write_all(environment.template_from_str(header)?.render(&around)?.as_bytes())?;
+        }
+        let template = environment.template_from_str(&self.record)?;
+        for (index, record) in records.iter().enumerate() {
+            output.write_all(template.render(context! { record => record, index => index })?.as_bytes())?;
+        }
+        if let Some(footer) = &self.footer {
+            output.write_all(environment.template_from_str(footer)?.render(&around)?.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn environment(&self) -> Environment<'_> {
+        let mut environment = Environment::new();
+        // Record templates usually end with the newline separating records.
+        environment.set_keep_trailing_newline(true);
+        if self.escape_html {
+            environment.set_auto_escape_callback(|_| AutoEscape::Html);
+        }
+        environment
+    }
+}
+
+/// Renders records to a file through a `RecordTemplate` when the pipeline finishes.
+#[cfg(feature = "native")]
+pub struct TemplateSink {
+    template: RecordTemplate,
+    path: String,
+    records: Vec<Value>,
+}
+
+#[cfg(feature = "native")]
+impl TemplateSink {
+    pub fn new(template: RecordTemplate, path: &str) -> Self {
+        TemplateSink {
+            template,
+            path: path.to_string(),
+            records: Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "native")]
+impl RecordSink for TemplateSink {
+    fn write(&mut self, records: &[Value]) -> Result<(), Box<dyn std::error::Error>> {
+        self.records.extend_from_slice(records);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.template.write(&self.records, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn renders_records_between_header_and_footer() {
+        let template = RecordTemplate::new("| {{ index }} | {{ record.name }} |\n")
+            .with_header("| # | Name |\n|---|---|\n")
+            .with_footer("{{ count }} records\n");
+        let output = template.render_all(&[json!({"name": "Lamp"}), json!({"name": "Desk"})]).unwrap();
+        assert_eq!(output, "| # | Name |\n|---|---|\n| 0 | Lamp |\n| 1 | Desk |\n2 records\n");
+    }
+
+    #[test]
+    fn escapes_html_only_when_asked() {
+        let record = json!({"name": "<b>Lamp</b>"});
+        assert_eq!(RecordTemplate::new("{{ record.name }}").render(&record, 0).unwrap(), "<b>Lamp</b>");
+        let escaped = RecordTemplate::new("{{ record.name }}").escape_html().render(&record, 0).unwrap();
+        assert_eq!(escaped, "&lt;b&gt;Lamp&lt;&#x2f;b&gt;");
+    }
+
+    #[test]
+    fn writes_rendered_records_to_a_file() {
+        let path = std::env::temp_dir().join(format!("rust-scrapper-template-{}.md", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        RecordTemplate::new("- {{ record.name }}\n").write(&[json!({"name": "Lamp"})], &path).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "- Lamp\n");
+    }
+
+    #[test]
+    fn reports_template_syntax_errors() {
+        assert!(RecordTemplate::new("{{ record.name").render(&json!({}), 0).is_err());
+    }
+}
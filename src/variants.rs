@@ -0,0 +1,108 @@
+//! Discovery of lighter page variants.
+//!
+//! Many sites publish an AMP or mobile version of their pages and announce it in the head of
+//! the desktop page with `<link rel="amphtml">` or `<link rel="alternate" media="...">`. These
+//! variants are often smaller and simpler to parse. `PageVariants::discover` finds them;
+//! `RustScrapper::prefer_variants` scrapes them instead of the desktop page.
+
+use crate::schema::parse_selector;
+use scraper::Html;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// A variant of a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Variant {
+    /// The AMP version, `<link rel="amphtml">`.
+    Amp,
+    /// The mobile version, `<link rel="alternate">` with a small-screen media query.
+    Mobile,
+}
+
+/// The variants a page announces, as absolute URLs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageVariants {
+    /// `<link rel="canonical">`, usually the desktop page.
+    pub canonical: Option<String>,
+    pub amp: Option<String>,
+    pub mobile: Option<String>,
+}
+
+impl PageVariants {
+    /// Finds the variants announced in `body`, resolving them against the page's `url`.
+    pub fn discover(body: &str, url: &str) -> Self {
+        let document = Html::parse_document(body);
+        let base = Url::parse(url).ok();
+        let mut variants = PageVariants::default();
+        let Ok(selector) = parse_selector("link[rel][href]") else {
+            return variants;
+        };
+        for link in document.select(&selector) {
+            let (Some(rel), Some(href)) = (link.value().attr("rel"), link.value().attr("href")) else {
+                continue;
+            };
+            let href = match &base {
+                Some(base) => match base.join(href.trim()) {
+                    Ok(href) => href.to_string(),
+                    Err(_) => continue,
+                },
+                None => href.trim().to_string(),
+            };
+            let rels: Vec<String> = rel.split_whitespace().map(str::to_ascii_lowercase).collect();
+            if rels.iter().any(|rel| rel == "amphtml") {
+                variants.amp.get_or_insert(href);
+            } else if rels.iter().any(|rel| rel == "canonical") {
+                variants.canonical.get_or_insert(href);
+            } else if rels.iter().any(|rel| rel == "alternate")
+                && link.value().attr("hreflang").is_none()
+                && link.value().attr("media").is_some_and(is_small_screen_query)
+            {
+                variants.mobile.get_or_insert(href);
+            }
+        }
+        variants
+    }
+
+    /// The URL of a variant, if the page announces it.
+    pub fn get(&self, variant: Variant) -> Option<&str> {
+        match variant {
+            Variant::Amp => self.amp.as_deref(),
+            Variant::Mobile => self.mobile.as_deref(),
+        }
+    }
+
+    /// The URL of the first announced variant of `preferred`, in order.
+    pub fn preferred(&self, preferred: &[Variant]) -> Option<&str> {
+        preferred.iter().find_map(|variant| self.get(*variant))
+    }
+}
+
+/// Whether a media query targets small screens, e.g. "only screen and (max-width: 640px)"
+/// or "handheld".
+fn is_small_screen_query(media: &str) -> bool {
+    let media = media.to_ascii_lowercase();
+    media.contains("handheld") || media.contains("max-width") || media.contains("max-device-width")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discovers_amp_and_mobile_variants() {
+        let body = r#"<head>
+            <link rel="canonical" href="https://shop.com/lamp">
+            <link rel="alternate" hreflang="de" href="/de/lamp">
+            <link rel="alternate" media="only screen and (max-width: 640px)" href="https://m.shop.com/lamp">
+            <link rel="amphtml" href="/amp/lamp">
+        </head>"#;
+        let variants = PageVariants::discover(body, "https://shop.com/lamp?ref=1");
+
+        assert_eq!(variants.canonical.as_deref(), Some("https://shop.com/lamp"));
+        assert_eq!(variants.amp.as_deref(), Some("https://shop.com/amp/lamp"));
+        assert_eq!(variants.mobile.as_deref(), Some("https://m.shop.com/lamp"));
+        assert_eq!(variants.preferred(&[Variant::Mobile, Variant::Amp]), Some("https://m.shop.com/lamp"));
+        assert_eq!(PageVariants::default().preferred(&[Variant::Amp]), None);
+    }
+}
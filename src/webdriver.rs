@@ -0,0 +1,154 @@
+//! WebDriver browser backend, built with the `webdriver` feature.
+//!
+//! Renders pages through a WebDriver server (geckodriver, chromedriver, Selenium) for
+//! environments where headless_chrome cannot run, e.g. ARM servers or Firefox-only policies.
+//! `JsScrapeOptions` are translated into Chrome and Firefox capabilities; a driver ignores
+//! the capabilities of the other vendor.
+
+use crate::js::{FrameSelector, JsScrapeOptions, PageMetrics, WaitFor};
+use fantoccini::{Client, ClientBuilder, Locator};
+use log::warn;
+use serde_json::{json, Map, Value};
+use std::time::{Duration, Instant};
+
+/// Loads a page in a new WebDriver session and returns its rendered HTML and load timing.
+pub(crate) async fn render(
+    webdriver_url: &str,
+    url: &str,
+    options: &JsScrapeOptions,
+) -> Result<(String, PageMetrics), Box<dyn std::error::Error>> {
+    let client = ClientBuilder::native()
+        .capabilities(capabilities(options))
+        .connect(webdriver_url)
+        .await?;
+    if options.timezone.is_some() {
+        warn!("Timezone overrides are not supported by the WebDriver backend");
+    }
+    if !options.blocked_types.is_empty() || !options.blocked_urls.is_empty() || !options.capture_patterns.is_empty() {
+        warn!("Request blocking and response capturing are not supported by the WebDriver backend");
+    }
+    if options.storage_state.is_some() {
+        warn!("Storage state is not supported by the WebDriver backend");
+    }
+
+    let result = async {
+        if let Some((width, height)) = options.viewport {
+            client.set_window_size(width, height).await?;
+        }
+        client.goto(url).await?;
+        wait(&client, options).await?;
+        let metrics = client.execute(&format!("return {};", crate::js::METRICS_SCRIPT), Vec::new()).await?;
+        let metrics = PageMetrics::from_script_result(Some(&metrics));
+        match &options.frame {
+            Some(FrameSelector::Element(selector)) => {
+                client.find(Locator::Css(selector)).await?.enter_frame().await?;
+            }
+            Some(FrameSelector::Url(_)) => return Err("The WebDriver backend selects frames by element only".into()),
+            None => {}
+        }
+        if !options.pierce_shadow_roots {
+            return Ok((client.source().await?, metrics));
+        }
+        let script = format!("return {};", crate::js::outer_html_script(true));
+        let html = client.execute(&script, Vec::new()).await?.as_str().unwrap_or_default().to_string();
+        Ok::<_, Box<dyn std::error::Error>>((html, metrics))
+    }
+    .await;
+    // Always end the session, even when loading the page failed.
+    client.close().await?;
+    result
+}
+
+/// Async counterpart of `js::run_waits`.
+async fn wait(client: &Client, options: &JsScrapeOptions) -> Result<(), Box<dyn std::error::Error>> {
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+    let deadline = Instant::now() + options.wait_timeout;
+    for wait in &options.waits {
+        if let WaitFor::Delay(delay) = wait {
+            tokio::time::sleep(*delay).await;
+            continue;
+        }
+        let script = format!("return {};", wait.script(options.pierce_shadow_roots).unwrap_or_default());
+        let mut last_change = (Value::Null, Instant::now());
+        loop {
+            let value = client.execute(&script, Vec::new()).await?;
+            if wait.is_met(value, &mut last_change) {
+                break;
+            }
+            if Instant::now() >= deadline {
+                return Err(format!("Timed out waiting for {:?}", wait).into());
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+    Ok(())
+}
+
+fn capabilities(options: &JsScrapeOptions) -> Map<String, Value> {
+    let mut chrome_args = options.chrome_args.clone();
+    let mut firefox_args = Vec::new();
+    let mut firefox_prefs = Map::new();
+    if options.headless {
+        chrome_args.push("--headless".to_string());
+        firefox_args.push("-headless".to_string());
+    }
+    if let Some(user_agent) = &options.user_agent {
+        chrome_args.push(format!("--user-agent={}", user_agent));
+        firefox_prefs.insert("general.useragent.override".to_string(), json!(user_agent));
+    }
+    if let Some(language) = &options.language {
+        chrome_args.push(format!("--lang={}", language));
+        firefox_prefs.insert("intl.accept_languages".to_string(), json!(language));
+    }
+    if options.hide_webdriver {
+        firefox_prefs.insert("dom.webdriver.enabled".to_string(), json!(false));
+    }
+
+    let mut chrome_options = json!({ "args": chrome_args });
+    if options.mobile || options.touch {
+        let (width, height) = options.viewport.unwrap_or((800, 600));
+        chrome_options["mobileEmulation"] = json!({
+            "deviceMetrics": {
+                "width": width,
+                "height": height,
+                "pixelRatio": options.device_scale_factor,
+                "mobile": options.mobile,
+                "touch": options.touch,
+            },
+        });
+    }
+    if let Some(path) = &options.chrome_path {
+        chrome_options["binary"] = json!(path);
+    }
+    let mut capabilities = Map::new();
+    capabilities.insert("goog:chromeOptions".to_string(), chrome_options);
+    capabilities.insert("moz:firefoxOptions".to_string(), json!({ "args": firefox_args, "prefs": firefox_prefs }));
+    capabilities
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_options_into_capabilities() {
+        let options = JsScrapeOptions::stealth().user_agent("bot/1.0").language("tr-TR");
+        let capabilities = capabilities(&options);
+        let chrome_args = &capabilities["goog:chromeOptions"]["args"];
+        assert!(chrome_args.as_array().unwrap().contains(&json!("--user-agent=bot/1.0")));
+        assert!(chrome_args.as_array().unwrap().contains(&json!("--headless")));
+        let firefox = &capabilities["moz:firefoxOptions"];
+        assert_eq!(firefox["args"], json!(["-headless"]));
+        assert_eq!(firefox["prefs"]["intl.accept_languages"], json!("tr-TR"));
+        assert_eq!(firefox["prefs"]["dom.webdriver.enabled"], json!(false));
+    }
+
+    #[test]
+    fn emulates_mobile_devices_in_chrome() {
+        let capabilities = capabilities(&JsScrapeOptions::new().device(crate::js::Device::iphone_se()));
+        let metrics = &capabilities["goog:chromeOptions"]["mobileEmulation"]["deviceMetrics"];
+        assert_eq!(metrics["width"], json!(375));
+        assert_eq!(metrics["pixelRatio"], json!(2.0));
+        assert_eq!(metrics["touch"], json!(true));
+    }
+}
@@ -0,0 +1,135 @@
+//! WebSocket scraping, built with the `websocket` feature.
+//!
+//! Some dashboards push their data over a WebSocket instead of rendering it into HTML.
+//! A `WsScraper` connects, optionally sends a subscribe message and yields the incoming
+//! messages as JSON values until a message count or duration cutoff is reached.
+
+use futures_util::{stream, SinkExt, Stream, StreamExt};
+use serde_json::Value;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::{timeout_at, Instant};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+/// Error type of streamed messages. It is `Send` so the stream can be moved into a spawned task.
+pub type WsError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Collects messages from a WebSocket endpoint.
+#[derive(Debug, Clone)]
+pub struct WsScraper {
+    url: String,
+    subscribe: Option<String>,
+    json_pointer: Option<String>,
+    max_messages: Option<usize>,
+    max_duration: Option<Duration>,
+}
+
+struct StreamState {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    received: usize,
+    max_messages: Option<usize>,
+    deadline: Option<Instant>,
+    json_pointer: Option<String>,
+}
+
+impl WsScraper {
+    /// Creates a scraper for a `ws://` or `wss://` URL with no cutoff.
+    pub fn new(url: &str) -> Self {
+        WsScraper {
+            url: url.to_string(),
+            subscribe: None,
+            json_pointer: None,
+            max_messages: None,
+            max_duration: None,
+        }
+    }
+
+    /// Sends this text message right after connecting, e.g. a channel subscription.
+    pub fn subscribe(mut self, message: &str) -> Self {
+        self.subscribe = Some(message.to_string());
+        self
+    }
+
+    /// Yields only the value at this JSON pointer; messages without it are skipped.
+    pub fn json_pointer(mut self, pointer: &str) -> Self {
+        self.json_pointer = Some(pointer.to_string());
+        self
+    }
+
+    /// Stops after this many yielded messages.
+    pub fn max_messages(mut self, max_messages: usize) -> Self {
+        self.max_messages = Some(max_messages);
+        self
+    }
+
+    /// Stops once this much time has passed since connecting.
+    pub fn max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Connects and returns the stream of incoming messages.
+    /// Text messages that are not valid JSON are yielded as JSON strings.
+    pub async fn stream(&self) -> Result<impl Stream<Item = Result<Value, WsError>>, Box<dyn std::error::Error>> {
+        let (mut socket, _) = connect_async(self.url.as_str()).await?;
+        if let Some(message) = &self.subscribe {
+            socket.send(Message::Text(message.clone())).await?;
+        }
+        let state = StreamState {
+            socket,
+            received: 0,
+            max_messages: self.max_messages,
+            deadline: self.max_duration.map(|duration| Instant::now() + duration),
+            json_pointer: self.json_pointer.clone(),
+        };
+        Ok(stream::unfold(state, next_message))
+    }
+
+    /// Connects and collects all messages until the cutoff or until the server closes the connection.
+    pub async fn collect(&self) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+        let mut messages = Box::pin(self.stream().await?);
+        let mut results = Vec::new();
+        while let Some(message) = messages.next().await {
+            match message {
+                Ok(value) => results.push(value),
+                Err(e) => return Err(e as Box<dyn std::error::Error>),
+            }
+        }
+        Ok(results)
+    }
+}
+
+async fn next_message(mut state: StreamState) -> Option<(Result<Value, WsError>, StreamState)> {
+    loop {
+        if state.max_messages.is_some_and(|max| state.received >= max) {
+            return None;
+        }
+        let next = match state.deadline {
+            Some(deadline) => timeout_at(deadline, state.socket.next()).await.ok()?,
+            None => state.socket.next().await,
+        };
+        let text = match next? {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Binary(bytes)) => match String::from_utf8(bytes) {
+                Ok(text) => text,
+                Err(_) => continue,
+            },
+            Ok(Message::Close(_)) => return None,
+            // Pings are answered by tungstenite itself.
+            Ok(_) => continue,
+            Err(e) => return Some((Err(e.into()), state)),
+        };
+
+        let value = serde_json::from_str(&text).unwrap_or(Value::String(text));
+        let value = match &state.json_pointer {
+            Some(pointer) => match value.pointer(pointer) {
+                Some(value) => value.clone(),
+                None => continue,
+            },
+            None => value,
+        };
+        state.received += 1;
+        return Some((Ok(value), state));
+    }
+}
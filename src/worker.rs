@@ -0,0 +1,186 @@
+//! Scraping driven by external job queues.
+//!
+//! A `Worker` consumes `ScrapeJob` messages from a channel, executes them and sends a
+//! `JobResult` for every job to a sink channel, so the scraper can be driven from any
+//! queueing infrastructure: a consumer feeds the job channel, a producer drains the results.
+
+use crate::config::ScrapeJob;
+use crate::report::ScrapeReport;
+use crate::{RateLimiter, RustScrapper, ScrapeConfig};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::mpsc::{Receiver, Sender};
+
+/// The outcome of a job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobResult {
+    /// The executed job, e.g. to acknowledge or requeue its message.
+    pub job: ScrapeJob,
+    pub records: Vec<Value>,
+    /// The error message if the job failed.
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+impl JobResult {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Executes jobs from a channel, several at a time if wanted.
+pub struct Worker {
+    concurrency: usize,
+    rate_limiter: Option<RateLimiter>,
+}
+
+impl Worker {
+    /// Creates a worker executing one job at a time.
+    pub fn new() -> Self {
+        Worker {
+            concurrency: 1,
+            rate_limiter: None,
+        }
+    }
+
+    /// Executes up to `jobs` jobs concurrently.
+    pub fn with_concurrency(mut self, jobs: usize) -> Self {
+        self.concurrency = jobs.max(1);
+        self
+    }
+
+    /// Throttles all jobs with one limiter instead of the per-job limiter of their
+    /// `min_delay_ms` setting. Pass clones of a limiter to several workers to throttle
+    /// them together.
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Consumes jobs until the job channel is closed and drained, or the result channel's
+    /// receiver is dropped, and returns a report of the executed jobs. Each job runs with a
+    /// scraper built from its settings and writes its export target like `ScrapeJob::execute`.
+    /// Failed jobs are reported in their result and do not stop the worker.
+    ///
+    /// Jobs run on the current task, so `run` must be awaited rather than spawned.
+    pub async fn run(self, receiver: Receiver<ScrapeJob>, sink: Sender<JobResult>) -> ScrapeReport {
+        let started = Instant::now();
+        let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+        let report = Arc::new(Mutex::new(ScrapeReport::default()));
+        let local = tokio::task::LocalSet::new();
+        for _ in 0..self.concurrency {
+            let (receiver, sink, report) = (receiver.clone(), sink.clone(), report.clone());
+            let rate_limiter = self.rate_limiter.clone();
+            local.spawn_local(async move {
+                loop {
+                    let Some(job) = receiver.lock().await.recv().await else {
+                        break;
+                    };
+                    let result = execute(job, rate_limiter.as_ref()).await;
+                    record(&mut report.lock().unwrap(), &result);
+                    if sink.send(result).await.is_err() {
+                        log::warn!("Result receiver dropped, stopping worker");
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sink);
+        local.await;
+        let mut report = report.lock().unwrap().clone();
+        report.duration_secs = started.elapsed().as_secs_f64();
+        report
+    }
+}
+
+impl Default for Worker {
+    fn default() -> Self {
+        Worker::new()
+    }
+}
+
+async fn execute(job: ScrapeJob, rate_limiter: Option<&RateLimiter>) -> JobResult {
+    let started = Instant::now();
+    let records = run_job(&job, rate_limiter).await;
+    let (records, error) = match records {
+        Ok(records) => (records, None),
+        Err(e) => {
+            log::error!("Job {:?} failed: {}", job.job.name, e);
+            (Vec::new(), Some(e.to_string()))
+        }
+    };
+    JobResult {
+        job,
+        records,
+        error,
+        duration_ms: started.elapsed().as_millis() as u64,
+    }
+}
+
+async fn run_job(job: &ScrapeJob, rate_limiter: Option<&RateLimiter>) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+    let config = ScrapeConfig {
+        scraper: job.scraper.clone(),
+        jobs: Vec::new(),
+    };
+    let mut scrapper = RustScrapper::from_config(&config)?;
+    scrapper.set_context(job.job.context.clone());
+    if let Some(limiter) = rate_limiter {
+        scrapper = scrapper.with_rate_limiter(limiter.clone());
+    }
+    let records = job.job.run_async(&mut scrapper).await?;
+    job.job.export(&records)?;
+    Ok(records)
+}
+
+fn record(report: &mut ScrapeReport, result: &JobResult) {
+    report.attempted += 1;
+    match &result.error {
+        Some(error) => report.record_failure(error),
+        None => {
+            report.succeeded += 1;
+            report.items += result.records.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::config::JobConfig;
+
+    fn job(name: &str, selector: Option<&str>) -> ScrapeJob {
+        ScrapeJob {
+            job: JobConfig {
+                name: name.to_string(),
+                urls: vec!["data:text/html,<b>1</b><b>2</b>".to_string()],
+                selector: selector.map(str::to_string),
+                ..JobConfig::default()
+            },
+            ..ScrapeJob::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn executes_jobs_and_reports_failures() {
+        let (jobs, receiver) = tokio::sync::mpsc::channel(4);
+        let (sink, mut results) = tokio::sync::mpsc::channel(4);
+        jobs.send(job("bold", Some("b"))).await.unwrap();
+        jobs.send(job("broken", None)).await.unwrap();
+        drop(jobs);
+
+        let report = Worker::new().with_concurrency(2).run(receiver, sink).await;
+        let mut received = Vec::new();
+        while let Some(result) = results.recv().await {
+            received.push(result);
+        }
+        received.sort_by(|a, b| a.job.job.name.cmp(&b.job.job.name));
+
+        assert!(received[0].is_ok());
+        assert_eq!(received[0].records, vec![Value::from("1"), Value::from("2")]);
+        assert!(received[1].error.as_deref().unwrap().contains("neither a selector nor a schema"));
+        assert_eq!((report.attempted, report.succeeded, report.items), (2, 1, 2));
+    }
+}